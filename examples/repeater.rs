@@ -22,7 +22,7 @@ async fn main() -> irc::error::Result<()> {
         let message = stream.select_next_some().await?;
 
         if let Command::PRIVMSG(ref target, ref msg) = message.command {
-            if msg.starts_with(client.current_nickname()) {
+            if msg.starts_with(&*client.current_nickname()) {
                 let tokens: Vec<_> = msg.split(' ').collect();
                 if tokens.len() > 2 {
                     let n = tokens[0].len() + tokens[1].len() + 2;
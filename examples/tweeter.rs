@@ -1,3 +1,6 @@
+// `tokio::select!` expands to code newer than our MSRV; this is only an example.
+#![allow(clippy::incompatible_msrv)]
+
 use irc::client::prelude::*;
 use std::time::Duration;
 use tokio_stream::StreamExt as _;
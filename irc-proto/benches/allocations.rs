@@ -0,0 +1,60 @@
+//! Checks that splitting messages into a borrowed `MessageRef` makes no allocations, unlike
+//! parsing them into an owned `Message`.
+//!
+//! This lives in its own binary because counting allocations needs a global allocator, which
+//! would otherwise skew the timings in the parsing benchmarks.
+//!
+//! Run with `cargo bench -p irc-proto --bench allocations`.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::black_box;
+use irc_proto::{Message, MessageRef};
+
+/// Wraps the system allocator to count allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Counts the allocations made by `f`.
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+const MESSAGES: &[&str] = &[
+    ":nick!user@host.example.com PRIVMSG #channel :Hello, everyone! How is it going?\r\n",
+    "@time=2023-05-01T12:00:00.000Z;account=nick;msgid=a1b2c3 :nick!user@host.example.com \
+     PRIVMSG #channel :Tagged message with a few words in it\r\n",
+    "@+draft/reply=abc;label=a\\sb\\:c :nick!user@host PRIVMSG #channel :escaped tags\r\n",
+    ":irc.example.com 353 me = #channel :@op +voiced nick1 nick2 nick3 nick4 nick5 nick6\r\n",
+    ":irc.example.com 005 me CHANTYPES=# EXCEPTS INVEX CHANMODES=eIbq,k,flj,CFLMPQScgimnprstz \
+     CHANLIMIT=#:120 PREFIX=(ov)@+ MAXLIST=bqeI:100 :are supported by this server\r\n",
+    "PING :irc.example.com\r\n",
+];
+
+fn main() {
+    for msg in MESSAGES {
+        let borrowed = allocations(|| MessageRef::parse(msg).unwrap());
+        assert_eq!(borrowed, 0, "borrowed parse of {:?} allocated", msg);
+        let owned = allocations(|| msg.parse::<Message>().unwrap());
+        assert!(owned > 0, "owned parse of {:?} did not allocate", msg);
+    }
+}
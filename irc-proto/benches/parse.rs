@@ -0,0 +1,81 @@
+//! Benchmarks for parsing messages, the hot path when receiving from a busy connection.
+//!
+//! Run with `cargo bench -p irc-proto`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use irc_proto::{Message, MessageRef};
+
+/// A representative mix of the traffic a client sees on a busy network.
+const MESSAGES: &[&str] = &[
+    ":nick!user@host.example.com PRIVMSG #channel :Hello, everyone! How is it going?\r\n",
+    "@time=2023-05-01T12:00:00.000Z;account=nick;msgid=a1b2c3 :nick!user@host.example.com \
+     PRIVMSG #channel :Tagged message with a few words in it\r\n",
+    "@+draft/reply=abc;label=a\\sb\\:c :nick!user@host PRIVMSG #channel :escaped tags\r\n",
+    ":irc.example.com 353 me = #channel :@op +voiced nick1 nick2 nick3 nick4 nick5 nick6\r\n",
+    ":irc.example.com 005 me CHANTYPES=# EXCEPTS INVEX CHANMODES=eIbq,k,flj,CFLMPQScgimnprstz \
+     CHANLIMIT=#:120 PREFIX=(ov)@+ MAXLIST=bqeI:100 :are supported by this server\r\n",
+    ":nick!user@host.example.com JOIN #channel\r\n",
+    ":nick!user@host.example.com PART #channel :Leaving\r\n",
+    ":nick!user@host.example.com QUIT :Quit: Connection reset by peer\r\n",
+    ":op!user@host MODE #channel +ov nick1 nick2\r\n",
+    "PING :irc.example.com\r\n",
+];
+
+fn parse(c: &mut Criterion) {
+    let bytes: usize = MESSAGES.iter().map(|m| m.len()).sum();
+    let mut group = c.benchmark_group("parse");
+
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("mix", |b| {
+        b.iter(|| {
+            for msg in MESSAGES {
+                black_box(black_box(msg).parse::<Message>().unwrap());
+            }
+        })
+    });
+
+    group.throughput(Throughput::Elements(1));
+    for (name, msg) in [
+        ("privmsg", MESSAGES[0]),
+        ("tagged", MESSAGES[1]),
+        ("escaped_tags", MESSAGES[2]),
+        ("numeric", MESSAGES[3]),
+        ("isupport", MESSAGES[4]),
+        ("ping", MESSAGES[9]),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(black_box(msg).parse::<Message>().unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+fn parse_borrowed(c: &mut Criterion) {
+    let bytes: usize = MESSAGES.iter().map(|m| m.len()).sum();
+    let mut group = c.benchmark_group("parse_borrowed");
+
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("mix", |b| {
+        b.iter(|| {
+            for msg in MESSAGES {
+                black_box(MessageRef::parse(black_box(msg)).unwrap());
+            }
+        })
+    });
+
+    group.throughput(Throughput::Elements(1));
+    for (name, msg) in [
+        ("privmsg", MESSAGES[0]),
+        ("tagged", MESSAGES[1]),
+        ("isupport", MESSAGES[4]),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(MessageRef::parse(black_box(msg)).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, parse, parse_borrowed);
+criterion_main!(benches);
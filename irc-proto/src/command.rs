@@ -192,10 +192,14 @@ pub enum Command {
     METADATA(String, Option<MetadataSubCommand>, Option<Vec<String>>),
     /// MONITOR command [nicklist]
     MONITOR(String, Option<String>),
+    /// WATCH [(+|-)nickname | C | L | l | S]*
+    WATCH(Vec<String>),
     /// BATCH (+/-)reference-tag [type [params]]
     BATCH(String, Option<BatchSubCommand>, Option<Vec<String>>),
     /// CHGHOST user host
     CHGHOST(String, String),
+    /// SETNAME :realname
+    SETNAME(String),
 
     // Default option.
     /// An IRC response code with arguments and optional suffix.
@@ -239,6 +243,7 @@ impl<'a> From<&'a Command> for String {
             Command::SERVICE(ref nick, ref r0, ref dist, ref typ, ref r1, ref info) => {
                 stringify("SERVICE", &[nick, r0, dist, typ, r1, info])
             }
+            // The comment is always a trailing parameter, even when it's a single word.
             Command::QUIT(Some(ref m)) => stringify("QUIT", &[m]),
             Command::QUIT(None) => stringify("QUIT", &[]),
             Command::SQUIT(ref s, ref c) => stringify("SQUIT", &[s, c]),
@@ -370,7 +375,7 @@ impl<'a> From<&'a Command> for String {
             }
             Command::METADATA(ref t, Some(ref c), Some(ref a)) => stringify(
                 "METADATA",
-                &vec![t, &c.to_str().to_owned()]
+                &[t, &c.to_str().to_owned()]
                     .iter()
                     .map(|s| &s[..])
                     .chain(a.iter().map(|s| &s[..]))
@@ -384,9 +389,12 @@ impl<'a> From<&'a Command> for String {
 
             Command::MONITOR(ref c, Some(ref t)) => stringify("MONITOR", &[c, t]),
             Command::MONITOR(ref c, None) => stringify("MONITOR", &[c]),
+            Command::WATCH(ref a) => {
+                stringify("WATCH", &a.iter().map(|s| &s[..]).collect::<Vec<_>>())
+            }
             Command::BATCH(ref t, Some(ref c), Some(ref a)) => stringify(
                 "BATCH",
-                &vec![t, &c.to_str().to_owned()]
+                &[t, &c.to_str().to_owned()]
                     .iter()
                     .map(|s| &s[..])
                     .chain(a.iter().map(|s| &s[..]))
@@ -395,14 +403,14 @@ impl<'a> From<&'a Command> for String {
             Command::BATCH(ref t, Some(ref c), None) => stringify("BATCH", &[t, c.to_str()]),
             Command::BATCH(ref t, None, Some(ref a)) => stringify(
                 "BATCH",
-                &vec![t]
-                    .iter()
+                &[t].iter()
                     .map(|s| &s[..])
                     .chain(a.iter().map(|s| &s[..]))
                     .collect::<Vec<_>>(),
             ),
             Command::BATCH(ref t, None, None) => stringify("BATCH", &[t]),
             Command::CHGHOST(ref u, ref h) => stringify("CHGHOST", &[u, h]),
+            Command::SETNAME(ref r) => stringify("SETNAME", &[r]),
 
             Command::Response(ref resp, ref a) => stringify(
                 &format!("{:03}", *resp as u16),
@@ -418,6 +426,17 @@ impl<'a> From<&'a Command> for String {
 impl Command {
     /// Constructs a new Command.
     pub fn new(cmd: &str, args: Vec<&str>) -> Result<Command, MessageParseError> {
+        // Numeric replies make up much of the traffic from a server, and can never match one of the
+        // named commands below, so we skip straight to them.
+        if cmd.len() == 3 && cmd.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(match cmd.parse() {
+                Ok(resp) => {
+                    Command::Response(resp, args.into_iter().map(|s| s.to_owned()).collect())
+                }
+                Err(_) => raw(cmd, args),
+            });
+        }
+
         Ok(if cmd.eq_ignore_ascii_case("PASS") {
             if args.len() != 1 {
                 raw(cmd, args)
@@ -938,6 +957,8 @@ impl Command {
             } else {
                 raw(cmd, args)
             }
+        } else if cmd.eq_ignore_ascii_case("WATCH") {
+            Command::WATCH(args.into_iter().map(|s| s.to_owned()).collect())
         } else if cmd.eq_ignore_ascii_case("BATCH") {
             if args.len() == 1 {
                 Command::BATCH(args[0].to_owned(), None, None)
@@ -958,12 +979,37 @@ impl Command {
             } else {
                 raw(cmd, args)
             }
+        } else if cmd.eq_ignore_ascii_case("SETNAME") {
+            if args.len() == 1 {
+                Command::SETNAME(args[0].to_owned())
+            } else {
+                raw(cmd, args)
+            }
         } else if let Ok(resp) = cmd.parse() {
             Command::Response(resp, args.into_iter().map(|s| s.to_owned()).collect())
         } else {
             raw(cmd, args)
         })
     }
+
+    /// Constructs an `INVITE` command inviting the given user to the given channel.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Command;
+    /// # fn main() {
+    /// let invite = Command::invite("alice", "#rust");
+    /// assert_eq!(String::from(&invite), "INVITE alice #rust");
+    /// # }
+    /// ```
+    pub fn invite<S1, S2>(nick: S1, chan: S2) -> Command
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Command::INVITE(nick.into(), chan.into())
+    }
 }
 
 /// Makes a raw message from the specified command, arguments, and suffix.
@@ -1155,4 +1201,34 @@ mod test {
             cmd
         );
     }
+
+    #[test]
+    fn invite() {
+        let line = Message::from(Command::invite("alice", "#rust")).to_string();
+        assert_eq!(line, "INVITE alice #rust\r\n");
+        assert_eq!(
+            line.parse::<Message>().unwrap().command,
+            Command::INVITE("alice".to_owned(), "#rust".to_owned())
+        );
+    }
+
+    #[test]
+    fn watch() {
+        let cmd = Command::WATCH(vec!["+alice".to_owned(), "-bob".to_owned()]);
+        let line = Message::from(cmd.clone()).to_string();
+        assert_eq!(line, "WATCH +alice -bob\r\n");
+        assert_eq!(line.parse::<Message>().unwrap().command, cmd);
+        assert_eq!(
+            "WATCH".parse::<Message>().unwrap().command,
+            Command::WATCH(vec![])
+        );
+    }
+
+    #[test]
+    fn setname() {
+        let cmd = Command::SETNAME("New Real Name".to_owned());
+        let line = Message::from(cmd.clone()).to_string();
+        assert_eq!(line, "SETNAME :New Real Name\r\n");
+        assert_eq!(line.parse::<Message>().unwrap().command, cmd);
+    }
 }
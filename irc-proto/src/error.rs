@@ -21,6 +21,38 @@ pub enum ProtocolError {
         #[source]
         cause: MessageParseError,
     },
+
+    /// Error for received lines that exceed the maximum line length.
+    #[error("line exceeded the maximum length of {} bytes", max)]
+    LineTooLong {
+        /// The maximum line length in bytes.
+        max: usize,
+    },
+
+    /// Error for messages that exceed the maximum line length.
+    #[error("message is too long ({} bytes)", length)]
+    MessageTooLong {
+        /// The length of the message in bytes, excluding tags.
+        length: usize,
+    },
+
+    /// Error for received lines that are not valid in the encoding used to decode them.
+    #[error("failed to decode line as {}", encoding)]
+    DecodeFailed {
+        /// The name of the encoding.
+        encoding: &'static str,
+        /// The raw bytes of the line, without the line ending.
+        line: Vec<u8>,
+    },
+
+    /// Error for lines that cannot be represented in the encoding used to send them.
+    #[error("failed to encode line as {}", encoding)]
+    EncodeFailed {
+        /// The name of the encoding.
+        encoding: &'static str,
+        /// The line that failed to encode.
+        line: String,
+    },
 }
 
 impl From<std::io::Error> for ProtocolError {
@@ -50,6 +82,14 @@ pub enum MessageParseError {
         cause: ModeParseError,
     },
 
+    /// A component of the message could not be represented on the wire (e.g. it contained a line
+    /// break or, for a middle parameter, a space).
+    #[error("invalid parameter: {:?}", param)]
+    InvalidParameter {
+        /// The offending parameter.
+        param: String,
+    },
+
     /// The subcommand used was invalid.
     #[error("invalid {} subcommand: {}", cmd, sub)]
     InvalidSubcommand {
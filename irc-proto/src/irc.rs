@@ -2,19 +2,106 @@
 use bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
 
+use crate::command::Command;
 use crate::error;
 use crate::line::LineCodec;
 use crate::message::Message;
 
+/// The maximum length in bytes of a line sent to the server, including the trailing CRLF but not
+/// including any message tags.
+pub const MAX_LINE_LENGTH: usize = 512;
+
 /// An IRC codec built around an inner codec.
 pub struct IrcCodec {
     inner: LineCodec,
+    split_long_messages: bool,
+    keep_raw_bytes: bool,
+    raw_bytes: Option<Vec<u8>>,
+    bytes_decoded: u64,
 }
 
 impl IrcCodec {
     /// Creates a new instance of IrcCodec wrapping a LineCodec with the specific encoding.
     pub fn new(label: &str) -> error::Result<IrcCodec> {
-        LineCodec::new(label).map(|codec| IrcCodec { inner: codec })
+        IrcCodec::with_encodings(label, label)
+    }
+
+    /// Creates a new instance of IrcCodec wrapping a LineCodec that decodes received messages with
+    /// one encoding and encodes sent messages with another.
+    pub fn with_encodings(recv_label: &str, send_label: &str) -> error::Result<IrcCodec> {
+        LineCodec::with_encodings(recv_label, send_label).map(|codec| IrcCodec {
+            inner: codec,
+            split_long_messages: false,
+            keep_raw_bytes: false,
+            raw_bytes: None,
+            bytes_decoded: 0,
+        })
+    }
+
+    /// Sets whether `PRIVMSG` and `NOTICE` messages longer than
+    /// [`MAX_LINE_LENGTH`](constant.MAX_LINE_LENGTH.html) are split into several messages on
+    /// UTF-8 character boundaries. Otherwise, encoding any message that is too long fails with
+    /// `ProtocolError::MessageTooLong`. This is disabled by default.
+    ///
+    /// Note that servers prepend our prefix when relaying messages, so recipients may still see
+    /// split messages truncated slightly.
+    pub fn set_split_long_messages(&mut self, split: bool) {
+        self.split_long_messages = split;
+    }
+
+    /// Sets whether the codec keeps the bytes of each line it decodes, which are then available
+    /// through [`take_raw_bytes`](#method.take_raw_bytes). This is disabled by default.
+    pub fn set_keep_raw_bytes(&mut self, keep: bool) {
+        self.keep_raw_bytes = keep;
+        if !keep {
+            self.raw_bytes = None;
+        }
+    }
+
+    /// Takes the bytes of the line most recently decoded, without the line ending, if the codec
+    /// keeps them. These are exactly the bytes received, even if they were not valid in the
+    /// codec's encoding.
+    pub fn take_raw_bytes(&mut self) -> Option<Vec<u8>> {
+        self.raw_bytes.take()
+    }
+
+    /// Checks that the message can be encoded by a codec with the given
+    /// [`set_split_long_messages`](#method.set_split_long_messages) setting, failing with
+    /// `ProtocolError::MessageTooLong` if it is too long. Checking before handing messages to the
+    /// codec avoids failing the whole sink over a single message.
+    pub fn check_line_length(msg: &Message, split_long_messages: bool) -> error::Result<()> {
+        let length = line_length(&IrcCodec::sanitize(msg.to_string()));
+        if length <= MAX_LINE_LENGTH
+            || (split_long_messages && split_message(msg, length).is_some())
+        {
+            return Ok(());
+        }
+        Err(error::ProtocolError::MessageTooLong { length })
+    }
+
+    /// Gets the total number of bytes taken from the buffer by `decode`, including line endings
+    /// and any lines that failed to decode.
+    pub fn bytes_decoded(&self) -> u64 {
+        self.bytes_decoded
+    }
+
+    /// Sets the maximum length in bytes of a received line. See
+    /// [`LineCodec::set_max_line_length`](../line/struct.LineCodec.html#method.set_max_line_length).
+    pub fn set_max_line_length(&mut self, max: usize) {
+        self.inner.set_max_line_length(max);
+    }
+
+    /// Sets the encodings to try when a received message is not valid in the primary encoding. See
+    /// [`LineCodec::set_fallback_encodings`](../line/struct.LineCodec.html#method.set_fallback_encodings).
+    pub fn set_fallback_encodings<S: AsRef<str>>(&mut self, labels: &[S]) -> error::Result<()> {
+        self.inner.set_fallback_encodings(labels)
+    }
+
+    /// Sets whether a received message that can't be decoded fails instead of being decoded with
+    /// replacement characters. See
+    /// [`LineCodec::set_strict_decoding`](../line/struct.LineCodec.html#method.set_strict_decoding).
+    pub fn set_strict_decoding(&mut self, strict: bool) {
+        self.inner.set_strict_decoding(strict);
     }
 
     /// Sanitizes the input string by cutting up to (and including) the first occurence of a line
@@ -31,6 +118,24 @@ impl IrcCodec {
         }
         data
     }
+
+    /// Decodes the next message, without counting the bytes taken from the buffer.
+    fn decode_message(&mut self, src: &mut BytesMut) -> error::Result<Option<Message>> {
+        if !self.keep_raw_bytes {
+            return self
+                .inner
+                .decode(src)
+                .and_then(|res| res.map_or(Ok(None), |msg| msg.parse::<Message>().map(Some)));
+        }
+
+        match self.inner.decode_raw(src)? {
+            Some((line, raw)) => {
+                self.raw_bytes = Some(raw.to_vec());
+                line.parse::<Message>().map(Some)
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 impl Decoder for IrcCodec {
@@ -38,9 +143,10 @@ impl Decoder for IrcCodec {
     type Error = error::ProtocolError;
 
     fn decode(&mut self, src: &mut BytesMut) -> error::Result<Option<Message>> {
-        self.inner
-            .decode(src)
-            .and_then(|res| res.map_or(Ok(None), |msg| msg.parse::<Message>().map(Some)))
+        let len = src.len();
+        let result = self.decode_message(src);
+        self.bytes_decoded += (len - src.len()) as u64;
+        result
     }
 }
 
@@ -48,6 +154,255 @@ impl Encoder<Message> for IrcCodec {
     type Error = error::ProtocolError;
 
     fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> error::Result<()> {
-        self.inner.encode(IrcCodec::sanitize(msg.to_string()), dst)
+        let line = IrcCodec::sanitize(msg.to_string());
+        let length = line_length(&line);
+        if length <= MAX_LINE_LENGTH {
+            return self.inner.encode(line, dst);
+        }
+
+        match split_message(&msg, length) {
+            Some(lines) if self.split_long_messages => {
+                for line in lines {
+                    self.inner.encode(line, dst)?;
+                }
+                Ok(())
+            }
+            _ => Err(error::ProtocolError::MessageTooLong { length }),
+        }
+    }
+}
+
+/// Gets the length of a serialized line as counted against `MAX_LINE_LENGTH`, i.e. without tags.
+fn line_length(line: &str) -> usize {
+    match line.strip_prefix('@') {
+        Some(rest) => rest.find(' ').map_or(0, |i| rest.len() - i - 1),
+        None => line.len(),
+    }
+}
+
+/// Splits the text of a `PRIVMSG` or `NOTICE` whose serialized line is `length` bytes long across
+/// as many lines as needed. Returns `None` for other commands, or if nothing would fit.
+fn split_message(msg: &Message, length: usize) -> Option<Vec<String>> {
+    let (target, text, is_notice) = match msg.command {
+        Command::PRIVMSG(ref target, ref text) => (target, text, false),
+        Command::NOTICE(ref target, ref text) => (target, text, true),
+        _ => return None,
+    };
+    // Anything after a line break would have been cut off by sanitization.
+    let text = text.split(['\r', '\n']).next().unwrap_or("");
+    // Reserve a byte for the colon before the text, which short chunks might otherwise omit.
+    let available = (MAX_LINE_LENGTH + text.len()).checked_sub(length + 1)?;
+
+    let mut lines = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = available.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            return None;
+        }
+
+        let chunk = rest[..end].to_owned();
+        let command = if is_notice {
+            Command::NOTICE(target.clone(), chunk)
+        } else {
+            Command::PRIVMSG(target.clone(), chunk)
+        };
+        // Only the first line keeps the `label`, so that the server labels a single response.
+        let tags = if lines.is_empty() {
+            msg.tags.clone()
+        } else {
+            msg.tags.as_ref().and_then(|tags| {
+                let tags: Vec<_> = tags.iter().filter(|t| t.0 != "label").cloned().collect();
+                Some(tags).filter(|tags| !tags.is_empty())
+            })
+        };
+        lines.push(
+            Message {
+                tags,
+                prefix: msg.prefix.clone(),
+                command,
+            }
+            .to_string(),
+        );
+        rest = &rest[end..];
+    }
+    Some(lines)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::{IrcCodec, MAX_LINE_LENGTH};
+    use crate::command::Command::PRIVMSG;
+    use crate::error::ProtocolError;
+    use crate::message::{Message, Tag};
+
+    fn long_privmsg() -> Message {
+        // 600 bytes of text, including some multi-byte characters near the split point.
+        let mut text = "a".repeat(490);
+        text.push_str(&"é".repeat(10));
+        text.push_str(&"b".repeat(90));
+        PRIVMSG("#test".to_owned(), text).into()
+    }
+
+    #[test]
+    fn decode_latin1_encode_utf8() {
+        let mut codec = IrcCodec::with_encodings("ISO-8859-1", "UTF-8").unwrap();
+        let mut src = BytesMut::from(&b":a!b@c PRIVMSG #test :caf\xe9\r\n"[..]);
+        let msg = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(msg.command, PRIVMSG("#test".to_owned(), "café".to_owned()));
+
+        let mut dst = BytesMut::new();
+        codec.encode(msg.command.into(), &mut dst).unwrap();
+        assert_eq!(&dst[..], "PRIVMSG #test café\r\n".as_bytes());
+    }
+
+    #[test]
+    fn bytes_decoded() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        let mut src = BytesMut::from(&b"PING :x\r\nPRIVMSG #te"[..]);
+        assert!(codec.decode(&mut src).unwrap().is_some());
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(codec.bytes_decoded(), 9);
+        src.extend_from_slice(b"st :hi\n");
+        assert!(codec.decode(&mut src).unwrap().is_some());
+        assert_eq!(codec.bytes_decoded(), 27);
+    }
+
+    #[test]
+    fn decode_keep_raw_bytes() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        codec.set_keep_raw_bytes(true);
+        let mut src = BytesMut::from(&b"PRIVMSG #test :caf\xe9\r\nPING :x\n"[..]);
+
+        let msg = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(
+            msg.command,
+            PRIVMSG("#test".to_owned(), "caf\u{fffd}".to_owned())
+        );
+        assert_eq!(
+            codec.take_raw_bytes().as_deref(),
+            Some(&b"PRIVMSG #test :caf\xe9"[..])
+        );
+        assert_eq!(codec.take_raw_bytes(), None);
+
+        codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(codec.take_raw_bytes().as_deref(), Some(&b"PING :x"[..]));
+    }
+
+    #[test]
+    fn decode_without_raw_bytes() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        let mut src = BytesMut::from("PING :x\r\n");
+        codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(codec.take_raw_bytes(), None);
+    }
+
+    #[test]
+    fn encode_too_long() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        let mut dst = BytesMut::new();
+        match codec.encode(long_privmsg(), &mut dst) {
+            Err(ProtocolError::MessageTooLong { length }) => assert_eq!(length, 616),
+            other => panic!("expected the message to be too long, got {:?}", other),
+        }
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    fn check_line_length() {
+        let msg = long_privmsg();
+        assert!(matches!(
+            IrcCodec::check_line_length(&msg, false),
+            Err(ProtocolError::MessageTooLong { length: 616 })
+        ));
+        assert!(IrcCodec::check_line_length(&msg, true).is_ok());
+        assert!(IrcCodec::check_line_length(&"PING :x".parse().unwrap(), false).is_ok());
+    }
+
+    #[test]
+    fn encode_split() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        codec.set_split_long_messages(true);
+        let mut dst = BytesMut::new();
+        codec.encode(long_privmsg(), &mut dst).unwrap();
+
+        let data = String::from_utf8(dst.to_vec()).unwrap();
+        let lines: Vec<_> = data.split_inclusive("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let text: String = lines
+            .iter()
+            .map(|line| match line.parse::<Message>().unwrap().command {
+                PRIVMSG(ref target, ref text) if target == "#test" => text.clone(),
+                other => panic!("unexpected command {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            Some(text),
+            match long_privmsg().command {
+                PRIVMSG(_, text) => Some(text),
+                _ => None,
+            }
+        );
+    }
+
+    #[test]
+    fn encode_split_labels_first_line() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        codec.set_split_long_messages(true);
+        let mut dst = BytesMut::new();
+        let mut msg = long_privmsg();
+        msg.tags = Some(vec![
+            Tag("label".to_owned(), Some("abc".to_owned())),
+            Tag("+draft/reply".to_owned(), Some("123".to_owned())),
+        ]);
+        codec.encode(msg, &mut dst).unwrap();
+
+        let data = String::from_utf8(dst.to_vec()).unwrap();
+        let tags: Vec<_> = data
+            .split_inclusive("\r\n")
+            .map(|line| line.parse::<Message>().unwrap().tags.unwrap())
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                vec![
+                    Tag("label".to_owned(), Some("abc".to_owned())),
+                    Tag("+draft/reply".to_owned(), Some("123".to_owned())),
+                ],
+                vec![Tag("+draft/reply".to_owned(), Some("123".to_owned()))],
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_ignores_tags_in_length() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        let mut dst = BytesMut::new();
+        let msg = Message {
+            tags: Some(vec![Tag("label".to_owned(), Some("x".repeat(600)))]),
+            prefix: None,
+            command: PRIVMSG("#test".to_owned(), "short".to_owned()),
+        };
+        codec.encode(msg, &mut dst).unwrap();
+    }
+
+    #[test]
+    fn encode_split_only_messages() {
+        let mut codec = IrcCodec::new("UTF-8").unwrap();
+        codec.set_split_long_messages(true);
+        let mut dst = BytesMut::new();
+        let msg = Message::new(None, "TOPIC", vec!["#test", &"a".repeat(600)]).unwrap();
+        assert!(matches!(
+            codec.encode(msg, &mut dst),
+            Err(ProtocolError::MessageTooLong { .. })
+        ));
     }
 }
@@ -15,6 +15,7 @@ pub mod message;
 pub mod mode;
 pub mod prefix;
 pub mod response;
+pub mod standard_reply;
 
 pub use self::caps::{Capability, NegotiationVersion};
 pub use self::chan::ChannelExt;
@@ -22,7 +23,8 @@ pub use self::colors::FormattedStringExt;
 pub use self::command::{BatchSubCommand, CapSubCommand, Command};
 #[cfg(feature = "tokio")]
 pub use self::irc::IrcCodec;
-pub use self::message::Message;
+pub use self::message::{Message, MessageBuilder, MessageRef};
 pub use self::mode::{ChannelMode, Mode, UserMode};
 pub use self::prefix::Prefix;
 pub use self::response::Response;
+pub use self::standard_reply::{StandardReply, StandardReplyKind};
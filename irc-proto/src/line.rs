@@ -7,53 +7,145 @@ use encoding::label::encoding_from_whatwg_label;
 use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
 use tokio_util::codec::{Decoder, Encoder};
 
-use crate::error;
+use crate::error::{self, ProtocolError};
+
+/// The default maximum length in bytes of a received line, including its line ending.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 16 * 1024;
 
 /// A line-based codec parameterized by an encoding.
 pub struct LineCodec {
+    decoding: EncodingRef,
+    fallback_decodings: Vec<EncodingRef>,
     encoding: EncodingRef,
     next_index: usize,
+    max_line_length: usize,
+    strict_decoding: bool,
 }
 
 impl LineCodec {
     /// Creates a new instance of LineCodec from the specified encoding.
     pub fn new(label: &str) -> error::Result<LineCodec> {
-        encoding_from_whatwg_label(label)
-            .map(|enc| LineCodec {
-                encoding: enc,
-                next_index: 0,
-            })
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    &format!("Attempted to use unknown codec {}.", label)[..],
-                )
-                .into()
+        LineCodec::with_encodings(label, label)
+    }
+
+    /// Creates a new instance of LineCodec that decodes received lines with one encoding and
+    /// encodes sent lines with another.
+    pub fn with_encodings(recv_label: &str, send_label: &str) -> error::Result<LineCodec> {
+        Ok(LineCodec {
+            decoding: lookup_encoding(recv_label)?,
+            fallback_decodings: Vec::new(),
+            encoding: lookup_encoding(send_label)?,
+            next_index: 0,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            strict_decoding: false,
+        })
+    }
+
+    /// Sets the maximum length in bytes of a received line, including its line ending. Decoding a
+    /// longer line fails with [`ProtocolError::LineTooLong`] and discards the line. This defaults
+    /// to [`DEFAULT_MAX_LINE_LENGTH`].
+    pub fn set_max_line_length(&mut self, max: usize) {
+        self.max_line_length = max;
+    }
+
+    /// Sets the encodings to try, in order, when a received line is not valid in the primary
+    /// encoding. If none of them can decode the line either, it is decoded with the primary
+    /// encoding and any invalid sequences are replaced.
+    pub fn set_fallback_encodings<S: AsRef<str>>(&mut self, labels: &[S]) -> error::Result<()> {
+        self.fallback_decodings = labels
+            .iter()
+            .map(|label| lookup_encoding(label.as_ref()))
+            .collect::<error::Result<_>>()?;
+        Ok(())
+    }
+
+    /// Sets whether a received line that can't be decoded, even with the fallback encodings, fails
+    /// with [`ProtocolError::DecodeFailed`] instead of being decoded with replacement characters.
+    /// This is disabled by default.
+    pub fn set_strict_decoding(&mut self, strict: bool) {
+        self.strict_decoding = strict;
+    }
+
+    fn decode_line(&self, line: &[u8]) -> error::Result<String> {
+        if !self.fallback_decodings.is_empty() {
+            let strict = std::iter::once(&self.decoding)
+                .chain(&self.fallback_decodings)
+                .find_map(|enc| enc.decode(line, DecoderTrap::Strict).ok());
+            if let Some(data) = strict {
+                return Ok(data);
+            }
+        }
+
+        let trap = if self.strict_decoding {
+            DecoderTrap::Strict
+        } else {
+            DecoderTrap::Replace
+        };
+        self.decoding
+            .decode(line, trap)
+            .map_err(|_| ProtocolError::DecodeFailed {
+                encoding: self.decoding.name(),
+                line: line.to_vec(),
             })
     }
 }
 
+fn lookup_encoding(label: &str) -> error::Result<EncodingRef> {
+    encoding_from_whatwg_label(label).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            &format!("Attempted to use unknown codec {}.", label)[..],
+        )
+        .into()
+    })
+}
+
 impl Decoder for LineCodec {
     type Item = String;
     type Error = error::ProtocolError;
 
     fn decode(&mut self, src: &mut BytesMut) -> error::Result<Option<String>> {
+        self.decode_raw(src)
+            .map(|res| res.map(|(decoded, _)| decoded))
+    }
+}
+
+impl LineCodec {
+    /// Decodes the next line like `Decoder::decode`, but also returns the raw bytes of the line as
+    /// they were received, without the line ending.
+    pub(crate) fn decode_raw(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> error::Result<Option<(String, BytesMut)>> {
         if let Some(offset) = src[self.next_index..].iter().position(|b| *b == b'\n') {
             // Remove the next frame from the buffer.
-            let line = src.split_to(self.next_index + offset + 1);
+            let mut line = src.split_to(self.next_index + offset + 1);
 
             // Set the search start index back to 0 since we found a newline.
             self.next_index = 0;
 
-            // Decode the line using the codec's encoding.
-            match self.encoding.decode(line.as_ref(), DecoderTrap::Replace) {
-                Ok(data) => Ok(Some(data)),
-                Err(data) => Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    &format!("Failed to decode {} as {}.", data, self.encoding.name())[..],
-                )
-                .into()),
+            if line.len() > self.max_line_length {
+                return Err(ProtocolError::LineTooLong {
+                    max: self.max_line_length,
+                });
             }
+
+            // Strip the trailing `\n` along with a `\r` right before it, but leave any other `\r`.
+            line.truncate(line.len() - 1);
+            if line.ends_with(b"\r") {
+                line.truncate(line.len() - 1);
+            }
+
+            // Decode the line using the codec's encoding, or one of its fallbacks.
+            self.decode_line(&line).map(|decoded| Some((decoded, line)))
+        } else if src.len() > self.max_line_length {
+            // Drop everything buffered so far so that a peer that never sends a newline cannot
+            // make us buffer without bound.
+            src.clear();
+            self.next_index = 0;
+            Err(ProtocolError::LineTooLong {
+                max: self.max_line_length,
+            })
         } else {
             // Set the search start index to the current length since we know that none of the
             // characters we've already looked at are newlines.
@@ -68,20 +160,141 @@ impl Encoder<String> for LineCodec {
 
     fn encode(&mut self, msg: String, dst: &mut BytesMut) -> error::Result<()> {
         // Encode the message using the codec's encoding.
-        let data: error::Result<Vec<u8>> = self
+        let data = self
             .encoding
             .encode(&msg, EncoderTrap::Replace)
-            .map_err(|data| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    &format!("Failed to encode {} as {}.", data, self.encoding.name())[..],
-                )
-                .into()
-            });
+            .map_err(|_| ProtocolError::EncodeFailed {
+                encoding: self.encoding.name(),
+                line: msg,
+            })?;
 
         // Write the encoded message to the output buffer.
-        dst.extend(&data?);
+        dst.extend(&data);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::{LineCodec, DEFAULT_MAX_LINE_LENGTH};
+    use crate::error::ProtocolError;
+
+    fn decode(data: &str) -> Vec<String> {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        let mut src = BytesMut::from(data);
+        let mut lines = Vec::new();
+        while let Some(line) = codec.decode(&mut src).unwrap() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn decode_crlf() {
+        assert_eq!(decode("foo\r\n"), vec!["foo"]);
+    }
+
+    #[test]
+    fn decode_lf() {
+        assert_eq!(decode("foo\n"), vec!["foo"]);
+    }
+
+    #[test]
+    fn decode_inner_cr() {
+        assert_eq!(decode("foo\rbar\r\n"), vec!["foo\rbar"]);
+    }
+
+    #[test]
+    fn decode_partial() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        let mut src = BytesMut::from("foo\r");
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.extend_from_slice(b"\nbar\n");
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("foo".to_owned()));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("bar".to_owned()));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn decode_unbounded_line() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        let mut src = BytesMut::from(&[b'a'; 64 * 1024][..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::LineTooLong {
+                max: DEFAULT_MAX_LINE_LENGTH
+            })
+        ));
+        assert!(src.is_empty());
+        src.extend_from_slice(b"foo\r\n");
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("foo".to_owned()));
+    }
+
+    #[test]
+    fn decode_max_line_length() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        codec.set_max_line_length(8);
+        let mut src = BytesMut::from("toolongline\r\nfoo\r\n");
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::LineTooLong { max: 8 })
+        ));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("foo".to_owned()));
+    }
+
+    #[test]
+    fn decode_latin1_encode_utf8() {
+        let mut codec = LineCodec::with_encodings("ISO-8859-1", "UTF-8").unwrap();
+        let mut src = BytesMut::from(&b"caf\xe9\r\n"[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("café".to_owned()));
+
+        let mut dst = BytesMut::new();
+        codec.encode("café\r\n".to_owned(), &mut dst).unwrap();
+        assert_eq!(&dst[..], "café\r\n".as_bytes());
+    }
+
+    #[test]
+    fn decode_fallback() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        codec.set_fallback_encodings(&["ISO-8859-1"]).unwrap();
+        let mut src = BytesMut::from(&b"caf\xe9\r\ncaf\xc3\xa9\r\n"[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("café".to_owned()));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("café".to_owned()));
+    }
+
+    #[test]
+    fn decode_fallback_exhausted() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        codec.set_fallback_encodings(&["ISO-2022-JP"]).unwrap();
+        let mut src = BytesMut::from(&b"caf\xe9\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some("caf\u{fffd}".to_owned())
+        );
+    }
+
+    #[test]
+    fn decode_strict_failure() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        codec.set_strict_decoding(true);
+        let mut src = BytesMut::from(&b"caf\xe9\r\nfoo\r\n"[..]);
+        match codec.decode(&mut src) {
+            Err(ProtocolError::DecodeFailed { encoding, line }) => {
+                assert_eq!(encoding, "utf-8");
+                assert_eq!(line, b"caf\xe9");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(codec.decode(&mut src).unwrap(), Some("foo".to_owned()));
+    }
+
+    #[test]
+    fn unknown_fallback() {
+        let mut codec = LineCodec::new("UTF-8").unwrap();
+        assert!(codec.set_fallback_encodings(&["not-an-encoding"]).is_err());
+    }
+}
@@ -8,6 +8,7 @@ use crate::command::Command;
 use crate::error;
 use crate::error::{MessageParseError, ProtocolError};
 use crate::prefix::Prefix;
+use crate::response::Response;
 
 /// A data structure representing an IRC message according to the protocol specification. It
 /// consists of a collection of IRCv3 tags, a prefix (describing the source of the message), and
@@ -64,6 +65,30 @@ impl Message {
         })
     }
 
+    /// Creates a builder for constructing a validated message piece by piece.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let message = Message::builder()
+    ///     .prefix("nickname!username@hostname")
+    ///     .command("PRIVMSG")
+    ///     .param("#channel")
+    ///     .trailing("Hello, world!")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     message.to_string(),
+    ///     ":nickname!username@hostname PRIVMSG #channel :Hello, world!\r\n"
+    /// );
+    /// # }
+    /// ```
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+
     /// Gets the nickname of the message source, if it exists.
     ///
     /// # Example
@@ -86,6 +111,47 @@ impl Message {
         })
     }
 
+    /// Gets the username of the message source, if it exists.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let message = Message::new(
+    ///     Some("nickname!username@hostname"), "JOIN", vec!["#channel"]
+    /// ).unwrap();
+    /// assert_eq!(message.source_username(), Some("username"));
+    /// # }
+    /// ```
+    pub fn source_username(&self) -> Option<&str> {
+        self.prefix.as_ref().and_then(|p| match p {
+            Prefix::Nickname(_, user, _) if !user.is_empty() => Some(&user[..]),
+            _ => None,
+        })
+    }
+
+    /// Gets the hostname of the message source, if it exists. Messages sent by a server have no
+    /// source hostname; use the `prefix` directly to get the server name.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let message = Message::new(
+    ///     Some("nickname!username@hostname"), "JOIN", vec!["#channel"]
+    /// ).unwrap();
+    /// assert_eq!(message.source_hostname(), Some("hostname"));
+    /// # }
+    /// ```
+    pub fn source_hostname(&self) -> Option<&str> {
+        self.prefix.as_ref().and_then(|p| match p {
+            Prefix::Nickname(_, _, host) if !host.is_empty() => Some(&host[..]),
+            _ => None,
+        })
+    }
+
     /// Gets the likely intended place to respond to this message.
     /// If the type of the message is a `PRIVMSG` or `NOTICE` and the message is sent to a channel,
     /// the result will be that channel. In all other cases, this will call `source_nickname`.
@@ -112,6 +178,141 @@ impl Message {
             _ => self.source_nickname(),
         }
     }
+
+    /// Gets the likely intended place to respond to this message like `response_target`, but
+    /// treats only names starting with one of the given `chantypes` as channels. This is meant to
+    /// be used with the value of the server's `CHANTYPES` ISUPPORT token.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let msg = Message::new(
+    ///     Some("ada"), "PRIVMSG", vec!["&channel", "Hi, everyone!"]
+    /// ).unwrap();
+    /// assert_eq!(msg.response_target_with_chantypes("#&"), Some("&channel"));
+    /// assert_eq!(msg.response_target_with_chantypes("#"), Some("ada"));
+    /// # }
+    /// ```
+    pub fn response_target_with_chantypes(&self, chantypes: &str) -> Option<&str> {
+        let is_channel = |target: &str| target.starts_with(|c| chantypes.contains(c));
+        match self.command {
+            Command::PRIVMSG(ref target, _) if is_channel(target) => Some(target),
+            Command::NOTICE(ref target, _) if is_channel(target) => Some(target),
+            _ => self.source_nickname(),
+        }
+    }
+
+    /// Determines whether this is one of our own messages echoed back by the server, i.e. a
+    /// `PRIVMSG`, `NOTICE` or `TAGMSG` sent by the given nickname. Servers echo the messages we
+    /// send when the `echo-message` capability is enabled.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let msg = Message::new(
+    ///     Some("ada!ada@example.com"), "PRIVMSG", vec!["#channel", "Hi, everyone!"]
+    /// ).unwrap();
+    /// assert!(msg.is_echo("ada"));
+    /// assert!(!msg.is_echo("betsy"));
+    /// # }
+    /// ```
+    pub fn is_echo(&self, nickname: &str) -> bool {
+        let is_message = match self.command {
+            Command::PRIVMSG(..) | Command::NOTICE(..) => true,
+            Command::Raw(ref command, _) => command.eq_ignore_ascii_case("TAGMSG"),
+            _ => false,
+        };
+        is_message
+            && self
+                .source_nickname()
+                .map_or(false, |source| source.eq_ignore_ascii_case(nickname))
+    }
+
+    /// Determines whether this is a `PRIVMSG` whose text mentions the given nickname (or any other
+    /// word) as a whole word, e.g. in `bob: hi` or `hey bob!` but not in `bobcat`. Words are
+    /// delimited by anything that cannot appear in a nickname, and are compared using the
+    /// `rfc1459` case mapping, under which `[]\\~` are the upper case forms of `{}|^`.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let msg = Message::new(
+    ///     Some("ada!ada@example.com"), "PRIVMSG", vec!["#channel", "hey Bob!"]
+    /// ).unwrap();
+    /// assert!(msg.mentions("bob"));
+    /// assert!(!msg.mentions("ada"));
+    /// # }
+    /// ```
+    pub fn mentions(&self, word: &str) -> bool {
+        self.mentions_with(word, rfc1459_lowercase)
+    }
+
+    /// Determines whether this is a `PRIVMSG` whose text mentions the given word like
+    /// [`mentions`](#method.mentions), but compares using the given case mapping instead, e.g. the
+    /// one the server advertised in its `CASEMAPPING` token. The mapping must not change the
+    /// length of the text it lowercases.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::Message;
+    /// # fn main() {
+    /// let msg = Message::new(
+    ///     Some("ada!ada@example.com"), "PRIVMSG", vec!["#channel", "hey [bob]!"]
+    /// ).unwrap();
+    /// assert!(msg.mentions("{bob}"));
+    /// assert!(!msg.mentions_with("{bob}", |text| text.to_ascii_lowercase()));
+    /// # }
+    /// ```
+    pub fn mentions_with<F>(&self, word: &str, lowercase: F) -> bool
+    where
+        F: Fn(&str) -> String,
+    {
+        let text = match self.command {
+            Command::PRIVMSG(_, ref text) => text,
+            _ => return false,
+        };
+        if word.is_empty() {
+            return false;
+        }
+        let is_nick_char = |c: char| c.is_alphanumeric() || "[]\\`_^{|}-".contains(c);
+        let text = lowercase(text);
+        let word = lowercase(word);
+        text.match_indices(&word[..]).any(|(start, _)| {
+            let end = start + word.len();
+            !text[..start]
+                .chars()
+                .next_back()
+                .map_or(false, is_nick_char)
+                && !text[end..].chars().next().map_or(false, is_nick_char)
+        })
+    }
+
+    /// Gets the numeric response of this message, if it is one.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc_proto;
+    /// # use irc_proto::{Message, Response};
+    /// # fn main() {
+    /// let msg: Message = ":irc.test.net 433 * test :Nickname is already in use".parse().unwrap();
+    /// assert_eq!(msg.response(), Some(Response::ERR_NICKNAMEINUSE));
+    /// let msg: Message = ":test!test@test PRIVMSG #test :Hi!".parse().unwrap();
+    /// assert_eq!(msg.response(), None);
+    /// # }
+    /// ```
+    pub fn response(&self) -> Option<Response> {
+        match self.command {
+            Command::Response(resp, _) => Some(resp),
+            _ => None,
+        }
+    }
 }
 
 impl From<Command> for Message {
@@ -124,10 +325,160 @@ impl From<Command> for Message {
     }
 }
 
+/// A builder for messages, created with [`Message::builder`](struct.Message.html#method.builder).
+/// Every component is validated when the message is built, so that it serializes to a single
+/// well-formed line.
+#[derive(Clone, Debug, Default)]
+pub struct MessageBuilder {
+    tags: Vec<Tag>,
+    prefix: Option<String>,
+    command: String,
+    params: Vec<String>,
+    trailing: Option<String>,
+}
+
+impl MessageBuilder {
+    /// Adds a message tag with an optional value.
+    pub fn tag<K: Into<String>>(mut self, key: K, value: Option<&str>) -> Self {
+        self.tags.push(Tag(key.into(), value.map(|v| v.to_owned())));
+        self
+    }
+
+    /// Sets the message prefix (or source).
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets the command, e.g. `PRIVMSG` or a three-digit numeric reply.
+    pub fn command<S: Into<String>>(mut self, command: S) -> Self {
+        self.command = command.into();
+        self
+    }
+
+    /// Adds a middle parameter, which may not contain spaces.
+    pub fn param<S: Into<String>>(mut self, param: S) -> Self {
+        self.params.push(param.into());
+        self
+    }
+
+    /// Adds several middle parameters, none of which may contain spaces.
+    pub fn params<I, S>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.params.extend(params.into_iter().map(|p| p.into()));
+        self
+    }
+
+    /// Sets the trailing parameter, which comes after every middle parameter and may contain
+    /// spaces.
+    pub fn trailing<S: Into<String>>(mut self, trailing: S) -> Self {
+        self.trailing = Some(trailing.into());
+        self
+    }
+
+    /// Builds the message, failing if the command is empty or not alphanumeric, or if any
+    /// component would break the line, e.g. a middle parameter containing a space.
+    pub fn build(self) -> Result<Message, MessageParseError> {
+        fn breaks_word(s: &str) -> bool {
+            s.is_empty() || s.contains([' ', '\r', '\n', '\0'])
+        }
+        fn invalid(param: &str) -> MessageParseError {
+            MessageParseError::InvalidParameter {
+                param: param.to_owned(),
+            }
+        }
+
+        if self.command.is_empty() || !self.command.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(MessageParseError::InvalidCommand);
+        }
+        if let Some(Tag(ref key, _)) = self
+            .tags
+            .iter()
+            .find(|Tag(key, _)| breaks_word(key) || key.contains([';', '=']))
+        {
+            return Err(invalid(key));
+        }
+        if let Some(prefix) = self.prefix.as_ref().filter(|p| breaks_word(p)) {
+            return Err(invalid(prefix));
+        }
+        if let Some(param) = self
+            .params
+            .iter()
+            .find(|p| breaks_word(p) || p.starts_with(':'))
+        {
+            return Err(invalid(param));
+        }
+        if let Some(trailing) = self
+            .trailing
+            .as_ref()
+            .filter(|t| t.contains(['\r', '\n', '\0']))
+        {
+            return Err(invalid(trailing));
+        }
+
+        let mut args: Vec<&str> = self.params.iter().map(|p| &p[..]).collect();
+        args.extend(self.trailing.as_deref());
+        let tags = Some(self.tags).filter(|tags| !tags.is_empty());
+        Message::with_tags(tags, self.prefix.as_deref(), &self.command, args)
+    }
+}
+
 impl FromStr for Message {
     type Err = ProtocolError;
 
     fn from_str(s: &str) -> Result<Message, Self::Err> {
+        MessageRef::parse(s)?.to_message()
+    }
+}
+
+/// Converts the text to lower case under the `rfc1459` case mapping.
+fn rfc1459_lowercase(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '[' => '{',
+            ']' => '}',
+            '\\' => '|',
+            '~' => '^',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// The most parameters a message can have: fourteen middle parameters and a trailing one.
+const MAX_PARAMS: usize = 15;
+
+/// A message borrowed from the line it was parsed from, which can be inspected without allocating.
+/// This suits code that only looks at a few fields of most messages, such as a bridge deciding what
+/// to relay. Use [`to_message`](#method.to_message) to get a full [`Message`](struct.Message.html).
+///
+/// # Example
+/// ```
+/// # extern crate irc_proto;
+/// # use irc_proto::MessageRef;
+/// # fn main() {
+/// let message = MessageRef::parse(":ada!a@host PRIVMSG #rust :Hello, world!\r\n").unwrap();
+/// assert_eq!(message.prefix(), Some("ada!a@host"));
+/// assert_eq!(message.command(), "PRIVMSG");
+/// assert_eq!(message.params(), ["#rust", "Hello, world!"]);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    line: &'a str,
+    tags: Option<&'a str>,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    params: [&'a str; MAX_PARAMS],
+    param_count: usize,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Splits a line into the components of a message. The command and its parameters are only
+    /// validated when converting to an owned `Message`.
+    pub fn parse(s: &'a str) -> Result<MessageRef<'a>, ProtocolError> {
         if s.is_empty() {
             return Err(ProtocolError::InvalidMessage {
                 string: s.to_owned(),
@@ -137,28 +488,32 @@ impl FromStr for Message {
 
         let mut state = s;
 
-        let tags = if state.starts_with('@') {
-            let tags = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i + 1..]);
-            tags.map(|ts| {
-                ts.split(';')
-                    .filter(|s| !s.is_empty())
-                    .map(|s: &str| {
-                        let mut iter = s.splitn(2, '=');
-                        let (fst, snd) = (iter.next(), iter.next());
-                        let snd = snd.map(unescape_tag_value);
-                        Tag(fst.unwrap_or("").to_owned(), snd)
-                    })
-                    .collect::<Vec<_>>()
-            })
+        let tags = if let Some(rest) = state.strip_prefix('@') {
+            match rest.find(' ') {
+                Some(i) => {
+                    state = &rest[i + 1..];
+                    Some(&rest[..i])
+                }
+                None => {
+                    state = "";
+                    None
+                }
+            }
         } else {
             None
         };
 
-        let prefix = if state.starts_with(':') {
-            let prefix = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i + 1..]);
-            prefix
+        let prefix = if let Some(rest) = state.strip_prefix(':') {
+            match rest.find(' ') {
+                Some(i) => {
+                    state = &rest[i + 1..];
+                    Some(&rest[..i])
+                }
+                None => {
+                    state = "";
+                    None
+                }
+            }
         } else {
             None
         };
@@ -174,20 +529,22 @@ impl FromStr for Message {
         }
         .len();
 
-        let suffix = if state.contains(" :") {
-            let suffix = state
-                .find(" :")
-                .map(|i| &state[i + 2..state.len() - line_ending_len]);
-            state = state.find(" :").map_or("", |i| &state[..i + 1]);
-            suffix
-        } else {
-            state = &state[..state.len() - line_ending_len];
-            None
+        let suffix = match state.find(" :") {
+            Some(i) => {
+                let suffix = &state[i + 2..state.len() - line_ending_len];
+                state = &state[..i + 1];
+                Some(suffix)
+            }
+            None => {
+                state = &state[..state.len() - line_ending_len];
+                None
+            }
         };
 
-        let command = match state.find(' ').map(|i| &state[..i]) {
-            Some(cmd) => {
-                state = state.find(' ').map_or("", |i| &state[i + 1..]);
+        let command = match state.find(' ') {
+            Some(i) => {
+                let cmd = &state[..i];
+                state = &state[i + 1..];
                 cmd
             }
             // If there's no arguments but the "command" starts with colon, it's not a command.
@@ -205,14 +562,73 @@ impl FromStr for Message {
             }
         };
 
-        let mut args: Vec<_> = state.splitn(14, ' ').filter(|s| !s.is_empty()).collect();
-        if let Some(suffix) = suffix {
-            args.push(suffix);
+        let mut params = [""; MAX_PARAMS];
+        let mut param_count = 0;
+        for param in state
+            .splitn(MAX_PARAMS - 1, ' ')
+            .filter(|s| !s.is_empty())
+            .chain(suffix)
+        {
+            params[param_count] = param;
+            param_count += 1;
         }
 
-        Message::with_tags(tags, prefix, command, args).map_err(|e| ProtocolError::InvalidMessage {
-            string: s.to_owned(),
-            cause: e,
+        Ok(MessageRef {
+            line: s,
+            tags,
+            prefix,
+            command,
+            params,
+            param_count,
+        })
+    }
+
+    /// Gets the line this message was parsed from.
+    pub fn as_str(&self) -> &'a str {
+        self.line
+    }
+
+    /// Gets the tags of the message as pairs of a key and a value, if any. The values are still
+    /// escaped as they were on the wire.
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+        self.tags
+            .unwrap_or("")
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(|tag| match tag.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (tag, None),
+            })
+    }
+
+    /// Gets the prefix (or source) of the message, if it has one.
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.prefix
+    }
+
+    /// Gets the command as it was sent, e.g. `PRIVMSG` or `001`.
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    /// Gets the parameters of the command, including the trailing parameter.
+    pub fn params(&self) -> &[&'a str] {
+        &self.params[..self.param_count]
+    }
+
+    /// Converts this into an owned `Message`, parsing the command. This fails if the command or its
+    /// parameters are invalid.
+    pub fn to_message(&self) -> Result<Message, ProtocolError> {
+        let tags = self.tags.map(|_| {
+            self.tags()
+                .map(|(key, value)| Tag(key.to_owned(), value.map(unescape_tag_value)))
+                .collect()
+        });
+        Message::with_tags(tags, self.prefix, self.command, self.params().to_vec()).map_err(|e| {
+            ProtocolError::InvalidMessage {
+                string: self.line.to_owned(),
+                cause: e,
+            }
         })
     }
 }
@@ -281,6 +697,10 @@ fn escape_tag_value(f: &mut dyn Write, value: &str) -> FmtResult {
 }
 
 fn unescape_tag_value(value: &str) -> String {
+    if !value.contains('\\') {
+        return value.to_owned();
+    }
+
     let mut unescaped = String::with_capacity(value.len());
     let mut iter = value.chars();
     while let Some(c) = iter.next() {
@@ -304,15 +724,17 @@ fn unescape_tag_value(value: &str) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::{Message, Tag};
-    use crate::command::Command::{Raw, PRIVMSG, QUIT};
+    use super::{Message, MessageRef, Tag};
+    use crate::command::Command::{self, Raw, PRIVMSG, QUIT};
+    use crate::mode::{ChannelMode, Mode};
+    use crate::response::Response;
 
     #[test]
     fn new() {
         let message = Message {
             tags: None,
             prefix: None,
-            command: PRIVMSG(format!("test"), format!("Testing!")),
+            command: PRIVMSG("test".to_string(), "Testing!".to_string()),
         };
         assert_eq!(
             Message::new(None, "PRIVMSG", vec!["test", "Testing!"]).unwrap(),
@@ -320,6 +742,48 @@ mod test {
         )
     }
 
+    #[test]
+    fn mentions() {
+        let privmsg = |text: &str| Message::from(PRIVMSG("#test".to_owned(), text.to_owned()));
+        assert!(privmsg("hey bob!").mentions("bob"));
+        assert!(privmsg("BOB: hi").mentions("bob"));
+        assert!(privmsg("hi @bob, bobcat").mentions("bob"));
+        assert!(privmsg("\u{1}ACTION pokes bob\u{1}").mentions("bob"));
+        assert!(privmsg("hi [bob]").mentions("{BOB}"));
+        assert!(!privmsg("I saw a bobcat").mentions("bob"));
+        assert!(!privmsg("bob_ is away").mentions("bob"));
+        assert!(!privmsg("anything").mentions(""));
+        assert!(
+            !Message::from(Command::NOTICE("#test".to_owned(), "bob".to_owned())).mentions("bob")
+        );
+    }
+
+    #[test]
+    fn mentions_with() {
+        let privmsg = |text: &str| Message::from(PRIVMSG("#test".to_owned(), text.to_owned()));
+        let ascii = |text: &str| text.to_ascii_lowercase();
+        assert!(privmsg("BOB: hi").mentions_with("bob", ascii));
+        assert!(privmsg("hi [bob]").mentions_with("[BOB]", ascii));
+        assert!(!privmsg("hi [bob]").mentions_with("{bob}", ascii));
+    }
+
+    #[test]
+    fn source_user_and_host() {
+        let message = Message::new(Some("nick!user@host"), "PING", vec!["data"]).unwrap();
+        assert_eq!(message.source_nickname(), Some("nick"));
+        assert_eq!(message.source_username(), Some("user"));
+        assert_eq!(message.source_hostname(), Some("host"));
+
+        let message = Message::new(Some("nick@host"), "PING", vec!["data"]).unwrap();
+        assert_eq!(message.source_username(), None);
+        assert_eq!(message.source_hostname(), Some("host"));
+
+        let message = Message::new(Some("irc.test.net"), "PING", vec!["data"]).unwrap();
+        assert_eq!(message.source_nickname(), None);
+        assert_eq!(message.source_username(), None);
+        assert_eq!(message.source_hostname(), None);
+    }
+
     #[test]
     fn source_nickname() {
         assert_eq!(
@@ -384,13 +848,13 @@ mod test {
         let message = Message {
             tags: None,
             prefix: None,
-            command: PRIVMSG(format!("test"), format!("Testing!")),
+            command: PRIVMSG("test".to_string(), "Testing!".to_string()),
         };
         assert_eq!(&message.to_string()[..], "PRIVMSG test Testing!\r\n");
         let message = Message {
             tags: None,
             prefix: Some("test!test@test".into()),
-            command: PRIVMSG(format!("test"), format!("Still testing!")),
+            command: PRIVMSG("test".to_string(), "Still testing!".to_string()),
         };
         assert_eq!(
             &message.to_string()[..],
@@ -403,7 +867,7 @@ mod test {
         let message = Message {
             tags: None,
             prefix: None,
-            command: PRIVMSG(format!("test"), format!("Testing!")),
+            command: PRIVMSG("test".to_string(), "Testing!".to_string()),
         };
         assert_eq!(
             "PRIVMSG test :Testing!\r\n".parse::<Message>().unwrap(),
@@ -412,7 +876,7 @@ mod test {
         let message = Message {
             tags: None,
             prefix: Some("test!test@test".into()),
-            command: PRIVMSG(format!("test"), format!("Still testing!")),
+            command: PRIVMSG("test".to_string(), "Still testing!".to_string()),
         };
         assert_eq!(
             ":test!test@test PRIVMSG test :Still testing!\r\n"
@@ -422,12 +886,12 @@ mod test {
         );
         let message = Message {
             tags: Some(vec![
-                Tag(format!("aaa"), Some(format!("bbb"))),
-                Tag(format!("ccc"), None),
-                Tag(format!("example.com/ddd"), Some(format!("eee"))),
+                Tag("aaa".to_string(), Some("bbb".to_string())),
+                Tag("ccc".to_string(), None),
+                Tag("example.com/ddd".to_string(), Some("eee".to_string())),
             ]),
             prefix: Some("test!test@test".into()),
-            command: PRIVMSG(format!("test"), format!("Testing with tags!")),
+            command: PRIVMSG("test".to_string(), "Testing with tags!".to_string()),
         };
         assert_eq!(
             "@aaa=bbb;ccc;example.com/ddd=eee :test!test@test PRIVMSG test :Testing with \
@@ -443,7 +907,7 @@ mod test {
         let message = Message {
             tags: None,
             prefix: None,
-            command: PRIVMSG(format!("test"), format!("Testing!")),
+            command: PRIVMSG("test".to_string(), "Testing!".to_string()),
         };
         assert_eq!(
             "PRIVMSG test :Testing!\r".parse::<Message>().unwrap(),
@@ -467,19 +931,97 @@ mod test {
         assert_eq!(message.parse::<Message>().unwrap().to_string(), message);
     }
 
+    #[test]
+    fn trailing_round_trip() {
+        for line in &[
+            ":a!b@c PRIVMSG #test :\r\n",
+            ":a!b@c PRIVMSG #test :Hello there\r\n",
+            ":a!b@c PRIVMSG #test ::)\r\n",
+            ":a!b@c TOPIC #test :\r\n",
+            ":a!b@c FOO bar :\r\n",
+            ":a!b@c FOO bar ::baz\r\n",
+            ":irc.test.net 301 test alice :\r\n",
+            "AWAY :\r\n",
+        ] {
+            assert_eq!(&line.parse::<Message>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn typed_commands_round_trip() {
+        let cases = vec![
+            (
+                ":a!b@c NOTICE #test :Hello there\r\n",
+                Command::NOTICE("#test".to_owned(), "Hello there".to_owned()),
+            ),
+            (
+                ":a!b@c JOIN #test\r\n",
+                Command::JOIN("#test".to_owned(), None, None),
+            ),
+            (
+                ":a!b@c PART #test :Goodbye\r\n",
+                Command::PART("#test".to_owned(), Some("Goodbye".to_owned())),
+            ),
+            (
+                ":a!b@c MODE #test +o d\r\n",
+                Command::ChannelMODE(
+                    "#test".to_owned(),
+                    vec![Mode::Plus(ChannelMode::Oper, Some("d".to_owned()))],
+                ),
+            ),
+            (
+                ":a!b@c KICK #test d :Bye\r\n",
+                Command::KICK("#test".to_owned(), "d".to_owned(), Some("Bye".to_owned())),
+            ),
+            (
+                ":a!b@c TOPIC #test :New topic\r\n",
+                Command::TOPIC("#test".to_owned(), Some("New topic".to_owned())),
+            ),
+            (":a!b@c NICK d\r\n", Command::NICK("d".to_owned())),
+            (
+                ":a!b@c QUIT :Leaving now\r\n",
+                QUIT(Some("Leaving now".to_owned())),
+            ),
+            (
+                ":irc.test.net 433 * a :Nickname is already in use\r\n",
+                Command::Response(
+                    Response::ERR_NICKNAMEINUSE,
+                    vec![
+                        "*".to_owned(),
+                        "a".to_owned(),
+                        "Nickname is already in use".to_owned(),
+                    ],
+                ),
+            ),
+            (
+                ":a!b@c FOO bar :baz qux\r\n",
+                Raw(
+                    "FOO".to_owned(),
+                    vec!["bar".to_owned(), "baz qux".to_owned()],
+                ),
+            ),
+        ];
+        for (line, command) in cases {
+            let message = line.parse::<Message>().unwrap();
+            assert_eq!(message.command, command);
+            // Serializing may drop a colon that isn't needed, but must not change the meaning.
+            assert_eq!(message.to_string().parse::<Message>().unwrap(), message);
+        }
+    }
+
     #[test]
     fn to_message() {
         let message = Message {
             tags: None,
             prefix: None,
-            command: PRIVMSG(format!("test"), format!("Testing!")),
+            command: PRIVMSG("test".to_string(), "Testing!".to_string()),
         };
         let msg: Message = "PRIVMSG test :Testing!\r\n".into();
         assert_eq!(msg, message);
         let message = Message {
             tags: None,
             prefix: Some("test!test@test".into()),
-            command: PRIVMSG(format!("test"), format!("Still testing!")),
+            command: PRIVMSG("test".to_string(), "Still testing!".to_string()),
         };
         let msg: Message = ":test!test@test PRIVMSG test :Still testing!\r\n".into();
         assert_eq!(msg, message);
@@ -493,7 +1035,7 @@ mod test {
             tags: None,
             prefix: Some("test!test@test".into()),
             command: Raw(
-                format!("COMMAND"),
+                "COMMAND".to_string(),
                 vec![format!("ARG:test"), format!("Testing!")],
             ),
         };
@@ -565,4 +1107,113 @@ mod test {
         let message = "PRIVMSG #test ::test\r\n";
         assert_eq!(msg, message);
     }
+
+    #[test]
+    fn builder() {
+        let message = Message::builder()
+            .tag("time", Some("2019-01-04T14:33:26.123Z"))
+            .tag("+draft/typing", None)
+            .prefix("test!test@test")
+            .command("PRIVMSG")
+            .param("#test")
+            .trailing("Hello, world!")
+            .build()
+            .unwrap();
+        assert_eq!(
+            message.to_string(),
+            "@time=2019-01-04T14:33:26.123Z;+draft/typing :test!test@test PRIVMSG #test \
+             :Hello, world!\r\n"
+        );
+        assert_eq!(
+            message.command,
+            PRIVMSG("#test".to_owned(), "Hello, world!".to_owned())
+        );
+    }
+
+    #[test]
+    fn builder_params() {
+        let message = Message::builder()
+            .command("MODE")
+            .params(vec!["#test", "+o"])
+            .param("test")
+            .build()
+            .unwrap();
+        assert_eq!(message.to_string(), "MODE #test +o test\r\n");
+    }
+
+    #[test]
+    fn builder_invalid() {
+        assert!(Message::builder().param("#test").build().is_err());
+        assert!(Message::builder()
+            .command("PRIVMSG")
+            .param("#test channel")
+            .trailing("hi")
+            .build()
+            .is_err());
+        assert!(Message::builder()
+            .command("PRIVMSG")
+            .param("#test")
+            .trailing("hi\r\nQUIT")
+            .build()
+            .is_err());
+        assert!(Message::builder()
+            .tag("a=b", None)
+            .command("TAGMSG")
+            .param("#test")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn message_ref() {
+        let msg = MessageRef::parse(
+            "@time=2023-05-01T12:00:00.000Z;label=a\\sb;+typing :ada!a@host PRIVMSG #rust :Hi, all!\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            msg.tags().collect::<Vec<_>>(),
+            vec![
+                ("time", Some("2023-05-01T12:00:00.000Z")),
+                ("label", Some("a\\sb")),
+                ("+typing", None),
+            ]
+        );
+        assert_eq!(msg.prefix(), Some("ada!a@host"));
+        assert_eq!(msg.command(), "PRIVMSG");
+        assert_eq!(msg.params(), ["#rust", "Hi, all!"]);
+
+        let msg = MessageRef::parse("PING").unwrap();
+        assert_eq!(msg.tags().count(), 0);
+        assert_eq!(msg.prefix(), None);
+        assert_eq!(msg.params(), [""; 0]);
+
+        assert!(MessageRef::parse("").is_err());
+    }
+
+    #[test]
+    fn message_ref_agrees_with_owned() {
+        for line in [
+            ":ada!a@host PRIVMSG #rust :Hello, world!\r\n",
+            "@account=ada;msgid=abc :ada!a@host NOTICE #rust :tagged\r\n",
+            ":irc.test.net 353 test = #rust :@ada +bob carol\r\n",
+            ":irc.test.net 005 test CHANTYPES=# EXCEPTS PREFIX=(ov)@+ :are supported\r\n",
+            ":op!o@host MODE #rust +o ada\r\n",
+            ":ada!a@host QUIT :Leaving\r\n",
+            "PING :irc.test.net\r\n",
+        ] {
+            let borrowed = MessageRef::parse(line).unwrap();
+            let owned: Message = line.parse().unwrap();
+            assert_eq!(borrowed.to_message().unwrap(), owned);
+
+            let serialized = owned.to_string();
+            let reparsed = MessageRef::parse(&serialized).unwrap();
+            assert_eq!(
+                reparsed.tags().collect::<Vec<_>>(),
+                borrowed.tags().collect::<Vec<_>>()
+            );
+            assert_eq!(reparsed.prefix(), borrowed.prefix());
+            assert_eq!(reparsed.command(), borrowed.command());
+            assert_eq!(reparsed.params(), borrowed.params());
+        }
+    }
 }
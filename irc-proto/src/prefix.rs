@@ -26,53 +26,18 @@ impl Prefix {
     /// # }
     /// ```
     pub fn new_from_str(s: &str) -> Prefix {
-        #[derive(Copy, Clone, Eq, PartialEq)]
-        enum Active {
-            Name,
-            User,
-            Host,
-        }
-
-        let mut name = String::new();
-        let mut user = String::new();
-        let mut host = String::new();
-        let mut active = Active::Name;
-        let mut is_server = false;
-
-        for c in s.chars() {
-            if c == '.' && active == Active::Name {
-                // We won't return Nickname("nick", "", "") but if @ or ! are
-                // encountered, then we set this back to false
-                is_server = true;
+        // The nickname ends at the first `!` or `@`. After a `!`, the username runs until the next
+        // `@`, and everything after the `@` belongs to the hostname.
+        match s.find(['!', '@']) {
+            Some(i) if s.as_bytes()[i] == b'!' => {
+                let rest = &s[i + 1..];
+                let (user, host) = rest.split_once('@').unwrap_or((rest, ""));
+                Prefix::Nickname(s[..i].to_owned(), user.to_owned(), host.to_owned())
             }
-
-            match c {
-                '!' if active == Active::Name => {
-                    is_server = false;
-                    active = Active::User;
-                }
-
-                '@' if active != Active::Host => {
-                    is_server = false;
-                    active = Active::Host;
-                }
-
-                _ => {
-                    // Push onto the active buffer
-                    match active {
-                        Active::Name => &mut name,
-                        Active::User => &mut user,
-                        Active::Host => &mut host,
-                    }
-                    .push(c)
-                }
-            }
-        }
-
-        if is_server {
-            Prefix::ServerName(name)
-        } else {
-            Prefix::Nickname(name, user, host)
+            Some(i) => Prefix::Nickname(s[..i].to_owned(), String::new(), s[i + 1..].to_owned()),
+            // Without a `!` or `@`, a name containing a `.` can only be a server.
+            None if s.contains('.') => Prefix::ServerName(s.to_owned()),
+            None => Prefix::Nickname(s.to_owned(), String::new(), String::new()),
         }
     }
 }
@@ -102,7 +67,7 @@ impl fmt::Display for Prefix {
     }
 }
 
-impl<'a> From<&'a str> for Prefix {
+impl From<&str> for Prefix {
     fn from(s: &str) -> Self {
         Prefix::new_from_str(s)
     }
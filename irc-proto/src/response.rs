@@ -16,8 +16,8 @@ macro_rules! make_response {
         }
 
         impl Response {
-            /// Generates a Response from a u16.
-            fn from_u16(val: u16) -> Option<Response> {
+            /// Generates a Response from a u16, if it is a known numeric.
+            pub fn from_u16(val: u16) -> Option<Response> {
                 match val {
                     $($value => Some(Response::$variant),)+
                     _ => None
@@ -48,6 +48,10 @@ make_response! {
     /// RFC2812, but does so consistently in modern IRCd implementations. RPL_BOUNCE has been moved
     /// to `010`.
     RPL_ISUPPORT        =   5,
+    /// `008 <nick> <snomask> :Server notice mask` (Source: ircd-hybrid/charybdis)
+    ///
+    /// Sent to an operator when their server notice mask changes.
+    RPL_SNOMASK         =   8,
     /// `010 Try server <server name>, port <port number>` (Source: Modern)
     RPL_BOUNCE          =  10,
     /// Undefined format. (Source: Modern)
@@ -122,6 +126,9 @@ make_response! {
     RPL_WHOREPLY        = 352,
     /// `315 <name> :End of WHO list` (Source: RFC2812)
     RPL_ENDOFWHO        = 315,
+    /// `354 <token> <fields>...` with the fields selected by a WHOX query, in a fixed order
+    /// (Source: Modern)
+    RPL_WHOSPCRPL       = 354,
     /// `353 ( "=" / "*" / "@" ) <channel> :[ "@" / "+" ] <nick> *( " " [ "@" / "+" ] <nick> )`
     /// (Source: RFC2812)
     RPL_NAMREPLY        = 353,
@@ -238,6 +245,26 @@ make_response! {
     RPL_GLOBALUSERS     = 266,
     /// `276 <client> <nick> :has client certificate fingerprint <fingerprint>` (Source: Modern)
     RPL_WHOISCERTFP     = 276,
+    /// `600 <client> <nick> <user> <host> <time> :logged online` (Source: WATCH)
+    RPL_LOGON           = 600,
+    /// `601 <client> <nick> <user> <host> <time> :logged offline` (Source: WATCH)
+    RPL_LOGOFF          = 601,
+    /// `602 <client> <nick> <user> <host> <time> :stopped watching` (Source: WATCH)
+    RPL_WATCHOFF        = 602,
+    /// `603 <client> :You have <n> and are on <m> WATCH entries` (Source: WATCH)
+    RPL_WATCHSTAT       = 603,
+    /// `604 <client> <nick> <user> <host> <time> :is online` (Source: WATCH)
+    RPL_NOWON           = 604,
+    /// `605 <client> <nick> <user> <host> <time> :is offline` (Source: WATCH)
+    RPL_NOWOFF          = 605,
+    /// `606 <client> :<nicklist>` (Source: WATCH)
+    RPL_WATCHLIST       = 606,
+    /// `607 <client> :End of WATCH list` (Source: WATCH)
+    RPL_ENDOFWATCHLIST  = 607,
+    /// `728 <client> <channel> q <mask> [<setter> <time>]` (Source: Charybdis)
+    RPL_QUIETLIST       = 728,
+    /// `729 <client> <channel> q :End of channel quiet list` (Source: Charybdis)
+    RPL_ENDOFQUIETLIST  = 729,
     /// `730 <nick> :target[,target2]*` (Source: RFC2812)
     RPL_MONONLINE       = 730,
     /// `731 <nick> :target[,target2]*` (Source: RFC2812)
@@ -343,6 +370,8 @@ make_response! {
     ERR_YOUWILLBEBANNED     = 466,
     /// `467 <channel> :Channel key already set` (Source: RFC2812)
     ERR_KEYSET              = 467,
+    /// `470 <channel> <forwarded channel> :Forwarding to another channel` (Source: Modern)
+    ERR_LINKCHANNEL         = 470,
     /// `471 <channel> :Cannot join channel (+l)` (Source: RFC2812)
     ERR_CHANNELISFULL       = 471,
     /// `472 <char> :is unknown mode char to me for <channel>` (Source: RFC2812)
@@ -355,7 +384,8 @@ make_response! {
     ERR_BADCHANNELKEY       = 475,
     /// `476 <channel> :Bad Channel Mask` (Source: RFC2812)
     ERR_BADCHANMASK         = 476,
-    /// `477 <channel> :Channel doesn't support modes` (Source: RFC2812)
+    /// `477 <channel> :Channel doesn't support modes` (Source: RFC2812), which most servers send
+    /// as `477 <channel> :Cannot join channel (+r)` when registration is required to join.
     ERR_NOCHANMODES         = 477,
     /// `478 <channel> <char> :Channel list is full` (Source: RFC2812)
     ERR_BANLISTFULL         = 478,
@@ -375,6 +405,8 @@ make_response! {
     ERR_UMODEUNKNOWNFLAG    = 501,
     /// `502 :Cannot change mode for other users` (Source: RFC2812)
     ERR_USERSDONTMATCH      = 502,
+    /// `512 <client> <nick> :Maximum size for WATCH-list is <limit> entries` (Source: WATCH)
+    ERR_TOOMANYWATCH        = 512,
     /// `723 <client> <priv> :Insufficient oper privileges.` (Source: Modern)
     ///
     /// Sent to an operator to indicate that they don't have the specific privileges to perform the
@@ -415,6 +447,11 @@ impl Response {
     pub fn is_error(&self) -> bool {
         *self as u16 >= 400
     }
+
+    /// Gets the numeric code of this response, e.g. `433` for `ERR_NICKNAMEINUSE`.
+    pub fn as_u16(&self) -> u16 {
+        *self as u16
+    }
 }
 
 impl FromStr for Response {
@@ -435,6 +472,25 @@ impl FromStr for Response {
 mod test {
     use super::Response;
 
+    #[test]
+    fn from_u16() {
+        assert_eq!(Response::from_u16(1), Some(Response::RPL_WELCOME));
+        assert_eq!(Response::from_u16(376), Some(Response::RPL_ENDOFMOTD));
+        assert_eq!(Response::from_u16(433), Some(Response::ERR_NICKNAMEINUSE));
+        assert_eq!(Response::from_u16(999), None);
+    }
+
+    #[test]
+    fn as_u16() {
+        assert_eq!(Response::RPL_NAMREPLY.as_u16(), 353);
+        assert_eq!(Response::ERR_NICKNAMEINUSE.as_u16(), 433);
+        for code in 0..1000 {
+            if let Some(resp) = Response::from_u16(code) {
+                assert_eq!(resp.as_u16(), code);
+            }
+        }
+    }
+
     #[test]
     fn is_error() {
         assert!(!Response::RPL_NAMREPLY.is_error());
@@ -0,0 +1,135 @@
+//! Parsing of IRCv3 standard replies (`FAIL`, `WARN` and `NOTE`).
+use std::fmt;
+
+use crate::command::Command;
+
+/// The severity of a [`StandardReply`](struct.StandardReply.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StandardReplyKind {
+    /// `FAIL`: the command could not be processed.
+    Fail,
+    /// `WARN`: the command was processed, but something noteworthy happened.
+    Warn,
+    /// `NOTE`: purely informational.
+    Note,
+}
+
+impl StandardReplyKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            StandardReplyKind::Fail => "FAIL",
+            StandardReplyKind::Warn => "WARN",
+            StandardReplyKind::Note => "NOTE",
+        }
+    }
+}
+
+/// A [standard reply](https://ircv3.net/specs/extensions/standard-replies) of the form
+/// `<FAIL|WARN|NOTE> <command> <code> [<context>...] :<description>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StandardReply {
+    /// The severity of the reply.
+    pub kind: StandardReplyKind,
+    /// The command the reply relates to, or `*` if it does not relate to a specific command.
+    pub command: String,
+    /// The machine-readable code describing the reply, e.g. `ACCOUNT_EXISTS`.
+    pub code: String,
+    /// Any additional context parameters, e.g. the account name for `REGISTER`.
+    pub context: Vec<String>,
+    /// The human-readable description of the reply.
+    pub description: String,
+}
+
+impl StandardReply {
+    /// Parses a standard reply from the given command. This returns `None` if the command is not
+    /// a `FAIL`, `WARN` or `NOTE`, or does not have at least a command, a code and a description.
+    pub fn from_command(command: &Command) -> Option<StandardReply> {
+        let (name, args) = match *command {
+            Command::Raw(ref name, ref args) => (name, args),
+            _ => return None,
+        };
+        let kind = match &name.to_ascii_uppercase()[..] {
+            "FAIL" => StandardReplyKind::Fail,
+            "WARN" => StandardReplyKind::Warn,
+            "NOTE" => StandardReplyKind::Note,
+            _ => return None,
+        };
+        if args.len() < 3 {
+            return None;
+        }
+
+        Some(StandardReply {
+            kind,
+            command: args[0].clone(),
+            code: args[1].clone(),
+            context: args[2..args.len() - 1].to_vec(),
+            description: args[args.len() - 1].clone(),
+        })
+    }
+}
+
+impl fmt::Display for StandardReply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.kind.as_str(), self.command, self.code)?;
+        for context in &self.context {
+            write!(f, " {}", context)?;
+        }
+        write!(f, ": {}", self.description)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StandardReply, StandardReplyKind};
+    use crate::Message;
+
+    fn parse(line: &str) -> Option<StandardReply> {
+        StandardReply::from_command(&line.parse::<Message>().unwrap().command)
+    }
+
+    #[test]
+    fn fail_with_context() {
+        let reply =
+            parse(":irc.test.net FAIL REGISTER ACCOUNT_EXISTS test :Account already exists\r\n")
+                .unwrap();
+        assert_eq!(
+            reply,
+            StandardReply {
+                kind: StandardReplyKind::Fail,
+                command: "REGISTER".to_owned(),
+                code: "ACCOUNT_EXISTS".to_owned(),
+                context: vec!["test".to_owned()],
+                description: "Account already exists".to_owned(),
+            }
+        );
+        assert_eq!(
+            reply.to_string(),
+            "FAIL REGISTER ACCOUNT_EXISTS test: Account already exists"
+        );
+    }
+
+    #[test]
+    fn fail_join() {
+        let reply =
+            parse(":irc.test.net FAIL JOIN CHANNEL_IS_FULL #x :Channel is full\r\n").unwrap();
+        assert_eq!(reply.kind, StandardReplyKind::Fail);
+        assert_eq!(reply.command, "JOIN");
+        assert_eq!(reply.code, "CHANNEL_IS_FULL");
+        assert_eq!(reply.context, vec!["#x".to_owned()]);
+        assert_eq!(reply.description, "Channel is full");
+    }
+
+    #[test]
+    fn note_without_context() {
+        let reply = parse("NOTE * OPER_MESSAGE :The message\r\n").unwrap();
+        assert_eq!(reply.kind, StandardReplyKind::Note);
+        assert_eq!(reply.command, "*");
+        assert!(reply.context.is_empty());
+    }
+
+    #[test]
+    fn not_a_standard_reply() {
+        assert!(parse("FAIL REGISTER\r\n").is_none());
+        assert!(parse("PRIVMSG #test :FAIL REGISTER CODE :description\r\n").is_none());
+    }
+}
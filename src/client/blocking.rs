@@ -0,0 +1,210 @@
+//! A blocking facade over the async [`Client`](../struct.Client.html), for scripts and other
+//! programs that do not otherwise use an async runtime.
+//!
+//! The [`BlockingClient`](struct.BlockingClient.html) runs the client on a runtime of its own.
+//! Everything that [`Client`](../struct.Client.html) can already do synchronously (e.g. `send`,
+//! `send_privmsg` and the other helpers) is available through `Deref`, while receiving messages,
+//! flushing and quitting block the current thread until they are done.
+//!
+//! Note that the runtime only makes progress while the current thread is blocked in one of these
+//! methods, so messages sent in the meantime are written out when the client next blocks.
+//!
+//! # Example
+//! ```no_run
+//! use irc::client::{blocking::BlockingClient, prelude::*};
+//!
+//! # fn main() -> irc::error::Result<()> {
+//! let mut client = BlockingClient::new("config.toml")?;
+//! client.identify()?;
+//! client.for_each_incoming(|client, message| {
+//!     if let Command::PRIVMSG(ref target, ref text) = message.command {
+//!         if text == "!ping" {
+//!             client.send_privmsg(target, "pong")?;
+//!         }
+//!     }
+//!     Ok(())
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{future::Future, ops::Deref, path::Path};
+
+use futures_util::{
+    future::{self, Either},
+    stream::StreamExt,
+};
+use tokio::{
+    runtime::{Builder, Runtime},
+    task::JoinHandle,
+};
+
+use crate::{
+    client::{data::Config, Client, ClientStream},
+    error,
+    proto::Message,
+};
+
+/// A [`Client`](../struct.Client.html) that blocks instead of returning futures.
+#[derive(Debug)]
+pub struct BlockingClient {
+    client: Client,
+    stream: ClientStream,
+    /// The task writing outgoing messages to the connection, until it fails.
+    outgoing: Option<JoinHandle<error::Result<()>>>,
+    runtime: Runtime,
+}
+
+impl BlockingClient {
+    /// Creates a new client from the configuration at the specified path, connecting
+    /// immediately. This is short-hand for loading the configuration and then calling
+    /// `BlockingClient::from_config`.
+    pub fn new<P: AsRef<Path>>(config: P) -> error::Result<BlockingClient> {
+        BlockingClient::from_config(Config::load(config)?)
+    }
+
+    /// Creates a new client from the specified configuration, blocking until it is connected.
+    pub fn from_config(config: Config) -> error::Result<BlockingClient> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let mut client = runtime.block_on(Client::from_config(config))?;
+        let outgoing = client.outgoing().map(|outgoing| runtime.spawn(outgoing));
+        let stream = client.stream()?;
+
+        Ok(BlockingClient {
+            client,
+            stream,
+            outgoing,
+            runtime,
+        })
+    }
+
+    /// Gets the underlying client.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Blocks until the next message is received, returning `None` once the connection has been
+    /// closed.
+    pub fn next_message(&mut self) -> Option<error::Result<Message>> {
+        let BlockingClient {
+            ref mut stream,
+            ref mut outgoing,
+            ref runtime,
+            ..
+        } = *self;
+        block_on(runtime, outgoing, stream.next()).unwrap_or_else(|e| Some(Err(e)))
+    }
+
+    /// Calls `f` with each message received until the connection is closed, or until either
+    /// receiving a message or `f` fails.
+    pub fn for_each_incoming<F>(&mut self, mut f: F) -> error::Result<()>
+    where
+        F: FnMut(&Client, Message) -> error::Result<()>,
+    {
+        while let Some(message) = self.next_message() {
+            f(&self.client, message?)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks until every message sent so far has been written to the connection.
+    pub fn flush(&mut self) -> error::Result<()> {
+        let flush = self.client.flush();
+        block_on(&self.runtime, &mut self.outgoing, flush)?
+    }
+
+    /// Quits the server with the given message, or the configured `quit_message` if `None` is
+    /// given, blocking until the `QUIT` has been written to the connection.
+    pub fn quit(&mut self, message: Option<&str>) -> error::Result<()> {
+        let quit = self.client.quit(message);
+        block_on(&self.runtime, &mut self.outgoing, quit)?
+    }
+}
+
+impl Deref for BlockingClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+/// Runs `fut` to completion, failing early if the outgoing task fails in the meantime.
+fn block_on<F: Future>(
+    runtime: &Runtime,
+    outgoing: &mut Option<JoinHandle<error::Result<()>>>,
+    fut: F,
+) -> error::Result<F::Output> {
+    runtime.block_on(async {
+        let task = match outgoing.as_mut() {
+            Some(task) => task,
+            None => return Ok(fut.await),
+        };
+        match future::select(Box::pin(fut), task).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right((result, fut)) => {
+                *outgoing = None;
+                match result {
+                    Ok(Ok(())) => Ok(fut.await),
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(error::Error::AsyncChannelClosed),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlockingClient;
+    use crate::client::{data::Config, test::test_config};
+    use crate::proto::Command;
+
+    #[test]
+    fn send_and_receive() {
+        let mut client = BlockingClient::from_config(Config {
+            mock_initial_value: Some(
+                ":irc.test.net 001 test :Welcome\r\n\
+                 :alice!a@host PRIVMSG #test :hello\r\n"
+                    .to_owned(),
+            ),
+            ..test_config()
+        })
+        .unwrap();
+        client.send_privmsg("#test", "hi").unwrap();
+        client.flush().unwrap();
+
+        let mut received = Vec::new();
+        client
+            .for_each_incoming(|_, message| {
+                received.push(message.command);
+                Ok(())
+            })
+            .unwrap();
+        assert!(received.contains(&Command::PRIVMSG("#test".to_owned(), "hello".to_owned())));
+        assert_eq!(client.current_nickname(), "test");
+
+        let sent = client.log_view().sent().unwrap();
+        assert!(sent
+            .iter()
+            .any(|message| message.to_string() == "PRIVMSG #test hi\r\n"));
+    }
+
+    #[test]
+    fn scripted() {
+        let mut client = BlockingClient::from_config(Config {
+            mock_script: vec![(
+                "PRIVMSG alice ping".to_owned(),
+                ":alice!a@host PRIVMSG test pong".to_owned(),
+            )],
+            ..test_config()
+        })
+        .unwrap();
+        client.send_privmsg("alice", "ping").unwrap();
+        let message = client.next_message().unwrap().unwrap();
+        assert_eq!(
+            message.command,
+            Command::PRIVMSG("test".to_owned(), "pong".to_owned())
+        );
+        assert!(client.mock_mismatches().is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+//! Data for choosing which IP version to connect over.
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The IP version used to connect to the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AddressFamily {
+    /// Connects over either IPv4 or IPv6, whichever works first.
+    #[default]
+    Any,
+    /// Only connects over IPv4.
+    V4,
+    /// Only connects over IPv6.
+    V6,
+}
+
+impl AddressFamily {
+    /// Determines whether the address belongs to this family.
+    pub fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::V4 => addr.is_ipv4(),
+            AddressFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AddressFamily::Any => "any",
+            AddressFamily::V4 => "IPv4",
+            AddressFamily::V6 => "IPv6",
+        })
+    }
+}
+
+impl FromStr for AddressFamily {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<AddressFamily, &'static str> {
+        match &s.to_ascii_lowercase()[..] {
+            "any" => Ok(AddressFamily::Any),
+            "v4" | "ipv4" => Ok(AddressFamily::V4),
+            "v6" | "ipv6" => Ok(AddressFamily::V6),
+            _ => Err("Invalid address family."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use super::AddressFamily;
+
+    #[test]
+    fn matches() {
+        let v4: SocketAddr = "127.0.0.1:6667".parse().unwrap();
+        let v6: SocketAddr = "[::1]:6667".parse().unwrap();
+        assert!(AddressFamily::Any.matches(&v4) && AddressFamily::Any.matches(&v6));
+        assert!(AddressFamily::V4.matches(&v4) && !AddressFamily::V4.matches(&v6));
+        assert!(AddressFamily::V6.matches(&v6) && !AddressFamily::V6.matches(&v4));
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("V4".parse(), Ok(AddressFamily::V4));
+        assert_eq!("ipv6".parse(), Ok(AddressFamily::V6));
+        assert_eq!("any".parse(), Ok(AddressFamily::Any));
+        assert!("v5".parse::<AddressFamily>().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Data for tracking the ban and quiet lists of channels.
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+/// An entry in a channel's ban (`+b`) or quiet (`+q`) list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BanEntry {
+    /// The banned mask, e.g. `*!*@example.com`.
+    pub mask: String,
+    /// Who set the ban, if the server disclosed it. This is usually a nickname or a full prefix.
+    pub setter: Option<String>,
+    /// When the ban was set, if the server disclosed it.
+    pub set_at: Option<DateTime<Utc>>,
+}
+
+impl BanEntry {
+    /// Parses the arguments of an `RPL_BANLIST` (`367`) reply, returning the channel along with
+    /// the entry.
+    pub(crate) fn from_banlist(args: &[String]) -> Option<(&str, BanEntry)> {
+        // <client> <channel> <mask> [<setter> <time>]
+        Some((&args.get(1)?[..], BanEntry::from_args(&args[2..])?))
+    }
+
+    /// Parses the arguments of an `RPL_QUIETLIST` (`728`) reply, returning the channel along with
+    /// the entry.
+    pub(crate) fn from_quietlist(args: &[String]) -> Option<(&str, BanEntry)> {
+        // <client> <channel> q <mask> [<setter> <time>]
+        Some((&args.get(1)?[..], BanEntry::from_args(args.get(3..)?)?))
+    }
+
+    fn from_args(args: &[String]) -> Option<BanEntry> {
+        Some(BanEntry {
+            mask: args.first()?.clone(),
+            setter: args.get(1).cloned(),
+            set_at: args
+                .get(2)
+                .and_then(|time| time.parse().ok())
+                .and_then(|time| Utc.timestamp_opt(time, 0).single()),
+        })
+    }
+}
+
+/// The ban or quiet lists of the channels we've requested them for, keyed by channel. Callers fold
+/// the channel with the server's case mapping before passing it in.
+#[derive(Debug, Default)]
+pub(crate) struct BanLists {
+    lists: HashMap<String, Vec<BanEntry>>,
+    /// Lists that are still being received, which replace the known ones once they end.
+    incoming: HashMap<String, Vec<BanEntry>>,
+}
+
+impl BanLists {
+    /// Gets the list for the given channel, if it has been received.
+    pub(crate) fn get(&self, chan: &str) -> Option<&Vec<BanEntry>> {
+        self.lists.get(chan)
+    }
+
+    /// Records an entry received in reply to a request for the list.
+    pub(crate) fn handle_entry(&mut self, chan: &str, entry: BanEntry) {
+        self.incoming
+            .entry(chan.to_owned())
+            .or_default()
+            .push(entry);
+    }
+
+    /// Replaces the list for the given channel with the entries received since the last end.
+    pub(crate) fn handle_end(&mut self, chan: &str) {
+        let entries = self.incoming.remove(chan).unwrap_or_default();
+        self.lists.insert(chan.to_owned(), entries);
+    }
+
+    /// Adds an entry set with `MODE`, if the list for the channel is known.
+    pub(crate) fn add(&mut self, chan: &str, entry: BanEntry) {
+        if let Some(list) = self.lists.get_mut(chan) {
+            list.retain(|e| e.mask != entry.mask);
+            list.push(entry);
+        }
+    }
+
+    /// Removes an entry unset with `MODE`, if the list for the channel is known.
+    pub(crate) fn remove(&mut self, chan: &str, mask: &str) {
+        if let Some(list) = self.lists.get_mut(chan) {
+            list.retain(|e| e.mask != mask);
+        }
+    }
+
+    /// Forgets the list for the given channel, e.g. after we left it.
+    pub(crate) fn remove_channel(&mut self, chan: &str) {
+        self.lists.remove(chan);
+        self.incoming.remove(chan);
+    }
+
+    /// Forgets every list.
+    pub(crate) fn clear(&mut self) {
+        self.lists.clear();
+        self.incoming.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{BanEntry, BanLists};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn from_banlist() {
+        let args = args(&["test", "#test", "*!*@host", "op!op@op", "1546612406"]);
+        let (chan, entry) = BanEntry::from_banlist(&args).unwrap();
+        assert_eq!(chan, "#test");
+        assert_eq!(entry.mask, "*!*@host");
+        assert_eq!(entry.setter.as_deref(), Some("op!op@op"));
+        assert_eq!(entry.set_at, Utc.timestamp_opt(1546612406, 0).single());
+
+        let args = self::args(&["test", "#test", "*!*@host"]);
+        let (_, entry) = BanEntry::from_banlist(&args).unwrap();
+        assert_eq!(entry.setter, None);
+        assert_eq!(entry.set_at, None);
+    }
+
+    #[test]
+    fn from_quietlist() {
+        let args = args(&["test", "#test", "q", "*!*@host", "op", "1546612406"]);
+        let (chan, entry) = BanEntry::from_quietlist(&args).unwrap();
+        assert_eq!(chan, "#test");
+        assert_eq!(entry.mask, "*!*@host");
+        assert_eq!(entry.setter.as_deref(), Some("op"));
+    }
+
+    #[test]
+    fn lists() {
+        let entry = |mask: &str| BanEntry {
+            mask: mask.to_owned(),
+            setter: None,
+            set_at: None,
+        };
+        let mut lists = BanLists::default();
+        lists.add("#test", entry("ignored!*@*"));
+        assert_eq!(lists.get("#test"), None);
+
+        lists.handle_entry("#test", entry("a!*@*"));
+        lists.handle_entry("#test", entry("b!*@*"));
+        assert_eq!(lists.get("#test"), None);
+        lists.handle_end("#test");
+        assert_eq!(lists.get("#test").map(|l| l.len()), Some(2));
+
+        lists.add("#test", entry("c!*@*"));
+        lists.remove("#test", "a!*@*");
+        let masks: Vec<_> = lists
+            .get("#test")
+            .unwrap()
+            .iter()
+            .map(|e| &e.mask[..])
+            .collect();
+        assert_eq!(masks, vec!["b!*@*", "c!*@*"]);
+
+        lists.remove_channel("#test");
+        assert_eq!(lists.get("#test"), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Data for grouping messages sent in IRCv3 batches.
+use std::collections::HashMap;
+
+use crate::{
+    client::tag_value,
+    proto::{BatchSubCommand, Command, Message},
+};
+
+/// The most batches that may be open at once. Batches the server opens beyond this are not
+/// collected, so that a server that never ends its batches can't grow them without limit.
+const MAX_OPEN_BATCHES: usize = 64;
+
+/// A group of messages sent by the server between `BATCH +reference` and `BATCH -reference`, as
+/// described by the IRCv3 [`batch`](https://ircv3.net/specs/extensions/batch) extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    /// The reference tag the server used to identify the batch.
+    pub reference: String,
+    /// The type of the batch, e.g. `NETJOIN`. Other types are uppercased, e.g. `CHATHISTORY`.
+    pub kind: BatchSubCommand,
+    /// Any additional parameters given when the batch was opened.
+    pub params: Vec<String>,
+    /// The messages in the batch, in the order they were received, not including nested batches.
+    pub messages: Vec<Message>,
+    /// The batches nested in this one, in the order they ended.
+    pub batches: Vec<Batch>,
+}
+
+/// A batch that has been opened but not yet ended.
+#[derive(Debug)]
+struct OpenBatch {
+    /// The reference of the batch this one is nested in, if any.
+    parent: Option<String>,
+    batch: Batch,
+}
+
+/// Collects messages into the batches they belong to, keyed by their references.
+#[derive(Debug, Default)]
+pub(crate) struct BatchCollector {
+    open: HashMap<String, OpenBatch>,
+}
+
+impl BatchCollector {
+    /// Records the message in the batch it belongs to, if any. Returns a batch once it ends,
+    /// unless it is nested in another batch, in which case it is added to that batch instead.
+    pub(crate) fn handle(&mut self, msg: &Message) -> Option<Batch> {
+        let parent = tag_value(msg, "batch").filter(|parent| self.open.contains_key(*parent));
+
+        if let Command::BATCH(ref reference, ref kind, ref params) = msg.command {
+            if let Some(reference) = reference.strip_prefix('+') {
+                if self.open.len() >= MAX_OPEN_BATCHES {
+                    log::warn!("not collecting batch {}: too many are open", reference);
+                    return None;
+                }
+                let batch = Batch {
+                    reference: reference.to_owned(),
+                    kind: kind
+                        .clone()
+                        .unwrap_or_else(|| BatchSubCommand::CUSTOM(String::new())),
+                    params: params.clone().unwrap_or_default(),
+                    messages: Vec::new(),
+                    batches: Vec::new(),
+                };
+                let parent = parent.map(|parent| parent.to_owned());
+                self.open
+                    .insert(reference.to_owned(), OpenBatch { parent, batch });
+                return None;
+            } else if let Some(reference) = reference.strip_prefix('-') {
+                let ended = self.open.remove(reference)?;
+                return match ended.parent.and_then(|parent| self.open.get_mut(&parent)) {
+                    Some(parent) => {
+                        parent.batch.batches.push(ended.batch);
+                        None
+                    }
+                    None => Some(ended.batch),
+                };
+            }
+        }
+
+        if let Some(parent) = parent.and_then(|parent| self.open.get_mut(parent)) {
+            parent.batch.messages.push(msg.clone());
+        }
+        None
+    }
+
+    /// Forgets every batch that has not ended yet.
+    pub(crate) fn clear(&mut self) {
+        self.open.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BatchCollector;
+    use crate::proto::{BatchSubCommand, Message};
+
+    fn collect(lines: &[&str]) -> Vec<super::Batch> {
+        let mut collector = BatchCollector::default();
+        lines
+            .iter()
+            .filter_map(|line| collector.handle(&line.parse::<Message>().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn netjoin() {
+        let batches = collect(&[
+            ":irc.test.net BATCH +yXNAbvnRHTRBv netjoin irc.hub other.host",
+            "@batch=yXNAbvnRHTRBv :aji!a@a QUIT :irc.hub other.host",
+            "@batch=yXNAbvnRHTRBv :nenolod!a@a QUIT :irc.hub other.host",
+            ":nick!user@host PRIVMSG #channel :This is not in batch",
+            ":irc.test.net BATCH -yXNAbvnRHTRBv",
+        ]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].reference, "yXNAbvnRHTRBv");
+        assert_eq!(batches[0].kind, BatchSubCommand::NETJOIN);
+        assert_eq!(batches[0].params, vec!["irc.hub", "other.host"]);
+        assert_eq!(batches[0].messages.len(), 2);
+        assert_eq!(batches[0].messages[1].source_nickname(), Some("nenolod"));
+        assert!(batches[0].batches.is_empty());
+    }
+
+    #[test]
+    fn nested() {
+        let batches = collect(&[
+            ":irc.test.net BATCH +outer example.com/foo",
+            "@batch=outer :irc.test.net BATCH +inner example.com/bar",
+            "@batch=inner :nick!user@host PRIVMSG #channel :Inner",
+            "@batch=outer :nick!user@host PRIVMSG #channel :Outer",
+            ":irc.test.net BATCH -inner",
+            "@batch=outer :nick!user@host PRIVMSG #channel :Outer again",
+            ":irc.test.net BATCH -outer",
+        ]);
+        assert_eq!(batches.len(), 1);
+        let outer = &batches[0];
+        assert_eq!(outer.reference, "outer");
+        assert_eq!(outer.messages.len(), 2);
+        assert_eq!(outer.batches.len(), 1);
+        let inner = &outer.batches[0];
+        assert_eq!(inner.reference, "inner");
+        assert_eq!(
+            inner.kind,
+            BatchSubCommand::CUSTOM("EXAMPLE.COM/BAR".to_owned())
+        );
+        assert_eq!(inner.messages.len(), 1);
+    }
+
+    #[test]
+    fn open_batches_bounded() {
+        let mut collector = BatchCollector::default();
+        for i in 0..=super::MAX_OPEN_BATCHES {
+            let line = format!(":irc.test.net BATCH +b{} netjoin", i);
+            collector.handle(&line.parse::<Message>().unwrap());
+        }
+        assert_eq!(collector.open.len(), super::MAX_OPEN_BATCHES);
+        let last = format!(":irc.test.net BATCH -b{}", super::MAX_OPEN_BATCHES);
+        assert!(collector.handle(&last.parse().unwrap()).is_none());
+        assert!(collector
+            .handle(&":irc.test.net BATCH -b0".parse().unwrap())
+            .is_some());
+    }
+
+    #[test]
+    fn unknown_end() {
+        assert!(collect(&[":irc.test.net BATCH -nope"]).is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+//! Data for the channels listed in reply to a `LIST` command.
+
+/// A channel listed by the server in an `RPL_LIST` (`322`) reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelListEntry {
+    /// The name of the channel.
+    pub channel: String,
+    /// The number of users visible in the channel.
+    pub users: u32,
+    /// The channel's topic, which is empty if none is set.
+    pub topic: String,
+}
+
+impl ChannelListEntry {
+    /// Parses the arguments of an `RPL_LIST` reply.
+    pub(crate) fn from_args(args: &[String]) -> Option<ChannelListEntry> {
+        // <client> <channel> <client count> :<topic>
+        Some(ChannelListEntry {
+            channel: args.get(1)?.clone(),
+            users: args.get(2)?.parse().ok()?,
+            topic: args.get(3).cloned().unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChannelListEntry;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn from_args() {
+        assert_eq!(
+            ChannelListEntry::from_args(&args(&["test", "#rust", "42", "Rust talk"])),
+            Some(ChannelListEntry {
+                channel: "#rust".to_owned(),
+                users: 42,
+                topic: "Rust talk".to_owned(),
+            })
+        );
+        assert_eq!(
+            ChannelListEntry::from_args(&args(&["test", "#empty", "1"])).map(|e| e.topic),
+            Some(String::new())
+        );
+    }
+
+    #[test]
+    fn invalid_user_count() {
+        assert_eq!(
+            ChannelListEntry::from_args(&args(&["test", "#rust", "many", ""])),
+            None
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Data for requesting message history with the IRCv3 `CHATHISTORY` command.
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{client::tag_value, proto::Message};
+
+/// A reference to a message in the history of a target, used to anchor `CHATHISTORY` requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageReference {
+    /// The message with the given `msgid` tag.
+    MsgId(String),
+    /// The point in time given in the same format as the `time` tag, e.g.
+    /// `2019-01-04T14:33:26.123Z`.
+    Timestamp(String),
+}
+
+impl MessageReference {
+    /// Gets a reference to the given message, preferring its `msgid` tag over its `time` tag. This
+    /// is useful to page through history, e.g. by requesting the messages before the oldest one
+    /// received so far.
+    pub fn from_message(msg: &Message) -> Option<MessageReference> {
+        tag_value(msg, "msgid")
+            .map(|id| MessageReference::MsgId(id.to_owned()))
+            .or_else(|| tag_value(msg, "time").map(|t| MessageReference::Timestamp(t.to_owned())))
+    }
+}
+
+impl fmt::Display for MessageReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MessageReference::MsgId(ref id) => write!(f, "msgid={}", id),
+            MessageReference::Timestamp(ref time) => write!(f, "timestamp={}", time),
+        }
+    }
+}
+
+/// The messages to request with `Client::chathistory`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HistoryQuery {
+    /// The most recent messages, optionally only those after the given message.
+    Latest(Option<MessageReference>),
+    /// The messages right before the given message.
+    Before(MessageReference),
+    /// The messages right after the given message.
+    After(MessageReference),
+    /// The messages between the two given messages, starting from the first.
+    Between(MessageReference, MessageReference),
+}
+
+impl HistoryQuery {
+    /// Gets the arguments of a `CHATHISTORY` command for this query.
+    pub(crate) fn to_args(&self, target: &str, limit: u32) -> Vec<String> {
+        let (subcommand, mut args) = match *self {
+            HistoryQuery::Latest(ref after) => (
+                "LATEST",
+                vec![after.as_ref().map_or("*".to_owned(), |r| r.to_string())],
+            ),
+            HistoryQuery::Before(ref msg) => ("BEFORE", vec![msg.to_string()]),
+            HistoryQuery::After(ref msg) => ("AFTER", vec![msg.to_string()]),
+            HistoryQuery::Between(ref start, ref end) => {
+                ("BETWEEN", vec![start.to_string(), end.to_string()])
+            }
+        };
+        args.insert(0, target.to_owned());
+        args.insert(0, subcommand.to_owned());
+        args.push(limit.to_string());
+        args
+    }
+}
+
+/// Sorts messages chronologically by their `time` tags. Messages without a valid `time` tag are
+/// kept in the order they were received, before the others.
+pub(crate) fn sort_by_time(messages: &mut [Message]) {
+    messages.sort_by_key(|msg| {
+        tag_value(msg, "time")
+            .and_then(|time| DateTime::<FixedOffset>::parse_from_rfc3339(time).ok())
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sort_by_time, HistoryQuery, MessageReference};
+    use crate::proto::Message;
+
+    #[test]
+    fn to_args() {
+        assert_eq!(
+            HistoryQuery::Latest(None).to_args("#test", 50),
+            vec!["LATEST", "#test", "*", "50"]
+        );
+        assert_eq!(
+            HistoryQuery::Between(
+                MessageReference::MsgId("abc".to_owned()),
+                MessageReference::Timestamp("2019-01-04T14:33:26.123Z".to_owned()),
+            )
+            .to_args("alice", 10),
+            vec![
+                "BETWEEN",
+                "alice",
+                "msgid=abc",
+                "timestamp=2019-01-04T14:33:26.123Z",
+                "10"
+            ]
+        );
+    }
+
+    #[test]
+    fn from_message() {
+        let msg: Message = "@msgid=abc;time=2019-01-04T14:33:26.123Z :a PRIVMSG #test :hi"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            MessageReference::from_message(&msg),
+            Some(MessageReference::MsgId("abc".to_owned()))
+        );
+        let msg: Message = "@time=2019-01-04T14:33:26.123Z :a PRIVMSG #test :hi"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            MessageReference::from_message(&msg),
+            Some(MessageReference::Timestamp(
+                "2019-01-04T14:33:26.123Z".to_owned()
+            ))
+        );
+        let msg: Message = ":a PRIVMSG #test :hi".parse().unwrap();
+        assert_eq!(MessageReference::from_message(&msg), None);
+    }
+
+    #[test]
+    fn sort() {
+        let mut messages: Vec<Message> = [
+            "@time=2019-01-04T14:33:27.000Z :a PRIVMSG #test :2",
+            "@time=2019-01-04T14:33:26.500+00:00 :a PRIVMSG #test :1",
+            ":a PRIVMSG #test :0",
+        ]
+        .iter()
+        .map(|line| line.parse().unwrap())
+        .collect();
+        sort_by_time(&mut messages);
+        let texts: Vec<_> = messages
+            .iter()
+            .map(|msg| String::from(&msg.command))
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["PRIVMSG #test 0", "PRIVMSG #test 1", "PRIVMSG #test 2"]
+        );
+    }
+}
@@ -2,9 +2,11 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fs::File,
     io::prelude::*,
+    net::IpAddr,
     path::{Path, PathBuf},
 };
 
@@ -15,6 +17,8 @@ use serde_yaml;
 #[cfg(feature = "toml_config")]
 use toml;
 
+use crate::client::data::address_family::AddressFamily;
+use crate::client::data::length_policy::LengthPolicy;
 #[cfg(feature = "proxy")]
 use crate::client::data::proxy::ProxyType;
 
@@ -23,6 +27,38 @@ use crate::error::Error::InvalidConfig;
 use crate::error::TomlError;
 use crate::error::{ConfigError, Result};
 
+/// Replaces the placeholders in a configured string, as is done for the real name, the CTCP
+/// VERSION and USERINFO replies, and the quit message. The placeholders are:
+///
+/// - `{version}`, the version of this crate.
+/// - `{os}`, the operating system, e.g. `linux`.
+/// - `{rust}`, the value of the `IRC_RUSTC_VERSION` environment variable when the crate was
+///   built, which an application may set to the version of its compiler. This is best-effort:
+///   without the variable, the placeholder is left as is.
+///
+/// # Example
+/// ```
+/// # extern crate irc;
+/// use irc::client::data::expand_placeholders;
+/// # fn main() {
+/// let version = format!("irc {}", env!("CARGO_PKG_VERSION"));
+/// assert_eq!(expand_placeholders("irc {version}"), version);
+/// assert_eq!(expand_placeholders("no placeholders"), "no placeholders");
+/// # }
+/// ```
+pub fn expand_placeholders(text: &str) -> Cow<'_, str> {
+    if !text.contains('{') {
+        return Cow::Borrowed(text);
+    }
+    let text = text
+        .replace("{version}", env!("CARGO_PKG_VERSION"))
+        .replace("{os}", std::env::consts::OS);
+    Cow::Owned(match option_env!("IRC_RUSTC_VERSION") {
+        Some(rust) => text.replace("{rust}", rust),
+        None => text,
+    })
+}
+
 /// Configuration for IRC clients.
 ///
 /// # Building a configuration programmatically
@@ -75,31 +111,100 @@ pub struct Config {
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
     #[cfg_attr(feature = "serde", serde(default))]
     pub owners: Vec<String>,
+    /// Masks (e.g. `*!*@spammer.host`) of users whose `PRIVMSG`s and `NOTICE`s are ignored.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ignores: Vec<String>,
+    /// Capabilities the server must offer, e.g. `sasl`, which `Client::identify` requests before
+    /// ending capability negotiation. With `sasl`, negotiation only ends once we've authenticated
+    /// (see `Client::identify`). If one of these is missing from the server's `CAP LS` reply,
+    /// is refused with `CAP NAK`, or the server registers us without listing its capabilities at
+    /// all, the `ClientStream` fails with `Error::RequiredCapabilityUnavailable` rather than
+    /// carrying on without it.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub required_capabilities: Vec<String>,
+    /// Keywords, besides our nickname, that count as mentioning us (see `Client::mentions_me`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub highlights: Vec<String>,
     /// The client's nickname.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub nickname: Option<String>,
     /// The client's NICKSERV password.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub nick_password: Option<String>,
-    /// Alternative nicknames for the client, if the default is taken.
+    /// Alternative nicknames for the client, if the default is taken. Once these are exhausted,
+    /// the client falls back to the default nickname with an incrementing numeric suffix.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
     #[cfg_attr(feature = "serde", serde(default))]
     pub alt_nicks: Vec<String>,
     /// The client's username.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub username: Option<String>,
-    /// The client's real name.
+    /// The client's real name, which may contain the placeholders of `expand_placeholders`.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub realname: Option<String>,
+    /// The mode parameter of the `USER` command sent on registration. Under RFC 2812, this is a
+    /// bitmask in which `4` requests `+w` (wallops) and `8` requests `+i` (invisible). Setting it
+    /// to `*` sends the `* *` placeholders some RFC 1459 servers expect instead.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub registration_mode: Option<String>,
+    /// What to do with a nickname or username longer than the server's `NICKLEN` or `USERLEN`,
+    /// once the server has advertised them after registration.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub length_policy: Option<LengthPolicy>,
     /// The server to connect to.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub server: Option<String>,
     /// The port to connect on.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub port: Option<u16>,
-    /// The password to connect to the server.
+    /// The password to connect to the server, sent with `PASS` before registering. This is not
+    /// the password of an account, which goes in `nick_password` instead.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub server_password: Option<String>,
+    /// A deprecated name for `server_password`, which is used when that is not set.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub password: Option<String>,
+    /// The password the server expects from this gateway in `WEBIRC`, which is sent as soon as the
+    /// connection is established so that the server attributes it to `webirc_ip`. `WEBIRC` is only
+    /// sent when `webirc_password`, `webirc_gateway` and `webirc_ip` are all set.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub webirc_password: Option<String>,
+    /// The name of this gateway sent in `WEBIRC`, e.g. `cgiirc`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub webirc_gateway: Option<String>,
+    /// The hostname of the real client sent in `WEBIRC`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub webirc_hostname: Option<String>,
+    /// The IP address of the real client sent in `WEBIRC`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub webirc_ip: Option<String>,
+    /// The local IP address to connect from, for hosts with more than one.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub bind_address: Option<String>,
+    /// The path of a UNIX domain socket to connect to instead of the server's address, e.g. for a
+    /// local bouncer. TLS and proxy settings do not apply to such a connection.
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub unix_socket: Option<String>,
+    /// The IP version to connect over.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub address_family: Option<AddressFamily>,
+    /// The delay in milliseconds before trying the next of the server's addresses while earlier
+    /// attempts are still connecting.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub connection_attempt_delay: Option<u32>,
+    /// The amount of idle time in seconds before TCP keepalive probes are sent.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tcp_keepalive: Option<u32>,
+    /// The time in seconds between TCP keepalive probes.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tcp_keepalive_interval: Option<u32>,
+    /// The number of unanswered TCP keepalive probes before the connection is dropped.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tcp_keepalive_retries: Option<u32>,
     /// The proxy type to connect to.
     #[cfg(feature = "proxy")]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
@@ -125,6 +230,11 @@ pub struct Config {
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub use_tls: Option<bool>,
+    /// The hostname to verify the server's TLS certificate against and send in SNI, if it differs
+    /// from the server connected to, e.g. when connecting by IP address.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tls_sni: Option<String>,
     /// The path to the TLS certificate for this server in DER format.
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
@@ -133,10 +243,28 @@ pub struct Config {
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub client_cert_path: Option<String>,
-    /// The password for the certificate to use in CertFP authentication.
+    /// The path to the private key for the client certificate in PEM format, in which case the
+    /// client certificate is read as a PEM certificate chain.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub client_key_path: Option<String>,
+    /// The password for the certificate to use in CertFP authentication, which also decrypts the
+    /// client key when it is an encrypted PKCS#8 key.
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub client_cert_pass: Option<String>,
+    /// The protocols to offer with ALPN when connecting over TLS, e.g. `irc`, for servers behind a
+    /// load balancer that routes connections by protocol. No protocols are offered when empty.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub alpn_protocols: Vec<String>,
+    /// The SHA-256 fingerprint of the server's certificate in hex, optionally with colons between
+    /// bytes. When set, only a certificate with this fingerprint is accepted, whether or not it is
+    /// signed by a trusted authority, which is the safe way to connect to a self-signed server.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub pinned_cert_sha256: Option<String>,
     /// On `true`, all certificate validations are skipped. Defaults to `false`.
     ///
     /// # Warning
@@ -148,23 +276,58 @@ pub struct Config {
     /// This is typically UTF-8, but could be something else.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub encoding: Option<String>,
+    /// The encoding used to decode messages received from the server, if it differs from
+    /// `encoding`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub recv_encoding: Option<String>,
+    /// The encoding used to encode messages sent to the server, if it differs from `encoding`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub send_encoding: Option<String>,
+    /// A list of encodings to try, in order, when a received message is not valid in the receive
+    /// encoding. When empty, invalid sequences are simply replaced.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fallback_encodings: Vec<String>,
+    /// Whether received messages should keep the exact bytes they were parsed from, which are then
+    /// available through `ClientStream::raw`. This is useful for bridges and loggers that must not
+    /// lose anything to decoding.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keep_raw_bytes: bool,
     /// A list of channels to join on connection.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
     #[cfg_attr(feature = "serde", serde(default))]
     pub channels: Vec<String>,
+    /// The maximum number of channels to join with a single `JOIN` command on connection.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub channels_per_join: Option<u32>,
+    /// The delay in milliseconds between the `JOIN` commands sent on connection.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub join_delay: Option<u32>,
+    /// The time in seconds to wait for the server to confirm a `JOIN` sent with `Client::join`.
+    /// Zero waits indefinitely.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub join_timeout: Option<u32>,
     /// User modes to set on connect. Example: "+RB -x"
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "serde", serde(alias = "user_modes"))]
     pub umodes: Option<String>,
-    /// The text that'll be sent in response to CTCP USERINFO requests.
+    /// The text that'll be sent in response to CTCP USERINFO requests, which may contain the
+    /// placeholders of `expand_placeholders`.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub user_info: Option<String>,
-    /// The text that'll be sent in response to CTCP VERSION requests.
+    /// The text that'll be sent in response to CTCP VERSION requests, which may contain the
+    /// placeholders of `expand_placeholders`.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub version: Option<String>,
     /// The text that'll be sent in response to CTCP SOURCE requests.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub source: Option<String>,
-    /// The amount of inactivity in seconds before the client will ping the server.
+    /// The default message sent when quitting with `Client::quit`, which may contain the
+    /// placeholders of `expand_placeholders`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub quit_message: Option<String>,
+    /// The interval in seconds at which the client pings the server once registered.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub ping_time: Option<u32>,
     /// The amount of time in seconds for a client to reconnect due to no ping response.
@@ -181,6 +344,55 @@ pub struct Config {
     /// Messages are automatically delayed as appropriate.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub max_messages_in_burst: Option<u32>,
+    /// Whether `PRIVMSG` and `NOTICE` messages longer than the 512-byte line limit should be split
+    /// into several messages. Otherwise, sending such a message fails with
+    /// `Error::MessageTooLong`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub split_long_messages: bool,
+    /// The maximum length in bytes of a line received from the server. Longer lines are discarded
+    /// and reported as `Error::LineTooLong` so that a broken or hostile server cannot make the
+    /// client buffer without bound.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub max_line_length: Option<u32>,
+    /// The number of recently sent and received messages to keep for inspection through
+    /// `Client::history`. No history is kept when this is zero, which is the default.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub history_length: Option<u32>,
+    /// The number of recently received messages to remember by their `msgid` tag (or, failing
+    /// that, by the source, target, text and `time` tag of a `PRIVMSG` or `NOTICE`), so that ones
+    /// received again, e.g. when a bouncer replays them after reconnecting, are handled but not
+    /// yielded again. Messages in batches, such as requested history, are never suppressed.
+    /// Messages are not deduplicated when this is zero, which is the default.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dedup_window: Option<u32>,
+    /// Whether the client should join a channel from `channels` again after being kicked from it.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_rejoin: bool,
+    /// The delay in seconds before rejoining a channel after being kicked from it, which helps
+    /// avoid kick loops. Channels are rejoined immediately by default.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub auto_rejoin_delay: Option<u32>,
+    /// The maximum number of times to rejoin each channel after being kicked from it over the
+    /// course of a connection, so as not to fight a determined operator.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub max_rejoin_attempts: Option<u32>,
+    /// The line sent to identify with NickServ, in which `{nick}` and `{password}` are replaced
+    /// by `nickname` and `nick_password`, e.g. `PRIVMSG NickServ :IDENTIFY {nick} {password}`.
+    /// When set, it is sent as soon as the server welcomes us rather than after the MOTD.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub nickserv_identify: Option<String>,
+    /// Whether to wait for the server to confirm our identification with `RPL_LOGGEDIN` or
+    /// `RPL_HOSTHIDDEN` before joining channels, so that our account and any cloak are applied
+    /// first. This has no effect if `nick_password` is not set.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub wait_for_identify: bool,
+    /// The time in seconds to wait for identification to be confirmed when `wait_for_identify` is
+    /// set, after which channels are joined anyway. Zero waits indefinitely.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub identify_timeout: Option<u32>,
     /// Whether the client should use NickServ GHOST to reclaim its primary nickname if it is in
     /// use. This has no effect if `nick_password` is not set.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
@@ -202,6 +414,17 @@ pub struct Config {
     /// set this, but it is used in unit testing for the `irc` crate.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub mock_initial_value: Option<String>,
+    /// Pairs of a line the fake connection expects the client to send next and the lines it sends
+    /// back in response. You probably will never need to set this, but it is used in unit testing
+    /// for the `irc` crate.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mock_script: Vec<(String, String)>,
+    /// Whether or not the fake connection stays open for lines injected by the test. You probably
+    /// will never need to enable this, but it is used in unit testing for the `irc` crate.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mock_keep_open: bool,
 
     /// A mapping of channel names to keys for join-on-connect.
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "HashMap::is_empty"))]
@@ -220,12 +443,90 @@ pub struct Config {
     pub path: Option<PathBuf>,
 }
 
+/// Reads configuration values from prefixed environment variables for `Config::from_env`.
+struct EnvVars<'a> {
+    prefix: &'a str,
+}
+
+impl<'a> EnvVars<'a> {
+    fn name(&self, field: &str) -> String {
+        format!("{}_{}", self.prefix, field)
+    }
+
+    fn string(&self, field: &str) -> Option<String> {
+        std::env::var(self.name(field))
+            .ok()
+            .filter(|value| !value.is_empty())
+    }
+
+    fn list(&self, field: &str) -> Vec<String> {
+        self.string(field)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn parse<T: std::str::FromStr>(&self, field: &str) -> Result<Option<T>> {
+        match self.string(field) {
+            Some(value) => value.trim().parse().map(Some).map_err(|_| InvalidConfig {
+                path: "<environment>".to_owned(),
+                cause: ConfigError::InvalidEnvVar {
+                    name: self.name(field),
+                    value,
+                },
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parses a SHA-256 fingerprint written in hex, optionally with colons between bytes.
+#[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+fn parse_fingerprint(fingerprint: &str) -> Option<Vec<u8>> {
+    let hex: String = fingerprint.chars().filter(|&c| c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 #[cfg(feature = "serde")]
 fn is_false(v: &bool) -> bool {
     !v
 }
 
 impl Config {
+    /// Creates a [`ConfigBuilder`](struct.ConfigBuilder.html) for building a configuration
+    /// programmatically with fluent setters.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc;
+    /// use irc::client::prelude::Config;
+    ///
+    /// # fn main() -> irc::error::Result<()> {
+    /// let config = Config::builder()
+    ///     .nickname("test")
+    ///     .server("irc.example.com")
+    ///     .channels(vec!["#rust", "#haskell"])
+    ///     .build()?;
+    /// assert_eq!(config.nickname()?, "test");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
     fn with_path<P: AsRef<Path>>(mut self, path: P) -> Config {
         self.path = Some(path.as_ref().to_owned());
         self
@@ -241,7 +542,7 @@ impl Config {
 
     /// Loads a configuration from the desired path. This will use the file extension to detect
     /// which format to parse the file as (json, toml, or yaml). Using each format requires having
-    /// its respective crate feature enabled. Only json is available by default.
+    /// its respective crate feature enabled. Only toml is available by default.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
         let mut file = File::open(&path)?;
         let mut data = String::new();
@@ -266,6 +567,229 @@ impl Config {
         res.map(|config| config.with_path(path))
     }
 
+    /// Loads a configuration from environment variables whose names start with the given prefix,
+    /// followed by an underscore and the upper-case name of the field, e.g. `IRC_NICKNAME` and
+    /// `IRC_SERVER` for the prefix `IRC`. List fields (`OWNERS`, `ALT_NICKS` and `CHANNELS`) are
+    /// comma-separated, and unset or empty variables leave the corresponding field unset.
+    ///
+    /// `channel_keys` and `options` are not read from the environment. Both a nickname and a
+    /// server are required, and it is an error for a numeric or boolean variable to be malformed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # extern crate irc;
+    /// # use irc::client::prelude::Config;
+    /// # fn main() -> irc::error::Result<()> {
+    /// // With IRC_NICKNAME=bot IRC_SERVER=irc.example.com IRC_CHANNELS=#rust,#irc-crate
+    /// let config = Config::from_env("IRC")?;
+    /// assert_eq!(config.channels, vec!["#rust", "#irc-crate"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<Config> {
+        let env = EnvVars { prefix };
+        let config = Config {
+            owners: env.list("OWNERS"),
+            ignores: env.list("IGNORES"),
+            required_capabilities: env.list("REQUIRED_CAPABILITIES"),
+            highlights: env.list("HIGHLIGHTS"),
+            nickname: env.string("NICKNAME"),
+            nick_password: env.string("NICK_PASSWORD"),
+            alt_nicks: env.list("ALT_NICKS"),
+            username: env.string("USERNAME"),
+            realname: env.string("REALNAME"),
+            registration_mode: env.string("REGISTRATION_MODE"),
+            length_policy: env.parse("LENGTH_POLICY")?,
+            server: env.string("SERVER"),
+            port: env.parse("PORT")?,
+            server_password: env.string("SERVER_PASSWORD"),
+            password: env.string("PASSWORD"),
+            webirc_password: env.string("WEBIRC_PASSWORD"),
+            webirc_gateway: env.string("WEBIRC_GATEWAY"),
+            webirc_hostname: env.string("WEBIRC_HOSTNAME"),
+            webirc_ip: env.string("WEBIRC_IP"),
+            bind_address: env.string("BIND_ADDRESS"),
+            #[cfg(unix)]
+            unix_socket: env.string("UNIX_SOCKET"),
+            address_family: env.parse("ADDRESS_FAMILY")?,
+            connection_attempt_delay: env.parse("CONNECTION_ATTEMPT_DELAY")?,
+            tcp_keepalive: env.parse("TCP_KEEPALIVE")?,
+            tcp_keepalive_interval: env.parse("TCP_KEEPALIVE_INTERVAL")?,
+            tcp_keepalive_retries: env.parse("TCP_KEEPALIVE_RETRIES")?,
+            #[cfg(feature = "proxy")]
+            proxy_server: env.string("PROXY_SERVER"),
+            #[cfg(feature = "proxy")]
+            proxy_port: env.parse("PROXY_PORT")?,
+            #[cfg(feature = "proxy")]
+            proxy_username: env.string("PROXY_USERNAME"),
+            #[cfg(feature = "proxy")]
+            proxy_password: env.string("PROXY_PASSWORD"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            use_tls: env.parse("USE_TLS")?,
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            tls_sni: env.string("TLS_SNI"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            cert_path: env.string("CERT_PATH"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            client_cert_path: env.string("CLIENT_CERT_PATH"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            client_key_path: env.string("CLIENT_KEY_PATH"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            client_cert_pass: env.string("CLIENT_CERT_PASS"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            alpn_protocols: env.list("ALPN_PROTOCOLS"),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            pinned_cert_sha256: env.string("PINNED_CERT_SHA256"),
+            dangerously_accept_invalid_certs: env.parse("DANGEROUSLY_ACCEPT_INVALID_CERTS")?,
+            encoding: env.string("ENCODING"),
+            recv_encoding: env.string("RECV_ENCODING"),
+            send_encoding: env.string("SEND_ENCODING"),
+            fallback_encodings: env.list("FALLBACK_ENCODINGS"),
+            keep_raw_bytes: env.parse("KEEP_RAW_BYTES")?.unwrap_or(false),
+            channels: env.list("CHANNELS"),
+            channels_per_join: env.parse("CHANNELS_PER_JOIN")?,
+            join_delay: env.parse("JOIN_DELAY")?,
+            join_timeout: env.parse("JOIN_TIMEOUT")?,
+            umodes: env.string("UMODES"),
+            user_info: env.string("USER_INFO"),
+            version: env.string("VERSION"),
+            source: env.string("SOURCE"),
+            quit_message: env.string("QUIT_MESSAGE"),
+            ping_time: env.parse("PING_TIME")?,
+            ping_timeout: env.parse("PING_TIMEOUT")?,
+            burst_window_length: env.parse("BURST_WINDOW_LENGTH")?,
+            max_messages_in_burst: env.parse("MAX_MESSAGES_IN_BURST")?,
+            split_long_messages: env.parse("SPLIT_LONG_MESSAGES")?.unwrap_or(false),
+            max_line_length: env.parse("MAX_LINE_LENGTH")?,
+            history_length: env.parse("HISTORY_LENGTH")?,
+            dedup_window: env.parse("DEDUP_WINDOW")?,
+            auto_rejoin: env.parse("AUTO_REJOIN")?.unwrap_or(false),
+            auto_rejoin_delay: env.parse("AUTO_REJOIN_DELAY")?,
+            max_rejoin_attempts: env.parse("MAX_REJOIN_ATTEMPTS")?,
+            nickserv_identify: env.string("NICKSERV_IDENTIFY"),
+            wait_for_identify: env.parse("WAIT_FOR_IDENTIFY")?.unwrap_or(false),
+            identify_timeout: env.parse("IDENTIFY_TIMEOUT")?,
+            should_ghost: env.parse("SHOULD_GHOST")?.unwrap_or(false),
+            ghost_sequence: env
+                .string("GHOST_SEQUENCE")
+                .map(|_| env.list("GHOST_SEQUENCE")),
+            ..Config::default()
+        };
+
+        config.nickname()?;
+        config.server()?;
+        Ok(config)
+    }
+
+    /// Checks that the configuration makes sense before connecting with it. This requires a
+    /// non-empty nickname (without spaces) and server, rejects port 0, and rejects TLS or proxy
+    /// options that would be ignored because TLS or the proxy is disabled.
+    pub fn validate(&self) -> std::result::Result<(), ConfigError> {
+        match self.nickname.as_deref() {
+            None => return Err(ConfigError::NicknameNotSpecified),
+            Some(nick) if nick.is_empty() || nick.contains(' ') => {
+                return Err(ConfigError::InvalidNickname {
+                    nickname: nick.to_owned(),
+                })
+            }
+            Some(_) => (),
+        }
+        // The server's address isn't needed when connecting over a UNIX socket.
+        #[cfg(unix)]
+        let has_unix_socket = self.unix_socket.is_some();
+        #[cfg(not(unix))]
+        let has_unix_socket = false;
+        if self.server.as_deref().map_or(true, str::is_empty) && !has_unix_socket {
+            return Err(ConfigError::ServerNotSpecified);
+        }
+        if self.port == Some(0) {
+            return Err(ConfigError::InvalidPort { port: 0 });
+        }
+        if let Some(ref address) = self.bind_address {
+            match address.parse::<IpAddr>() {
+                Err(_) => {
+                    return Err(ConfigError::InvalidBindAddress {
+                        address: address.clone(),
+                    })
+                }
+                Ok(IpAddr::V4(_)) if self.address_family() == AddressFamily::V6 => {
+                    return Err(ConfigError::ConflictingOptions {
+                        option: "bind_address",
+                        conflict: "address_family = \"V6\"",
+                    })
+                }
+                Ok(IpAddr::V6(_)) if self.address_family() == AddressFamily::V4 => {
+                    return Err(ConfigError::ConflictingOptions {
+                        option: "bind_address",
+                        conflict: "address_family = \"V4\"",
+                    })
+                }
+                Ok(_) => (),
+            }
+        }
+
+        #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+        {
+            if !self.use_tls() {
+                let tls_options = [
+                    ("tls_sni", self.tls_sni.is_some()),
+                    ("cert_path", self.cert_path.is_some()),
+                    ("client_cert_path", self.client_cert_path.is_some()),
+                    ("client_key_path", self.client_key_path.is_some()),
+                    ("client_cert_pass", self.client_cert_pass.is_some()),
+                    ("alpn_protocols", !self.alpn_protocols.is_empty()),
+                    ("pinned_cert_sha256", self.pinned_cert_sha256.is_some()),
+                    (
+                        "dangerously_accept_invalid_certs",
+                        self.dangerously_accept_invalid_certs(),
+                    ),
+                ];
+                if let Some(&(option, _)) = tls_options.iter().find(|&&(_, set)| set) {
+                    return Err(ConfigError::ConflictingOptions {
+                        option,
+                        conflict: "use_tls = false",
+                    });
+                }
+            }
+            if let Some(ref fingerprint) = self.pinned_cert_sha256 {
+                if parse_fingerprint(fingerprint).is_none() {
+                    return Err(ConfigError::InvalidFingerprint {
+                        fingerprint: fingerprint.clone(),
+                    });
+                }
+                if self.dangerously_accept_invalid_certs() {
+                    return Err(ConfigError::ConflictingOptions {
+                        option: "pinned_cert_sha256",
+                        conflict: "dangerously_accept_invalid_certs = true",
+                    });
+                }
+            }
+        }
+
+        #[cfg(feature = "proxy")]
+        {
+            if self.proxy_port == Some(0) {
+                return Err(ConfigError::InvalidPort { port: 0 });
+            }
+            if self.proxy_type() == ProxyType::None {
+                let proxy_options = [
+                    ("proxy_server", self.proxy_server.is_some()),
+                    ("proxy_port", self.proxy_port.is_some()),
+                    ("proxy_username", self.proxy_username.is_some()),
+                    ("proxy_password", self.proxy_password.is_some()),
+                ];
+                if let Some(&(option, _)) = proxy_options.iter().find(|&&(_, set)| set) {
+                    return Err(ConfigError::ConflictingOptions {
+                        option,
+                        conflict: "proxy_type = \"None\"",
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[cfg(feature = "json_config")]
     fn load_json<P: AsRef<Path>>(path: P, data: &str) -> Result<Config> {
         serde_json::from_str(data).map_err(|e| InvalidConfig {
@@ -316,7 +840,7 @@ impl Config {
 
     /// Saves a configuration to the desired path. This will use the file extension to detect
     /// which format to parse the file as (json, toml, or yaml). Using each format requires having
-    /// its respective crate feature enabled. Only json is available by default.
+    /// its respective crate feature enabled. Only toml is available by default.
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let _ = self.path.take();
         let mut file = File::create(&path)?;
@@ -397,6 +921,24 @@ impl Config {
         self.owners.iter().any(|n| n == nickname)
     }
 
+    /// Gets the masks of the users whose messages are ignored.
+    /// This defaults to an empty vector when not specified.
+    pub fn ignores(&self) -> &[String] {
+        &self.ignores
+    }
+
+    /// Gets the capabilities the server must offer.
+    /// This defaults to an empty vector when not specified.
+    pub fn required_capabilities(&self) -> &[String] {
+        &self.required_capabilities
+    }
+
+    /// Gets the keywords, besides our nickname, that count as mentioning us.
+    /// This defaults to an empty vector when not specified.
+    pub fn highlights(&self) -> &[String] {
+        &self.highlights
+    }
+
     /// Gets the nickname specified in the configuration.
     pub fn nickname(&self) -> Result<&str> {
         self.nickname.as_deref().ok_or_else(|| InvalidConfig {
@@ -433,6 +975,22 @@ impl Config {
             .map_or(self.nickname().unwrap_or("irc"), |s| s)
     }
 
+    /// Gets the mode parameter of the `USER` command sent on registration.
+    /// This defaults to `0` when not specified.
+    pub fn registration_mode(&self) -> &str {
+        self.registration_mode.as_deref().unwrap_or("0")
+    }
+
+    /// Gets what to do with a nickname or username longer than the server's `NICKLEN` or
+    /// `USERLEN` token allows. Names are checked as they are sent, once the server has advertised
+    /// these tokens, which it does after registration. So this applies to later nickname changes,
+    /// while the names we register with are sent as they are and the server decides what to do
+    /// with them. Either way, the nickname we're tracked under is the one the server confirms.
+    /// This defaults to `LengthPolicy::Send` when not specified.
+    pub fn length_policy(&self) -> LengthPolicy {
+        self.length_policy.unwrap_or_default()
+    }
+
     /// Gets the address of the server specified in the configuration.
     pub fn server(&self) -> Result<&str> {
         self.server.as_deref().ok_or_else(|| InvalidConfig {
@@ -458,10 +1016,97 @@ impl Config {
         self.port.as_ref().cloned().unwrap_or(6667)
     }
 
+    /// Gets the server password specified in the configuration, falling back to the deprecated
+    /// `password` field. This defaults to an empty string when not specified.
+    pub fn server_password(&self) -> &str {
+        self.server_password
+            .as_ref()
+            .or(self.password.as_ref())
+            .map_or("", String::as_str)
+    }
+
     /// Gets the server password specified in the configuration.
     /// This defaults to an empty string when not specified.
+    #[deprecated(note = "use `server_password` instead")]
     pub fn password(&self) -> &str {
-        self.password.as_ref().map_or("", String::as_str)
+        self.server_password()
+    }
+
+    /// Gets the `WEBIRC` password, gateway name, hostname and IP address specified in the
+    /// configuration, if `WEBIRC` is to be sent. The hostname defaults to the IP address when not
+    /// specified.
+    pub fn webirc(&self) -> Option<(&str, &str, &str, &str)> {
+        let ip = self.webirc_ip.as_deref()?;
+        Some((
+            self.webirc_password.as_deref()?,
+            self.webirc_gateway.as_deref()?,
+            self.webirc_hostname.as_deref().unwrap_or(ip),
+            ip,
+        ))
+    }
+
+    /// Gets the path of the UNIX domain socket to connect to instead of the server's address.
+    /// This defaults to `None` when not specified.
+    #[cfg(unix)]
+    pub fn unix_socket(&self) -> Option<&str> {
+        self.unix_socket.as_deref()
+    }
+
+    /// Gets the local IP address to connect from specified in the configuration, which fails if
+    /// it is not a valid IP address. When not specified, the operating system picks one.
+    pub fn bind_address(&self) -> Result<Option<IpAddr>> {
+        match self.bind_address {
+            Some(ref address) => match address.parse() {
+                Ok(address) => Ok(Some(address)),
+                Err(_) => Err(InvalidConfig {
+                    path: self.path(),
+                    cause: ConfigError::InvalidBindAddress {
+                        address: address.clone(),
+                    },
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the IP version to connect over specified in the configuration.
+    /// This defaults to `AddressFamily::Any` when not specified.
+    pub fn address_family(&self) -> AddressFamily {
+        self.address_family.unwrap_or_default()
+    }
+
+    /// Gets the delay in milliseconds before trying the next of the server's addresses while
+    /// earlier attempts are still connecting, so that a broken IPv6 or IPv4 route doesn't hold up
+    /// the connection.
+    /// This defaults to 250 milliseconds when not specified.
+    pub fn connection_attempt_delay(&self) -> u32 {
+        self.connection_attempt_delay
+            .as_ref()
+            .cloned()
+            .unwrap_or(250)
+    }
+
+    /// Gets the amount of idle time in seconds before TCP keepalive probes are sent, which keeps
+    /// NAT gateways and firewalls from dropping a quiet connection. TCP keepalive is enabled when
+    /// this or any other `tcp_keepalive` option is specified, using the system defaults for the
+    /// ones that are not.
+    /// This defaults to the system default when not specified.
+    pub fn tcp_keepalive(&self) -> Option<u32> {
+        self.tcp_keepalive
+    }
+
+    /// Gets the time in seconds between TCP keepalive probes. This is ignored on platforms that
+    /// don't support setting it.
+    /// This defaults to the system default when not specified.
+    pub fn tcp_keepalive_interval(&self) -> Option<u32> {
+        self.tcp_keepalive_interval
+    }
+
+    /// Gets the number of unanswered TCP keepalive probes before the connection is dropped. This is
+    /// ignored on platforms that don't support setting it.
+    /// This defaults to the system default when not specified.
+    pub fn tcp_keepalive_retries(&self) -> Option<u32> {
+        self.tcp_keepalive_retries
     }
 
     /// Gets the type of the proxy specified in the configuration.
@@ -508,12 +1153,47 @@ impl Config {
         self.use_tls.as_ref().cloned().map_or(true, |s| s)
     }
 
+    /// Gets the hostname to verify the server's TLS certificate against and send in SNI.
+    /// This defaults to the server when not specified.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn tls_sni(&self) -> Result<&str> {
+        match self.tls_sni {
+            Some(ref sni) => Ok(sni),
+            None => self.server(),
+        }
+    }
+
     /// Gets the path to the TLS certificate in DER format if specified.
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     pub fn cert_path(&self) -> Option<&str> {
         self.cert_path.as_deref()
     }
 
+    /// Gets the protocols to offer with ALPN when connecting over TLS.
+    /// This defaults to an empty list if it's not specified.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn alpn_protocols(&self) -> &[String] {
+        &self.alpn_protocols
+    }
+
+    /// Gets the SHA-256 fingerprint the server's certificate must have, which fails if it is not
+    /// 32 bytes of hex. When not specified, the certificate is validated as usual.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn pinned_cert_sha256(&self) -> Result<Option<Vec<u8>>> {
+        match self.pinned_cert_sha256 {
+            Some(ref fingerprint) => match parse_fingerprint(fingerprint) {
+                Some(fingerprint) => Ok(Some(fingerprint)),
+                None => Err(InvalidConfig {
+                    path: self.path(),
+                    cause: ConfigError::InvalidFingerprint {
+                        fingerprint: fingerprint.clone(),
+                    },
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
     /// Gets whether or not to dangerously accept invalid certificates.
     /// This defaults to `false` when not specified.
     pub fn dangerously_accept_invalid_certs(&self) -> bool {
@@ -529,6 +1209,13 @@ impl Config {
         self.client_cert_path.as_deref()
     }
 
+    /// Gets the path to the private key for the client authentication certificate in PEM format
+    /// if specified.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn client_key_path(&self) -> Option<&str> {
+        self.client_key_path.as_deref()
+    }
+
     /// Gets the password to the client authentication certificate.
     #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
     pub fn client_cert_pass(&self) -> &str {
@@ -541,18 +1228,77 @@ impl Config {
         self.encoding.as_ref().map_or("UTF-8", |s| s)
     }
 
+    /// Gets the encoding to use for messages received from the server.
+    /// This defaults to the value of `encoding` when not specified.
+    pub fn recv_encoding(&self) -> &str {
+        self.recv_encoding
+            .as_deref()
+            .unwrap_or_else(|| self.encoding())
+    }
+
+    /// Gets the encoding to use for messages sent to the server.
+    /// This defaults to the value of `encoding` when not specified.
+    pub fn send_encoding(&self) -> &str {
+        self.send_encoding
+            .as_deref()
+            .unwrap_or_else(|| self.encoding())
+    }
+
+    /// Gets the encodings to try when a received message is not valid in the receive encoding.
+    /// This defaults to an empty list if it's not specified.
+    pub fn fallback_encodings(&self) -> &[String] {
+        &self.fallback_encodings
+    }
+
+    /// Gets whether or not received messages keep the bytes they were parsed from.
+    /// This defaults to false when not specified.
+    pub fn keep_raw_bytes(&self) -> bool {
+        self.keep_raw_bytes
+    }
+
     /// Gets the channels to join upon connection.
     /// This defaults to an empty vector if it's not specified.
     pub fn channels(&self) -> &[String] {
         &self.channels
     }
 
+    /// Gets the maximum number of channels to join with a single `JOIN` command on connection,
+    /// e.g. `JOIN #a,#b`. Fewer are joined at once if the command would otherwise be too long.
+    /// This defaults to 1 when not specified.
+    pub fn channels_per_join(&self) -> u32 {
+        self.channels_per_join.as_ref().cloned().unwrap_or(1).max(1)
+    }
+
+    /// Gets the delay in milliseconds between the `JOIN` commands sent on connection, which keeps
+    /// a long list of channels from tripping the server's join rate limits.
+    /// This defaults to 0 (joining every channel at once) when not specified.
+    pub fn join_delay(&self) -> u32 {
+        self.join_delay.as_ref().cloned().unwrap_or(0)
+    }
+
+    /// Gets the time in seconds to wait for the server to confirm a `JOIN` sent with
+    /// `Client::join`, or zero to wait indefinitely.
+    /// This defaults to 30 seconds when not specified.
+    pub fn join_timeout(&self) -> u32 {
+        self.join_timeout.as_ref().cloned().unwrap_or(30)
+    }
+
     /// Gets the key for the specified channel if it exists in the configuration.
+    /// Channel names are compared case-insensitively, so a key for `#Rust` is used for `#rust`.
     pub fn channel_key(&self, chan: &str) -> Option<&str> {
-        self.channel_keys.get(chan).map(String::as_str)
+        match self.channel_keys.get(chan) {
+            Some(key) => Some(key),
+            None => self
+                .channel_keys
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(chan))
+                .map(|(_, key)| &key[..]),
+        }
     }
 
-    /// Gets the user modes to set on connect specified in the configuration.
+    /// Gets the user modes to set on connect specified in the configuration, e.g. `+iB` to be
+    /// invisible and marked as a bot. They are sent as soon as registration has finished and the
+    /// server has sent its MOTD. Configuration files may also call this field `user_modes`.
     /// This defaults to an empty string when not specified.
     pub fn umodes(&self) -> &str {
         self.umodes.as_ref().map_or("", String::as_str)
@@ -579,7 +1325,17 @@ impl Config {
             .map_or("https://github.com/aatxe/irc", String::as_str)
     }
 
+    /// Gets the default message to send when quitting.
+    /// This defaults to `Powered by Rust.` when not specified.
+    pub fn quit_message(&self) -> &str {
+        self.quit_message
+            .as_ref()
+            .map_or("Powered by Rust.", String::as_str)
+    }
+
     /// Gets the amount of time in seconds for the interval at which the client pings the server.
+    /// Pinging starts at the end of the MOTD, and each ping that goes unanswered for `ping_timeout`
+    /// seconds ends the connection with `Error::PingTimeout`.
     /// This defaults to 180 seconds when not specified.
     pub fn ping_time(&self) -> u32 {
         self.ping_time.as_ref().cloned().unwrap_or(180)
@@ -603,12 +1359,74 @@ impl Config {
     /// The maximum number of messages that can be sent in a burst window before they'll be delayed.
     /// Messages are automatically delayed until the start of the next window. The message throttling
     /// system maintains the invariant that in the past `burst_window_length` seconds, the maximum
-    /// number of messages sent is `max_messages_in_burst`.
+    /// number of messages sent is `max_messages_in_burst`. `PONG` and `QUIT` are always sent right
+    /// away, and at most 1024 messages are held back before sending waits for the queue to drain.
     /// This defaults to 15 messages when not specified.
     pub fn max_messages_in_burst(&self) -> u32 {
         self.max_messages_in_burst.as_ref().cloned().unwrap_or(15)
     }
 
+    /// Gets whether or not to split messages that exceed the line length limit.
+    /// This defaults to false when not specified.
+    pub fn split_long_messages(&self) -> bool {
+        self.split_long_messages
+    }
+
+    /// Gets the maximum length in bytes of a line received from the server.
+    /// This defaults to 16384 bytes when not specified.
+    pub fn max_line_length(&self) -> u32 {
+        self.max_line_length.as_ref().cloned().unwrap_or(16 * 1024)
+    }
+
+    /// Gets the number of recently sent and received messages to keep.
+    /// This defaults to 0 (no history) when not specified.
+    pub fn history_length(&self) -> u32 {
+        self.history_length.as_ref().cloned().unwrap_or(0)
+    }
+
+    /// Gets the number of recently received messages to remember for deduplication.
+    /// This defaults to 0 (no deduplication) when not specified.
+    pub fn dedup_window(&self) -> u32 {
+        self.dedup_window.as_ref().cloned().unwrap_or(0)
+    }
+
+    /// Gets whether or not to rejoin channels from `channels` after being kicked from them.
+    /// This defaults to false when not specified.
+    pub fn auto_rejoin(&self) -> bool {
+        self.auto_rejoin
+    }
+
+    /// Gets the delay in seconds before rejoining a channel after being kicked from it.
+    /// This defaults to 0 (rejoining immediately) when not specified.
+    pub fn auto_rejoin_delay(&self) -> u32 {
+        self.auto_rejoin_delay.as_ref().cloned().unwrap_or(0)
+    }
+
+    /// Gets the maximum number of times to rejoin each channel after being kicked from it.
+    /// This defaults to 3 when not specified.
+    pub fn max_rejoin_attempts(&self) -> u32 {
+        self.max_rejoin_attempts.as_ref().cloned().unwrap_or(3)
+    }
+
+    /// Gets the line sent to identify with NickServ, with `{nick}` and `{password}` placeholders.
+    /// When not specified, `NICKSERV IDENTIFY <password>` is sent after the MOTD.
+    pub fn nickserv_identify(&self) -> Option<&str> {
+        self.nickserv_identify.as_deref()
+    }
+
+    /// Gets whether or not to wait for identification to be confirmed before joining channels.
+    /// This defaults to false when not specified.
+    pub fn wait_for_identify(&self) -> bool {
+        self.wait_for_identify
+    }
+
+    /// Gets the time in seconds to wait for identification to be confirmed before joining
+    /// channels anyway, or zero to wait indefinitely.
+    /// This defaults to 30 seconds when not specified.
+    pub fn identify_timeout(&self) -> u32 {
+        self.identify_timeout.as_ref().cloned().unwrap_or(30)
+    }
+
     /// Gets whether or not to attempt nickname reclamation using NickServ GHOST.
     /// This defaults to false when not specified.
     pub fn should_ghost(&self) -> bool {
@@ -638,11 +1456,315 @@ impl Config {
     pub fn mock_initial_value(&self) -> &str {
         self.mock_initial_value.as_ref().map_or("", |s| s)
     }
+
+    /// Gets the script for the mock connection, as pairs of a line the client is expected to send
+    /// and the lines sent back once it does. The connection stays open until the script is done.
+    /// This defaults to an empty vector when not specified.
+    /// This has no effect if `use_mock_connection` is not `true`.
+    pub fn mock_script(&self) -> &[(String, String)] {
+        &self.mock_script
+    }
+
+    /// Gets whether or not the mock connection stays open after its initial value and script,
+    /// reading lines injected by the test until it disconnects.
+    /// This defaults to false when not specified.
+    /// This has no effect if `use_mock_connection` is not `true`.
+    pub fn mock_keep_open(&self) -> bool {
+        self.mock_keep_open
+    }
+}
+
+/// A builder for [`Config`](struct.Config.html), created with `Config::builder()`.
+///
+/// Any field that isn't set is left unspecified, so the usual defaults documented on each of the
+/// `Config` getters apply (e.g. the port is derived from whether TLS is in use).
+#[derive(Clone, Default, Debug)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+macro_rules! builder_setters {
+    ($($(#[$attr:meta])* $field:ident: String,)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $field<S: Into<String>>(mut self, $field: S) -> ConfigBuilder {
+                self.config.$field = Some($field.into());
+                self
+            }
+        )*
+    };
+    ($($(#[$attr:meta])* $field:ident: $ty:ty,)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $field(mut self, $field: $ty) -> ConfigBuilder {
+                self.config.$field = Some($field);
+                self
+            }
+        )*
+    };
+}
+
+impl ConfigBuilder {
+    builder_setters! {
+        /// Sets the client's nickname.
+        nickname: String,
+        /// Sets the client's NickServ password.
+        nick_password: String,
+        /// Sets the client's username.
+        username: String,
+        /// Sets the client's real name.
+        realname: String,
+        /// Sets the mode parameter of the `USER` command sent on registration.
+        registration_mode: String,
+        /// Sets the server to connect to.
+        server: String,
+        /// Sets the password to connect to the server.
+        server_password: String,
+        /// Sets the password to connect to the server.
+        #[deprecated(note = "use `server_password` instead")]
+        password: String,
+        /// Sets the password the server expects from this gateway in `WEBIRC`.
+        webirc_password: String,
+        /// Sets the name of this gateway sent in `WEBIRC`.
+        webirc_gateway: String,
+        /// Sets the hostname of the real client sent in `WEBIRC`.
+        webirc_hostname: String,
+        /// Sets the IP address of the real client sent in `WEBIRC`.
+        webirc_ip: String,
+        /// Sets the local IP address to connect from.
+        bind_address: String,
+        /// Sets the encoding used for this connection.
+        encoding: String,
+        /// Sets the encoding used for messages received from the server.
+        recv_encoding: String,
+        /// Sets the encoding used for messages sent to the server.
+        send_encoding: String,
+        /// Sets the user modes to set on connect.
+        umodes: String,
+        /// Sets the text sent in response to CTCP USERINFO requests.
+        user_info: String,
+        /// Sets the text sent in response to CTCP VERSION requests.
+        version: String,
+        /// Sets the text sent in response to CTCP SOURCE requests.
+        source: String,
+        /// Sets the default message sent when quitting.
+        quit_message: String,
+        /// Sets the line sent to identify with NickServ, with `{nick}` and `{password}`
+        /// placeholders.
+        nickserv_identify: String,
+    }
+
+    builder_setters! {
+        /// Sets the port to connect on.
+        port: u16,
+        /// Sets the delay in milliseconds before trying the next of the server's addresses.
+        connection_attempt_delay: u32,
+        /// Sets the amount of idle time in seconds before TCP keepalive probes are sent.
+        tcp_keepalive: u32,
+        /// Sets the time in seconds between TCP keepalive probes.
+        tcp_keepalive_interval: u32,
+        /// Sets the number of unanswered TCP keepalive probes before the connection is dropped.
+        tcp_keepalive_retries: u32,
+        /// Sets the IP version to connect over.
+        address_family: AddressFamily,
+        /// Sets what to do with a nickname or username longer than the server allows.
+        length_policy: LengthPolicy,
+        /// Sets the interval in seconds at which the client pings the server.
+        ping_time: u32,
+        /// Sets the amount of time in seconds to wait for a ping response.
+        ping_timeout: u32,
+        /// Sets the length in seconds of the rolling window for message throttling.
+        burst_window_length: u32,
+        /// Sets the maximum number of messages that can be sent in a burst window.
+        max_messages_in_burst: u32,
+        /// Sets the maximum length in bytes of a line received from the server.
+        max_line_length: u32,
+        /// Sets the number of recently sent and received messages to keep.
+        history_length: u32,
+        /// Sets the number of recently received messages to remember for deduplication.
+        dedup_window: u32,
+        /// Sets the maximum number of channels to join with a single `JOIN` command.
+        channels_per_join: u32,
+        /// Sets the delay in milliseconds between the `JOIN` commands sent on connection.
+        join_delay: u32,
+        /// Sets the time in seconds to wait for the server to confirm a `JOIN`.
+        join_timeout: u32,
+        /// Sets the delay in seconds before rejoining a channel after being kicked from it.
+        auto_rejoin_delay: u32,
+        /// Sets the maximum number of times to rejoin each channel after being kicked from it.
+        max_rejoin_attempts: u32,
+        /// Sets the time in seconds to wait for identification to be confirmed before joining.
+        identify_timeout: u32,
+        /// Sets whether to skip all certificate validation. See
+        /// `Config::dangerously_accept_invalid_certs` before using this.
+        dangerously_accept_invalid_certs: bool,
+    }
+
+    #[cfg(feature = "proxy")]
+    builder_setters! {
+        /// Sets the type of proxy to connect through.
+        proxy_type: ProxyType,
+        /// Sets the port of the proxy server.
+        proxy_port: u16,
+    }
+
+    #[cfg(feature = "proxy")]
+    builder_setters! {
+        /// Sets the proxy server to connect through.
+        proxy_server: String,
+        /// Sets the username to authenticate with the proxy server.
+        proxy_username: String,
+        /// Sets the password to authenticate with the proxy server.
+        proxy_password: String,
+    }
+
+    #[cfg(unix)]
+    builder_setters! {
+        /// Sets the path of a UNIX domain socket to connect to instead of the server's address.
+        unix_socket: String,
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    builder_setters! {
+        /// Sets whether or not to use TLS.
+        use_tls: bool,
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    builder_setters! {
+        /// Sets the hostname to verify the server's TLS certificate against and send in SNI.
+        tls_sni: String,
+        /// Sets the path to the TLS certificate for this server in DER format.
+        cert_path: String,
+        /// Sets the path to a TLS certificate to use for CertFP client authentication.
+        client_cert_path: String,
+        /// Sets the path to the private key for the client certificate in PEM format.
+        client_key_path: String,
+        /// Sets the password for the certificate to use in CertFP authentication.
+        client_cert_pass: String,
+        /// Sets the SHA-256 fingerprint the server's certificate must have.
+        pinned_cert_sha256: String,
+    }
+
+    /// Sets the owners of the client by nickname.
+    pub fn owners<S: Into<String>>(mut self, owners: Vec<S>) -> ConfigBuilder {
+        self.config.owners = owners.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the masks of the users whose messages are ignored.
+    pub fn ignores<S: Into<String>>(mut self, ignores: Vec<S>) -> ConfigBuilder {
+        self.config.ignores = ignores.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the capabilities the server must offer.
+    pub fn required_capabilities<S: Into<String>>(mut self, caps: Vec<S>) -> ConfigBuilder {
+        self.config.required_capabilities = caps.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the keywords, besides our nickname, that count as mentioning us.
+    pub fn highlights<S: Into<String>>(mut self, highlights: Vec<S>) -> ConfigBuilder {
+        self.config.highlights = highlights.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the alternative nicknames for the client.
+    pub fn alt_nicks<S: Into<String>>(mut self, alt_nicks: Vec<S>) -> ConfigBuilder {
+        self.config.alt_nicks = alt_nicks.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the encodings to try when a received message is not valid in the receive encoding.
+    pub fn fallback_encodings<S: Into<String>>(mut self, encodings: Vec<S>) -> ConfigBuilder {
+        self.config.fallback_encodings = encodings.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the protocols to offer with ALPN when connecting over TLS.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn alpn_protocols<S: Into<String>>(mut self, protocols: Vec<S>) -> ConfigBuilder {
+        self.config.alpn_protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the channels to join on connection.
+    pub fn channels<S: Into<String>>(mut self, channels: Vec<S>) -> ConfigBuilder {
+        self.config.channels = channels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the key to use when joining the specified channel on connection.
+    pub fn channel_key<S1, S2>(mut self, channel: S1, key: S2) -> ConfigBuilder
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.config.channel_keys.insert(channel.into(), key.into());
+        self
+    }
+
+    /// Sets whether messages that exceed the line length limit should be split.
+    pub fn split_long_messages(mut self, split_long_messages: bool) -> ConfigBuilder {
+        self.config.split_long_messages = split_long_messages;
+        self
+    }
+
+    /// Sets whether received messages should keep the bytes they were parsed from.
+    pub fn keep_raw_bytes(mut self, keep_raw_bytes: bool) -> ConfigBuilder {
+        self.config.keep_raw_bytes = keep_raw_bytes;
+        self
+    }
+
+    /// Sets whether channels from `channels` should be rejoined after being kicked from them.
+    pub fn auto_rejoin(mut self, auto_rejoin: bool) -> ConfigBuilder {
+        self.config.auto_rejoin = auto_rejoin;
+        self
+    }
+
+    /// Sets whether to wait for our identification to be confirmed before joining channels.
+    pub fn wait_for_identify(mut self, wait_for_identify: bool) -> ConfigBuilder {
+        self.config.wait_for_identify = wait_for_identify;
+        self
+    }
+
+    /// Sets whether the client should use NickServ GHOST to reclaim its primary nickname.
+    pub fn should_ghost(mut self, should_ghost: bool) -> ConfigBuilder {
+        self.config.should_ghost = should_ghost;
+        self
+    }
+
+    /// Sets the NickServ commands sent to reclaim the primary nickname, e.g. `["RECOVER"]`.
+    pub fn ghost_sequence<S: Into<String>>(mut self, sequence: Vec<S>) -> ConfigBuilder {
+        self.config.ghost_sequence = Some(sequence.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets an additional option in the options map.
+    pub fn option<S1, S2>(mut self, option: S1, value: S2) -> ConfigBuilder
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.config.options.insert(option.into(), value.into());
+        self
+    }
+
+    /// Builds the configuration, checking that the required `nickname` and `server` are set.
+    pub fn build(self) -> Result<Config> {
+        self.config.nickname()?;
+        self.config.server()?;
+        Ok(self.config)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Config;
+    #[cfg(feature = "proxy")]
+    use super::ProxyType;
+    use super::{AddressFamily, Config};
+    use crate::error::ConfigError;
     use std::collections::HashMap;
 
     #[cfg(any(
@@ -659,17 +1781,56 @@ mod test {
             nickname: Some("test".to_string()),
             username: Some("test".to_string()),
             realname: Some("test".to_string()),
-            password: Some(String::new()),
+            server_password: Some(String::new()),
             umodes: Some("+BR".to_string()),
             server: Some("irc.test.net".to_string()),
             port: Some(6667),
             encoding: Some("UTF-8".to_string()),
             channels: vec!["#test".to_string(), "#test2".to_string()],
-
+            options: {
+                let mut map = HashMap::new();
+                map.insert("testing".to_string(), "test".to_string());
+                map.insert("second".to_string(), "with = sign".to_string());
+                map
+            },
             ..Default::default()
         }
     }
 
+    #[test]
+    fn server_password() {
+        let cfg = Config {
+            password: Some("old".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(cfg.server_password(), "old");
+        let cfg = Config {
+            server_password: Some("new".to_string()),
+            ..cfg
+        };
+        assert_eq!(cfg.server_password(), "new");
+        assert_eq!(Config::default().server_password(), "");
+    }
+
+    #[test]
+    fn webirc() {
+        let cfg = Config {
+            webirc_password: Some("secret".to_string()),
+            webirc_gateway: Some("gateway".to_string()),
+            webirc_ip: Some("192.0.2.1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            cfg.webirc(),
+            Some(("secret", "gateway", "192.0.2.1", "192.0.2.1"))
+        );
+        let cfg = Config {
+            webirc_ip: None,
+            ..cfg
+        };
+        assert_eq!(cfg.webirc(), None);
+    }
+
     #[test]
     fn is_owner() {
         let cfg = Config {
@@ -681,6 +1842,393 @@ mod test {
         assert!(!cfg.is_owner("test3"));
     }
 
+    #[test]
+    fn builder() -> crate::error::Result<()> {
+        let cfg = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .port(6667)
+            .channels(vec!["#test", "#test2"])
+            .channel_key("#test2", "password")
+            .option("testing", "test")
+            .build()?;
+        assert_eq!(
+            cfg,
+            Config {
+                nickname: Some("test".to_string()),
+                server: Some("irc.test.net".to_string()),
+                port: Some(6667),
+                channels: vec!["#test".to_string(), "#test2".to_string()],
+                channel_keys: {
+                    let mut map = HashMap::new();
+                    map.insert("#test2".to_string(), "password".to_string());
+                    map
+                },
+                options: {
+                    let mut map = HashMap::new();
+                    map.insert("testing".to_string(), "test".to_string());
+                    map
+                },
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn builder_recovery_and_certs() -> crate::error::Result<()> {
+        let cfg = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .ghost_sequence(vec!["RECOVER", "RELEASE"])
+            .dangerously_accept_invalid_certs(true)
+            .build()?;
+        assert_eq!(
+            cfg.ghost_sequence(),
+            Some(&["RECOVER".to_owned(), "RELEASE".to_owned()][..])
+        );
+        assert!(cfg.dangerously_accept_invalid_certs());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "proxy")]
+    fn builder_proxy() -> crate::error::Result<()> {
+        let cfg = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .proxy_type(ProxyType::Socks5)
+            .proxy_server("127.0.0.1")
+            .proxy_port(9050)
+            .proxy_username("user")
+            .proxy_password("pass")
+            .build()?;
+        assert_eq!(cfg.proxy_type(), ProxyType::Socks5);
+        assert_eq!(cfg.proxy_server(), "127.0.0.1");
+        assert_eq!(cfg.proxy_port(), 9050);
+        assert_eq!(cfg.proxy_username(), "user");
+        assert_eq!(cfg.proxy_password(), "pass");
+        Ok(())
+    }
+
+    #[test]
+    fn builder_minimal_defaults() -> crate::error::Result<()> {
+        let cfg = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .build()?;
+        assert_eq!(cfg.nickname()?, "test");
+        assert_eq!(cfg.server()?, "irc.test.net");
+        assert_eq!(cfg.username(), "test");
+        assert_eq!(cfg.encoding(), "UTF-8");
+        #[cfg(not(any(feature = "tls-native", feature = "tls-rust")))]
+        assert_eq!(cfg.port(), 6667);
+        #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+        assert_eq!(cfg.port(), 6697);
+        Ok(())
+    }
+
+    #[test]
+    fn directional_encodings() {
+        let cfg = Config {
+            encoding: Some("ISO-8859-1".to_owned()),
+            ..Config::default()
+        };
+        assert_eq!(cfg.recv_encoding(), "ISO-8859-1");
+        assert_eq!(cfg.send_encoding(), "ISO-8859-1");
+
+        let cfg = Config {
+            recv_encoding: Some("ISO-8859-1".to_owned()),
+            ..Config::default()
+        };
+        assert_eq!(cfg.recv_encoding(), "ISO-8859-1");
+        assert_eq!(cfg.send_encoding(), "UTF-8");
+    }
+
+    #[test]
+    fn builder_missing_required() {
+        use crate::error::Error;
+
+        match Config::builder().server("irc.test.net").build() {
+            Err(Error::InvalidConfig {
+                cause: ConfigError::NicknameNotSpecified,
+                ..
+            }) => (),
+            other => panic!("expected a missing nickname error, got {:?}", other),
+        }
+        match Config::builder().nickname("test").build() {
+            Err(Error::InvalidConfig {
+                cause: ConfigError::ServerNotSpecified,
+                ..
+            }) => (),
+            other => panic!("expected a missing server error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_env() -> crate::error::Result<()> {
+        std::env::set_var("IRC_TEST_ENV_NICKNAME", "test");
+        std::env::set_var("IRC_TEST_ENV_SERVER", "irc.test.net");
+        std::env::set_var("IRC_TEST_ENV_PORT", "6667");
+        std::env::set_var("IRC_TEST_ENV_CHANNELS", "#test, #test2,,");
+        std::env::set_var("IRC_TEST_ENV_SHOULD_GHOST", "true");
+        std::env::set_var("IRC_TEST_ENV_UMODES", "");
+        let cfg = Config::from_env("IRC_TEST_ENV")?;
+        assert_eq!(
+            cfg,
+            Config {
+                nickname: Some("test".to_string()),
+                server: Some("irc.test.net".to_string()),
+                port: Some(6667),
+                channels: vec!["#test".to_string(), "#test2".to_string()],
+                should_ghost: true,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn from_env_missing_server() {
+        use crate::error::Error;
+
+        std::env::set_var("IRC_TEST_ENV_MISSING_NICKNAME", "test");
+        match Config::from_env("IRC_TEST_ENV_MISSING") {
+            Err(Error::InvalidConfig {
+                cause: ConfigError::ServerNotSpecified,
+                ..
+            }) => (),
+            other => panic!("expected a missing server error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_env_invalid_port() {
+        use crate::error::Error;
+
+        std::env::set_var("IRC_TEST_BAD_PORT_NICKNAME", "test");
+        std::env::set_var("IRC_TEST_BAD_PORT_SERVER", "irc.test.net");
+        std::env::set_var("IRC_TEST_BAD_PORT_PORT", "66670");
+        match Config::from_env("IRC_TEST_BAD_PORT") {
+            Err(Error::InvalidConfig {
+                cause: ConfigError::InvalidEnvVar { name, value },
+                ..
+            }) => {
+                assert_eq!(name, "IRC_TEST_BAD_PORT_PORT");
+                assert_eq!(value, "66670");
+            }
+            other => panic!("expected an invalid variable error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate() {
+        assert!(test_config().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_nickname() {
+        let cfg = Config {
+            nickname: None,
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::NicknameNotSpecified)
+        ));
+        let cfg = Config {
+            nickname: Some(String::new()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidNickname { nickname }) if nickname.is_empty()
+        ));
+        let cfg = Config {
+            nickname: Some("te st".to_string()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidNickname { nickname }) if nickname == "te st"
+        ));
+    }
+
+    #[test]
+    fn validate_server() {
+        let cfg = Config {
+            server: None,
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ServerNotSpecified)
+        ));
+        let cfg = Config {
+            server: Some(String::new()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ServerNotSpecified)
+        ));
+    }
+
+    #[test]
+    fn validate_port() {
+        let cfg = Config {
+            port: Some(0),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidPort { port: 0 })
+        ));
+    }
+
+    #[test]
+    fn validate_bind_address() {
+        let cfg = Config {
+            bind_address: Some("127.0.0.1".to_string()),
+            ..test_config()
+        };
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.bind_address().unwrap(), Some([127, 0, 0, 1].into()));
+        let cfg = Config {
+            bind_address: Some("localhost".to_string()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidBindAddress { ref address }) if address == "localhost"
+        ));
+        assert!(cfg.bind_address().is_err());
+    }
+
+    #[test]
+    fn validate_address_family() {
+        let cfg = Config {
+            bind_address: Some("::1".to_string()),
+            address_family: Some(AddressFamily::V4),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ConflictingOptions {
+                option: "bind_address",
+                ..
+            })
+        ));
+        let cfg = Config {
+            bind_address: Some("::1".to_string()),
+            address_family: Some(AddressFamily::V6),
+            ..test_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    fn validate_tls_options() {
+        let cfg = Config {
+            use_tls: Some(false),
+            cert_path: Some("cert.der".to_string()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ConflictingOptions {
+                option: "cert_path",
+                ..
+            })
+        ));
+        let cfg = Config {
+            use_tls: Some(false),
+            dangerously_accept_invalid_certs: Some(true),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ConflictingOptions {
+                option: "dangerously_accept_invalid_certs",
+                ..
+            })
+        ));
+        let cfg = Config {
+            cert_path: Some("cert.der".to_string()),
+            ..test_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    fn pinned_cert_sha256() {
+        let fingerprint = "6A:10:E3:5C:DC:A5:3F:41:57:81:91:CA:4D:8C:86:59:\
+                           E0:DA:EE:78:BA:0D:12:C4:CB:DF:F7:C5:22:9B:11:47";
+        let cfg = Config {
+            pinned_cert_sha256: Some(fingerprint.to_string()),
+            ..test_config()
+        };
+        assert!(cfg.validate().is_ok());
+        let parsed = cfg.pinned_cert_sha256().unwrap().unwrap();
+        assert_eq!(parsed.len(), 32);
+        assert_eq!(&parsed[..2], &[0x6a, 0x10]);
+        let cfg = Config {
+            pinned_cert_sha256: Some(fingerprint.replace(':', "").to_lowercase()),
+            ..test_config()
+        };
+        assert_eq!(cfg.pinned_cert_sha256().unwrap(), Some(parsed));
+
+        let cfg = Config {
+            pinned_cert_sha256: Some("6A:10:E3".to_string()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidFingerprint { .. })
+        ));
+        let cfg = Config {
+            pinned_cert_sha256: Some(fingerprint.to_string()),
+            dangerously_accept_invalid_certs: Some(true),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ConflictingOptions {
+                option: "pinned_cert_sha256",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "proxy")]
+    fn validate_proxy_options() {
+        use super::ProxyType;
+
+        let cfg = Config {
+            proxy_server: Some("127.0.0.1".to_string()),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::ConflictingOptions {
+                option: "proxy_server",
+                ..
+            })
+        ));
+        let cfg = Config {
+            proxy_type: Some(ProxyType::Socks5),
+            proxy_port: Some(0),
+            ..test_config()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(ConfigError::InvalidPort { port: 0 })
+        ));
+    }
+
     #[test]
     fn get_option() {
         let cfg = Config {
@@ -717,6 +2265,60 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(all(feature = "json_config", feature = "toml_config"))]
+    fn toml_matches_json() -> Result<()> {
+        let toml = Config::load_toml("client_config.toml", include_str!("client_config.toml"))?;
+        let json = Config::load_json("client_config.json", include_str!("client_config.json"))?;
+        assert_eq!(toml, json);
+        Ok(())
+    }
+
+    #[test]
+    fn channel_key() {
+        let cfg = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .channel_key("#Test2", "password")
+            .build()
+            .unwrap();
+        assert_eq!(cfg.channel_key("#Test2"), Some("password"));
+        assert_eq!(cfg.channel_key("#test2"), Some("password"));
+        assert_eq!(cfg.channel_key("#test"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "toml_config")]
+    fn user_modes_alias() -> Result<()> {
+        let cfg = Config::load_toml(
+            "<test>",
+            "nickname = \"test\"\nserver = \"irc.test.net\"\nuser_modes = \"+iB\"\n",
+        )?;
+        assert_eq!(cfg.umodes(), "+iB");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "toml_config")]
+    fn toml_round_trip() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("irc-config-{}.toml", std::process::id()));
+        let mut cfg = Config {
+            alt_nicks: vec!["test2".to_string()],
+            use_tls: Some(false),
+            channel_keys: {
+                let mut map = HashMap::new();
+                map.insert("#test2".to_string(), "password".to_string());
+                map
+            },
+            ..test_config()
+        };
+        cfg.save(&path)?;
+        let loaded = Config::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded?, cfg);
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "yaml_config")]
     fn load_from_yaml() -> Result<()> {
@@ -0,0 +1,141 @@
+//! Data for suppressing messages received more than once, e.g. when a bouncer replays them.
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    client::tag_value,
+    proto::{Command, Message},
+};
+
+/// What identifies a message as one seen before.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum MessageKey {
+    /// The message's IRCv3 `msgid` tag.
+    Id(String),
+    /// A hash of the source, target, text and `time` tag of a `PRIVMSG` or `NOTICE`.
+    Hash(u64),
+}
+
+impl MessageKey {
+    fn new(msg: &Message) -> Option<MessageKey> {
+        if let Some(id) = tag_value(msg, "msgid") {
+            return Some(MessageKey::Id(id.to_owned()));
+        }
+        // Without a `time` tag, the same text may well be sent twice on purpose.
+        let time = tag_value(msg, "time")?;
+        let (target, text) = match msg.command {
+            Command::PRIVMSG(ref target, ref text) | Command::NOTICE(ref target, ref text) => {
+                (target, text)
+            }
+            _ => return None,
+        };
+        let mut hasher = DefaultHasher::new();
+        msg.prefix
+            .as_ref()
+            .map(|prefix| prefix.to_string())
+            .hash(&mut hasher);
+        (target, text, time).hash(&mut hasher);
+        Some(MessageKey::Hash(hasher.finish()))
+    }
+}
+
+/// The keys of the most recently received messages, used to recognize ones received again.
+#[derive(Debug, Default)]
+pub(crate) struct Deduplicator {
+    window: usize,
+    order: VecDeque<MessageKey>,
+    seen: HashSet<MessageKey>,
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator remembering the given number of messages, or none if it is zero.
+    pub(crate) fn new(window: usize) -> Deduplicator {
+        Deduplicator {
+            window,
+            ..Deduplicator::default()
+        }
+    }
+
+    /// Records the message, returning `true` if it was already seen within the window. Messages
+    /// in a batch, e.g. requested history, are expected to repeat ones seen live, so they are
+    /// neither remembered nor suppressed.
+    pub(crate) fn is_duplicate(&mut self, msg: &Message) -> bool {
+        if self.window == 0 || tag_value(msg, "batch").is_some() {
+            return false;
+        }
+        let key = match MessageKey::new(msg) {
+            Some(key) => key,
+            None => return false,
+        };
+        if self.seen.contains(&key) {
+            return true;
+        }
+        if self.order.len() == self.window {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Deduplicator;
+    use crate::proto::Message;
+
+    fn message(line: &str) -> Message {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn msgid() {
+        let mut dedup = Deduplicator::new(2);
+        let first = message("@msgid=a :alice!a@host PRIVMSG #test :hi");
+        assert!(!dedup.is_duplicate(&first));
+        assert!(dedup.is_duplicate(&first));
+        assert!(dedup.is_duplicate(&message("@msgid=a :alice!a@host PRIVMSG #test :edited")));
+        assert!(!dedup.is_duplicate(&message("@msgid=b :alice!a@host PRIVMSG #test :hi")));
+        // `a` falls out of the window once a third message is seen.
+        assert!(!dedup.is_duplicate(&message("@msgid=c :alice!a@host PRIVMSG #test :hi")));
+        assert!(!dedup.is_duplicate(&first));
+    }
+
+    #[test]
+    fn fallback_hash() {
+        let mut dedup = Deduplicator::new(10);
+        let line = "@time=2023-01-01T00:00:00.000Z :alice!a@host PRIVMSG #test :hi";
+        assert!(!dedup.is_duplicate(&message(line)));
+        assert!(dedup.is_duplicate(&message(line)));
+        assert!(!dedup.is_duplicate(&message(
+            "@time=2023-01-01T00:00:01.000Z :alice!a@host PRIVMSG #test :hi"
+        )));
+        // Messages without a `msgid` or `time` tag are never considered duplicates.
+        assert!(!dedup.is_duplicate(&message(":alice!a@host PRIVMSG #test :hi")));
+        assert!(!dedup.is_duplicate(&message(":alice!a@host PRIVMSG #test :hi")));
+    }
+
+    #[test]
+    fn batched() {
+        let mut dedup = Deduplicator::new(10);
+        let live = message("@msgid=a :alice!a@host PRIVMSG #test :hi");
+        assert!(!dedup.is_duplicate(&live));
+        let batched = message("@batch=h;msgid=a :alice!a@host PRIVMSG #test :hi");
+        assert!(!dedup.is_duplicate(&batched));
+        assert!(!dedup.is_duplicate(&message("@batch=h;msgid=b :bob!b@host PRIVMSG #test :hi")));
+        assert!(!dedup.is_duplicate(&message("@msgid=b :bob!b@host PRIVMSG #test :hi")));
+        assert!(dedup.is_duplicate(&live));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut dedup = Deduplicator::new(0);
+        let line = "@msgid=a :alice!a@host PRIVMSG #test :hi";
+        assert!(!dedup.is_duplicate(&message(line)));
+        assert!(!dedup.is_duplicate(&message(line)));
+    }
+}
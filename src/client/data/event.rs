@@ -0,0 +1,474 @@
+//! Data for the typed events yielded by `ClientStream::events`.
+use crate::{
+    client::data::{ModeChange, ServerInfo},
+    proto::{mode::ModeType, Command, Message, Mode, Prefix, Response, StandardReply},
+};
+
+/// A message received from the server, sorted into what it means for a bot. Every event keeps the
+/// message it was made from, which is also available through [`message`](#method.message).
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A `PRIVMSG` sent to a channel.
+    ChannelMessage {
+        /// The nickname of the sender.
+        nickname: String,
+        /// The channel the message was sent to. For a message sent only to the members with a
+        /// certain status, e.g. to `@#rust`, this is the channel without the status prefix.
+        channel: String,
+        /// The text of the message.
+        text: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A `PRIVMSG` sent to us directly.
+    PrivateMessage {
+        /// The nickname of the sender.
+        nickname: String,
+        /// The text of the message.
+        text: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A `NOTICE`, sent to a channel or to us.
+    Notice {
+        /// The nickname of the sender, or the name of the server that sent it.
+        source: Option<String>,
+        /// The channel or nickname the notice was sent to.
+        target: String,
+        /// The text of the notice.
+        text: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A CTCP query sent in a `PRIVMSG`, such as `ACTION` or `VERSION`.
+    Ctcp {
+        /// The nickname of the sender.
+        nickname: String,
+        /// The channel or nickname the query was sent to.
+        target: String,
+        /// The CTCP command, e.g. `ACTION`.
+        command: String,
+        /// Everything after the CTCP command, if anything.
+        params: Option<String>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A user (possibly us) joined a channel.
+    Join {
+        /// The nickname of the user who joined.
+        nickname: String,
+        /// The channel they joined.
+        channel: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A user (possibly us) left a channel.
+    Part {
+        /// The nickname of the user who left.
+        nickname: String,
+        /// The channel they left.
+        channel: String,
+        /// The reason they gave, if any.
+        reason: Option<String>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A user (possibly us) was kicked from a channel.
+    Kick {
+        /// The channel the user was kicked from.
+        channel: String,
+        /// The nickname of the user who was kicked.
+        nickname: String,
+        /// The nickname of the user who kicked them, if known.
+        kicker: Option<String>,
+        /// The reason given, if any.
+        reason: Option<String>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A user disconnected from the server.
+    Quit {
+        /// The nickname of the user who quit.
+        nickname: String,
+        /// The reason they gave, if any.
+        reason: Option<String>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A user (possibly us) changed their nickname.
+    NickChange {
+        /// The user's previous nickname.
+        old_nickname: String,
+        /// The user's new nickname.
+        new_nickname: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// The modes of a channel or user changed.
+    ModeChange {
+        /// The nickname or server that changed the modes, if known.
+        source: Option<String>,
+        /// The channel or nickname whose modes changed.
+        target: String,
+        /// The individual changes.
+        changes: Vec<ModeChange>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// The topic of a channel changed.
+    Topic {
+        /// The nickname of the user who changed the topic, if known.
+        nickname: Option<String>,
+        /// The channel whose topic changed.
+        channel: String,
+        /// The new topic, which is empty if it was unset.
+        topic: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// The server finished sending the users in a channel with `RPL_ENDOFNAMES`, so its list of
+    /// users is now complete.
+    NamesComplete {
+        /// The channel whose users were listed.
+        channel: String,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A standard reply (`FAIL`, `WARN` or `NOTE`) from the server, e.g.
+    /// `FAIL JOIN CHANNEL_IS_FULL #rust :Channel is full`.
+    StandardReply {
+        /// The parsed reply.
+        reply: StandardReply,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// A numeric reply from the server.
+    Numeric {
+        /// The kind of reply.
+        response: Response,
+        /// The arguments of the reply, starting with the target (usually our nickname).
+        args: Vec<String>,
+        /// The message this event was made from.
+        message: Message,
+    },
+    /// Any other message.
+    Other(Message),
+}
+
+impl Event {
+    /// Sorts the given message into an event. The server's `CHANTYPES` token is used to tell
+    /// channel messages apart from private ones, and its `STATUSMSG` token to recognize messages
+    /// sent only to the members of a channel with a certain status, such as `@#rust`.
+    pub fn from_message(message: Message, server_info: &ServerInfo) -> Event {
+        let nickname = message.source_nickname().map(|nick| nick.to_owned());
+        let source = source(&message);
+        let is_channel = |name: &str| {
+            name.chars()
+                .next()
+                .map_or(false, |c| server_info.chantypes().contains(c))
+        };
+        let channel = |target: &str| {
+            let stripped = target.trim_start_matches(|c| server_info.statusmsg().contains(c));
+            [stripped, target]
+                .iter()
+                .find(|name| is_channel(name))
+                .map(|name| name.to_string())
+        };
+
+        if let Some(reply) = StandardReply::from_command(&message.command) {
+            return Event::StandardReply { reply, message };
+        }
+
+        match (&message.command, nickname) {
+            (Command::PRIVMSG(target, body), Some(nickname))
+                if body.starts_with('\u{1}') && body.len() > 1 =>
+            {
+                let body = body[1..].strip_suffix('\u{1}').unwrap_or(&body[1..]);
+                let (command, params) = match body.split_once(' ') {
+                    Some((command, params)) => (command, Some(params.to_owned())),
+                    None => (body, None),
+                };
+                Event::Ctcp {
+                    nickname,
+                    target: target.clone(),
+                    command: command.to_owned(),
+                    params,
+                    message,
+                }
+            }
+            (Command::PRIVMSG(target, text), Some(nickname)) => match channel(target) {
+                Some(channel) => Event::ChannelMessage {
+                    nickname,
+                    channel,
+                    text: text.clone(),
+                    message,
+                },
+                None => Event::PrivateMessage {
+                    nickname,
+                    text: text.clone(),
+                    message,
+                },
+            },
+            (Command::NOTICE(target, text), _) => Event::Notice {
+                source,
+                target: target.clone(),
+                text: text.clone(),
+                message,
+            },
+            (Command::JOIN(channel, _, _), Some(nickname)) => Event::Join {
+                nickname,
+                channel: channel.clone(),
+                message,
+            },
+            (Command::PART(channel, reason), Some(nickname)) => Event::Part {
+                nickname,
+                channel: channel.clone(),
+                reason: reason.clone(),
+                message,
+            },
+            (Command::KICK(channel, nickname, reason), kicker) => Event::Kick {
+                channel: channel.clone(),
+                nickname: nickname.clone(),
+                kicker,
+                reason: reason.clone(),
+                message,
+            },
+            (Command::QUIT(reason), Some(nickname)) => Event::Quit {
+                nickname,
+                reason: reason.clone(),
+                message,
+            },
+            (Command::NICK(new_nickname), Some(old_nickname)) => Event::NickChange {
+                old_nickname,
+                new_nickname: new_nickname.clone(),
+                message,
+            },
+            (Command::ChannelMODE(target, modes), _) => Event::ModeChange {
+                source,
+                target: target.clone(),
+                changes: mode_changes(modes),
+                message,
+            },
+            (Command::UserMODE(target, modes), _) => Event::ModeChange {
+                source,
+                target: target.clone(),
+                changes: mode_changes(modes),
+                message,
+            },
+            (Command::TOPIC(channel, Some(topic)), nickname) => Event::Topic {
+                nickname,
+                channel: channel.clone(),
+                topic: topic.clone(),
+                message,
+            },
+            (Command::Response(Response::RPL_ENDOFNAMES, args), _) if args.len() > 1 => {
+                Event::NamesComplete {
+                    channel: args[1].clone(),
+                    message,
+                }
+            }
+            (Command::Response(response, args), _) => Event::Numeric {
+                response: *response,
+                args: args.clone(),
+                message,
+            },
+            _ => Event::Other(message),
+        }
+    }
+
+    /// Gets the message this event was made from.
+    pub fn message(&self) -> &Message {
+        match *self {
+            Event::ChannelMessage { ref message, .. }
+            | Event::PrivateMessage { ref message, .. }
+            | Event::Notice { ref message, .. }
+            | Event::Ctcp { ref message, .. }
+            | Event::Join { ref message, .. }
+            | Event::Part { ref message, .. }
+            | Event::Kick { ref message, .. }
+            | Event::Quit { ref message, .. }
+            | Event::NickChange { ref message, .. }
+            | Event::ModeChange { ref message, .. }
+            | Event::Topic { ref message, .. }
+            | Event::NamesComplete { ref message, .. }
+            | Event::StandardReply { ref message, .. }
+            | Event::Numeric { ref message, .. }
+            | Event::Other(ref message) => message,
+        }
+    }
+}
+
+/// Gets the nickname or server name the message came from.
+fn source(message: &Message) -> Option<String> {
+    match message.prefix {
+        Some(Prefix::Nickname(ref nick, _, _)) => Some(nick.clone()),
+        Some(Prefix::ServerName(ref name)) => Some(name.clone()),
+        None => None,
+    }
+}
+
+/// Converts parsed modes into mode changes, leaving out modes without a `+` or `-`.
+fn mode_changes<T: ModeType>(modes: &[Mode<T>]) -> Vec<ModeChange> {
+    modes
+        .iter()
+        .filter_map(|mode| {
+            let (adding, mode, param) = match *mode {
+                Mode::Plus(ref mode, ref param) => (true, mode, param),
+                Mode::Minus(ref mode, ref param) => (false, mode, param),
+                Mode::NoPrefix(_) => return None,
+            };
+            Some(ModeChange {
+                adding,
+                mode: mode.to_string().chars().next()?,
+                param: param.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Event;
+    use crate::client::data::{ModeChange, ServerInfo};
+    use crate::proto::{Message, Response, StandardReplyKind};
+
+    fn event(line: &str) -> Event {
+        Event::from_message(line.parse().unwrap(), &ServerInfo::new())
+    }
+
+    #[test]
+    fn join() {
+        match event(":alice!a@host JOIN #rust") {
+            Event::Join {
+                nickname, channel, ..
+            } => {
+                assert_eq!(nickname, "alice");
+                assert_eq!(channel, "#rust");
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn channel_message() {
+        let ev = event(":alice!a@host PRIVMSG #rust :Hello, world!");
+        match ev {
+            Event::ChannelMessage {
+                ref nickname,
+                ref channel,
+                ref text,
+                ..
+            } => {
+                assert_eq!(nickname, "alice");
+                assert_eq!(channel, "#rust");
+                assert_eq!(text, "Hello, world!");
+            }
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+        let message: Message = ":alice!a@host PRIVMSG #rust :Hello, world!"
+            .parse()
+            .unwrap();
+        assert_eq!(ev.message(), &message);
+    }
+
+    #[test]
+    fn status_message() {
+        let mut info = ServerInfo::new();
+        let args: Vec<_> = ["test", "STATUSMSG=@+", "are supported"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        info.handle_isupport(&args);
+        let message: Message = ":alice!a@host PRIVMSG @#rust :ops only".parse().unwrap();
+        match Event::from_message(message, &info) {
+            Event::ChannelMessage { channel, text, .. } => {
+                assert_eq!(channel, "#rust");
+                assert_eq!(text, "ops only");
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+        // Without the token, the prefix isn't recognized.
+        assert!(matches!(
+            event(":alice!a@host PRIVMSG @#rust :ops only"),
+            Event::PrivateMessage { .. }
+        ));
+    }
+
+    #[test]
+    fn private_message_and_ctcp() {
+        assert!(matches!(
+            event(":alice!a@host PRIVMSG test :hi"),
+            Event::PrivateMessage { .. }
+        ));
+        match event(":alice!a@host PRIVMSG #rust :\u{1}ACTION waves\u{1}") {
+            Event::Ctcp {
+                command, params, ..
+            } => {
+                assert_eq!(command, "ACTION");
+                assert_eq!(params.as_deref(), Some("waves"));
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn mode_change() {
+        match event(":op!op@host MODE #rust +o-v alice bob") {
+            Event::ModeChange {
+                source, changes, ..
+            } => {
+                assert_eq!(source.as_deref(), Some("op"));
+                assert_eq!(
+                    changes,
+                    vec![
+                        ModeChange {
+                            adding: true,
+                            mode: 'o',
+                            param: Some("alice".to_owned()),
+                        },
+                        ModeChange {
+                            adding: false,
+                            mode: 'v',
+                            param: Some("bob".to_owned()),
+                        },
+                    ]
+                );
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn names_complete() {
+        match event(":irc.test.net 366 test #rust :End of /NAMES list.") {
+            Event::NamesComplete { channel, .. } => assert_eq!(channel, "#rust"),
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn standard_reply() {
+        match event(":irc.test.net FAIL JOIN CHANNEL_IS_FULL #x :Channel is full") {
+            Event::StandardReply { reply, .. } => {
+                assert_eq!(reply.kind, StandardReplyKind::Fail);
+                assert_eq!(reply.command, "JOIN");
+                assert_eq!(reply.code, "CHANNEL_IS_FULL");
+                assert_eq!(reply.context, vec!["#x".to_owned()]);
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn numeric_and_other() {
+        assert!(matches!(
+            event(":irc.test.net 001 test :Welcome"),
+            Event::Numeric {
+                response: Response::RPL_WELCOME,
+                ..
+            }
+        ));
+        assert!(matches!(event("PING :irc.test.net"), Event::Other(_)));
+    }
+}
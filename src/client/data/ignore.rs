@@ -0,0 +1,120 @@
+//! Data for ignoring messages from users matching hostmasks.
+
+/// Determines whether the given `nick!user@host` matches the mask, where `*` matches any number
+/// of characters and `?` matches exactly one. Letters are compared case-insensitively.
+///
+/// # Example
+/// ```
+/// # extern crate irc;
+/// use irc::client::data::mask_matches;
+/// # fn main() {
+/// assert!(mask_matches("*!*@spammer.host", "spam!bot@Spammer.Host"));
+/// assert!(mask_matches("alice!?@*", "alice!a@example.com"));
+/// assert!(!mask_matches("*!*@spammer.host", "alice!a@example.com"));
+/// # }
+/// ```
+pub fn mask_matches(mask: &str, hostmask: &str) -> bool {
+    let mask: Vec<char> = mask.chars().collect();
+    let text: Vec<char> = hostmask.chars().collect();
+    let (mut m, mut t) = (0, 0);
+    // The position of the last `*` seen, and of the text it has been matched up to so far.
+    let mut star = None;
+    let mut star_end = 0;
+
+    while t < text.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m].eq_ignore_ascii_case(&text[t])) {
+            m += 1;
+            t += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            star = Some(m);
+            star_end = t;
+            m += 1;
+        } else if let Some(star) = star {
+            // Let the last `*` swallow one more character, and retry from there.
+            m = star + 1;
+            star_end += 1;
+            t = star_end;
+        } else {
+            return false;
+        }
+    }
+    mask[m..].iter().all(|&c| c == '*')
+}
+
+/// The masks of the users whose messages are ignored.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IgnoreList {
+    masks: Vec<String>,
+}
+
+impl IgnoreList {
+    /// Creates a list ignoring the given masks.
+    pub(crate) fn new(masks: &[String]) -> IgnoreList {
+        let mut list = IgnoreList::default();
+        for mask in masks {
+            list.add(mask);
+        }
+        list
+    }
+
+    /// Gets the ignored masks, in the order they were added.
+    pub(crate) fn masks(&self) -> &[String] {
+        &self.masks
+    }
+
+    /// Adds the mask, returning `false` if it was already ignored.
+    pub(crate) fn add(&mut self, mask: &str) -> bool {
+        if self.masks.iter().any(|m| m.eq_ignore_ascii_case(mask)) {
+            return false;
+        }
+        self.masks.push(mask.to_owned());
+        true
+    }
+
+    /// Removes the mask, returning `false` if it was not ignored.
+    pub(crate) fn remove(&mut self, mask: &str) -> bool {
+        let len = self.masks.len();
+        self.masks.retain(|m| !m.eq_ignore_ascii_case(mask));
+        self.masks.len() != len
+    }
+
+    /// Determines whether the given `nick!user@host` matches any of the ignored masks.
+    pub(crate) fn matches(&self, hostmask: &str) -> bool {
+        self.masks.iter().any(|mask| mask_matches(mask, hostmask))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mask_matches, IgnoreList};
+
+    #[test]
+    fn wildcards() {
+        assert!(mask_matches("*!*@spammer.host", "spam!bot@spammer.host"));
+        assert!(mask_matches(
+            "*!*@*.spammer.host",
+            "spam!bot@a.b.spammer.host"
+        ));
+        assert!(mask_matches("sp?m!*@*", "spam!bot@example.com"));
+        assert!(mask_matches("*", ""));
+        assert!(mask_matches("*!*b*t@*", "spam!bbbot@host"));
+        assert!(!mask_matches(
+            "*!*@spammer.host",
+            "spam!bot@spammer.host.example"
+        ));
+        assert!(!mask_matches("sp?m!*@*", "spm!bot@example.com"));
+        assert!(!mask_matches("alice!*@*", "alicia!a@example.com"));
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut list = IgnoreList::new(&["*!*@spammer.host".to_owned()]);
+        assert!(!list.add("*!*@SPAMMER.host"));
+        assert!(list.add("bob!*@*"));
+        assert!(list.matches("bob!b@example.com"));
+        assert!(list.remove("BOB!*@*"));
+        assert!(!list.remove("bob!*@*"));
+        assert!(!list.matches("bob!b@example.com"));
+        assert_eq!(list.masks(), &["*!*@spammer.host".to_owned()]);
+    }
+}
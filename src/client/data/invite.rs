@@ -0,0 +1,54 @@
+//! Data for invitations to channels.
+use crate::proto::{Command, Message};
+
+/// An invitation to a channel, as received in an `INVITE` message.
+///
+/// Invitations are usually addressed to us, but with the `invite-notify` capability, the server
+/// also tells us about invitations of other users into channels we're in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invite {
+    /// The nickname of the user who sent the invitation, if known.
+    pub inviter: Option<String>,
+    /// The nickname of the invited user.
+    pub nickname: String,
+    /// The channel the user was invited to.
+    pub channel: String,
+}
+
+impl Invite {
+    /// Gets the invitation described by the given message, if it is an `INVITE`.
+    pub fn from_message(msg: &Message) -> Option<Invite> {
+        match msg.command {
+            Command::INVITE(ref nick, ref chan) => Some(Invite {
+                inviter: msg.source_nickname().map(|nick| nick.to_owned()),
+                nickname: nick.clone(),
+                channel: chan.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Determines whether the invitation is addressed to the given nickname.
+    pub fn is_for(&self, nick: &str) -> bool {
+        self.nickname.eq_ignore_ascii_case(nick)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Invite;
+    use crate::proto::Message;
+
+    #[test]
+    fn from_message() {
+        let msg: Message = ":alice!a@host INVITE Test #rust".parse().unwrap();
+        let invite = Invite::from_message(&msg).unwrap();
+        assert_eq!(invite.inviter.as_deref(), Some("alice"));
+        assert_eq!(invite.channel, "#rust");
+        assert!(invite.is_for("test"));
+        assert!(!invite.is_for("alice"));
+
+        let msg: Message = ":alice!a@host JOIN #rust".parse().unwrap();
+        assert_eq!(Invite::from_message(&msg), None);
+    }
+}
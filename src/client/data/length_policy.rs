@@ -0,0 +1,101 @@
+//! Data for choosing what to do with names longer than the server allows.
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What to do with a nickname or username that is longer than the server's `NICKLEN` or
+/// `USERLEN` token allows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LengthPolicy {
+    /// Sends the name as it is, leaving it to the server to truncate or reject it.
+    #[default]
+    Send,
+    /// Truncates the name to the advertised length before sending it.
+    Truncate,
+    /// Fails with `Error::NameTooLong` instead of sending the name.
+    Error,
+}
+
+impl LengthPolicy {
+    /// Applies the policy to a name of the given kind (e.g. `nickname`), given the maximum length
+    /// advertised by the server, if any.
+    pub(crate) fn apply(
+        self,
+        kind: &'static str,
+        name: &str,
+        max: Option<usize>,
+    ) -> crate::error::Result<String> {
+        let max = match max {
+            Some(max) if name.len() > max => max,
+            _ => return Ok(name.to_owned()),
+        };
+        match self {
+            LengthPolicy::Send => Ok(name.to_owned()),
+            LengthPolicy::Truncate => {
+                let mut end = max;
+                while !name.is_char_boundary(end) {
+                    end -= 1;
+                }
+                Ok(name[..end].to_owned())
+            }
+            LengthPolicy::Error => Err(crate::error::Error::NameTooLong {
+                kind,
+                name: name.to_owned(),
+                max,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for LengthPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            LengthPolicy::Send => "send",
+            LengthPolicy::Truncate => "truncate",
+            LengthPolicy::Error => "error",
+        })
+    }
+}
+
+impl FromStr for LengthPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<LengthPolicy, &'static str> {
+        match &s.to_ascii_lowercase()[..] {
+            "send" => Ok(LengthPolicy::Send),
+            "truncate" => Ok(LengthPolicy::Truncate),
+            "error" => Ok(LengthPolicy::Error),
+            _ => Err("Invalid length policy."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LengthPolicy;
+    use crate::error::Error;
+
+    #[test]
+    fn apply() {
+        let apply = |policy: LengthPolicy, max| policy.apply("nickname", "longnick", max);
+        assert_eq!(apply(LengthPolicy::Truncate, Some(4)).unwrap(), "long");
+        assert_eq!(apply(LengthPolicy::Truncate, None).unwrap(), "longnick");
+        assert_eq!(apply(LengthPolicy::Send, Some(4)).unwrap(), "longnick");
+        assert_eq!(apply(LengthPolicy::Error, Some(8)).unwrap(), "longnick");
+        match apply(LengthPolicy::Error, Some(4)) {
+            Err(Error::NameTooLong { kind, name, max }) => {
+                assert_eq!((kind, &name[..], max), ("nickname", "longnick", 4));
+            }
+            other => panic!("expected the name to be too long, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("Truncate".parse(), Ok(LengthPolicy::Truncate));
+        assert_eq!("error".parse(), Ok(LengthPolicy::Error));
+        assert!("drop".parse::<LengthPolicy>().is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Search filters for the `LIST` command.
+use std::fmt;
+
+/// A filter for channels listed with `Client::send_list`.
+///
+/// Apart from listing channels by name, these rely on the search extensions a server advertises
+/// with the `ELIST` ISUPPORT token, and `Client::send_list` refuses to send filters the server
+/// has not advertised support for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListFilter {
+    /// Channels matching the given name or mask. Masks with wildcards require `ELIST=M`.
+    Mask(String),
+    /// Channels not matching the given mask. Requires `ELIST=N`.
+    NotMask(String),
+    /// Channels with more than the given number of users. Requires `ELIST=U`.
+    MoreUsersThan(u32),
+    /// Channels with fewer than the given number of users. Requires `ELIST=U`.
+    FewerUsersThan(u32),
+    /// Channels created within the given number of minutes. Requires `ELIST=C`.
+    CreatedWithin(u32),
+    /// Channels created more than the given number of minutes ago. Requires `ELIST=C`.
+    CreatedBefore(u32),
+    /// Channels whose topic changed within the given number of minutes. Requires `ELIST=T`.
+    TopicChangedWithin(u32),
+    /// Channels whose topic changed more than the given number of minutes ago. Requires `ELIST=T`.
+    TopicChangedBefore(u32),
+}
+
+impl ListFilter {
+    /// Gets the `ELIST` token the server must advertise to support this filter, if any.
+    pub fn elist_token(&self) -> Option<char> {
+        match *self {
+            ListFilter::Mask(ref mask) if mask.contains(['*', '?']) => Some('M'),
+            ListFilter::Mask(_) => None,
+            ListFilter::NotMask(_) => Some('N'),
+            ListFilter::MoreUsersThan(_) | ListFilter::FewerUsersThan(_) => Some('U'),
+            ListFilter::CreatedWithin(_) | ListFilter::CreatedBefore(_) => Some('C'),
+            ListFilter::TopicChangedWithin(_) | ListFilter::TopicChangedBefore(_) => Some('T'),
+        }
+    }
+}
+
+impl fmt::Display for ListFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ListFilter::Mask(ref mask) => write!(f, "{}", mask),
+            ListFilter::NotMask(ref mask) => write!(f, "!{}", mask),
+            ListFilter::MoreUsersThan(n) => write!(f, ">{}", n),
+            ListFilter::FewerUsersThan(n) => write!(f, "<{}", n),
+            ListFilter::CreatedWithin(n) => write!(f, "C<{}", n),
+            ListFilter::CreatedBefore(n) => write!(f, "C>{}", n),
+            ListFilter::TopicChangedWithin(n) => write!(f, "T<{}", n),
+            ListFilter::TopicChangedBefore(n) => write!(f, "T>{}", n),
+        }
+    }
+}
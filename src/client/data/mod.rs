@@ -1,11 +1,42 @@
 //! Data related to IRC functionality.
 
-pub use crate::client::data::config::Config;
+pub use crate::client::data::address_family::AddressFamily;
+pub use crate::client::data::ban::BanEntry;
+pub use crate::client::data::batch::Batch;
+pub use crate::client::data::channel_list::ChannelListEntry;
+pub use crate::client::data::chathistory::{HistoryQuery, MessageReference};
+pub use crate::client::data::config::{expand_placeholders, Config, ConfigBuilder};
+pub use crate::client::data::event::Event;
+pub use crate::client::data::ignore::mask_matches;
+pub use crate::client::data::invite::Invite;
+pub use crate::client::data::length_policy::LengthPolicy;
+pub use crate::client::data::list_filter::ListFilter;
+pub use crate::client::data::mode_change::{parse_modes, ModeChange};
+pub use crate::client::data::monitor::MonitorEvent;
 #[cfg(feature = "proxy")]
 pub use crate::client::data::proxy::ProxyType;
+pub use crate::client::data::server_info::ServerInfo;
+pub use crate::client::data::server_notice::{ServerNotice, ServerNoticeKind};
 pub use crate::client::data::user::{AccessLevel, User};
+pub use crate::client::data::who::{WhoFlags, WhoReply};
 
+pub mod address_family;
+pub mod ban;
+pub mod batch;
+pub mod channel_list;
+pub mod chathistory;
 pub mod config;
+pub(crate) mod dedup;
+pub mod event;
+pub mod ignore;
+pub mod invite;
+pub mod length_policy;
+pub mod list_filter;
+pub mod mode_change;
+pub mod monitor;
 #[cfg(feature = "proxy")]
 pub mod proxy;
+pub mod server_info;
+pub mod server_notice;
 pub mod user;
+pub mod who;
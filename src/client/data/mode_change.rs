@@ -0,0 +1,183 @@
+//! Data for the individual changes in a `MODE` command.
+use std::fmt;
+
+/// The channel modes assumed when the server did not advertise `CHANMODES`, in the same format:
+/// list modes, modes that always take a parameter, modes that only take one when set, and modes
+/// that never take one.
+pub(crate) const DEFAULT_CHANMODES: &str = "beI,k,l,imnprst";
+
+/// The channel membership modes assumed when the server did not advertise `PREFIX`.
+pub(crate) const DEFAULT_PREFIX_MODES: &str = "qaohv";
+
+/// The number of modes with a parameter per `MODE` command assumed when the server did not
+/// advertise `MODES`, as in RFC 2812.
+pub(crate) const DEFAULT_MODES_LIMIT: usize = 3;
+
+/// A single mode being set or unset by a `MODE` command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModeChange {
+    /// Whether the mode is being set (`+`) rather than unset (`-`).
+    pub adding: bool,
+    /// The mode character, e.g. `o` or `b`.
+    pub mode: char,
+    /// The parameter the mode was given, e.g. a nickname or a ban mask.
+    pub param: Option<String>,
+}
+
+impl fmt::Display for ModeChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.adding { '+' } else { '-' };
+        match self.param {
+            Some(ref param) => write!(f, "{}{} {}", sign, self.mode, param),
+            None => write!(f, "{}{}", sign, self.mode),
+        }
+    }
+}
+
+/// Parses a mode string and its parameters into the individual changes, e.g. `+o-v alice bob`
+/// into setting `o` for `alice` and unsetting `v` for `bob`.
+///
+/// Channel modes consume parameters according to the RFC defaults. To respect the `CHANMODES` and
+/// `PREFIX` tokens the server advertised, use
+/// [`ServerInfo::parse_modes`](../struct.ServerInfo.html#method.parse_modes) instead. User modes never
+/// consume parameters. Modes missing a parameter they require are still returned, without one.
+pub fn parse_modes(mode_string: &str, params: &[&str], is_channel: bool) -> Vec<ModeChange> {
+    parse_modes_with(
+        mode_string,
+        params,
+        is_channel,
+        DEFAULT_CHANMODES,
+        DEFAULT_PREFIX_MODES,
+    )
+}
+
+/// Parses modes given the server's `CHANMODES` value and membership prefix modes.
+pub(crate) fn parse_modes_with(
+    mode_string: &str,
+    params: &[&str],
+    is_channel: bool,
+    chanmodes: &str,
+    prefix_modes: &str,
+) -> Vec<ModeChange> {
+    let mut groups = chanmodes.split(',');
+    let list = groups.next().unwrap_or("");
+    let always = groups.next().unwrap_or("");
+    let when_set = groups.next().unwrap_or("");
+
+    let mut params = params.iter();
+    let mut adding = true;
+    let mut changes = Vec::new();
+    for mode in mode_string.chars() {
+        match mode {
+            '+' => adding = true,
+            '-' => adding = false,
+            _ => {
+                let takes_param = is_channel
+                    && (list.contains(mode)
+                        || always.contains(mode)
+                        || prefix_modes.contains(mode)
+                        || (adding && when_set.contains(mode)));
+                let param = if takes_param {
+                    params.next().map(|&p| p.to_owned())
+                } else {
+                    None
+                };
+                changes.push(ModeChange {
+                    adding,
+                    mode,
+                    param,
+                });
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_modes, parse_modes_with, ModeChange};
+
+    fn change(adding: bool, mode: char, param: Option<&str>) -> ModeChange {
+        ModeChange {
+            adding,
+            mode,
+            param: param.map(|p| p.to_owned()),
+        }
+    }
+
+    #[test]
+    fn op_and_devoice() {
+        assert_eq!(
+            parse_modes("+o-v", &["alice", "bob"], true),
+            vec![
+                change(true, 'o', Some("alice")),
+                change(false, 'v', Some("bob"))
+            ]
+        );
+    }
+
+    #[test]
+    fn ban() {
+        assert_eq!(
+            parse_modes("+b", &["*!*@host"], true),
+            vec![change(true, 'b', Some("*!*@host"))]
+        );
+        assert_eq!(parse_modes("+b", &[], true), vec![change(true, 'b', None)]);
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            parse_modes("+nt", &[], true),
+            vec![change(true, 'n', None), change(true, 't', None)]
+        );
+    }
+
+    #[test]
+    fn limit_only_takes_param_when_set() {
+        assert_eq!(
+            parse_modes("+l-l+k", &["10", "secret"], true),
+            vec![
+                change(true, 'l', Some("10")),
+                change(false, 'l', None),
+                change(true, 'k', Some("secret"))
+            ]
+        );
+    }
+
+    #[test]
+    fn server_chanmodes() {
+        assert_eq!(
+            parse_modes_with(
+                "+fjY",
+                &["#overflow", "3:5", "alice"],
+                true,
+                "b,f,j,n",
+                "Yov"
+            ),
+            vec![
+                change(true, 'f', Some("#overflow")),
+                change(true, 'j', Some("3:5")),
+                change(true, 'Y', Some("alice"))
+            ]
+        );
+    }
+
+    #[test]
+    fn user_modes() {
+        assert_eq!(
+            parse_modes("+iw-o", &["ignored"], false),
+            vec![
+                change(true, 'i', None),
+                change(true, 'w', None),
+                change(false, 'o', None)
+            ]
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(change(false, 'v', Some("bob")).to_string(), "-v bob");
+        assert_eq!(change(true, 'n', None).to_string(), "+n");
+    }
+}
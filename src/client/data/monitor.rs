@@ -0,0 +1,144 @@
+//! Data for tracking the online status of nicknames watched with `MONITOR` or `WATCH`.
+use crate::proto::{Command, Response};
+
+/// A change in the online status of a monitored nickname, as reported by the server in
+/// `RPL_MONONLINE` (`730`) and `RPL_MONOFFLINE` (`731`) replies, or in their `WATCH` equivalents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorEvent {
+    /// The nickname is now online.
+    Online(String),
+    /// The nickname is now offline.
+    Offline(String),
+}
+
+impl MonitorEvent {
+    /// Gets the nickname whose status changed.
+    pub fn nickname(&self) -> &str {
+        match *self {
+            MonitorEvent::Online(ref nick) | MonitorEvent::Offline(ref nick) => nick,
+        }
+    }
+
+    /// Determines whether the nickname is now online.
+    pub fn is_online(&self) -> bool {
+        matches!(*self, MonitorEvent::Online(_))
+    }
+
+    /// Gets the status changes reported by the given command, if it is an `RPL_MONONLINE` or
+    /// `RPL_MONOFFLINE` reply. Targets in `RPL_MONONLINE` may be full `nick!user@host` masks, of
+    /// which only the nickname is kept.
+    ///
+    /// The `WATCH` replies `RPL_LOGON` (`600`) and `RPL_NOWON` (`604`) are reported as the nickname
+    /// coming online, and `RPL_LOGOFF` (`601`) and `RPL_NOWOFF` (`605`) as it going offline.
+    pub fn from_command(cmd: &Command) -> Vec<MonitorEvent> {
+        match *cmd {
+            Command::Response(Response::RPL_LOGON, ref args)
+            | Command::Response(Response::RPL_NOWON, ref args) => {
+                return args
+                    .get(1)
+                    .map(|nick| MonitorEvent::Online(nick.clone()))
+                    .into_iter()
+                    .collect();
+            }
+            Command::Response(Response::RPL_LOGOFF, ref args)
+            | Command::Response(Response::RPL_NOWOFF, ref args) => {
+                return args
+                    .get(1)
+                    .map(|nick| MonitorEvent::Offline(nick.clone()))
+                    .into_iter()
+                    .collect();
+            }
+            _ => (),
+        }
+
+        let (online, targets) = match *cmd {
+            Command::Response(Response::RPL_MONONLINE, ref args) => (true, args.get(1)),
+            Command::Response(Response::RPL_MONOFFLINE, ref args) => (false, args.get(1)),
+            _ => return Vec::new(),
+        };
+
+        targets
+            .map(|targets| {
+                targets
+                    .split(',')
+                    .filter_map(|target| target.split('!').next())
+                    .filter(|nick| !nick.is_empty())
+                    .map(|nick| {
+                        if online {
+                            MonitorEvent::Online(nick.to_owned())
+                        } else {
+                            MonitorEvent::Offline(nick.to_owned())
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MonitorEvent;
+    use crate::proto::{Command, Response};
+
+    fn response(resp: Response, args: &[&str]) -> Command {
+        Command::Response(resp, args.iter().map(|&s| s.to_owned()).collect())
+    }
+
+    #[test]
+    fn online() {
+        let cmd = response(
+            Response::RPL_MONONLINE,
+            &["test", "alice!a@example.com,bob"],
+        );
+        assert_eq!(
+            MonitorEvent::from_command(&cmd),
+            vec![
+                MonitorEvent::Online("alice".to_owned()),
+                MonitorEvent::Online("bob".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offline() {
+        let cmd = response(Response::RPL_MONOFFLINE, &["test", "alice"]);
+        let events = MonitorEvent::from_command(&cmd);
+        assert_eq!(events, vec![MonitorEvent::Offline("alice".to_owned())]);
+        assert_eq!(events[0].nickname(), "alice");
+        assert!(!events[0].is_online());
+    }
+
+    #[test]
+    fn other_command() {
+        let cmd = response(Response::RPL_MONLIST, &["test", "alice"]);
+        assert!(MonitorEvent::from_command(&cmd).is_empty());
+    }
+
+    #[test]
+    fn watch() {
+        let cmd = response(
+            Response::RPL_LOGON,
+            &[
+                "test",
+                "alice",
+                "a",
+                "example.com",
+                "1546612406",
+                "logged online",
+            ],
+        );
+        assert_eq!(
+            MonitorEvent::from_command(&cmd),
+            vec![MonitorEvent::Online("alice".to_owned())]
+        );
+        let cmd = response(
+            Response::RPL_NOWOFF,
+            &["test", "bob", "*", "*", "0", "is offline"],
+        );
+        assert_eq!(
+            MonitorEvent::from_command(&cmd),
+            vec![MonitorEvent::Offline("bob".to_owned())]
+        );
+    }
+}
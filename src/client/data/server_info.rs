@@ -0,0 +1,433 @@
+//! Data for tracking information advertised by the server.
+use std::collections::HashMap;
+
+use crate::client::data::{
+    mode_change::{self, ModeChange},
+    AccessLevel,
+};
+
+/// Information advertised by the server over the course of a connection.
+///
+/// At the moment, this tracks the tokens sent in
+/// [`RPL_ISUPPORT`](https://modern.ircdocs.horse/#rplisupport-005) (`005`) replies. Servers may
+/// send several of these replies, re-advertise a token with a new value, or withdraw a token
+/// entirely by sending it prefixed with `-`, and all of these are reflected here.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerInfo {
+    /// A map of ISUPPORT token names to their (optional) values.
+    isupport: HashMap<String, Option<String>>,
+}
+
+impl ServerInfo {
+    /// Creates a new, empty `ServerInfo`.
+    pub fn new() -> ServerInfo {
+        ServerInfo::default()
+    }
+
+    /// Determines whether or not the server currently advertises the specified ISUPPORT token.
+    pub fn supports(&self, token: &str) -> bool {
+        self.isupport.contains_key(token)
+    }
+
+    /// Gets the value of the specified ISUPPORT token, if the server advertised one.
+    /// Tokens that are advertised without a value (e.g. `EXCEPTS`) return `None`.
+    pub fn isupport(&self, token: &str) -> Option<&str> {
+        self.isupport.get(token).and_then(|v| v.as_deref())
+    }
+
+    /// Determines whether or not the server advertises the given `LIST` search extension (e.g.
+    /// `U` for user count filters) in its `ELIST` token.
+    pub fn supports_elist(&self, extension: char) -> bool {
+        self.isupport("ELIST").map_or(false, |elist| {
+            elist.chars().any(|c| c.eq_ignore_ascii_case(&extension))
+        })
+    }
+
+    /// Gets the channel type prefixes advertised in the server's `CHANTYPES` token. If the token
+    /// was not advertised, this defaults to `#&+!`.
+    pub fn chantypes(&self) -> &str {
+        match self.isupport.get("CHANTYPES") {
+            Some(chantypes) => chantypes.as_deref().unwrap_or(""),
+            None => "#&+!",
+        }
+    }
+
+    /// Gets the prefixes advertised in the server's `STATUSMSG` token, with which a message can be
+    /// sent to only the members of a channel with a certain status, e.g. to `@#rust`. If the
+    /// token was not advertised, this is empty.
+    pub fn statusmsg(&self) -> &str {
+        self.isupport("STATUSMSG").unwrap_or("")
+    }
+
+    /// Gets the case mapping advertised in the server's `CASEMAPPING` token, which decides which
+    /// nicknames and channel names are equal. If the token was not advertised, this defaults to
+    /// `rfc1459`.
+    pub fn casemapping(&self) -> &str {
+        self.isupport("CASEMAPPING").unwrap_or("rfc1459")
+    }
+
+    /// Converts a nickname or channel name to lower case under the server's case mapping. With
+    /// `rfc1459`, `[]\\~` are the upper case forms of `{}|^`, and with `strict-rfc1459` the same
+    /// holds except for `~`. Any other mapping is treated as `ascii`.
+    pub fn fold_case(&self, name: &str) -> String {
+        let specials: &[(char, char)] = match self.casemapping() {
+            "rfc1459" => &[('[', '{'), (']', '}'), ('\\', '|'), ('~', '^')],
+            "strict-rfc1459" => &[('[', '{'), (']', '}'), ('\\', '|')],
+            _ => &[],
+        };
+        name.chars()
+            .map(|c| {
+                specials
+                    .iter()
+                    .find(|(upper, _)| *upper == c)
+                    .map_or(c.to_ascii_lowercase(), |(_, lower)| *lower)
+            })
+            .collect()
+    }
+
+    /// Determines whether two nicknames or channel names are equal under the server's case
+    /// mapping (see [`fold_case`](#method.fold_case)).
+    pub fn eq_ignore_case(&self, a: &str, b: &str) -> bool {
+        a.len() == b.len() && self.fold_case(a) == self.fold_case(b)
+    }
+
+    /// Gets the user mode the server uses to mark bots, as advertised in its `BOT` token.
+    pub fn bot_mode(&self) -> Option<char> {
+        self.isupport("BOT").and_then(|mode| mode.chars().next())
+    }
+
+    /// Gets the maximum length of a nickname, as advertised in the server's `NICKLEN` token.
+    pub fn nick_length(&self) -> Option<usize> {
+        self.isupport("NICKLEN").and_then(|len| len.parse().ok())
+    }
+
+    /// Gets the maximum length of a username, as advertised in the server's `USERLEN` token.
+    pub fn user_length(&self) -> Option<usize> {
+        self.isupport("USERLEN").and_then(|len| len.parse().ok())
+    }
+
+    /// Gets the maximum number of nicknames that may be watched with `MONITOR`, as advertised in
+    /// the server's `MONITOR` token. This is `None` if the server does not support `MONITOR` or
+    /// did not advertise a limit.
+    pub fn monitor_limit(&self) -> Option<usize> {
+        self.isupport("MONITOR")
+            .and_then(|limit| limit.parse().ok())
+    }
+
+    /// Gets the maximum number of channels of the given type (e.g. `#`) that may be joined at
+    /// once, as advertised in the server's `CHANLIMIT` token. Types listed together share their
+    /// limit, so `#&:50` allows joining 50 channels of either type in all.
+    pub fn chan_limit(&self, chantype: char) -> Option<usize> {
+        self.chan_limit_group(chantype).map(|(_, limit)| limit)
+    }
+
+    /// Gets the `CHANLIMIT` entry covering the given channel type, i.e. the types sharing the
+    /// limit and the limit itself.
+    pub(crate) fn chan_limit_group(&self, chantype: char) -> Option<(&str, usize)> {
+        self.isupport("CHANLIMIT")?
+            .split(',')
+            .filter_map(|entry| entry.split_once(':'))
+            .find(|(types, _)| types.contains(chantype))
+            .and_then(|(types, limit)| Some((types, limit.parse().ok()?)))
+    }
+
+    /// Gets the maximum number of modes with a parameter that may be changed in a single `MODE`
+    /// command, as advertised in the server's `MODES` token. This is `None` if the server
+    /// advertised `MODES` without a value, meaning there is no limit, and defaults to 3 when the
+    /// token was not advertised at all.
+    pub fn modes_limit(&self) -> Option<usize> {
+        match self.isupport.get("MODES") {
+            Some(limit) => limit.as_deref().and_then(|limit| limit.parse().ok()),
+            None => Some(mode_change::DEFAULT_MODES_LIMIT),
+        }
+    }
+
+    /// Gets the channel modes advertised in the server's `CHANMODES` token, as four
+    /// comma-separated groups: list modes, modes that always take a parameter, modes that only
+    /// take one when set, and modes that never take one. If the token was not advertised, this
+    /// defaults to the modes described in RFC 2811.
+    pub fn chanmodes(&self) -> &str {
+        self.isupport("CHANMODES")
+            .unwrap_or(mode_change::DEFAULT_CHANMODES)
+    }
+
+    /// Gets the channel membership modes (e.g. `ov`) advertised in the server's `PREFIX` token,
+    /// in order of decreasing rank. If the token was not advertised, this defaults to `qaohv`.
+    pub fn prefix_modes(&self) -> &str {
+        match self.isupport.get("PREFIX") {
+            Some(prefix) => prefix
+                .as_deref()
+                .and_then(|p| p.strip_prefix('('))
+                .and_then(|p| p.split(')').next())
+                .unwrap_or(""),
+            None => mode_change::DEFAULT_PREFIX_MODES,
+        }
+    }
+
+    /// Gets the rank of an access level among the membership modes in the server's `PREFIX`
+    /// token, where 0 is the highest. Levels whose mode the server does not use rank below every
+    /// mode it does, and plain members rank last.
+    pub(crate) fn access_rank(&self, level: AccessLevel) -> usize {
+        let mode = match level {
+            AccessLevel::Owner => 'q',
+            AccessLevel::Admin => 'a',
+            AccessLevel::Oper => 'o',
+            AccessLevel::HalfOp => 'h',
+            AccessLevel::Voice => 'v',
+            AccessLevel::Member => return usize::MAX,
+        };
+        let modes = self.prefix_modes();
+        modes.find(mode).unwrap_or(modes.len())
+    }
+
+    /// Parses a mode string and its parameters into the individual changes, consuming parameters
+    /// as described by the server's `CHANMODES` and `PREFIX` tokens.
+    pub fn parse_modes(
+        &self,
+        mode_string: &str,
+        params: &[&str],
+        is_channel: bool,
+    ) -> Vec<ModeChange> {
+        mode_change::parse_modes_with(
+            mode_string,
+            params,
+            is_channel,
+            self.chanmodes(),
+            self.prefix_modes(),
+        )
+    }
+
+    /// Gets the maximum number of nicknames that may be watched with `WATCH`, as advertised in the
+    /// server's `WATCH` token. This is `None` if the server does not support `WATCH` or did not
+    /// advertise a limit.
+    pub fn watch_limit(&self) -> Option<usize> {
+        self.isupport("WATCH").and_then(|limit| limit.parse().ok())
+    }
+
+    /// Updates the tracked tokens from the arguments of an `RPL_ISUPPORT` reply.
+    ///
+    /// The first argument (our nickname) and the last one (the human-readable trailing text) are
+    /// ignored.
+    pub(crate) fn handle_isupport(&mut self, args: &[String]) {
+        if args.len() < 2 {
+            return;
+        }
+
+        for token in &args[1..args.len() - 1] {
+            if let Some(name) = token.strip_prefix('-') {
+                self.isupport.remove(name);
+                continue;
+            }
+
+            let mut iter = token.splitn(2, '=');
+            let name = iter.next().unwrap_or("");
+            if name.is_empty() {
+                continue;
+            }
+            let value = iter.next().filter(|v| !v.is_empty()).map(unescape_value);
+            self.isupport.insert(name.to_owned(), value);
+        }
+    }
+}
+
+/// Unescapes the `\xHH` sequences that may appear in ISUPPORT token values.
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find("\\x") {
+        unescaped.push_str(&rest[..i]);
+        let escaped = rest
+            .get(i + 2..i + 4)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                unescaped.push(char::from(byte));
+                rest = &rest[i + 4..];
+            }
+            None => {
+                unescaped.push_str("\\x");
+                rest = &rest[i + 2..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServerInfo;
+    use crate::client::data::ModeChange;
+
+    fn isupport(tokens: &[&str]) -> Vec<String> {
+        let mut args = vec!["test".to_owned()];
+        args.extend(tokens.iter().map(|t| t.to_string()));
+        args.push("are supported by this server".to_owned());
+        args
+    }
+
+    #[test]
+    fn handle_isupport() {
+        let mut info = ServerInfo::new();
+        info.handle_isupport(&isupport(&["CHANTYPES=#&", "EXCEPTS", "NICKLEN=9"]));
+        assert_eq!(info.isupport("CHANTYPES"), Some("#&"));
+        assert!(info.supports("EXCEPTS"));
+        assert_eq!(info.isupport("EXCEPTS"), None);
+        assert_eq!(info.isupport("NICKLEN"), Some("9"));
+        assert!(!info.supports("TOPICLEN"));
+    }
+
+    #[test]
+    fn handle_isupport_empty_value() {
+        let mut info = ServerInfo::new();
+        info.handle_isupport(&isupport(&["EXCEPTS="]));
+        assert!(info.supports("EXCEPTS"));
+        assert_eq!(info.isupport("EXCEPTS"), None);
+    }
+
+    #[test]
+    fn handle_isupport_escaped_value() {
+        let mut info = ServerInfo::new();
+        info.handle_isupport(&isupport(&["NETWORK=Example\\x20Net", "BAD=\\xZZ"]));
+        assert_eq!(info.isupport("NETWORK"), Some("Example Net"));
+        assert_eq!(info.isupport("BAD"), Some("\\xZZ"));
+    }
+
+    #[test]
+    fn supports_elist() {
+        let mut info = ServerInfo::new();
+        assert!(!info.supports_elist('U'));
+        info.handle_isupport(&isupport(&["ELIST=cmntu"]));
+        assert!(info.supports_elist('U'));
+        assert!(info.supports_elist('C'));
+        assert!(info.supports_elist('n'));
+        assert!(!info.supports_elist('X'));
+    }
+
+    #[test]
+    fn chantypes() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.chantypes(), "#&+!");
+        info.handle_isupport(&isupport(&["CHANTYPES=#"]));
+        assert_eq!(info.chantypes(), "#");
+        info.handle_isupport(&isupport(&["CHANTYPES="]));
+        assert_eq!(info.chantypes(), "");
+    }
+
+    #[test]
+    fn eq_ignore_case() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.casemapping(), "rfc1459");
+        assert!(info.eq_ignore_case("Nick[a]", "nick{A}"));
+        assert_eq!(info.fold_case("Nick[~]"), "nick{^}");
+        assert!(info.eq_ignore_case("a~", "A^"));
+        assert!(!info.eq_ignore_case("nick", "nick2"));
+        info.handle_isupport(&isupport(&["CASEMAPPING=strict-rfc1459"]));
+        assert!(info.eq_ignore_case("Nick\\", "nick|"));
+        assert!(!info.eq_ignore_case("a~", "a^"));
+        info.handle_isupport(&isupport(&["CASEMAPPING=ascii"]));
+        assert!(info.eq_ignore_case("NICK", "nick"));
+        assert!(!info.eq_ignore_case("[", "{"));
+    }
+
+    #[test]
+    fn bot_mode() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.bot_mode(), None);
+        info.handle_isupport(&isupport(&["BOT=B"]));
+        assert_eq!(info.bot_mode(), Some('B'));
+    }
+
+    #[test]
+    fn monitor_limit() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.monitor_limit(), None);
+        info.handle_isupport(&isupport(&["MONITOR"]));
+        assert!(info.supports("MONITOR"));
+        assert_eq!(info.monitor_limit(), None);
+        info.handle_isupport(&isupport(&["MONITOR=100"]));
+        assert_eq!(info.monitor_limit(), Some(100));
+    }
+
+    #[test]
+    fn name_lengths() {
+        let mut info = ServerInfo::new();
+        assert_eq!((info.nick_length(), info.user_length()), (None, None));
+        info.handle_isupport(&isupport(&["NICKLEN=9", "USERLEN=10"]));
+        assert_eq!(
+            (info.nick_length(), info.user_length()),
+            (Some(9), Some(10))
+        );
+    }
+
+    #[test]
+    fn chan_limit() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.chan_limit('#'), None);
+        info.handle_isupport(&isupport(&["CHANLIMIT=#&:50,+:"]));
+        assert_eq!(info.chan_limit('#'), Some(50));
+        assert_eq!(info.chan_limit_group('&'), Some(("#&", 50)));
+        assert_eq!(info.chan_limit('+'), None);
+        assert_eq!(info.chan_limit('!'), None);
+    }
+
+    #[test]
+    fn access_rank() {
+        use crate::client::data::AccessLevel;
+
+        let mut info = ServerInfo::new();
+        assert_eq!(info.access_rank(AccessLevel::Owner), 0);
+        assert_eq!(info.access_rank(AccessLevel::Voice), 4);
+        info.handle_isupport(&isupport(&["PREFIX=(ov)@+"]));
+        assert_eq!(info.access_rank(AccessLevel::Oper), 0);
+        assert_eq!(info.access_rank(AccessLevel::Voice), 1);
+        assert_eq!(info.access_rank(AccessLevel::Owner), 2);
+        assert_eq!(info.access_rank(AccessLevel::Member), usize::MAX);
+    }
+
+    #[test]
+    fn modes_limit() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.modes_limit(), Some(3));
+        info.handle_isupport(&isupport(&["MODES=4"]));
+        assert_eq!(info.modes_limit(), Some(4));
+        info.handle_isupport(&isupport(&["MODES"]));
+        assert_eq!(info.modes_limit(), None);
+    }
+
+    #[test]
+    fn watch_limit() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.watch_limit(), None);
+        info.handle_isupport(&isupport(&["WATCH=128"]));
+        assert_eq!(info.watch_limit(), Some(128));
+    }
+
+    #[test]
+    fn parse_modes() {
+        let mut info = ServerInfo::new();
+        assert_eq!(info.prefix_modes(), "qaohv");
+        info.handle_isupport(&isupport(&["CHANMODES=b,k,fl,nt", "PREFIX=(Yov)!@+"]));
+        assert_eq!(info.prefix_modes(), "Yov");
+        assert_eq!(
+            info.parse_modes("+Yf-f", &["alice", "#overflow"], true),
+            vec![
+                ModeChange {
+                    adding: true,
+                    mode: 'Y',
+                    param: Some("alice".to_owned()),
+                },
+                ModeChange {
+                    adding: true,
+                    mode: 'f',
+                    param: Some("#overflow".to_owned()),
+                },
+                ModeChange {
+                    adding: false,
+                    mode: 'f',
+                    param: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Data for server notices sent to operators.
+use crate::proto::{Command, Message, Prefix};
+
+/// A notice sent by the server itself, typically to operators who have subscribed to server
+/// notices with a snomask (see `Client::send_snomask`).
+///
+/// There is no standard format for server notices, so the `kind` is determined on a best-effort
+/// basis from the text of the common notices sent by popular IRCds. The full text is always
+/// available for anything more specific.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerNotice {
+    /// The name of the server that sent the notice.
+    pub server: String,
+    /// The category of the notice, if it could be determined.
+    pub kind: ServerNoticeKind,
+    /// The text of the notice, without the leading `*** Notice --` (or `***`) marker.
+    pub text: String,
+}
+
+/// The category of a [`ServerNotice`](struct.ServerNotice.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServerNoticeKind {
+    /// A client connected to the server.
+    ClientConnect,
+    /// A client disconnected from the server.
+    ClientExit,
+    /// A K-Line (or similar ban) was added, removed or applied.
+    KLine,
+    /// A user became an IRC operator.
+    Oper,
+    /// A user changed their nickname.
+    NickChange,
+    /// Any other server notice.
+    Other,
+}
+
+impl ServerNotice {
+    /// Parses a server notice from the given message. This returns `None` if the message is not
+    /// a `NOTICE` from a server using the conventional `***` marker.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc;
+    /// use irc::client::data::{ServerNotice, ServerNoticeKind};
+    /// use irc::client::prelude::Message;
+    ///
+    /// # fn main() {
+    /// let msg: Message = ":irc.example.com NOTICE * :*** Notice -- Client connecting: \
+    ///                     ada (ada@example.com) [192.0.2.1] {users} [Ada]".into();
+    /// let notice = ServerNotice::from_message(&msg).unwrap();
+    /// assert_eq!(notice.kind, ServerNoticeKind::ClientConnect);
+    /// # }
+    /// ```
+    pub fn from_message(msg: &Message) -> Option<ServerNotice> {
+        let server = match msg.prefix {
+            Some(Prefix::ServerName(ref server)) => server,
+            _ => return None,
+        };
+        let text = match msg.command {
+            Command::NOTICE(_, ref text) => text.strip_prefix("***")?.trim_start(),
+            _ => return None,
+        };
+        let text = text.strip_prefix("Notice --").unwrap_or(text).trim_start();
+
+        Some(ServerNotice {
+            server: server.clone(),
+            kind: ServerNoticeKind::classify(text),
+            text: text.to_owned(),
+        })
+    }
+}
+
+impl ServerNoticeKind {
+    /// Determines the kind of a server notice from its text.
+    fn classify(text: &str) -> ServerNoticeKind {
+        let text = text.to_ascii_lowercase();
+        if text.contains("client connecting") {
+            ServerNoticeKind::ClientConnect
+        } else if text.contains("client exiting") {
+            ServerNoticeKind::ClientExit
+        } else if text.contains("k-line") || text.contains("kline") {
+            ServerNoticeKind::KLine
+        } else if text.contains("is now an operator") || text.contains("is now an irc operator") {
+            ServerNoticeKind::Oper
+        } else if text.contains("nick change") || text.contains("changed nickname") {
+            ServerNoticeKind::NickChange
+        } else {
+            ServerNoticeKind::Other
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ServerNotice, ServerNoticeKind};
+    use crate::proto::Message;
+
+    fn parse(line: &str) -> Option<ServerNotice> {
+        ServerNotice::from_message(&line.parse::<Message>().unwrap())
+    }
+
+    #[test]
+    fn client_connecting() {
+        let notice = parse(
+            ":irc.test.net NOTICE * :*** Notice -- Client connecting: test (test@test.net) \
+             [192.0.2.1] {users} [Test User]\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            notice,
+            ServerNotice {
+                server: "irc.test.net".to_owned(),
+                kind: ServerNoticeKind::ClientConnect,
+                text: "Client connecting: test (test@test.net) [192.0.2.1] {users} [Test User]"
+                    .to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn kline() {
+        let notice = parse(
+            ":irc.test.net NOTICE oper :*** Notice -- oper!oper@test.net{irc.test.net} added \
+             global 1440 min. K-Line for [*@192.0.2.1] [spam]\r\n",
+        )
+        .unwrap();
+        assert_eq!(notice.kind, ServerNoticeKind::KLine);
+        assert_eq!(notice.server, "irc.test.net");
+    }
+
+    #[test]
+    fn without_notice_marker() {
+        let notice =
+            parse(":irc.test.net NOTICE oper :*** Client exiting: test (Quit)\r\n").unwrap();
+        assert_eq!(notice.kind, ServerNoticeKind::ClientExit);
+        assert_eq!(notice.text, "Client exiting: test (Quit)");
+    }
+
+    #[test]
+    fn not_a_server_notice() {
+        assert!(parse(":test!test@test.net NOTICE oper :*** Client connecting\r\n").is_none());
+        assert!(parse(":irc.test.net NOTICE oper :Hello there\r\n").is_none());
+        assert!(parse(":irc.test.net PRIVMSG oper :*** Client connecting\r\n").is_none());
+    }
+}
@@ -4,7 +4,7 @@ use std::cmp::Ordering;
 use std::cmp::Ordering::{Equal, Greater, Less};
 use std::str::FromStr;
 
-use crate::proto::{ChannelMode, Mode};
+use crate::proto::{ChannelMode, Mode, Prefix};
 
 /// IRC User data.
 #[derive(Clone, Debug)]
@@ -19,17 +19,27 @@ pub struct User {
     highest_access_level: AccessLevel,
     /// All of the user's current access levels.
     access_levels: Vec<AccessLevel>,
+    /// Whether the user is marked as a bot.
+    is_bot: bool,
+    /// The user's away message, if they are known to be away.
+    away_message: Option<String>,
+    /// The account the user is logged into, if known.
+    account: Option<String>,
 }
 
 impl User {
-    /// Creates a new User.
+    /// Creates a new User from a nickname with any access level prefixes, optionally followed by
+    /// the username and hostname as in a message prefix, e.g. `@nick!user@host`. The parts are
+    /// parsed as by `Prefix`, so a username is kept even without a hostname, as in `nick!user`.
     pub fn new(string: &str) -> User {
         let ranks: Vec<_> = AccessLevelIterator::new(string).collect();
-        let mut state = &string[ranks.len()..];
-        let nickname = state.find('!').map_or(state, |i| &state[..i]).to_owned();
-        state = state.find('!').map_or("", |i| &state[i + 1..]);
-        let username = state.find('@').map(|i| state[..i].to_owned());
-        let hostname = state.find('@').map(|i| state[i + 1..].to_owned());
+        let (nickname, username, hostname) = match Prefix::new_from_str(&string[ranks.len()..]) {
+            Prefix::Nickname(nick, user, host) => {
+                let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+                (nick, non_empty(user), non_empty(host))
+            }
+            Prefix::ServerName(name) => (name, None, None),
+        };
         User {
             nickname,
             username,
@@ -48,6 +58,9 @@ impl User {
                 }
                 max
             },
+            is_bot: false,
+            away_message: None,
+            account: None,
         }
     }
 
@@ -56,18 +69,75 @@ impl User {
         &self.nickname
     }
 
+    /// Changes the nickname of the user, keeping its other details and access levels intact.
+    #[cfg(not(feature = "nochanlists"))]
+    pub(crate) fn set_nickname(&mut self, nickname: &str) {
+        self.nickname = nickname.to_owned();
+    }
+
     /// Gets the username of the user, if it's known.
-    /// This requires the IRCv3.2 extension `userhost-in-name`.
+    /// This requires the IRCv3.2 extension `userhost-in-name`, and is kept up to date with the
+    /// IRCv3.2 extension `chghost`.
     pub fn get_username(&self) -> Option<&str> {
         self.username.as_ref().map(|s| &s[..])
     }
 
     /// Gets the hostname of the user, if it's known.
-    /// This requires the IRCv3.2 extension `userhost-in-name`.
+    /// This requires the IRCv3.2 extension `userhost-in-name`, and is kept up to date with the
+    /// IRCv3.2 extension `chghost`.
     pub fn get_hostname(&self) -> Option<&str> {
         self.hostname.as_ref().map(|s| &s[..])
     }
 
+    /// Changes the username and hostname of the user, e.g. after a `CHGHOST`.
+    #[cfg(not(feature = "nochanlists"))]
+    pub(crate) fn set_host(&mut self, username: &str, hostname: &str) {
+        self.username = Some(username.to_owned());
+        self.hostname = Some(hostname.to_owned());
+    }
+
+    /// Determines whether the user is marked as a bot. This is learned from the bot mode advertised
+    /// in the server's `BOT` ISUPPORT token, either through the flags of a `WHO` reply or through
+    /// the `bot` message tag.
+    pub fn is_bot(&self) -> bool {
+        self.is_bot
+    }
+
+    /// Marks the user as a bot or not.
+    #[cfg(not(feature = "nochanlists"))]
+    pub(crate) fn set_bot(&mut self, is_bot: bool) {
+        self.is_bot = is_bot;
+    }
+
+    /// Determines whether the user is known to be away. This is learned from `RPL_AWAY` replies
+    /// and, with the IRCv3.1 extension `away-notify`, from `AWAY` messages.
+    pub fn is_away(&self) -> bool {
+        self.away_message.is_some()
+    }
+
+    /// Gets the user's away message, if they are known to be away.
+    pub fn away_message(&self) -> Option<&str> {
+        self.away_message.as_deref()
+    }
+
+    /// Marks the user as away with the given message, or as back if there is none.
+    #[cfg(not(feature = "nochanlists"))]
+    pub(crate) fn set_away(&mut self, message: Option<&str>) {
+        self.away_message = message.map(|m| m.to_owned());
+    }
+
+    /// Gets the account the user is logged into, if known. This is learned from the IRCv3.1
+    /// extensions `extended-join` and `account-notify`, and from WHOX replies.
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// Records the account the user is logged into, or that they are logged out if there is none.
+    #[cfg(not(feature = "nochanlists"))]
+    pub(crate) fn set_account(&mut self, account: Option<&str>) {
+        self.account = account.map(|a| a.to_owned());
+    }
+
     /// Gets the user's highest access level.
     pub fn highest_access_level(&self) -> AccessLevel {
         self.highest_access_level
@@ -253,6 +323,9 @@ mod test {
             hostname: None,
             highest_access_level: Owner,
             access_levels: vec![Owner, Member],
+            is_bot: false,
+            away_message: None,
+            account: None,
         };
         assert_eq!(user, exp);
         assert_eq!(user.highest_access_level, exp.highest_access_level);
@@ -268,6 +341,9 @@ mod test {
             hostname: None,
             highest_access_level: Owner,
             access_levels: vec![Owner, Admin, Voice, Member],
+            is_bot: false,
+            away_message: None,
+            account: None,
         };
         assert_eq!(user, exp);
         assert_eq!(user.highest_access_level, exp.highest_access_level);
@@ -288,6 +364,22 @@ mod test {
         assert_eq!(user.get_username(), None);
     }
 
+    #[test]
+    fn get_username_without_hostname() {
+        let user = User::new("user!username");
+        assert_eq!(user.get_nickname(), "user");
+        assert_eq!(user.get_username(), Some("username"));
+        assert_eq!(user.get_hostname(), None);
+    }
+
+    #[test]
+    fn get_host_without_username() {
+        let user = User::new("@user@hostname");
+        assert_eq!(user.get_nickname(), "user");
+        assert_eq!(user.get_username(), None);
+        assert_eq!(user.get_hostname(), Some("hostname"));
+    }
+
     #[test]
     fn get_hostname() {
         let user = User::new("user!username@hostname");
@@ -0,0 +1,221 @@
+//! Data for the replies to a `WHO` query.
+use crate::client::data::AccessLevel;
+
+/// The fields requested in WHOX queries: token, channel, username, host, server, nickname, flags,
+/// hop count, account and real name. Servers always send the selected fields in this order.
+pub(crate) const WHOX_FIELDS: &str = "tcuhsnfdar";
+
+/// A user matching a `WHO` query, as described by an `RPL_WHOREPLY` (`352`) reply, or by an
+/// `RPL_WHOSPCRPL` (`354`) reply if the server supports WHOX.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WhoReply {
+    /// A channel the user is in, if the server picked one.
+    pub channel: Option<String>,
+    /// The user's username.
+    pub username: String,
+    /// The user's hostname.
+    pub hostname: String,
+    /// The server the user is connected to.
+    pub server: String,
+    /// The user's nickname.
+    pub nickname: String,
+    /// The user's flags, e.g. `H` (here) or `G` (gone), followed by `*` for IRC operators and
+    /// their prefixes in the channel.
+    pub flags: String,
+    /// The number of hops between us and the user's server, if the server disclosed it.
+    pub hopcount: Option<u32>,
+    /// The user's real name.
+    pub realname: String,
+    /// The account the user is logged into. This is only available through WHOX.
+    pub account: Option<String>,
+}
+
+impl WhoReply {
+    /// Determines whether the user is marked as away.
+    pub fn is_away(&self) -> bool {
+        self.flags.starts_with('G')
+    }
+
+    /// Parses the user's flags.
+    pub fn parsed_flags(&self) -> WhoFlags {
+        WhoFlags::parse(&self.flags)
+    }
+
+    /// Parses the arguments of an `RPL_WHOREPLY` reply.
+    pub(crate) fn from_whoreply(args: &[String]) -> Option<WhoReply> {
+        // <client> <channel> <username> <host> <server> <nick> <flags> :<hopcount> <realname>
+        if args.len() < 8 {
+            return None;
+        }
+        let mut trailing = args[7].splitn(2, ' ');
+        let hopcount = trailing.next().and_then(|hops| hops.parse().ok());
+        Some(WhoReply {
+            channel: channel(&args[1]),
+            username: args[2].clone(),
+            hostname: args[3].clone(),
+            server: args[4].clone(),
+            nickname: args[5].clone(),
+            flags: args[6].clone(),
+            hopcount,
+            realname: trailing.next().unwrap_or("").to_owned(),
+            account: None,
+        })
+    }
+
+    /// Parses the arguments of an `RPL_WHOSPCRPL` reply to a query for `WHOX_FIELDS`, returning
+    /// the query token along with the reply.
+    pub(crate) fn from_whox(args: &[String]) -> Option<(&str, WhoReply)> {
+        // <client> <token> <channel> <username> <host> <server> <nick> <flags> <hopcount>
+        // <account> :<realname>
+        if args.len() < 11 {
+            return None;
+        }
+        let reply = WhoReply {
+            channel: channel(&args[2]),
+            username: args[3].clone(),
+            hostname: args[4].clone(),
+            server: args[5].clone(),
+            nickname: args[6].clone(),
+            flags: args[7].clone(),
+            hopcount: args[8].parse().ok(),
+            account: Some(&args[9]).filter(|a| *a != "0").cloned(),
+            realname: args[10].clone(),
+        };
+        Some((&args[1], reply))
+    }
+}
+
+/// The flags of a user in a `WHO` reply, e.g. `H@` or `G*`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WhoFlags {
+    /// Whether the user is marked as away (`G`) rather than here (`H`).
+    pub away: bool,
+    /// Whether the user is an IRC operator (`*`).
+    pub oper: bool,
+    /// The user's highest access level in the channel, or `Member` if they have none.
+    pub access_level: AccessLevel,
+    /// Whether the user is marked as a bot (`B`).
+    pub bot: bool,
+}
+
+impl WhoFlags {
+    /// Parses the flags, ignoring any that aren't known.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate irc;
+    /// use irc::client::data::{AccessLevel, WhoFlags};
+    /// # fn main() {
+    /// let flags = WhoFlags::parse("G*@");
+    /// assert!(flags.away && flags.oper && !flags.bot);
+    /// assert_eq!(flags.access_level, AccessLevel::Oper);
+    /// # }
+    /// ```
+    pub fn parse(flags: &str) -> WhoFlags {
+        let mut parsed = WhoFlags {
+            away: false,
+            oper: false,
+            access_level: AccessLevel::Member,
+            bot: false,
+        };
+        for (i, c) in flags.char_indices() {
+            match c {
+                'G' => parsed.away = true,
+                '*' => parsed.oper = true,
+                'B' => parsed.bot = true,
+                _ => {
+                    // With multi-prefix, a user may have several prefixes.
+                    if let Ok(level) = flags[i..].parse::<AccessLevel>() {
+                        if level > parsed.access_level {
+                            parsed.access_level = level;
+                        }
+                    }
+                }
+            }
+        }
+        parsed
+    }
+}
+
+/// Servers send `*` in place of the channel when they did not pick one.
+fn channel(chan: &str) -> Option<String> {
+    Some(chan).filter(|c| *c != "*").map(|c| c.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{WhoFlags, WhoReply};
+    use crate::client::data::AccessLevel;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn from_whoreply() {
+        let reply = WhoReply::from_whoreply(&args(&[
+            "test",
+            "#test",
+            "~alice",
+            "example.com",
+            "irc.test.net",
+            "alice",
+            "G@",
+            "0 Alice Liddell",
+        ]))
+        .unwrap();
+        assert_eq!(reply.channel.as_deref(), Some("#test"));
+        assert_eq!(reply.nickname, "alice");
+        assert_eq!(reply.hopcount, Some(0));
+        assert_eq!(reply.realname, "Alice Liddell");
+        assert_eq!(reply.account, None);
+        assert!(reply.is_away());
+    }
+
+    #[test]
+    fn from_whox() {
+        let args = args(&[
+            "test",
+            "42",
+            "*",
+            "~bob",
+            "example.com",
+            "irc.test.net",
+            "bob",
+            "H",
+            "3",
+            "0",
+            "Bob",
+        ]);
+        let (token, reply) = WhoReply::from_whox(&args).unwrap();
+        assert_eq!(token, "42");
+        assert_eq!(reply.channel, None);
+        assert_eq!(reply.hopcount, Some(3));
+        assert_eq!(reply.account, None);
+        assert_eq!(reply.realname, "Bob");
+        assert!(!reply.is_away());
+    }
+
+    #[test]
+    fn flags() {
+        let flags = WhoFlags::parse("H@");
+        assert!(!flags.away && !flags.oper && !flags.bot);
+        assert_eq!(flags.access_level, AccessLevel::Oper);
+
+        let flags = WhoFlags::parse("G*");
+        assert!(flags.away && flags.oper && !flags.bot);
+        assert_eq!(flags.access_level, AccessLevel::Member);
+
+        let flags = WhoFlags::parse("H+B");
+        assert!(!flags.away && !flags.oper && flags.bot);
+        assert_eq!(flags.access_level, AccessLevel::Voice);
+
+        assert_eq!(WhoFlags::parse("H+@").access_level, AccessLevel::Oper);
+    }
+
+    #[test]
+    fn too_few_args() {
+        assert_eq!(WhoReply::from_whoreply(&args(&["test", "#test"])), None);
+        assert_eq!(WhoReply::from_whox(&args(&["test", "42"])), None);
+    }
+}
@@ -0,0 +1,580 @@
+//! Support for DCC SEND file transfers.
+//!
+//! DCC transfers are negotiated over IRC with CTCP messages, but the file itself travels over a
+//! direct TCP connection between the two clients. The recipient acknowledges every chunk it
+//! receives with the total number of bytes received so far, as a 4-byte big-endian integer.
+//!
+//! # Receiving a file
+//! ```no_run
+//! # use irc::client::prelude::*;
+//! use futures::prelude::*;
+//! use irc::client::dcc::DccMessage;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> irc::error::Result<()> {
+//! # let mut client = Client::new("config.toml").await?;
+//! let mut stream = client.stream()?;
+//! while let Some(message) = stream.next().await.transpose()? {
+//!     if let Some(DccMessage::Send(offer)) = DccMessage::from_message(&message) {
+//!         let mut contents = Vec::new();
+//!         offer.download(&mut contents).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use futures_util::future;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    error,
+    proto::{Command, Message},
+};
+
+/// The size of the chunks that files are read and sent in.
+const CHUNK_SIZE: usize = 8192;
+
+/// A DCC request, as carried in the body of a CTCP message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DccMessage {
+    /// An offer to send a file (`DCC SEND`).
+    Send(DccSend),
+    /// A request to resume an offered file from a given position (`DCC RESUME`).
+    Resume(DccResume),
+    /// The acceptance of a request to resume (`DCC ACCEPT`).
+    Accept(DccResume),
+}
+
+impl DccMessage {
+    /// Parses the body of a CTCP message, with or without its `\x01` delimiters.
+    pub fn parse(ctcp: &str) -> Option<DccMessage> {
+        let ctcp = ctcp.trim_matches('\u{001}');
+        let rest = ctcp.strip_prefix("DCC ")?;
+        let (kind, rest) = rest.split_once(' ')?;
+        let (filename, rest) = split_filename(rest)?;
+        let mut args = rest.split(' ').filter(|arg| !arg.is_empty());
+
+        match kind {
+            "SEND" => {
+                let ip = parse_ip(args.next()?)?;
+                let port = args.next()?.parse().ok()?;
+                let size = match args.next() {
+                    Some(size) => Some(size.parse().ok()?),
+                    None => None,
+                };
+                Some(DccMessage::Send(DccSend {
+                    filename,
+                    addr: SocketAddr::new(ip, port),
+                    size,
+                }))
+            }
+            "RESUME" | "ACCEPT" => {
+                let resume = DccResume {
+                    filename,
+                    port: args.next()?.parse().ok()?,
+                    position: args.next()?.parse().ok()?,
+                };
+                if kind == "RESUME" {
+                    Some(DccMessage::Resume(resume))
+                } else {
+                    Some(DccMessage::Accept(resume))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses the DCC request carried by a CTCP `PRIVMSG`, if there is one.
+    pub fn from_message(msg: &Message) -> Option<DccMessage> {
+        match msg.command {
+            Command::PRIVMSG(_, ref body) if body.starts_with('\u{001}') => DccMessage::parse(body),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DccMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DccMessage::Send(send) => {
+                let ip = match send.addr.ip() {
+                    IpAddr::V4(ip) => u32::from(ip).to_string(),
+                    IpAddr::V6(ip) => ip.to_string(),
+                };
+                write!(
+                    f,
+                    "DCC SEND {} {} {}",
+                    Filename(&send.filename),
+                    ip,
+                    send.addr.port()
+                )?;
+                if let Some(size) = send.size {
+                    write!(f, " {}", size)?;
+                }
+                Ok(())
+            }
+            DccMessage::Resume(resume) | DccMessage::Accept(resume) => {
+                let kind = match self {
+                    DccMessage::Resume(_) => "RESUME",
+                    _ => "ACCEPT",
+                };
+                write!(
+                    f,
+                    "DCC {} {} {} {}",
+                    kind,
+                    Filename(&resume.filename),
+                    resume.port,
+                    resume.position
+                )
+            }
+        }
+    }
+}
+
+/// An offer to send a file, as made with `DCC SEND`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DccSend {
+    /// The name of the file being offered.
+    pub filename: String,
+    /// The address to connect to in order to receive the file.
+    pub addr: SocketAddr,
+    /// The size of the file in bytes, if it was advertised.
+    pub size: Option<u64>,
+}
+
+impl DccSend {
+    /// Creates the `DCC RESUME` request asking the sender to start from `position` instead of the
+    /// beginning of the file. Once the sender replies with a matching `DCC ACCEPT`, use
+    /// `download_from` with the same position.
+    pub fn resume(&self, position: u64) -> DccMessage {
+        DccMessage::Resume(DccResume {
+            filename: self.filename.clone(),
+            port: self.addr.port(),
+            position,
+        })
+    }
+
+    /// Downloads the whole file into `writer`, acknowledging every chunk received. Resolves to
+    /// the number of bytes received.
+    ///
+    /// If the offer advertised a size, the transfer fails with `Error::DccSizeMismatch` unless
+    /// exactly that many bytes are received.
+    pub async fn download<W>(&self, writer: W) -> error::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.download_from(0, writer).await
+    }
+
+    /// Downloads the rest of the file, starting at `position`, into `writer`. This is used to
+    /// resume an interrupted transfer after the sender has accepted a `DCC RESUME`. Resolves to
+    /// the number of bytes received, not counting those before `position`.
+    pub async fn download_from<W>(&self, position: u64, mut writer: W) -> error::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut stream = TcpStream::connect(self.addr).await?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut total = position;
+
+        loop {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+
+            total += read as u64;
+            if let Some(size) = self.size.filter(|size| total > *size) {
+                return Err(error::Error::DccSizeMismatch {
+                    expected: size,
+                    received: total,
+                });
+            }
+
+            writer.write_all(&buf[..read]).await?;
+            // Acknowledgements only have room for the lower 32 bits of the position.
+            stream.write_all(&(total as u32).to_be_bytes()).await?;
+
+            if Some(total) == self.size {
+                break;
+            }
+        }
+        writer.flush().await?;
+
+        match self.size {
+            Some(size) if size != total => Err(error::Error::DccSizeMismatch {
+                expected: size,
+                received: total,
+            }),
+            _ => Ok(total - position),
+        }
+    }
+}
+
+/// A request to resume a transfer from a given position, or the acceptance of one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DccResume {
+    /// The name of the file being transferred.
+    pub filename: String,
+    /// The port of the original offer, which identifies the transfer.
+    pub port: u16,
+    /// The position in bytes to resume from.
+    pub position: u64,
+}
+
+/// A file offered to another user with `DCC SEND`, waiting for them to connect.
+#[derive(Debug)]
+pub struct DccFileOffer {
+    listener: TcpListener,
+    offer: DccSend,
+    position: u64,
+}
+
+impl DccFileOffer {
+    /// Listens on `bind` for the recipient of a file. The offer advertises `advertised` as the
+    /// address to connect to, along with the port that was actually bound, so that clients behind
+    /// NAT can advertise their public address.
+    pub async fn bind(
+        bind: SocketAddr,
+        advertised: IpAddr,
+        filename: &str,
+        size: u64,
+    ) -> error::Result<DccFileOffer> {
+        let listener = TcpListener::bind(bind).await?;
+        let port = listener.local_addr()?.port();
+        Ok(DccFileOffer {
+            listener,
+            offer: DccSend {
+                filename: filename.to_owned(),
+                addr: SocketAddr::new(advertised, port),
+                size: Some(size),
+            },
+            position: 0,
+        })
+    }
+
+    /// Gets the `DCC SEND` offer to send to the recipient, e.g. with `Client::send_dcc`.
+    pub fn offer(&self) -> DccMessage {
+        DccMessage::Send(self.offer.clone())
+    }
+
+    /// Gets the position in the file that the transfer will start from.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Handles a `DCC RESUME` request for this offer, returning the `DCC ACCEPT` to send back. The
+    /// transfer will then start from the requested position. Requests for other offers or past the
+    /// end of the file are ignored.
+    pub fn accept_resume(&mut self, resume: &DccResume) -> Option<DccMessage> {
+        let size = self.offer.size.unwrap_or(0);
+        if resume.port != self.offer.addr.port() || resume.position > size {
+            return None;
+        }
+
+        self.position = resume.position;
+        Some(DccMessage::Accept(resume.clone()))
+    }
+
+    /// Waits for the recipient to connect and sends them the file read from `reader`, which must
+    /// already be positioned at `position()`. Resolves to the number of bytes sent once the
+    /// recipient has acknowledged all of them.
+    pub async fn send<R>(self, mut reader: R) -> error::Result<u64>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let size = self.offer.size.unwrap_or(0);
+        let (mut stream, _) = self.listener.accept().await?;
+        let (mut incoming, mut outgoing) = stream.split();
+
+        let send = async {
+            let mut buf = vec![0; CHUNK_SIZE];
+            let mut sent = self.position;
+            while sent < size {
+                let len = CHUNK_SIZE.min((size - sent) as usize);
+                let read = reader.read(&mut buf[..len]).await?;
+                if read == 0 {
+                    return Err(error::Error::DccSizeMismatch {
+                        expected: size,
+                        received: sent,
+                    });
+                }
+                outgoing.write_all(&buf[..read]).await?;
+                sent += read as u64;
+            }
+            outgoing.flush().await?;
+            Ok::<_, error::Error>(())
+        };
+
+        let acks = async {
+            let mut acked = self.position;
+            let mut ack = [0; 4];
+            while acked < size {
+                if incoming.read_exact(&mut ack).await.is_err() {
+                    return Err(error::Error::DccSizeMismatch {
+                        expected: size,
+                        received: acked,
+                    });
+                }
+                // Acknowledgements wrap around every 4 GiB, so only replace the lower 32 bits.
+                let low = u64::from(u32::from_be_bytes(ack));
+                let mut next = (acked & !0xFFFF_FFFF) | low;
+                if next < acked {
+                    next += 1 << 32;
+                }
+                acked = next;
+            }
+            Ok(())
+        };
+
+        future::try_join(Box::pin(send), Box::pin(acks)).await?;
+        Ok(size - self.position)
+    }
+}
+
+/// Splits a possibly quoted filename off the front of `rest`.
+fn split_filename(rest: &str) -> Option<(String, &str)> {
+    match rest.strip_prefix('"') {
+        Some(quoted) => {
+            let (filename, rest) = quoted.split_once('"')?;
+            Some((filename.to_owned(), rest.trim_start()))
+        }
+        None => {
+            let (filename, rest) = rest.split_once(' ')?;
+            Some((filename.to_owned(), rest))
+        }
+    }
+}
+
+/// Parses an address, which is sent as a decimal integer for IPv4 and in text form for IPv6.
+fn parse_ip(ip: &str) -> Option<IpAddr> {
+    match ip.parse::<u32>() {
+        Ok(ip) => Some(IpAddr::V4(Ipv4Addr::from(ip))),
+        Err(_) => ip.parse().ok(),
+    }
+}
+
+/// Formats a filename, quoting it if it contains spaces.
+struct Filename<'a>(&'a str);
+
+impl fmt::Display for Filename<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.contains(' ') {
+            write!(f, "\"{}\"", self.0)
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use anyhow::Result;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{DccFileOffer, DccMessage, DccResume, DccSend};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn send(filename: &str, port: u16, size: Option<u64>) -> DccMessage {
+        DccMessage::Send(DccSend {
+            filename: filename.to_owned(),
+            addr: SocketAddr::new(LOCALHOST, port),
+            size,
+        })
+    }
+
+    #[test]
+    fn parse_send() {
+        assert_eq!(
+            DccMessage::parse("\u{001}DCC SEND file.txt 2130706433 5000 1024\u{001}"),
+            Some(send("file.txt", 5000, Some(1024)))
+        );
+        assert_eq!(
+            DccMessage::parse("DCC SEND file.txt 2130706433 5000"),
+            Some(send("file.txt", 5000, None))
+        );
+    }
+
+    #[test]
+    fn parse_send_quoted() {
+        assert_eq!(
+            DccMessage::parse("DCC SEND \"my file.txt\" 2130706433 5000 1024"),
+            Some(send("my file.txt", 5000, Some(1024)))
+        );
+    }
+
+    #[test]
+    fn parse_send_ipv6() {
+        let expected = DccMessage::Send(DccSend {
+            filename: "file.txt".to_owned(),
+            addr: "[::1]:5000".parse().unwrap(),
+            size: Some(1),
+        });
+        assert_eq!(
+            DccMessage::parse("DCC SEND file.txt ::1 5000 1"),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(DccMessage::parse("DCC SEND file.txt"), None);
+        assert_eq!(DccMessage::parse("DCC SEND file.txt nowhere 5000"), None);
+        assert_eq!(DccMessage::parse("DCC CHAT chat 2130706433 5000"), None);
+        assert_eq!(DccMessage::parse("VERSION"), None);
+    }
+
+    #[test]
+    fn parse_resume_and_accept() {
+        let resume = DccResume {
+            filename: "my file.txt".to_owned(),
+            port: 5000,
+            position: 512,
+        };
+        assert_eq!(
+            DccMessage::parse("DCC RESUME \"my file.txt\" 5000 512"),
+            Some(DccMessage::Resume(resume.clone()))
+        );
+        assert_eq!(
+            DccMessage::parse("DCC ACCEPT \"my file.txt\" 5000 512"),
+            Some(DccMessage::Accept(resume))
+        );
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for ctcp in &[
+            "DCC SEND file.txt 2130706433 5000 1024",
+            "DCC SEND \"my file.txt\" 2130706433 5000",
+            "DCC RESUME \"my file.txt\" 5000 512",
+            "DCC ACCEPT file.txt 5000 512",
+        ] {
+            assert_eq!(DccMessage::parse(ctcp).unwrap().to_string(), *ctcp);
+        }
+    }
+
+    #[tokio::test]
+    async fn loopback_transfer() -> Result<()> {
+        let data: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let offer = DccFileOffer::bind(
+            SocketAddr::new(LOCALHOST, 0),
+            LOCALHOST,
+            "data.bin",
+            data.len() as u64,
+        )
+        .await?;
+        let dcc = match DccMessage::parse(&offer.offer().to_string()) {
+            Some(DccMessage::Send(dcc)) => dcc,
+            other => panic!("expected a DCC SEND, got {:?}", other),
+        };
+
+        let sent = tokio::spawn({
+            let data = data.clone();
+            async move { offer.send(&data[..]).await }
+        });
+        let mut received = Vec::new();
+        assert_eq!(dcc.download(&mut received).await?, data.len() as u64);
+        assert_eq!(sent.await??, data.len() as u64);
+        assert_eq!(received, data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn loopback_resume() -> Result<()> {
+        let data: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let mut offer = DccFileOffer::bind(
+            SocketAddr::new(LOCALHOST, 0),
+            LOCALHOST,
+            "data.bin",
+            data.len() as u64,
+        )
+        .await?;
+        let dcc = match offer.offer() {
+            DccMessage::Send(dcc) => dcc,
+            other => panic!("expected a DCC SEND, got {:?}", other),
+        };
+
+        let resume = match dcc.resume(12_000) {
+            DccMessage::Resume(resume) => resume,
+            other => panic!("expected a DCC RESUME, got {:?}", other),
+        };
+        assert_eq!(
+            offer.accept_resume(&resume),
+            Some(DccMessage::Accept(resume))
+        );
+
+        let sent = tokio::spawn({
+            let data = data.clone();
+            async move { offer.send(&data[12_000..]).await }
+        });
+        let mut received = Vec::new();
+        assert_eq!(dcc.download_from(12_000, &mut received).await?, 8_000);
+        assert_eq!(sent.await??, 8_000);
+        assert_eq!(received, &data[12_000..]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_acks() -> Result<()> {
+        let listener = TcpListener::bind(SocketAddr::new(LOCALHOST, 0)).await?;
+        let dcc = DccSend {
+            filename: "data.bin".to_owned(),
+            addr: listener.local_addr()?,
+            size: Some(300),
+        };
+
+        let sender = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let mut acks = Vec::new();
+            for _ in 0..3 {
+                stream.write_all(&[0; 100]).await?;
+                acks.push(stream.read_u32().await?);
+            }
+            Ok::<_, std::io::Error>(acks)
+        });
+
+        let mut received = Vec::new();
+        assert_eq!(dcc.download(&mut received).await?, 300);
+        assert_eq!(sender.await??, vec![100, 200, 300]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_too_short() -> Result<()> {
+        let listener = TcpListener::bind(SocketAddr::new(LOCALHOST, 0)).await?;
+        let dcc = DccSend {
+            filename: "data.bin".to_owned(),
+            addr: listener.local_addr()?,
+            size: Some(300),
+        };
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            stream.write_all(&[0; 100]).await?;
+            stream.read_u32().await?;
+            Ok::<_, std::io::Error>(())
+        });
+
+        match dcc.download(Vec::new()).await {
+            Err(crate::error::Error::DccSizeMismatch { expected, received }) => {
+                assert_eq!((expected, received), (300, 100))
+            }
+            other => panic!("expected a size mismatch, got {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,89 @@
 use std::{
+    collections::VecDeque,
     io::{self, Cursor, Read, Write},
     pin::Pin,
-    task::{Context, Poll},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
 };
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use parking_lot::Mutex;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+/// A line written to a scripted `MockStream` that was not the line it expected next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// The line the script expected, without the line ending.
+    pub expected: String,
+    /// The line that was written instead, without the line ending.
+    pub sent: String,
+}
+
+/// What a `MockStream` records for the test to inspect, shared with the test while the stream is
+/// owned by the client.
+#[derive(Debug, Default)]
+pub struct MockState {
+    /// The lines written that did not match the script.
+    pub mismatches: Mutex<Vec<Mismatch>>,
+    /// The number of times written data was flushed.
+    pub flushes: AtomicUsize,
+}
+
+/// A handle for pushing lines into a `MockStream` that was kept open, as if the server sent them.
+#[derive(Debug)]
+pub struct MockHandle {
+    tx: UnboundedSender<String>,
+}
+
+impl MockHandle {
+    /// Injects a line to be read from the stream, adding a line ending if it lacks one. The line
+    /// is always encoded as UTF-8.
+    pub fn inject(&self, line: &str) {
+        let mut line = line.to_owned();
+        if !line.ends_with('\n') {
+            line.push_str("\r\n");
+        }
+        // The stream is already gone if this fails, so there's no one left to read the line.
+        let _ = self.tx.send(line);
+    }
+
+    /// Ends the stream once everything injected so far has been read, as if the server closed the
+    /// connection.
+    pub fn disconnect(self) {}
+}
 
 /// A fake stream for testing network applications backed by buffers.
-#[derive(Clone, Debug)]
+///
+/// A scripted mock stream also acts like a simple server: each time a line is written that matches
+/// the next line in its script, the response scripted for it becomes readable. Reads wait for the
+/// next response rather than ending the stream while any of the script remains. The first line
+/// that doesn't match is recorded as a `Mismatch`, and the rest of the script is abandoned.
+///
+/// A mock stream that is kept open reads the lines injected with its `MockHandle` until the handle
+/// is dropped.
+#[derive(Debug)]
 pub struct MockStream {
     written: Cursor<Vec<u8>>,
     received: Cursor<Vec<u8>>,
+    /// The lines expected to be written, each with the bytes that become readable once it is.
+    script: VecDeque<(String, Vec<u8>)>,
+    /// The offset in `written` of the line currently being written.
+    line_start: usize,
+    /// Whether anything has been written since the last flush.
+    unflushed: bool,
+    /// What the stream records for the test.
+    state: Arc<MockState>,
+    /// The task waiting on the next scripted response.
+    read_waker: Option<Waker>,
+    /// Lines injected through the `MockHandle`, if the stream is kept open.
+    injected: Option<UnboundedReceiver<String>>,
+    /// The handle for injecting lines, until it is taken.
+    handle: Option<MockHandle>,
 }
 
 impl MockStream {
@@ -20,12 +94,38 @@ impl MockStream {
 
     /// Creates a new mock stream with the specified bytes to read.
     pub fn new(initial: &[u8]) -> MockStream {
+        MockStream::scripted(initial, VecDeque::new())
+    }
+
+    /// Creates a new mock stream with the specified bytes to read, followed by the responses to
+    /// the lines in the script as they are written.
+    pub fn scripted(initial: &[u8], script: VecDeque<(String, Vec<u8>)>) -> MockStream {
         MockStream {
             written: Cursor::new(vec![]),
             received: Cursor::new(initial.to_owned()),
+            script,
+            line_start: 0,
+            unflushed: false,
+            state: Arc::new(MockState::default()),
+            read_waker: None,
+            injected: None,
+            handle: None,
         }
     }
 
+    /// Keeps the stream open after its initial bytes and script, so that lines can be injected
+    /// with the handle from `take_handle`.
+    pub fn keep_open(&mut self) {
+        let (tx, rx) = unbounded_channel();
+        self.injected = Some(rx);
+        self.handle = Some(MockHandle { tx });
+    }
+
+    /// Takes the handle for injecting lines, if the stream is kept open.
+    pub fn take_handle(&mut self) -> Option<MockHandle> {
+        self.handle.take()
+    }
+
     /// Gets a slice of bytes representing the data that has been written.
     pub fn written(&self) -> &[u8] {
         self.written.get_ref()
@@ -35,17 +135,71 @@ impl MockStream {
     pub fn received(&self) -> &[u8] {
         self.received.get_ref()
     }
+
+    /// Gets a handle to what the stream records for the test.
+    pub fn state(&self) -> Arc<MockState> {
+        Arc::clone(&self.state)
+    }
+
+    /// Checks each newly completed line that was written against the script.
+    fn check_script(&mut self) {
+        while let Some(end) = self.written.get_ref()[self.line_start..]
+            .iter()
+            .position(|&b| b == b'\n')
+        {
+            let end = self.line_start + end;
+            let line = String::from_utf8_lossy(&self.written.get_ref()[self.line_start..end])
+                .trim_end_matches('\r')
+                .to_owned();
+            self.line_start = end + 1;
+
+            let (expected, response) = match self.script.pop_front() {
+                Some(next) => next,
+                None => return,
+            };
+            if line == expected {
+                self.received.get_mut().extend_from_slice(&response);
+            } else {
+                self.state.mismatches.lock().push(Mismatch {
+                    expected,
+                    sent: line,
+                });
+                self.script.clear();
+            }
+            if let Some(waker) = self.read_waker.take() {
+                waker.wake();
+            }
+        }
+    }
 }
 
 impl AsyncRead for MockStream {
     fn poll_read(
-        mut self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        let n = self.as_mut().received.read(buf.initialize_unfilled())?;
-        buf.advance(n);
-        Poll::Ready(Ok(()))
+        let this = self.get_mut();
+        loop {
+            let n = this.received.read(buf.initialize_unfilled())?;
+            if n > 0 {
+                buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+            match this.injected.as_mut().map(|rx| rx.poll_recv(cx)) {
+                Some(Poll::Ready(Some(line))) => {
+                    this.received.get_mut().extend_from_slice(line.as_bytes());
+                    continue;
+                }
+                Some(Poll::Ready(None)) => this.injected = None,
+                Some(Poll::Pending) | None => (),
+            }
+            if this.injected.is_some() || !this.script.is_empty() {
+                this.read_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            return Poll::Ready(Ok(()));
+        }
     }
 }
 
@@ -55,10 +209,16 @@ impl AsyncWrite for MockStream {
         _: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        Poll::Ready(self.as_mut().written.write(buf))
+        let n = self.as_mut().written.write(buf)?;
+        self.unflushed |= n > 0;
+        self.check_script();
+        Poll::Ready(Ok(n))
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        if std::mem::take(&mut self.unflushed) {
+            self.state.flushes.fetch_add(1, Ordering::SeqCst);
+        }
         Poll::Ready(self.as_mut().written.flush())
     }
 
@@ -38,7 +38,7 @@
 //!
 //! while let Some(message) = stream.next().await.transpose()? {
 //!     if let Command::PRIVMSG(channel, message) = message.command {
-//!         if message.contains(client.current_nickname()) {
+//!         if message.contains(&*client.current_nickname()) {
 //!             client.send_privmsg(&channel, "beep boop").unwrap();
 //!         }
 //!     }
@@ -47,48 +47,77 @@
 //! # }
 //! ```
 
-#[cfg(feature = "ctcp")]
 use chrono::prelude::*;
 use futures_util::{
-    future::{FusedFuture, Future},
+    future::{self, BoxFuture, FusedFuture, Future},
     ready,
     stream::{FusedStream, Stream},
+    task::AtomicWaker,
 };
 use futures_util::{
-    sink::Sink as _,
+    sink::{Sink as _, SinkExt as _},
     stream::{SplitSink, SplitStream, StreamExt as _},
 };
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     fmt,
+    net::SocketAddr,
     path::Path,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot, watch,
+    },
+    task::JoinHandle,
+    time::Sleep,
 };
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 use crate::{
     client::{
         conn::Connection,
-        data::{Config, User},
+        data::{
+            ban::BanLists, batch::BatchCollector, chathistory::sort_by_time, dedup::Deduplicator,
+            expand_placeholders, ignore::IgnoreList, who::WHOX_FIELDS, AccessLevel, BanEntry,
+            Batch, ChannelListEntry, Config, Event, HistoryQuery, Invite, LengthPolicy, ListFilter,
+            ModeChange, MonitorEvent, ServerInfo, User, WhoReply,
+        },
+        transport::{ConnectionStats, History, HistoryEntry, StatCounters},
     },
     error,
     proto::{
+        error::MessageParseError,
+        irc::{IrcCodec, MAX_LINE_LENGTH},
+        message::Tag,
         mode::ModeType,
-        CapSubCommand::{END, LS, REQ},
+        BatchSubCommand,
+        CapSubCommand::{ACK, DEL, END, LS, NAK, REQ},
         Capability, ChannelMode, Command,
         Command::{
-            ChannelMODE, AUTHENTICATE, CAP, INVITE, JOIN, KICK, KILL, NICK, NICKSERV, NOTICE, OPER,
-            PART, PASS, PONG, PRIVMSG, QUIT, SAMODE, SANICK, TOPIC, USER,
+            ChannelMODE, UserMODE, ACCOUNT, AUTHENTICATE, AWAY, CAP, INVITE, JOIN, KICK, KILL,
+            NICK, NICKSERV, NOTICE, OPER, PART, PASS, PONG, PRIVMSG, QUIT, SAMODE, SANICK, TOPIC,
+            USER,
         },
-        Message, Mode, NegotiationVersion, Response,
+        Message, Mode, NegotiationVersion, Prefix, Response, StandardReply, StandardReplyKind,
+        UserMode,
     },
 };
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod conn;
 pub mod data;
+#[cfg(feature = "ctcp")]
+pub mod dcc;
 mod mock;
 pub mod prelude;
 pub mod transport;
@@ -112,13 +141,17 @@ macro_rules! pub_state_base {
             self.send(JOIN(chanlist.to_string(), None, None))
         }
 
-        /// Joins the specified channel or chanlist using the specified key or keylist.
+        /// Joins the specified channel or chanlist using the specified key or keylist. This fails
+        /// with `Error::MessageTooLong` if the keys do not fit on a single line.
         pub fn send_join_with_keys<S1, S2>(&self, chanlist: S1, keylist: S2) -> error::Result<()>
         where
             S1: fmt::Display,
             S2: fmt::Display,
         {
-            self.send(JOIN(chanlist.to_string(), Some(keylist.to_string()), None))
+            self.send(fit_line(
+                JOIN(chanlist.to_string(), Some(keylist.to_string()), None),
+                false,
+            )?)
         }
 
         /// Sends a notice to the specified target.
@@ -138,6 +171,44 @@ macro_rules! pub_state_base {
 
 macro_rules! pub_sender_base {
     () => {
+        /// Sends a message assembled from its raw parts, bypassing `Command` entirely. Tags are
+        /// escaped as necessary and `trailing`, if specified, is sent as the final parameter. This
+        /// is an escape hatch for relays and similar software that need full control over what
+        /// goes out on the wire.
+        ///
+        /// The parts are validated so that they can't be used to inject additional lines or alter
+        /// the shape of the message: none may contain CR, LF or NUL, and the prefix, tag keys and
+        /// middle parameters may not contain spaces (nor may parameters start with `:`).
+        ///
+        /// # Example
+        /// ```no_run
+        /// # use irc::client::prelude::*;
+        /// use irc::proto::message::Tag;
+        ///
+        /// # #[tokio::main]
+        /// # async fn main() -> irc::error::Result<()> {
+        /// # let client = Client::new("config.toml").await?;
+        /// client.send_raw_with_tags(
+        ///     vec![Tag("+draft/reply".to_owned(), Some("abc123".to_owned()))],
+        ///     None,
+        ///     "PRIVMSG",
+        ///     &["#channel"],
+        ///     Some("I agree!"),
+        /// )?;
+        /// # Ok(())
+        /// # }
+        /// ```
+        pub fn send_raw_with_tags(
+            &self,
+            tags: Vec<Tag>,
+            prefix: Option<&str>,
+            command: &str,
+            params: &[&str],
+            trailing: Option<&str>,
+        ) -> error::Result<()> {
+            self.send(raw_message(tags, prefix, command, params, trailing)?)
+        }
+
         /// Sends a request for a list of server capabilities for a specific IRCv3 version.
         pub fn send_cap_ls(&self, version: NegotiationVersion) -> error::Result<()> {
             self.send(Command::CAP(
@@ -214,32 +285,42 @@ macro_rules! pub_sender_base {
 
         /// Sends a message to the specified target. If the message contains IRC newlines (`\r\n`), it
         /// will automatically be split and sent as multiple separate `PRIVMSG`s to the specified
-        /// target. If you absolutely must avoid this behavior, you can do
+        /// target. Lines that would be too long once relayed by the server are likewise split on
+        /// word boundaries, leaving room for the `:nick!user@host` source the server prepends. If
+        /// you absolutely must avoid this behavior, you can do
         /// `client.send(PRIVMSG(target, message))` directly.
         pub fn send_privmsg<S1, S2>(&self, target: S1, message: S2) -> error::Result<()>
         where
             S1: fmt::Display,
             S2: fmt::Display,
         {
+            let target = target.to_string();
             let message = message.to_string();
             for line in message.split("\r\n") {
-                self.send(PRIVMSG(target.to_string(), line.to_string()))?
+                for chunk in split_privmsg_text(&target, line) {
+                    self.send(PRIVMSG(target.clone(), chunk.to_owned()))?
+                }
             }
             Ok(())
         }
 
         /// Sets the topic of a channel or requests the current one.
-        /// If `topic` is an empty string, it won't be included in the message.
+        /// If `topic` is an empty string, it won't be included in the message. This fails with
+        /// `Error::MessageTooLong` if the topic is too long to be relayed without the server
+        /// truncating it.
         pub fn send_topic<S1, S2>(&self, channel: S1, topic: S2) -> error::Result<()>
         where
             S1: fmt::Display,
             S2: fmt::Display,
         {
             let topic = topic.to_string();
-            self.send(TOPIC(
-                channel.to_string(),
-                if topic.is_empty() { None } else { Some(topic) },
-            ))
+            self.send(fit_line(
+                TOPIC(
+                    channel.to_string(),
+                    if topic.is_empty() { None } else { Some(topic) },
+                ),
+                true,
+            )?)
         }
 
         /// Kills the target with the provided message.
@@ -252,7 +333,9 @@ macro_rules! pub_sender_base {
         }
 
         /// Kicks the listed nicknames from the listed channels with a comment.
-        /// If `message` is an empty string, it won't be included in the message.
+        /// If `message` is an empty string, it won't be included in the message. This fails with
+        /// `Error::MessageTooLong` if the comment is too long to be relayed without the server
+        /// truncating it.
         pub fn send_kick<S1, S2, S3>(
             &self,
             chanlist: S1,
@@ -265,15 +348,18 @@ macro_rules! pub_sender_base {
             S3: fmt::Display,
         {
             let message = message.to_string();
-            self.send(KICK(
-                chanlist.to_string(),
-                nicklist.to_string(),
-                if message.is_empty() {
-                    None
-                } else {
-                    Some(message)
-                },
-            ))
+            self.send(fit_line(
+                KICK(
+                    chanlist.to_string(),
+                    nicklist.to_string(),
+                    if message.is_empty() {
+                        None
+                    } else {
+                        Some(message)
+                    },
+                ),
+                true,
+            )?)
         }
 
         /// Changes the mode of the target by force.
@@ -320,14 +406,14 @@ macro_rules! pub_sender_base {
         }
 
         /// Quits the server entirely with a message.
-        /// This defaults to `Powered by Rust.` if none is specified.
+        /// This defaults to the configured `quit_message` if the message is empty.
         pub fn send_quit<S>(&self, msg: S) -> error::Result<()>
         where
             S: fmt::Display,
         {
             let msg = msg.to_string();
             self.send(QUIT(Some(if msg.is_empty() {
-                "Powered by Rust.".to_string()
+                self.default_quit_message().to_owned()
             } else {
                 msg
             })))
@@ -362,10 +448,20 @@ macro_rules! pub_sender_base {
             self.send_ctcp(target, &format!("ACTION {}", msg.to_string())[..])
         }
 
+        /// Sends a DCC request, such as a file offer, to the specified target.
+        /// This requires the CTCP feature to be enabled.
+        #[cfg(feature = "ctcp")]
+        pub fn send_dcc<S>(&self, target: S, dcc: &dcc::DccMessage) -> error::Result<()>
+        where
+            S: fmt::Display,
+        {
+            self.send_ctcp(target, dcc)
+        }
+
         /// Sends a finger request to the specified target.
         /// This requires the CTCP feature to be enabled.
         #[cfg(feature = "ctcp")]
-        pub fn send_finger<S: fmt::Display>(&self, target: S) -> error::Result<()>
+        pub fn send_finger<S>(&self, target: S) -> error::Result<()>
         where
             S: fmt::Display,
         {
@@ -425,18 +521,242 @@ macro_rules! pub_sender_base {
     };
 }
 
+/// A conservative estimate of the length of the `:nick!user@host ` source that the server prepends
+/// to a message when relaying it, allowing for a 30 byte nickname, a 10 byte username and a 63 byte
+/// hostname. This counts against the recipient's line length limit, but we can't know it exactly.
+const RELAYED_SOURCE_LENGTH: usize = 1 + 30 + 1 + 10 + 1 + 63 + 1;
+
+/// Splits the text of a `PRIVMSG` to `target` into chunks that fit within the line length limit
+/// once relayed, breaking on spaces where possible. Only text that is empty to begin with gives an
+/// empty chunk.
+fn split_privmsg_text<'a>(target: &str, text: &'a str) -> Vec<&'a str> {
+    // <source> PRIVMSG <target> :<text>\r\n
+    let overhead = RELAYED_SOURCE_LENGTH + "PRIVMSG ".len() + target.len() + " :".len() + 2;
+    let max = match MAX_LINE_LENGTH.checked_sub(overhead) {
+        Some(max) if max > 0 => max,
+        // Nothing fits, so let the codec decide what to do with the message.
+        _ => return vec![text],
+    };
+
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            // Not even one character fits, so send it on a line of its own anyway.
+            end = rest.chars().next().map_or(0, char::len_utf8);
+        }
+        // A space right after the chunk is as good a place to break as one inside it.
+        let space = if rest.as_bytes()[end] == b' ' {
+            Some(end)
+        } else {
+            rest[..end].rfind(' ')
+        };
+        match space {
+            Some(space) if space > 0 => {
+                chunks.push(&rest[..space]);
+                rest = &rest[space + 1..];
+            }
+            _ => {
+                chunks.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    // The text may have ended with the space we broke on.
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+/// Checks that `command` fits within the line length limit, leaving room for the source the server
+/// prepends if it is `relayed` to others, so that the server does not truncate it. Fails with
+/// `Error::MessageTooLong` otherwise.
+fn fit_line(command: Command, relayed: bool) -> error::Result<Message> {
+    let message = Message::from(command);
+    let mut length = message.to_string().len();
+    if relayed {
+        length += RELAYED_SOURCE_LENGTH;
+    }
+    if length > MAX_LINE_LENGTH {
+        return Err(error::Error::MessageTooLong { length });
+    }
+    Ok(message)
+}
+
+/// Groups channels and their keys into the channel and key lists of `JOIN` commands, with at
+/// most `per_join` channels each, starting a new command early whenever the line would otherwise
+/// be too long.
+fn group_joins(
+    chans: &[(String, Option<String>)],
+    per_join: usize,
+) -> Vec<(String, Option<String>)> {
+    let join = |group: &[&(String, Option<String>)]| {
+        let names: Vec<_> = group.iter().map(|(chan, _)| &chan[..]).collect();
+        let keys: Vec<_> = group.iter().filter_map(|(_, key)| key.as_deref()).collect();
+        let keys = if keys.is_empty() {
+            None
+        } else {
+            Some(keys.join(","))
+        };
+        (names.join(","), keys)
+    };
+    // JOIN <chanlist> <keylist>\r\n
+    let too_long = |(chans, keys): &(String, Option<String>)| {
+        "JOIN ".len() + chans.len() + keys.as_ref().map_or(0, |keys| keys.len() + 1) + 2
+            > MAX_LINE_LENGTH
+    };
+
+    let mut joins = Vec::new();
+    let mut group = Vec::new();
+    for chan in chans {
+        group.push(chan);
+        if group.len() > 1 && (group.len() > per_join || too_long(&join(&group))) {
+            group.pop();
+            joins.push(join(&group));
+            group = vec![chan];
+        }
+    }
+    if !group.is_empty() {
+        joins.push(join(&group));
+    }
+    joins
+}
+
+/// Packs mode changes on `target` into as few `MODE` commands as possible, putting at most `limit`
+/// changes with a parameter in each (or any number if `limit` is `None`) and keeping every line
+/// within the line length limit once relayed.
+fn pack_mode_changes(target: &str, changes: &[ModeChange], limit: Option<usize>) -> Vec<Command> {
+    // <source> MODE <target> <modes> <params>\r\n, with at most 15 parameters in all.
+    let max_params = 13;
+    let max =
+        MAX_LINE_LENGTH.saturating_sub(RELAYED_SOURCE_LENGTH + "MODE ".len() + target.len() + 2);
+
+    let mut commands = Vec::new();
+    let mut modes = String::new();
+    let mut params: Vec<String> = Vec::new();
+    let mut sign = None;
+    let mut length = 0;
+    for change in changes {
+        let param_length = change.param.as_ref().map_or(0, |param| param.len() + 1);
+        let full =
+            change.param.is_some() && (params.len() == limit.unwrap_or(max_params).min(max_params));
+        if !modes.is_empty() && (full || length + 2 + param_length > max) {
+            let mut args = vec![target.to_owned(), std::mem::take(&mut modes)];
+            args.append(&mut params);
+            commands.push(Command::Raw("MODE".to_owned(), args));
+            sign = None;
+            length = 0;
+        }
+        if sign != Some(change.adding) {
+            modes.push(if change.adding { '+' } else { '-' });
+            sign = Some(change.adding);
+            length += 1;
+        }
+        modes.push(change.mode);
+        length += 1 + param_length;
+        if let Some(ref param) = change.param {
+            params.push(param.clone());
+        }
+    }
+    if !modes.is_empty() {
+        let mut args = vec![target.to_owned(), modes];
+        args.append(&mut params);
+        commands.push(Command::Raw("MODE".to_owned(), args));
+    }
+    commands
+}
+
+/// Gets the value of the given tag on the message, if it has one.
+pub(crate) fn tag_value<'a>(msg: &'a Message, name: &str) -> Option<&'a str> {
+    msg.tags
+        .as_ref()?
+        .iter()
+        .find(|tag| tag.0 == name)
+        .and_then(|tag| tag.1.as_deref())
+}
+
+/// Assembles and validates a message from its raw parts for `send_raw_with_tags`.
+fn raw_message(
+    tags: Vec<Tag>,
+    prefix: Option<&str>,
+    command: &str,
+    params: &[&str],
+    trailing: Option<&str>,
+) -> error::Result<Message> {
+    fn invalid(part: &str) -> error::Error {
+        error::Error::InvalidMessage {
+            string: part.to_owned(),
+            cause: MessageParseError::InvalidParameter {
+                param: part.to_owned(),
+            },
+        }
+    }
+    let breaks_line = |s: &str| s.contains(['\r', '\n', '\0']);
+    let breaks_word = |s: &str| s.is_empty() || breaks_line(s) || s.contains(' ');
+
+    if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(error::Error::InvalidMessage {
+            string: command.to_owned(),
+            cause: MessageParseError::InvalidCommand,
+        });
+    }
+    if let Some(tag) = tags
+        .iter()
+        .find(|Tag(key, _)| breaks_word(key) || key.contains([';', '=']))
+    {
+        return Err(invalid(&tag.0));
+    }
+    if let Some(prefix) = prefix.filter(|p| breaks_word(p)) {
+        return Err(invalid(prefix));
+    }
+    if let Some(param) = params.iter().find(|p| breaks_word(p) || p.starts_with(':')) {
+        return Err(invalid(param));
+    }
+    if let Some(trailing) = trailing.filter(|t| breaks_line(t)) {
+        return Err(invalid(trailing));
+    }
+
+    let mut args: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+    if let Some(trailing) = trailing {
+        args.push(trailing.to_owned());
+    }
+
+    Ok(Message {
+        tags: if tags.is_empty() { None } else { Some(tags) },
+        prefix: prefix.map(Prefix::from),
+        command: Command::Raw(command.to_owned(), args),
+    })
+}
+
 /// A stream of `Messages` received from an IRC server via an `Client`.
 ///
 /// Interaction with this stream relies on the `futures` API, but is only expected for less
 /// traditional use cases. To learn more, you can view the documentation for the
 /// [`futures`](https://docs.rs/futures/) crate, or the tutorials for
 /// [`tokio`](https://tokio.rs/docs/getting-started/futures/).
-#[derive(Debug)]
 pub struct ClientStream {
     state: Arc<ClientState>,
     stream: SplitStream<Connection>,
     // In case the client stream also handles outgoing messages.
     outgoing: Option<Outgoing>,
+    /// The new connection being established after a call to `Client::reconnect_now`.
+    reconnecting: Option<BoxFuture<'static, error::Result<Connection>>>,
+}
+
+impl fmt::Debug for ClientStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientStream")
+            .field("state", &self.state)
+            .field("stream", &self.stream)
+            .field("outgoing", &self.outgoing)
+            .field("reconnecting", &self.reconnecting.is_some())
+            .finish()
+    }
 }
 
 impl ClientStream {
@@ -453,6 +773,172 @@ impl ClientStream {
 
         Ok(output)
     }
+
+    /// Sends everything queued so far, e.g. a `QUIT`, and then closes the connection cleanly. This
+    /// is a no-op if the stream isn't handling outgoing messages, in which case the `Outgoing`
+    /// future should be shut down instead.
+    ///
+    /// Dropping the stream or the `Outgoing` future closes the connection without flushing, since
+    /// writing to it would mean blocking in `Drop`, so messages still queued may be lost.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let mut client = Client::new("config.toml").await?;
+    /// let stream = client.stream()?;
+    /// client.send_quit("Goodbye!")?;
+    /// stream.shutdown().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn shutdown(mut self) -> error::Result<()> {
+        match self.outgoing.take() {
+            Some(outgoing) => outgoing.shutdown().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Turns this stream into a stream of typed [`Event`](./data/event/enum.Event.html)s. Messages
+    /// are still processed by the client as they pass through.
+    pub fn events(self) -> EventStream {
+        EventStream { stream: self }
+    }
+
+    /// Turns this stream into a stream of [`RawMessage`](./struct.RawMessage.html)s, which carry
+    /// the bytes each message was parsed from. `Config::keep_raw_bytes` must be enabled for the
+    /// bytes to be kept, otherwise they are always empty.
+    pub fn raw(self) -> RawStream {
+        RawStream { stream: self }
+    }
+
+    /// Starts connecting again if a reconnect was requested and everything queued for the current
+    /// connection (most importantly, the `QUIT`) has been flushed to it.
+    fn start_reconnect(&mut self) -> error::Result<()> {
+        let idle = match self.outgoing {
+            Some(ref outgoing) => outgoing.is_idle(),
+            None => true,
+        };
+        if !idle || !self.state.reconnect_requested.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if self.outgoing.is_none() {
+            return Err(error::Error::CannotReconnect);
+        }
+
+        let config = self.state.config.clone();
+        let sender = self.state.sender.clone();
+        self.reconnecting = Some(Box::pin(
+            async move { Connection::new(&config, sender).await },
+        ));
+        Ok(())
+    }
+
+    /// Replaces the current connection with a freshly established one and registers again.
+    fn finish_reconnect(&mut self, conn: Connection) -> error::Result<()> {
+        #[cfg(test)]
+        {
+            *self.state.reconnected_view.write() = conn.log_view();
+        }
+        #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+        {
+            *self.state.peer_certificate.write() = conn.peer_certificate();
+        }
+        *self.state.peer_addr.write() = conn.peer_addr();
+        *self.state.local_addr.write() = conn.local_addr();
+        *self.state.raw_bytes.write() = conn.raw_bytes();
+
+        let (sink, stream) = conn.split();
+        self.stream = stream;
+        if let Some(ref mut outgoing) = self.outgoing {
+            outgoing.sink = sink;
+        }
+
+        self.state.reset();
+        self.state.send_webirc()?;
+        self.state.identify()
+    }
+}
+
+/// A stream of typed `Event`s received from an IRC server, made with `ClientStream::events`.
+#[derive(Debug)]
+pub struct EventStream {
+    stream: ClientStream,
+}
+
+impl EventStream {
+    /// Turns this stream back into a stream of messages.
+    pub fn into_inner(self) -> ClientStream {
+        self.stream
+    }
+}
+
+impl FusedStream for EventStream {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event, error::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let message = match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        let server_info = self.stream.state.server_info.read();
+        Poll::Ready(Some(Ok(Event::from_message(message, &server_info))))
+    }
+}
+
+/// A received message along with the bytes of the line it was parsed from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawMessage {
+    /// The parsed message, as yielded by `ClientStream`.
+    pub message: Message,
+    /// The bytes of the line as received, without the line ending, even if they were not valid in
+    /// the connection's encoding.
+    pub bytes: Vec<u8>,
+}
+
+/// A stream of `RawMessage`s received from an IRC server, made with `ClientStream::raw`.
+#[derive(Debug)]
+pub struct RawStream {
+    stream: ClientStream,
+}
+
+impl RawStream {
+    /// Turns this stream back into a stream of messages.
+    pub fn into_inner(self) -> ClientStream {
+        self.stream
+    }
+}
+
+impl FusedStream for RawStream {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl Stream for RawStream {
+    type Item = Result<RawMessage, error::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let message = match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        // The transport keeps the bytes of the last line it decoded, which is this message's.
+        let bytes = self.stream.state.raw_bytes.read().lock().take();
+        Poll::Ready(Some(Ok(RawMessage {
+            message,
+            bytes: bytes.unwrap_or_default(),
+        })))
+    }
 }
 
 impl FusedStream for ClientStream {
@@ -465,6 +951,14 @@ impl Stream for ClientStream {
     type Item = Result<Message, error::Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.state.reconnect_waker.register(cx.waker());
+
+        if let Some(reconnecting) = self.as_mut().reconnecting.as_mut() {
+            let conn = ready!(reconnecting.as_mut().poll(cx));
+            self.reconnecting = None;
+            self.finish_reconnect(conn?)?;
+        }
+
         if let Some(outgoing) = self.as_mut().outgoing.as_mut() {
             match Pin::new(outgoing).poll(cx) {
                 Poll::Ready(Ok(())) => {
@@ -480,12 +974,210 @@ impl Stream for ClientStream {
             }
         }
 
-        match ready!(Pin::new(&mut self.as_mut().stream).poll_next(cx)) {
-            Some(Ok(msg)) => {
-                self.state.handle_message(&msg)?;
-                Poll::Ready(Some(Ok(msg)))
+        self.start_reconnect()?;
+        if self.reconnecting.is_some() {
+            // Poll the new connection right away so that it registers for wake-ups.
+            return self.poll_next(cx);
+        }
+
+        loop {
+            if self.state.error_pending.swap(false, Ordering::SeqCst) {
+                let reason = self.state.last_error.read().clone().unwrap_or_default();
+                return Poll::Ready(Some(Err(error::Error::ServerError { reason })));
+            }
+            let polled = Pin::new(&mut self.as_mut().stream).poll_next(cx);
+            if polled.is_pending() {
+                // Checked only once there's nothing left to read, so that a deadline set while
+                // handling messages is registered for wake-ups too.
+                self.state.poll_identify_timeout(cx)?;
+                return Poll::Pending;
+            }
+            match ready!(polled) {
+                Some(Ok(msg)) => {
+                    self.state.handle_message(&msg)?;
+                    // Messages received again are still handled, but have been yielded once.
+                    if self.state.is_ignored(&msg) || self.state.dedup.lock().is_duplicate(&msg) {
+                        continue;
+                    }
+                    // Clone the chain so handlers are free to use the client themselves.
+                    let middleware = self.state.middleware.read().clone();
+                    if let Some(msg) = middleware.process(msg) {
+                        return Poll::Ready(Some(Ok(msg)));
+                    }
+                }
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+}
+
+/// An ordered chain of handlers that messages pass through, either incoming ones before reaching
+/// the `ClientStream` or outgoing ones before being sent. Each handler may transform a message, or
+/// drop it by returning `None`.
+/// Cloning the chain is cheap, since the handlers are shared.
+#[derive(Clone)]
+struct Middleware {
+    handlers: Arc<[MiddlewareHandler]>,
+}
+
+/// A handler that transforms or drops a message.
+type MiddlewareHandler = Arc<dyn Fn(Message) -> Option<Message> + Send + Sync>;
+
+impl Middleware {
+    /// Adds a handler to the end of the chain.
+    fn push(&mut self, handler: MiddlewareHandler) {
+        let mut handlers = self.handlers.to_vec();
+        handlers.push(handler);
+        self.handlers = handlers.into();
+    }
+
+    /// Runs the message through every handler in order, stopping early if one drops it.
+    fn process(&self, msg: Message) -> Option<Message> {
+        self.handlers
+            .iter()
+            .try_fold(msg, |msg, handler| handler(msg))
+    }
+}
+
+impl Default for Middleware {
+    fn default() -> Self {
+        Middleware {
+            handlers: Arc::new([]),
+        }
+    }
+}
+
+impl fmt::Debug for Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Middleware({} handlers)", self.handlers.len())
+    }
+}
+
+/// A handler notified of events of the given type.
+type Handler<E> = Arc<dyn Fn(&E) + Send + Sync>;
+
+/// Handlers registered to be notified of events of the given type, e.g. status changes of
+/// monitored nicknames.
+struct Handlers<E> {
+    handlers: Vec<Handler<E>>,
+}
+
+impl<E> Handlers<E> {
+    /// Notifies every handler of the event, in the order they were added.
+    fn notify(&self, event: &E) {
+        for handler in &self.handlers {
+            handler(event);
+        }
+    }
+}
+
+impl<E> Clone for Handlers<E> {
+    fn clone(&self) -> Self {
+        Handlers {
+            handlers: self.handlers.clone(),
+        }
+    }
+}
+
+impl<E> Default for Handlers<E> {
+    fn default() -> Self {
+        Handlers {
+            handlers: Vec::new(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for Handlers<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handlers({} handlers)", self.handlers.len())
+    }
+}
+
+/// A request awaiting either a reply to its command or a `FAIL` standard reply.
+#[derive(Debug)]
+struct PendingRequest {
+    /// The name of the command that was sent, e.g. `REGISTER`.
+    command: String,
+    /// The context parameters that a `FAIL` reply is expected to echo back, e.g. the account name.
+    context: Vec<String>,
+    tx: oneshot::Sender<error::Result<Message>>,
+}
+
+impl PendingRequest {
+    /// Determines whether the given `FAIL` reply refers to this request. Context parameters given
+    /// as `*` by the server match anything.
+    fn matches(&self, reply: &StandardReply) -> bool {
+        reply.command.eq_ignore_ascii_case(&self.command)
+            && self
+                .context
+                .iter()
+                .zip(&reply.context)
+                .all(|(ours, theirs)| theirs == "*" || ours.eq_ignore_ascii_case(theirs))
+    }
+}
+
+/// A labeled command awaiting its response.
+#[derive(Debug)]
+struct PendingLabel {
+    /// The label attached to the command.
+    label: String,
+    /// The reference of the `labeled-response` batch carrying the response, once it has started.
+    batch: Option<String>,
+    responses: Vec<Message>,
+    tx: oneshot::Sender<Vec<Message>>,
+}
+
+/// A `CHATHISTORY` request awaiting the batch of messages it asked for.
+#[derive(Debug)]
+struct PendingHistory {
+    /// The target whose history was requested, which the server names in the batch.
+    target: String,
+    tx: oneshot::Sender<error::Result<Vec<Message>>>,
+}
+
+/// A `WHO` query collecting its replies until the server ends the list.
+#[derive(Debug)]
+struct PendingWho {
+    /// The mask that was queried, which the server echoes back in `RPL_ENDOFWHO`.
+    mask: String,
+    /// The token sent along with a WHOX query, or `None` for a plain `WHO`.
+    token: Option<String>,
+    replies: Vec<WhoReply>,
+    tx: oneshot::Sender<error::Result<Vec<WhoReply>>>,
+}
+
+/// A `JOIN` awaiting confirmation that we joined the channel.
+#[derive(Debug)]
+struct PendingJoin {
+    channel: String,
+    tx: oneshot::Sender<error::Result<()>>,
+}
+
+/// A handler for the channels listed in reply to a `LIST`, shared so that it can be called without
+/// holding the lock on the pending lists.
+type ListHandler = Arc<Mutex<Box<dyn FnMut(ChannelListEntry) + Send>>>;
+
+/// Where the channels listed in reply to a pending `LIST` go.
+enum ListSink {
+    /// The channels are collected and returned once the list ends.
+    Collect(Vec<ChannelListEntry>),
+    /// Each channel is passed to a handler as soon as it arrives.
+    Each(ListHandler),
+}
+
+/// A `LIST` awaiting the end of its replies.
+struct PendingList {
+    sink: ListSink,
+    tx: oneshot::Sender<Vec<ChannelListEntry>>,
+}
+
+impl fmt::Debug for PendingList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.sink {
+            ListSink::Collect(ref entries) => {
+                write!(f, "PendingList({} entries)", entries.len())
             }
-            other => Poll::Ready(other),
+            ListSink::Each(_) => write!(f, "PendingList(handler)"),
         }
     }
 }
@@ -498,19 +1190,186 @@ struct ClientState {
     config: Config,
     /// A thread-safe map of channels to the list of users in them.
     chanlists: RwLock<HashMap<String, Vec<User>>>,
+    /// The channels whose list of users is complete, i.e. whose `NAMES` burst has ended with
+    /// `RPL_ENDOFNAMES`, keyed by lowercase channel.
+    names_complete: RwLock<HashSet<String>>,
+    /// Where each user listed so far sits in the user list of the channels whose `NAMES` burst is
+    /// underway, keyed by folded channel and then nickname. Dropped at `RPL_ENDOFNAMES`.
+    names_index: Mutex<HashMap<String, HashMap<String, usize>>>,
     /// A thread-safe index to track the current alternative nickname being used.
     alt_nick_index: RwLock<usize>,
+    /// The nickname the server currently knows us by, once it has told us.
+    nickname: RwLock<Option<String>>,
+    /// Thread-safe information advertised by the server, e.g. ISUPPORT tokens.
+    server_info: RwLock<ServerInfo>,
+    /// The server's message of the day, once it has been received in full.
+    motd: RwLock<Option<String>>,
+    /// The lines of the message of the day received so far, while it is being received.
+    motd_lines: Mutex<Option<Vec<String>>>,
+    /// The certificate the server presented for the current connection, if it is secured.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    peer_certificate: RwLock<Option<Vec<u8>>>,
+    /// The address of the remote end of the current connection, if it is not a mock connection.
+    peer_addr: RwLock<Option<SocketAddr>>,
+    /// The address the current connection was made from, if it is not a mock connection.
+    local_addr: RwLock<Option<SocketAddr>>,
+    /// Where the current connection puts the bytes of the line the most recently received message
+    /// was parsed from, if they are kept.
+    raw_bytes: RwLock<Arc<Mutex<Option<Vec<u8>>>>>,
+    /// Whether or not the server has told us we're an IRC operator.
+    is_oper: RwLock<bool>,
+    /// Our current server notice mask, as last reported by the server.
+    snomask: RwLock<Option<String>>,
+    /// Our away message, if we have marked ourselves as away.
+    away_message: RwLock<Option<String>>,
+    /// The away messages of other users known to be away, keyed by lowercase nickname.
+    away_users: RwLock<HashMap<String, String>>,
+    /// The accounts users (including us) are known to be logged into, keyed by lowercase nickname.
+    accounts: RwLock<HashMap<String, String>>,
+    /// The real names of users (including us), keyed by lowercase nickname.
+    realnames: RwLock<HashMap<String, String>>,
+    /// The ban lists of the channels we've received them for.
+    ban_lists: RwLock<BanLists>,
+    /// The quiet lists of the channels we've received them for.
+    quiet_lists: RwLock<BanLists>,
+    /// The number of times we've rejoined each channel after being kicked, keyed by lowercase
+    /// channel.
+    rejoin_attempts: Mutex<HashMap<String, u32>>,
+    /// Invitations to us for channels we haven't joined since, oldest first.
+    pending_invites: RwLock<Vec<Invite>>,
+    /// User-registered handlers run when an invitation is received.
+    invite_handlers: RwLock<Handlers<Invite>>,
+    /// The recently received messages, used to suppress ones received again. This is kept across
+    /// reconnections, since that is when bouncers replay messages.
+    dedup: Mutex<Deduplicator>,
+    /// The masks of users whose messages are kept out of the `ClientStream`.
+    ignores: RwLock<IgnoreList>,
+    /// User-registered handlers run on incoming messages after the built-in handling.
+    middleware: RwLock<Middleware>,
+    /// User-registered handlers run on outgoing messages, shared with `Outgoing`.
+    outbound_middleware: Arc<RwLock<Middleware>>,
+    /// The nicknames watched with `MONITOR`, keyed in lowercase, with their last reported status.
+    monitored: RwLock<HashMap<String, Option<bool>>>,
+    /// User-registered handlers run when a monitored nickname comes online or goes offline.
+    monitor_handlers: RwLock<Handlers<MonitorEvent>>,
+    /// Batches that have been opened but have not ended yet.
+    batches: Mutex<BatchCollector>,
+    /// User-registered handlers run when a batch ends.
+    batch_handlers: RwLock<Handlers<Batch>>,
+    /// `CHATHISTORY` requests awaiting their batches, oldest first.
+    pending_histories: Mutex<VecDeque<PendingHistory>>,
+    /// Requests awaiting their replies, oldest first.
+    pending_requests: Mutex<Vec<PendingRequest>>,
+    /// The capabilities the server has acknowledged, which are enabled for this connection.
+    enabled_caps: RwLock<HashSet<String>>,
+    /// The capabilities listed so far in a `CAP LS` reply spanning several lines.
+    listed_caps: Mutex<HashSet<String>>,
+    /// Whether a complete `CAP LS` reply has been received on this connection.
+    caps_listed: AtomicBool,
+    /// Whether `CAP END` is held back until the required capabilities have been acknowledged.
+    cap_end_pending: AtomicBool,
+    /// Labeled commands awaiting their responses.
+    pending_labels: Mutex<Vec<PendingLabel>>,
+    /// A counter used to generate labels for `labeled-response`.
+    next_label: AtomicUsize,
+    /// `WHO` queries awaiting the end of their replies, oldest first.
+    pending_whos: Mutex<Vec<PendingWho>>,
+    /// `JOIN`s awaiting our own `JOIN` being echoed back, or an error reply.
+    pending_joins: Mutex<Vec<PendingJoin>>,
+    /// `LIST`s awaiting the end of their replies, oldest first.
+    pending_lists: Mutex<VecDeque<PendingList>>,
+    /// A counter used to tag WHOX queries so that their replies can be told apart.
+    next_who_token: AtomicUsize,
+    /// Whether we're waiting for NickServ to respond to our identification.
+    identify_pending: AtomicBool,
+    /// Whether joining channels was put off until NickServ responds to our identification.
+    joins_deferred: AtomicBool,
+    /// When to give up waiting for our identification to be confirmed and join channels anyway.
+    identify_deadline: Mutex<Option<Pin<Box<Sleep>>>>,
+    /// Whether we sent a `QUIT` on the current connection, so that the server's `ERROR` is expected.
+    quit_sent: AtomicBool,
+    /// Whether the server sent an `ERROR` we didn't ask for, which fails the stream once the
+    /// message itself has been yielded.
+    error_pending: AtomicBool,
+    /// The reason given by the server in the last `ERROR` it sent.
+    last_error: RwLock<Option<String>>,
+    /// Delayed work spawned for the current connection, which is aborted when it ends.
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    /// Whether `Client::reconnect_now` has asked the `ClientStream` to reconnect.
+    reconnect_requested: AtomicBool,
+    /// Wakes the `ClientStream` when a reconnect is requested.
+    reconnect_waker: AtomicWaker,
+    #[cfg(test)]
+    /// A view of the logs for the mock connection established by the last reconnect.
+    reconnected_view: RwLock<Option<self::transport::LogView>>,
     /// Default ghost sequence to send if one is required but none is configured.
     default_ghost_sequence: Vec<String>,
 }
 
 impl ClientState {
-    fn new(sender: Sender, config: Config) -> ClientState {
+    fn new(
+        sender: Sender,
+        config: Config,
+        outbound_middleware: Arc<RwLock<Middleware>>,
+    ) -> ClientState {
         ClientState {
             sender,
+            ignores: RwLock::new(IgnoreList::new(config.ignores())),
+            dedup: Mutex::new(Deduplicator::new(config.dedup_window() as usize)),
             config,
             chanlists: RwLock::new(HashMap::new()),
+            names_complete: RwLock::new(HashSet::new()),
+            names_index: Mutex::new(HashMap::new()),
             alt_nick_index: RwLock::new(0),
+            nickname: RwLock::new(None),
+            server_info: RwLock::new(ServerInfo::new()),
+            motd: RwLock::new(None),
+            motd_lines: Mutex::new(None),
+            #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+            peer_certificate: RwLock::new(None),
+            peer_addr: RwLock::new(None),
+            local_addr: RwLock::new(None),
+            raw_bytes: RwLock::new(Arc::new(Mutex::new(None))),
+            is_oper: RwLock::new(false),
+            snomask: RwLock::new(None),
+            away_message: RwLock::new(None),
+            away_users: RwLock::new(HashMap::new()),
+            accounts: RwLock::new(HashMap::new()),
+            realnames: RwLock::new(HashMap::new()),
+            ban_lists: RwLock::new(BanLists::default()),
+            quiet_lists: RwLock::new(BanLists::default()),
+            rejoin_attempts: Mutex::new(HashMap::new()),
+            pending_invites: RwLock::new(Vec::new()),
+            invite_handlers: RwLock::new(Handlers::default()),
+            middleware: RwLock::new(Middleware::default()),
+            outbound_middleware,
+            monitored: RwLock::new(HashMap::new()),
+            monitor_handlers: RwLock::new(Handlers::default()),
+            batches: Mutex::new(BatchCollector::default()),
+            batch_handlers: RwLock::new(Handlers::default()),
+            pending_histories: Mutex::new(VecDeque::new()),
+            pending_requests: Mutex::new(Vec::new()),
+            enabled_caps: RwLock::new(HashSet::new()),
+            listed_caps: Mutex::new(HashSet::new()),
+            caps_listed: AtomicBool::new(false),
+            cap_end_pending: AtomicBool::new(false),
+            pending_labels: Mutex::new(Vec::new()),
+            next_label: AtomicUsize::new(1),
+            pending_whos: Mutex::new(Vec::new()),
+            pending_joins: Mutex::new(Vec::new()),
+            pending_lists: Mutex::new(VecDeque::new()),
+            next_who_token: AtomicUsize::new(0),
+            identify_pending: AtomicBool::new(false),
+            joins_deferred: AtomicBool::new(false),
+            identify_deadline: Mutex::new(None),
+            quit_sent: AtomicBool::new(false),
+            error_pending: AtomicBool::new(false),
+            last_error: RwLock::new(None),
+            tasks: Mutex::new(Vec::new()),
+            reconnect_requested: AtomicBool::new(false),
+            reconnect_waker: AtomicWaker::new(),
+            #[cfg(test)]
+            reconnected_view: RwLock::new(None),
             default_ghost_sequence: vec![String::from("GHOST")],
         }
     }
@@ -519,1179 +1378,6962 @@ impl ClientState {
         &self.config
     }
 
-    fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
-        let msg = msg.into();
-        self.handle_sent_message(&msg)?;
-        self.sender.send(msg)
+    /// Determines whether the message is a `PRIVMSG` or `NOTICE` from an ignored user.
+    fn is_ignored(&self, msg: &Message) -> bool {
+        match (&msg.command, &msg.prefix) {
+            (PRIVMSG(..) | NOTICE(..), Some(Prefix::Nickname(nick, user, host))) => self
+                .ignores
+                .read()
+                .matches(&format!("{}!{}@{}", nick, user, host)),
+            _ => false,
+        }
     }
 
-    /// Gets the current nickname in use.
-    fn current_nickname(&self) -> &str {
-        let alt_nicks = self.config().alternate_nicknames();
-        let index = self.alt_nick_index.read();
-
-        match *index {
-            0 => self
-                .config()
-                .nickname()
-                .expect("current_nickname should not be callable if nickname is not defined."),
-            i => alt_nicks[i - 1].as_str(),
+    /// Forgets everything learned over the current connection, ahead of registering on a new one.
+    fn reset(&self) {
+        self.chanlists.write().clear();
+        *self.alt_nick_index.write() = 0;
+        *self.nickname.write() = None;
+        *self.server_info.write() = ServerInfo::new();
+        *self.motd.write() = None;
+        *self.motd_lines.lock() = None;
+        *self.is_oper.write() = false;
+        *self.snomask.write() = None;
+        // Our own away message is kept so that it can be restored after the MOTD.
+        self.away_users.write().clear();
+        self.accounts.write().clear();
+        self.realnames.write().clear();
+        self.ban_lists.write().clear();
+        self.quiet_lists.write().clear();
+        self.rejoin_attempts.lock().clear();
+        self.identify_pending.store(false, Ordering::SeqCst);
+        self.joins_deferred.store(false, Ordering::SeqCst);
+        *self.identify_deadline.lock() = None;
+        self.quit_sent.store(false, Ordering::SeqCst);
+        self.error_pending.store(false, Ordering::SeqCst);
+        // Whatever was scheduled for the old connection must not reach the new one.
+        for task in self.tasks.lock().drain(..) {
+            task.abort();
+        }
+        self.pending_invites.write().clear();
+        self.names_complete.write().clear();
+        self.names_index.lock().clear();
+        // The new connection starts with an empty MONITOR list, which is restored after the MOTD.
+        for status in self.monitored.write().values_mut() {
+            *status = None;
         }
+        self.pending_requests.lock().clear();
+        self.enabled_caps.write().clear();
+        self.listed_caps.lock().clear();
+        self.caps_listed.store(false, Ordering::SeqCst);
+        self.cap_end_pending.store(false, Ordering::SeqCst);
+        self.pending_labels.lock().clear();
+        self.pending_whos.lock().clear();
+        self.pending_joins.lock().clear();
+        self.pending_lists.lock().clear();
+        self.batches.lock().clear();
+        self.pending_histories.lock().clear();
     }
 
-    /// Handles sent messages internally for basic client functionality.
-    fn handle_sent_message(&self, msg: &Message) -> error::Result<()> {
-        log::trace!("[SENT] {}", msg.to_string());
-
-        if let PART(ref chan, _) = msg.command {
-            let _ = self.chanlists.write().remove(chan);
+    /// Sends the message as a request, returning a future that resolves with the first reply
+    /// using the same command, or with `Error::RequestFailed` if a matching `FAIL` arrives first.
+    fn request(
+        &self,
+        msg: Message,
+        context: Vec<String>,
+    ) -> impl Future<Output = error::Result<Message>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        let command = String::from(&msg.command);
+        let command = command.split(' ').next().unwrap_or("").to_owned();
+        let mut pending = self.pending_requests.lock();
+        // Requests whose futures were dropped would otherwise linger until the connection ends.
+        pending.retain(|req| !req.tx.is_closed());
+        pending.push(PendingRequest {
+            command,
+            context,
+            tx,
+        });
+        drop(pending);
+        let sent = self.send(msg);
+
+        async move {
+            sent?;
+            rx.await.map_err(|_| error::Error::OneShotCanceled)?
         }
-
-        Ok(())
     }
 
-    /// Handles received messages internally for basic client functionality.
-    fn handle_message(&self, msg: &Message) -> error::Result<()> {
-        log::trace!("[RECV] {}", msg.to_string());
-        match msg.command {
-            JOIN(ref chan, _, _) => self.handle_join(msg.source_nickname().unwrap_or(""), chan),
-            PART(ref chan, _) => self.handle_part(msg.source_nickname().unwrap_or(""), chan),
-            KICK(ref chan, ref user, _) => self.handle_part(user, chan),
-            QUIT(_) => self.handle_quit(msg.source_nickname().unwrap_or("")),
-            NICK(ref new_nick) => {
-                self.handle_nick_change(msg.source_nickname().unwrap_or(""), new_nick)
-            }
-            ChannelMODE(ref chan, ref modes) => self.handle_mode(chan, modes),
-            PRIVMSG(ref target, ref body) => {
-                if body.starts_with('\u{001}') {
-                    let tokens: Vec<_> = {
-                        let end = if body.ends_with('\u{001}') && body.len() > 1 {
-                            body.len() - 1
-                        } else {
-                            body.len()
-                        };
-                        body[1..end].split(' ').collect()
-                    };
-                    if target.starts_with('#') {
-                        self.handle_ctcp(target, &tokens)?
-                    } else if let Some(user) = msg.source_nickname() {
-                        self.handle_ctcp(user, &tokens)?
-                    }
+    /// Resolves the oldest pending request that the message is a reply to, if any.
+    fn handle_pending_request(&self, msg: &Message) {
+        let mut pending = self.pending_requests.lock();
+        let (index, result) = match StandardReply::from_command(&msg.command) {
+            Some(reply) if reply.kind == StandardReplyKind::Fail => {
+                match pending.iter().position(|req| req.matches(&reply)) {
+                    Some(index) => (index, Err(error::Error::RequestFailed { reply })),
+                    None => return,
                 }
             }
-            Command::Response(Response::RPL_NAMREPLY, ref args) => self.handle_namreply(args),
-            Command::Response(Response::RPL_ENDOFMOTD, _)
-            | Command::Response(Response::ERR_NOMOTD, _) => {
-                self.send_nick_password()?;
-                self.send_umodes()?;
-
-                let config_chans = self.config().channels();
-                for chan in config_chans {
-                    match self.config().channel_key(chan) {
-                        Some(key) => self.send_join_with_keys::<&str, &str>(chan, key)?,
-                        None => self.send_join(chan)?,
+            Some(_) => return,
+            None => match msg.command {
+                Command::Raw(ref command, _) => {
+                    match pending
+                        .iter()
+                        .position(|req| req.command.eq_ignore_ascii_case(command))
+                    {
+                        Some(index) => (index, Ok(msg.clone())),
+                        None => return,
                     }
                 }
-                let joined_chans = self.chanlists.read();
-                for chan in joined_chans
-                    .keys()
-                    .filter(|x| !config_chans.iter().any(|c| c == *x))
-                {
-                    self.send_join(chan)?
-                }
-            }
-            Command::Response(Response::ERR_NICKNAMEINUSE, _)
-            | Command::Response(Response::ERR_ERRONEOUSNICKNAME, _) => {
-                let alt_nicks = self.config().alternate_nicknames();
-                let mut index = self.alt_nick_index.write();
+                _ => return,
+            },
+        };
+        // The request's future may have been dropped, in which case nobody needs the result.
+        let _ = pending.remove(index).tx.send(result);
+    }
 
-                if *index >= alt_nicks.len() {
-                    return Err(error::Error::NoUsableNick);
-                } else {
-                    self.send(NICK(alt_nicks[*index].to_owned()))?;
-                    *index += 1;
-                }
-            }
-            _ => (),
-        }
-        Ok(())
+    /// Sends the message with a `label` tag, returning a future that resolves with the messages
+    /// the server labels as its response.
+    fn send_labeled(
+        &self,
+        msg: Message,
+    ) -> impl Future<Output = error::Result<Vec<Message>>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        let sent = if self.enabled_caps.read().contains("labeled-response") {
+            let label = self.next_label.fetch_add(1, Ordering::Relaxed).to_string();
+            let mut tags = msg.tags.unwrap_or_default();
+            tags.push(Tag("label".to_owned(), Some(label.clone())));
+            let mut pending = self.pending_labels.lock();
+            pending.retain(|p| !p.tx.is_closed());
+            pending.push(PendingLabel {
+                label,
+                batch: None,
+                responses: Vec::new(),
+                tx,
+            });
+            drop(pending);
+            self.send(Message {
+                tags: Some(tags),
+                ..msg
+            })
+        } else {
+            Err(error::Error::CapabilityNotEnabled {
+                capability: "labeled-response".to_owned(),
+            })
+        };
+
+        async move {
+            sent?;
+            rx.await.map_err(|_| error::Error::OneShotCanceled)
+        }
     }
 
-    fn send_nick_password(&self) -> error::Result<()> {
-        if self.config().nick_password().is_empty() {
-            Ok(())
+    /// Collects the responses to labeled commands. A response is either a single message carrying
+    /// the label, an `ACK` carrying the label if there is nothing to respond with, or a
+    /// `labeled-response` batch carrying the label whose messages are collected until it ends.
+    fn handle_pending_label(&self, msg: &Message) {
+        let mut pending = self.pending_labels.lock();
+        if pending.is_empty() {
+            return;
+        }
+
+        let index = if let Some(label) = tag_value(msg, "label") {
+            let index = match pending.iter().position(|p| p.label == label) {
+                Some(index) => index,
+                None => return,
+            };
+            match msg.command {
+                Command::BATCH(ref reference, _, _) if reference.starts_with('+') => {
+                    pending[index].batch = Some(reference[1..].to_owned());
+                    return;
+                }
+                Command::Raw(ref command, _) if command.eq_ignore_ascii_case("ACK") => (),
+                _ => pending[index].responses.push(msg.clone()),
+            }
+            index
         } else {
-            let mut index = self.alt_nick_index.write();
+            let in_batch = |reference: &str| {
+                pending
+                    .iter()
+                    .position(|p| p.batch.as_deref() == Some(reference))
+            };
+            match msg.command {
+                Command::BATCH(ref reference, _, _) if reference.starts_with('-') => {
+                    match in_batch(&reference[1..]) {
+                        Some(index) => index,
+                        None => return,
+                    }
+                }
+                _ => {
+                    if let Some(index) = tag_value(msg, "batch").and_then(in_batch) {
+                        pending[index].responses.push(msg.clone());
+                    }
+                    return;
+                }
+            }
+        };
 
-            if self.config().should_ghost() && *index != 0 {
-                let seq = match self.config().ghost_sequence() {
-                    Some(seq) => seq,
-                    None => &*self.default_ghost_sequence,
-                };
+        let label = pending.remove(index);
+        // The command's future may have been dropped, in which case nobody needs the response.
+        let _ = label.tx.send(label.responses);
+    }
 
-                for s in seq {
-                    self.send(NICKSERV(vec![
-                        s.to_string(),
-                        self.config().nickname()?.to_string(),
-                        self.config().nick_password().to_string(),
-                    ]))?;
+    /// Updates the enabled capabilities from a `CAP ACK` or `CAP DEL`.
+    fn handle_cap(&self, cmd: &Command) -> error::Result<()> {
+        let (subcommand, first, second) = match *cmd {
+            CAP(_, ref subcommand, ref first, ref second) => (subcommand, first, second),
+            _ => return Ok(()),
+        };
+        // The capability list is the last parameter, whether or not there's a `*` before it.
+        let caps = second.as_ref().or(first.as_ref());
+        let caps = caps.iter().flat_map(|caps| caps.split_whitespace());
+        let required = self.config().required_capabilities();
+        let unavailable = |cap: &str| error::Error::RequiredCapabilityUnavailable {
+            capability: cap.to_owned(),
+        };
+
+        match *subcommand {
+            ACK | DEL => {
+                let mut enabled = self.enabled_caps.write();
+                for cap in caps {
+                    match cap.strip_prefix('-') {
+                        Some(cap) => enabled.remove(cap),
+                        None if *subcommand == DEL => enabled.remove(cap),
+                        None => enabled.insert(cap.to_owned()),
+                    };
+                }
+                let acknowledged = required.iter().all(|cap| enabled.contains(cap));
+                drop(enabled);
+                // With `sasl` required, the caller authenticates first, and `RPL_SASLSUCCESS` ends
+                // negotiation instead.
+                let authenticating = required.iter().any(|cap| cap == "sasl");
+                if *subcommand == ACK
+                    && acknowledged
+                    && !authenticating
+                    && self.cap_end_pending.swap(false, Ordering::SeqCst)
+                {
+                    self.send(CAP(None, END, None, None))?;
                 }
-                *index = 0;
-                self.send(NICK(self.config().nickname()?.to_owned()))?
             }
-
-            self.send(NICKSERV(vec![
-                "IDENTIFY".to_string(),
-                self.config().nick_password().to_string(),
-            ]))
+            LS => {
+                let mut listed = self.listed_caps.lock();
+                // Capabilities may have values, e.g. `sasl=PLAIN,EXTERNAL`.
+                listed.extend(caps.map(|cap| cap.split('=').next().unwrap_or(cap).to_owned()));
+                // With version 302, a `*` before the list means that more lines follow.
+                if second.is_some() && first.as_deref() == Some("*") {
+                    return Ok(());
+                }
+                let caps = std::mem::take(&mut *listed);
+                drop(listed);
+                if let Some(cap) = required.iter().find(|cap| !caps.contains(&cap[..])) {
+                    return Err(unavailable(cap));
+                }
+                let first_listing = !self.caps_listed.swap(true, Ordering::SeqCst);
+                if first_listing && self.cap_end_pending.load(Ordering::SeqCst) {
+                    self.send(CAP(None, REQ, None, Some(required.join(" "))))?;
+                }
+            }
+            NAK => {
+                if let Some(cap) = caps
+                    .into_iter()
+                    .find(|cap| required.iter().any(|r| r == cap))
+                {
+                    return Err(unavailable(cap));
+                }
+            }
+            _ => (),
         }
+        Ok(())
     }
 
-    fn send_umodes(&self) -> error::Result<()> {
-        if self.config().umodes().is_empty() {
-            Ok(())
+    /// Sends a `CHATHISTORY` request, returning a future that resolves with the messages in the
+    /// `chathistory` batch the server sends in reply, sorted by their `time` tags.
+    fn chathistory(
+        &self,
+        target: &str,
+        query: &HistoryQuery,
+        limit: u32,
+    ) -> impl Future<Output = error::Result<Vec<Message>>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        let enabled = {
+            let caps = self.enabled_caps.read();
+            caps.contains("draft/chathistory") || caps.contains("chathistory")
+        };
+        let sent = if enabled {
+            let mut pending = self.pending_histories.lock();
+            pending.retain(|req| !req.tx.is_closed());
+            pending.push_back(PendingHistory {
+                target: target.to_owned(),
+                tx,
+            });
+            drop(pending);
+            self.send(Command::Raw(
+                "CHATHISTORY".to_owned(),
+                query.to_args(target, limit),
+            ))
         } else {
-            self.send_mode(
-                self.current_nickname(),
-                &Mode::as_user_modes(
-                    self.config()
-                        .umodes()
-                        .split(' ')
-                        .collect::<Vec<_>>()
-                        .as_ref(),
-                )
-                .map_err(|e| error::Error::InvalidMessage {
-                    string: format!(
-                        "MODE {} {}",
-                        self.current_nickname(),
-                        self.config().umodes()
-                    ),
-                    cause: e,
-                })?,
-            )
+            Err(error::Error::CapabilityNotEnabled {
+                capability: "draft/chathistory".to_owned(),
+            })
+        };
+
+        async move {
+            sent?;
+            let mut messages = rx.await.map_err(|_| error::Error::OneShotCanceled)??;
+            sort_by_time(&mut messages);
+            Ok(messages)
         }
     }
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_join(&self, _: &str, _: &str) {}
+    /// Resolves the oldest `CHATHISTORY` request for the target of any `chathistory` batch in the
+    /// given batch, which may itself be one or contain one, e.g. in a `labeled-response` batch.
+    fn handle_pending_history(&self, batch: &Batch) {
+        let mut pending = self.pending_histories.lock();
+        if pending.is_empty() {
+            return;
+        }
 
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_join(&self, src: &str, chan: &str) {
-        if let Some(vec) = self.chanlists.write().get_mut(&chan.to_owned()) {
-            if !src.is_empty() {
-                vec.push(User::new(src))
+        let is_history = batch.kind == BatchSubCommand::CUSTOM("CHATHISTORY".to_owned());
+        if let (true, Some(target)) = (is_history, batch.params.first()) {
+            if let Some(index) = pending
+                .iter()
+                .position(|req| req.target.eq_ignore_ascii_case(target))
+            {
+                let req = pending.remove(index).expect("index was just found");
+                // The request's future may have been dropped, in which case nobody needs it.
+                let _ = req.tx.send(Ok(batch.messages.clone()));
             }
+            return;
         }
-    }
+        drop(pending);
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_part(&self, _: &str, _: &str) {}
+        for nested in &batch.batches {
+            self.handle_pending_history(nested);
+        }
+    }
 
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_part(&self, src: &str, chan: &str) {
-        if let Some(vec) = self.chanlists.write().get_mut(&chan.to_owned()) {
-            if !src.is_empty() {
-                if let Some(n) = vec.iter().position(|x| x.get_nickname() == src) {
-                    vec.swap_remove(n);
-                }
+    /// Fails the `CHATHISTORY` request a `FAIL CHATHISTORY` refers to: the oldest one for a target
+    /// named in its context, or else the oldest one.
+    fn handle_history_fail(&self, msg: &Message) {
+        let reply = match StandardReply::from_command(&msg.command) {
+            Some(reply)
+                if reply.kind == StandardReplyKind::Fail
+                    && reply.command.eq_ignore_ascii_case("CHATHISTORY") =>
+            {
+                reply
             }
+            _ => return,
+        };
+        let mut pending = self.pending_histories.lock();
+        let index = pending
+            .iter()
+            .position(|req| {
+                reply
+                    .context
+                    .iter()
+                    .any(|param| req.target.eq_ignore_ascii_case(param))
+            })
+            .unwrap_or(0);
+        if let Some(req) = pending.remove(index) {
+            // The request's future may have been dropped, in which case nobody needs it.
+            let _ = req.tx.send(Err(error::Error::RequestFailed { reply }));
         }
     }
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_quit(&self, _: &str) {}
-
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_quit(&self, src: &str) {
-        if src.is_empty() {
-            return;
+    /// Sends a `WHO` for the mask, returning a future that resolves with every reply once the
+    /// server ends the list. WHOX is used when the server supports it.
+    fn who(&self, mask: &str) -> impl Future<Output = error::Result<Vec<WhoReply>>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        let token = if self.server_info.read().supports("WHOX") {
+            // WHOX tokens are limited to three digits.
+            let token = self.next_who_token.fetch_add(1, Ordering::Relaxed) % 999 + 1;
+            Some(token.to_string())
+        } else {
+            None
+        };
+        let msg = match token {
+            Some(ref token) => Command::Raw(
+                "WHO".to_owned(),
+                vec![mask.to_owned(), format!("%{},{}", WHOX_FIELDS, token)],
+            ),
+            None => Command::WHO(Some(mask.to_owned()), None),
+        };
+        let mut pending = self.pending_whos.lock();
+        pending.retain(|who| !who.tx.is_closed());
+        pending.push(PendingWho {
+            mask: mask.to_owned(),
+            token,
+            replies: Vec::new(),
+            tx,
+        });
+        drop(pending);
+        let sent = self.send(msg);
+
+        async move {
+            sent?;
+            rx.await.map_err(|_| error::Error::OneShotCanceled)?
         }
+    }
 
-        for vec in self.chanlists.write().values_mut() {
-            if let Some(p) = vec.iter().position(|x| x.get_nickname() == src) {
-                vec.swap_remove(p);
+    /// Collects `WHO` replies into the pending query they belong to, and resolves a query once the
+    /// server ends its list. WHOX replies are matched by their token, while plain replies go to
+    /// the oldest plain query since servers answer queries in order.
+    fn handle_pending_who(&self, msg: &Message) {
+        let mut pending = self.pending_whos.lock();
+        match msg.command {
+            Command::Response(Response::RPL_WHOREPLY, ref args) => {
+                if let (Some(reply), Some(who)) = (
+                    WhoReply::from_whoreply(args),
+                    pending.iter_mut().find(|who| who.token.is_none()),
+                ) {
+                    who.replies.push(reply);
+                }
+            }
+            Command::Response(Response::RPL_WHOSPCRPL, ref args) => {
+                if let Some((token, reply)) = WhoReply::from_whox(args) {
+                    if let Some(mode) = self.server_info.read().bot_mode() {
+                        self.handle_bot(&reply.nickname, reply.flags.contains(mode));
+                    }
+                    self.set_user_account(&reply.nickname, reply.account.as_deref());
+                    if let Some(who) = pending
+                        .iter_mut()
+                        .find(|who| who.token.as_deref() == Some(token))
+                    {
+                        who.replies.push(reply);
+                    }
+                }
+            }
+            Command::Response(Response::RPL_ENDOFWHO, ref args) => {
+                let mask = args.get(1).map_or("", |mask| &mask[..]);
+                if let Some(index) = pending
+                    .iter()
+                    .position(|who| who.mask.eq_ignore_ascii_case(mask))
+                {
+                    let who = pending.remove(index);
+                    // The query's future may have been dropped, in which case nobody needs it.
+                    let _ = who.tx.send(Ok(who.replies));
+                }
             }
+            _ => (),
         }
     }
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_nick_change(&self, _: &str, _: &str) {}
+    /// Sends a `JOIN` for the channel, returning a future that resolves once the server echoes our
+    /// `JOIN` back, or with `Error::JoinFailed` (or `Error::RequestFailed` for a `FAIL JOIN`) if it
+    /// refuses to let us in, or with `Error::JoinTimedOut` after the configured `join_timeout`. If
+    /// we are known to be in the channel already, nothing is sent and the future resolves right
+    /// away.
+    fn join(
+        &self,
+        chan: &str,
+        key: Option<&str>,
+    ) -> impl Future<Output = error::Result<()>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        let sent = if self.is_joined(chan) {
+            let _ = tx.send(Ok(()));
+            Ok(())
+        } else {
+            let mut pending = self.pending_joins.lock();
+            pending.retain(|join| !join.tx.is_closed());
+            pending.push(PendingJoin {
+                channel: chan.to_owned(),
+                tx,
+            });
+            drop(pending);
+            self.send(JOIN(chan.to_owned(), key.map(|k| k.to_owned()), None))
+        };
+        let timeout = self.config().join_timeout();
+        let channel = chan.to_owned();
+
+        async move {
+            sent?;
+            if timeout == 0 {
+                return rx.await.map_err(|_| error::Error::OneShotCanceled)?;
+            }
+            // A timed out join's sender is dropped with `rx`, and pruned once another is sent.
+            match tokio::time::timeout(Duration::from_secs(timeout.into()), rx).await {
+                Ok(result) => result.map_err(|_| error::Error::OneShotCanceled)?,
+                Err(_) => Err(error::Error::JoinTimedOut { channel }),
+            }
+        }
+    }
 
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_nick_change(&self, old_nick: &str, new_nick: &str) {
-        if old_nick.is_empty() || new_nick.is_empty() {
+    /// Resolves the pending joins for a channel once we joined it or the server refused.
+    fn handle_pending_join(&self, msg: &Message) {
+        let mut pending = self.pending_joins.lock();
+        if pending.is_empty() {
             return;
         }
 
-        for (_, vec) in self.chanlists.write().iter_mut() {
-            if let Some(n) = vec.iter().position(|x| x.get_nickname() == old_nick) {
-                let new_entry = User::new(new_nick);
-                vec[n] = new_entry;
+        // Servers using standard replies send e.g. `FAIL JOIN CHANNEL_IS_FULL #chan :...` instead
+        // of a numeric.
+        let fail = StandardReply::from_command(&msg.command).filter(|reply| {
+            reply.kind == StandardReplyKind::Fail && reply.command.eq_ignore_ascii_case("JOIN")
+        });
+        let (chan, response) = match msg.command {
+            JOIN(ref chan, _, _)
+                if msg
+                    .source_nickname()
+                    .map_or(false, |nick| self.is_current_nickname(nick)) =>
+            {
+                (&chan[..], None)
+            }
+            Command::Response(
+                response @ (Response::ERR_NOSUCHCHANNEL
+                | Response::ERR_TOOMANYCHANNELS
+                | Response::ERR_LINKCHANNEL
+                | Response::ERR_CHANNELISFULL
+                | Response::ERR_INVITEONLYCHAN
+                | Response::ERR_BANNEDFROMCHAN
+                | Response::ERR_BADCHANNELKEY
+                | Response::ERR_BADCHANMASK
+                | Response::ERR_NOCHANMODES),
+                ref args,
+            ) => match args.get(1) {
+                Some(chan) => (&chan[..], Some(response)),
+                None => return,
+            },
+            _ => match fail.as_ref().and_then(|reply| reply.context.first()) {
+                Some(chan) => (&chan[..], None),
+                None => return,
+            },
+        };
+
+        let mut i = 0;
+        while i < pending.len() {
+            if pending[i].channel.eq_ignore_ascii_case(chan) {
+                let join = pending.remove(i);
+                let result = match (response, &fail) {
+                    (Some(response), _) => Err(error::Error::JoinFailed {
+                        channel: join.channel,
+                        response,
+                    }),
+                    (None, Some(reply)) => Err(error::Error::RequestFailed {
+                        reply: reply.clone(),
+                    }),
+                    (None, None) => Ok(()),
+                };
+                // The join's future may have been dropped, in which case nobody needs the result.
+                let _ = join.tx.send(result);
+            } else {
+                i += 1;
             }
         }
     }
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_mode(&self, _: &str, _: &[Mode<ChannelMode>]) {}
+    /// Sends a `LIST`, returning a future that resolves once the server ends the list. Listed
+    /// channels go to the given sink, and are returned if they were collected.
+    fn list(
+        &self,
+        cmd: Command,
+        sink: ListSink,
+    ) -> impl Future<Output = error::Result<Vec<ChannelListEntry>>> + 'static {
+        let (tx, rx) = oneshot::channel();
+        // Unlike other requests, a dropped `LIST` keeps its place: its replies carry nothing to
+        // tell them apart, so they must still be consumed by it rather than the next one.
+        self.pending_lists
+            .lock()
+            .push_back(PendingList { sink, tx });
+        let sent = self.send(cmd);
+
+        async move {
+            sent?;
+            rx.await.map_err(|_| error::Error::OneShotCanceled)
+        }
+    }
 
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_mode(&self, chan: &str, modes: &[Mode<ChannelMode>]) {
-        for mode in modes {
-            match *mode {
-                Mode::Plus(_, Some(ref user)) | Mode::Minus(_, Some(ref user)) => {
-                    if let Some(vec) = self.chanlists.write().get_mut(chan) {
-                        if let Some(n) = vec.iter().position(|x| x.get_nickname() == user) {
-                            vec[n].update_access_level(mode)
+    /// Passes listed channels to the oldest pending `LIST`, and resolves it once the server ends
+    /// the list. Servers answer one `LIST` at a time, so replies always belong to the oldest.
+    fn handle_pending_list(&self, msg: &Message) {
+        let mut pending = self.pending_lists.lock();
+        match msg.command {
+            Command::Response(Response::RPL_LIST, ref args) => {
+                if let (Some(entry), Some(list)) =
+                    (ChannelListEntry::from_args(args), pending.front_mut())
+                {
+                    match list.sink {
+                        ListSink::Collect(ref mut entries) => entries.push(entry),
+                        ListSink::Each(ref handler) => {
+                            let handler = Arc::clone(handler);
+                            // The handler may well use the client, e.g. to start another list.
+                            drop(pending);
+                            (handler.lock())(entry);
                         }
                     }
                 }
-                _ => (),
             }
+            Command::Response(Response::RPL_LISTEND, _) => {
+                if let Some(list) = pending.pop_front() {
+                    let entries = match list.sink {
+                        ListSink::Collect(entries) => entries,
+                        ListSink::Each(_) => Vec::new(),
+                    };
+                    // The list's future may have been dropped, in which case nobody needs it.
+                    let _ = list.tx.send(entries);
+                }
+            }
+            _ => (),
         }
     }
 
-    #[cfg(feature = "nochanlists")]
-    fn handle_namreply(&self, _: &[String]) {}
-
-    #[cfg(not(feature = "nochanlists"))]
-    fn handle_namreply(&self, args: &[String]) {
-        if args.len() == 4 {
-            let chan = &args[2];
-            for user in args[3].split(' ') {
-                self.chanlists
-                    .write()
-                    .entry(chan.clone())
-                    .or_insert_with(Vec::new)
-                    .push(User::new(user))
-            }
+    /// Sends WEBIRC if it is configured. Servers only accept it before any other command, so this
+    /// is queued as soon as each connection is established.
+    fn send_webirc(&self) -> error::Result<()> {
+        if let Some((password, gateway, hostname, ip)) = self.config().webirc() {
+            self.send(Command::Raw(
+                "WEBIRC".to_owned(),
+                vec![
+                    password.to_owned(),
+                    gateway.to_owned(),
+                    hostname.to_owned(),
+                    ip.to_owned(),
+                ],
+            ))?;
         }
+        Ok(())
     }
 
-    #[cfg(feature = "ctcp")]
-    fn handle_ctcp(&self, resp: &str, tokens: &[&str]) -> error::Result<()> {
-        if tokens.is_empty() {
-            return Ok(());
-        }
-        if tokens[0].eq_ignore_ascii_case("FINGER") {
-            self.send_ctcp_internal(
-                resp,
-                &format!(
-                    "FINGER :{} ({})",
-                    self.config().real_name(),
-                    self.config().username()
-                ),
-            )
-        } else if tokens[0].eq_ignore_ascii_case("VERSION") {
-            self.send_ctcp_internal(resp, &format!("VERSION {}", self.config().version()))
-        } else if tokens[0].eq_ignore_ascii_case("SOURCE") {
-            self.send_ctcp_internal(resp, &format!("SOURCE {}", self.config().source()))
-        } else if tokens[0].eq_ignore_ascii_case("PING") && tokens.len() > 1 {
-            self.send_ctcp_internal(resp, &format!("PING {}", tokens[1]))
-        } else if tokens[0].eq_ignore_ascii_case("TIME") {
-            self.send_ctcp_internal(resp, &format!("TIME :{}", Local::now().to_rfc2822()))
-        } else if tokens[0].eq_ignore_ascii_case("USERINFO") {
-            self.send_ctcp_internal(resp, &format!("USERINFO :{}", self.config().user_info()))
+    /// Sends a CAP END, NICK and USER to identify. With required capabilities, a CAP LS is sent
+    /// instead of the CAP END, which then waits until the capabilities have been listed and
+    /// acknowledged.
+    fn identify(&self) -> error::Result<()> {
+        if self.config().required_capabilities().is_empty() {
+            // Send a CAP END to signify that we're IRCv3-compliant (and to end negotiations!).
+            self.send(CAP(None, END, None, None))?;
         } else {
-            Ok(())
+            self.cap_end_pending.store(true, Ordering::SeqCst);
+            self.send(CAP(None, LS, Some("302".to_owned()), None))?;
         }
+        if !self.config().server_password().is_empty() {
+            self.send(PASS(self.config().server_password().to_owned()))?;
+        }
+        self.send(NICK(self.config().nickname()?.to_owned()))?;
+        self.send(USER(
+            self.config().username().to_owned(),
+            self.config().registration_mode().to_owned(),
+            expand_placeholders(self.config().real_name()).into_owned(),
+        ))?;
+        Ok(())
     }
 
-    #[cfg(feature = "ctcp")]
-    fn send_ctcp_internal(&self, target: &str, msg: &str) -> error::Result<()> {
-        self.send_notice(target, format!("\u{001}{}\u{001}", msg))
+    fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
+        let msg = self.fit_names(msg.into())?;
+        self.handle_sent_message(&msg)?;
+        self.sender.send(msg)
     }
 
-    #[cfg(not(feature = "ctcp"))]
-    fn handle_ctcp(&self, _: &str, _: &[&str]) -> error::Result<()> {
-        Ok(())
+    fn send_all<I, M>(&self, msgs: I) -> error::Result<()>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        let msgs = msgs
+            .into_iter()
+            .map(|msg| {
+                let msg = self.fit_names(msg.into())?;
+                self.handle_sent_message(&msg)?;
+                Ok(msg)
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        self.sender.send_all(msgs)
     }
 
-    pub_state_base!();
-}
+    /// Applies the configured `length_policy` to the nickname or username in a `NICK` or `USER`
+    /// command, using the lengths the server advertised.
+    fn fit_names(&self, mut msg: Message) -> error::Result<Message> {
+        let policy = self.config().length_policy();
+        if policy == LengthPolicy::Send {
+            return Ok(msg);
+        }
+        let info = self.server_info.read();
+        match msg.command {
+            NICK(ref mut nick) => *nick = policy.apply("nickname", nick, info.nick_length())?,
+            USER(ref mut user, _, _) => {
+                *user = policy.apply("username", user, info.user_length())?
+            }
+            _ => (),
+        }
+        Ok(msg)
+    }
 
-/// Thread-safe sender that can be used with the client.
-#[derive(Debug, Clone)]
-pub struct Sender {
-    tx_outgoing: UnboundedSender<Message>,
-}
+    /// Gets the current nickname in use. This is the nickname the server last assigned to us, or
+    /// the nickname we are attempting to register with if it has not told us yet.
+    fn current_nickname(&self) -> String {
+        if let Some(ref nick) = *self.nickname.read() {
+            return nick.clone();
+        }
 
-impl Sender {
-    /// Send a single message to the unbounded queue.
-    pub fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
-        Ok(self.tx_outgoing.send(msg.into())?)
+        self.nickname_at(*self.alt_nick_index.read())
+            .expect("current_nickname should not be callable if nickname is not defined.")
     }
 
-    pub_state_base!();
-    pub_sender_base!();
-}
+    /// Determines whether the nickname is our current one under the server's case mapping.
+    fn is_current_nickname(&self, nick: &str) -> bool {
+        let current = self.current_nickname();
+        self.server_info.read().eq_ignore_case(nick, &current)
+    }
 
-/// Future to handle outgoing messages.
-///
-/// Note: this is essentially the same as a version of [SendAll](https://github.com/rust-lang-nursery/futures-rs/blob/master/futures-util/src/sink/send_all.rs) that owns it's sink and stream.
-#[derive(Debug)]
-pub struct Outgoing {
-    sink: SplitSink<Connection, Message>,
-    stream: UnboundedReceiver<Message>,
-    buffered: Option<Message>,
-}
+    /// Converts a nickname or channel name to lower case under the server's case mapping, e.g. for
+    /// use as a key.
+    fn fold_case(&self, name: &str) -> String {
+        self.server_info.read().fold_case(name)
+    }
 
-impl Outgoing {
-    fn try_start_send(
-        &mut self,
-        cx: &mut Context<'_>,
-        message: Message,
-    ) -> Poll<Result<(), error::Error>> {
-        debug_assert!(self.buffered.is_none());
+    /// Gets the nickname to register with on the given attempt. The first attempt uses the primary
+    /// nickname, followed by each of the alternative nicknames, and then the primary nickname with
+    /// an incrementing numeric suffix.
+    fn nickname_at(&self, index: usize) -> error::Result<String> {
+        let nick = self.config().nickname()?;
+        let alt_nicks = self.config().alternate_nicknames();
 
-        match Pin::new(&mut self.sink).poll_ready(cx)? {
-            Poll::Ready(()) => Poll::Ready(Pin::new(&mut self.sink).start_send(message)),
-            Poll::Pending => {
-                self.buffered = Some(message);
-                Poll::Pending
-            }
-        }
+        Ok(match index {
+            0 => nick.to_owned(),
+            i if i <= alt_nicks.len() => alt_nicks[i - 1].clone(),
+            i => format!("{}{}", nick, i - alt_nicks.len()),
+        })
     }
-}
 
-impl FusedFuture for Outgoing {
-    fn is_terminated(&self) -> bool {
-        // NB: outgoing stream never terminates.
-        // TODO: should it terminate if rx_outgoing is terminated?
-        false
-    }
-}
+    /// Handles sent messages internally for basic client functionality.
+    fn handle_sent_message(&self, msg: &Message) -> error::Result<()> {
+        log::trace!("[SENT] {}", msg);
 
-impl Future for Outgoing {
-    type Output = error::Result<()>;
+        match msg.command {
+            PART(ref chan, _) => {
+                let users = self.chanlists.write().remove(chan).unwrap_or_default();
+                let nicks: Vec<_> = users.iter().map(|user| user.get_nickname()).collect();
+                self.forget_unshared_users(&nicks, None);
+                let folded = self.fold_case(chan);
+                self.names_complete.write().remove(&folded);
+                self.names_index.lock().remove(&folded);
+            }
+            AWAY(ref message) => {
+                *self.away_message.write() = message.clone().filter(|m| !m.is_empty());
+            }
+            QUIT(_) => self.quit_sent.store(true, Ordering::SeqCst),
+            // Whoever ends negotiation first, we don't need to end it again.
+            CAP(_, END, _, _) => self.cap_end_pending.store(false, Ordering::SeqCst),
+            _ => (),
+        }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = &mut *self;
+        Ok(())
+    }
 
-        if let Some(message) = this.buffered.take() {
-            ready!(this.try_start_send(cx, message))?
+    /// Handles received messages internally for basic client functionality.
+    fn handle_message(&self, msg: &Message) -> error::Result<()> {
+        log::trace!("[RECV] {}", msg);
+        self.handle_pending_request(msg);
+        self.handle_cap(&msg.command)?;
+        self.handle_pending_label(msg);
+        self.handle_pending_who(msg);
+        self.handle_pending_join(msg);
+        self.handle_history_fail(msg);
+        self.handle_batch(msg);
+        self.handle_pending_list(msg);
+        if let Some(nick) = msg.source_nickname() {
+            let has_bot_tag = msg.tags.as_ref().map_or(false, |tags| {
+                tags.iter()
+                    .any(|tag| tag.0 == "bot" || tag.0 == "draft/bot")
+            });
+            if has_bot_tag {
+                self.handle_bot(nick, true);
+            }
         }
-
-        loop {
-            match this.stream.poll_recv(cx) {
-                Poll::Ready(Some(message)) => ready!(this.try_start_send(cx, message))?,
-                Poll::Ready(None) => {
-                    ready!(Pin::new(&mut this.sink).poll_flush(cx))?;
-                    return Poll::Ready(Ok(()));
+        match msg.command {
+            JOIN(ref chan, ref account, ref realname) => {
+                let nick = msg.source_nickname().unwrap_or("");
+                if self.is_current_nickname(nick) {
+                    self.pending_invites
+                        .write()
+                        .retain(|invite| !invite.channel.eq_ignore_ascii_case(chan));
+                    self.handle_self_join(chan);
                 }
-                Poll::Pending => {
-                    ready!(Pin::new(&mut this.sink).poll_flush(cx))?;
-                    return Poll::Pending;
+                self.handle_join(nick, chan);
+                // With `extended-join`, the account (or `*` if none) and real name follow.
+                if let (Some(account), Some(realname)) = (account, realname) {
+                    self.set_user_account(nick, Some(&account[..]).filter(|a| *a != "*"));
+                    self.set_user_realname(nick, realname);
                 }
             }
-        }
-    }
-}
-
-/// The canonical implementation of a connection to an IRC server.
-///
-/// For a full example usage, see [`irc::client`](./index.html).
-#[derive(Debug)]
-pub struct Client {
-    /// The internal, thread-safe server state.
-    state: Arc<ClientState>,
-    incoming: Option<SplitStream<Connection>>,
-    outgoing: Option<Outgoing>,
-    sender: Sender,
-    #[cfg(test)]
-    /// A view of the logs for a mock connection.
-    view: Option<self::transport::LogView>,
-}
+            ACCOUNT(ref account) => {
+                if let Some(nick) = msg.source_nickname() {
+                    self.set_user_account(nick, Some(&account[..]).filter(|a| *a != "*"));
+                }
+            }
+            Command::SETNAME(ref realname) => {
+                if let Some(nick) = msg.source_nickname() {
+                    self.set_user_realname(nick, realname);
+                }
+            }
+            Command::CHGHOST(ref user, ref host) => {
+                if let Some(nick) = msg.source_nickname() {
+                    self.handle_chghost(nick, user, host);
+                }
+            }
+            PART(ref chan, _) => {
+                let nick = msg.source_nickname().unwrap_or("");
+                if self.is_current_nickname(nick) {
+                    self.forget_channel_lists(chan);
+                }
+                self.handle_part(nick, chan);
+                self.forget_unshared_users(&[nick], None);
+            }
+            KICK(ref chan, ref user, _) => {
+                self.handle_part(user, chan);
+                if self.is_current_nickname(user) {
+                    self.forget_channel_lists(chan);
+                    let nicks: Vec<_> = self
+                        .chanlists
+                        .read()
+                        .get(chan)
+                        .map(|users| users.iter().map(|u| u.get_nickname().to_owned()).collect())
+                        .unwrap_or_default();
+                    let nicks: Vec<_> = nicks.iter().map(|nick| &nick[..]).collect();
+                    self.forget_unshared_users(&nicks, Some(chan));
+                    self.handle_kicked(chan)?
+                } else {
+                    self.forget_unshared_users(&[user], None);
+                }
+            }
+            QUIT(_) => {
+                let nick = msg.source_nickname().unwrap_or("");
+                let key = self.fold_case(nick);
+                self.away_users.write().remove(&key);
+                self.accounts.write().remove(&key);
+                self.realnames.write().remove(&key);
+                self.handle_quit(nick)
+            }
+            NICK(ref new_nick) => {
+                let old_nick = msg.source_nickname().unwrap_or("");
+                if !old_nick.is_empty() && self.is_current_nickname(old_nick) {
+                    *self.nickname.write() = Some(new_nick.clone());
+                }
+                let (old_key, new_key) = (self.fold_case(old_nick), self.fold_case(new_nick));
+                let mut away_users = self.away_users.write();
+                if let Some(message) = away_users.remove(&old_key) {
+                    away_users.insert(new_key.clone(), message);
+                }
+                drop(away_users);
+                let mut accounts = self.accounts.write();
+                if let Some(account) = accounts.remove(&old_key) {
+                    accounts.insert(new_key.clone(), account);
+                }
+                drop(accounts);
+                let mut realnames = self.realnames.write();
+                if let Some(realname) = realnames.remove(&old_key) {
+                    realnames.insert(new_key, realname);
+                }
+                drop(realnames);
+                self.handle_nick_change(old_nick, new_nick)
+            }
+            INVITE(..) => self.handle_invite(msg),
+            // Sent right before the server closes the connection, whether in answer to our QUIT or
+            // because we were e.g. banned, in which case the stream fails after yielding it.
+            Command::ERROR(ref reason) => {
+                *self.last_error.write() = Some(reason.clone());
+                if !self.quit_sent.load(Ordering::SeqCst) {
+                    log::warn!("server closed the connection: {}", reason);
+                    self.error_pending.store(true, Ordering::SeqCst);
+                }
+            }
+            ChannelMODE(ref chan, ref modes) => {
+                self.handle_list_modes(msg, chan, modes);
+                self.handle_mode(chan, modes)
+            }
+            // Ignored users get no automatic replies, as they'd be an easy way to flood us off.
+            PRIVMSG(ref target, ref body)
+                if body.starts_with('\u{001}') && !self.is_ignored(msg) =>
+            {
+                let tokens: Vec<_> = {
+                    let end = if body.ends_with('\u{001}') && body.len() > 1 {
+                        body.len() - 1
+                    } else {
+                        body.len()
+                    };
+                    body[1..end].split(' ').collect()
+                };
+                if target.starts_with('#') {
+                    self.handle_ctcp(target, &tokens)?
+                } else if let Some(user) = msg.source_nickname() {
+                    self.handle_ctcp(user, &tokens)?
+                }
+            }
+            Command::Response(Response::RPL_WELCOME, ref args) => {
+                // A server that registered us without ever listing its capabilities can't be
+                // offering the ones we require.
+                let required = self.config().required_capabilities();
+                if !required.is_empty() && !self.caps_listed.load(Ordering::SeqCst) {
+                    return Err(error::Error::RequiredCapabilityUnavailable {
+                        capability: required[0].clone(),
+                    });
+                }
+                if let Some(nick) = args.first() {
+                    *self.nickname.write() = Some(nick.clone());
+                }
+                if self.config().nickserv_identify().is_some() {
+                    self.send_identify()?;
+                }
+            }
+            Command::Response(Response::RPL_HOSTHIDDEN, _) => self.handle_identified()?,
+            Command::Response(Response::RPL_SASLSUCCESS, _)
+                if self.cap_end_pending.load(Ordering::SeqCst) =>
+            {
+                self.send(CAP(None, END, None, None))?;
+            }
+            Command::Response(Response::RPL_NAMREPLY, ref args) => self.handle_namreply(args),
+            Command::Response(Response::RPL_ENDOFNAMES, ref args) => {
+                if let Some(chan) = args.get(1) {
+                    let folded = self.fold_case(chan);
+                    self.names_index.lock().remove(&folded);
+                    self.names_complete.write().insert(folded);
+                }
+            }
+            Command::Response(Response::RPL_WHOREPLY, ref args) => self.handle_whoreply(args),
+            Command::Response(Response::RPL_YOUREOPER, _) => *self.is_oper.write() = true,
+            // Sent to channel members of ours when the `away-notify` capability is enabled.
+            AWAY(ref message) => {
+                if let Some(nick) = msg.source_nickname() {
+                    self.set_user_away(nick, message.as_deref().filter(|m| !m.is_empty()))
+                }
+            }
+            Command::Response(Response::RPL_AWAY, ref args) => {
+                if let (Some(nick), Some(message)) = (args.get(1), args.get(2)) {
+                    self.set_user_away(nick, Some(message))
+                }
+            }
+            Command::Response(Response::RPL_LOGGEDIN, ref args) => {
+                if let Some(account) = args.get(2) {
+                    self.set_user_account(&self.current_nickname(), Some(account))
+                }
+                self.handle_identified()?
+            }
+            Command::Response(Response::RPL_BANLIST, ref args) => {
+                if let Some((chan, entry)) = BanEntry::from_banlist(args) {
+                    let chan = self.fold_case(chan);
+                    self.ban_lists.write().handle_entry(&chan, entry)
+                }
+            }
+            Command::Response(Response::RPL_ENDOFBANLIST, ref args) => {
+                if let Some(chan) = args.get(1) {
+                    let chan = self.fold_case(chan);
+                    self.ban_lists.write().handle_end(&chan)
+                }
+            }
+            Command::Response(Response::RPL_QUIETLIST, ref args) => {
+                if let Some((chan, entry)) = BanEntry::from_quietlist(args) {
+                    let chan = self.fold_case(chan);
+                    self.quiet_lists.write().handle_entry(&chan, entry)
+                }
+            }
+            Command::Response(Response::RPL_ENDOFQUIETLIST, ref args) => {
+                if let Some(chan) = args.get(1) {
+                    let chan = self.fold_case(chan);
+                    self.quiet_lists.write().handle_end(&chan)
+                }
+            }
+            Command::Response(Response::RPL_LOGGEDOUT, _) => {
+                self.set_user_account(&self.current_nickname(), None)
+            }
+            Command::Response(Response::RPL_UNAWAY, _) => *self.away_message.write() = None,
+            Command::Response(Response::RPL_NOWAWAY, _) => {
+                // We may have marked ourselves as away without going through `set_away`.
+                self.away_message.write().get_or_insert_with(String::new);
+            }
+            Command::Response(Response::RPL_SNOMASK, ref args) => {
+                if let Some(snomask) = args.get(1) {
+                    *self.snomask.write() = Some(snomask.clone());
+                }
+            }
+            UserMODE(ref target, ref modes) if self.is_current_nickname(target) => {
+                self.handle_user_mode(modes)
+            }
+            Command::Response(Response::RPL_ISUPPORT, ref args) => {
+                self.server_info.write().handle_isupport(args)
+            }
+            Command::Response(Response::RPL_MONONLINE, _)
+            | Command::Response(Response::RPL_MONOFFLINE, _)
+            | Command::Response(Response::RPL_LOGON, _)
+            | Command::Response(Response::RPL_LOGOFF, _)
+            | Command::Response(Response::RPL_NOWON, _)
+            | Command::Response(Response::RPL_NOWOFF, _) => self.handle_monitor(&msg.command),
+            Command::Response(Response::ERR_TOOMANYWATCH, ref args) => {
+                // The server rejected this target, so we're not watching it after all.
+                if let Some(nick) = args.get(1) {
+                    let key = self.fold_case(nick);
+                    self.monitored.write().remove(&key);
+                }
+            }
+            Command::Response(Response::ERR_MONLISTFULL, ref args) => {
+                // The server rejected these targets, so we're not watching them after all.
+                if let Some(targets) = args.get(2) {
+                    let keys: Vec<_> = targets.split(',').map(|n| self.fold_case(n)).collect();
+                    let mut monitored = self.monitored.write();
+                    for key in keys {
+                        monitored.remove(&key);
+                    }
+                }
+            }
+            Command::Response(Response::RPL_MOTDSTART, _) => {
+                *self.motd_lines.lock() = Some(Vec::new())
+            }
+            Command::Response(Response::RPL_MOTD, ref args) => {
+                if let (Some(lines), Some(line)) = (&mut *self.motd_lines.lock(), args.last()) {
+                    // Servers usually start each line with `- `.
+                    let line = line.strip_prefix('-').unwrap_or(line);
+                    lines.push(line.strip_prefix(' ').unwrap_or(line).to_owned());
+                }
+            }
+            Command::Response(Response::RPL_ENDOFMOTD, _)
+            | Command::Response(Response::ERR_NOMOTD, _) => {
+                let lines = self.motd_lines.lock().take();
+                *self.motd.write() = lines.map(|lines| lines.join("\n"));
+                self.send_nick_password()?;
+                self.send_umodes()?;
 
-impl Client {
-    /// Creates a new `Client` from the configuration at the specified path, connecting
-    /// immediately. This function is short-hand for loading the configuration and then calling
-    /// `Client::from_config` and consequently inherits its behaviors.
-    ///
-    /// # Example
-    /// ```no_run
-    /// # use irc::client::prelude::*;
-    /// # #[tokio::main]
-    /// # async fn main() -> irc::error::Result<()> {
-    /// let client = Client::new("config.toml").await?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub async fn new<P: AsRef<Path>>(config: P) -> error::Result<Client> {
-        Client::from_config(Config::load(config)?).await
-    }
+                if self.identify_pending.load(Ordering::SeqCst) {
+                    self.joins_deferred.store(true, Ordering::SeqCst);
+                } else {
+                    self.join_channels()?;
+                }
 
-    /// Creates a `Future` of an `Client` from the specified configuration and on the event loop
-    /// corresponding to the given handle. This can be used to set up a number of `Clients` on a
-    /// single, shared event loop. It can also be used to take more control over execution and error
-    /// handling. Connection will not occur until the event loop is run.
-    pub async fn from_config(config: Config) -> error::Result<Client> {
-        let (tx_outgoing, rx_outgoing) = mpsc::unbounded_channel();
-        let conn = Connection::new(&config, tx_outgoing.clone()).await?;
+                let away_message = self.away_message.read().clone();
+                if let Some(message) = away_message.filter(|m| !m.is_empty()) {
+                    self.send(AWAY(Some(message)))?;
+                }
 
-        #[cfg(test)]
-        let view = conn.log_view();
+                let monitored: Vec<_> = self.monitored.read().keys().cloned().collect();
+                if self.server_info.read().supports("MONITOR") {
+                    self.send_monitor("+", &monitored)?;
+                } else if self.server_info.read().supports("WATCH") {
+                    self.send_watch('+', &monitored)?;
+                }
+            }
+            Command::Response(ref resp @ Response::ERR_NICKNAMEINUSE, _)
+            | Command::Response(ref resp @ Response::ERR_ERRONEOUSNICKNAME, _) => {
+                // Once we're registered, failed nickname changes are left to the user.
+                if self.nickname.read().is_some() {
+                    return Ok(());
+                }
 
-        let (sink, incoming) = conn.split();
+                let alt_nicks = self.config().alternate_nicknames();
+                let mut index = self.alt_nick_index.write();
 
-        let sender = Sender { tx_outgoing };
+                // A numeric suffix won't make an erroneous nickname acceptable.
+                if *index >= alt_nicks.len() && *resp == Response::ERR_ERRONEOUSNICKNAME {
+                    return Err(error::Error::NoUsableNick);
+                }
 
-        Ok(Client {
-            sender: sender.clone(),
-            state: Arc::new(ClientState::new(sender, config)),
-            incoming: Some(incoming),
-            outgoing: Some(Outgoing {
-                sink,
-                stream: rx_outgoing,
-                buffered: None,
-            }),
-            #[cfg(test)]
-            view,
-        })
+                *index += 1;
+                self.send(NICK(self.nickname_at(*index)?))?;
+            }
+            _ => (),
+        }
+        Ok(())
     }
 
-    /// Gets the log view from the internal transport. Only used for unit testing.
-    #[cfg(test)]
-    fn log_view(&self) -> &self::transport::LogView {
-        self.view
-            .as_ref()
-            .expect("there should be a log during testing")
-    }
+    /// Updates the status of monitored nicknames from an `RPL_MONONLINE` or `RPL_MONOFFLINE`
+    /// reply, and notifies the registered handlers.
+    fn handle_monitor(&self, cmd: &Command) {
+        let events = MonitorEvent::from_command(cmd);
+        {
+            let info = self.server_info.read();
+            let mut monitored = self.monitored.write();
+            for event in &events {
+                monitored.insert(info.fold_case(event.nickname()), Some(event.is_online()));
+            }
+        }
 
-    /// Take the outgoing future in order to drive it yourself.
-    ///
-    /// Must be called before `stream` if you intend to drive this future
-    /// yourself.
-    pub fn outgoing(&mut self) -> Option<Outgoing> {
-        self.outgoing.take()
+        // Handlers are cloned out so that they may register further handlers without deadlocking.
+        let handlers = self.monitor_handlers.read().clone();
+        for event in &events {
+            handlers.notify(event);
+        }
     }
 
-    /// Get access to a thread-safe sender that can be used with the client.
-    pub fn sender(&self) -> Sender {
-        self.sender.clone()
+    /// Collects messages into batches, and notifies the registered handlers when a batch ends.
+    /// Nothing is collected while there are neither handlers nor `CHATHISTORY` requests awaiting
+    /// batches, which saves copying the messages.
+    fn handle_batch(&self, msg: &Message) {
+        if self.batch_handlers.read().handlers.is_empty()
+            && self.pending_histories.lock().is_empty()
+        {
+            self.batches.lock().clear();
+            return;
+        }
+        let batch = self.batches.lock().handle(msg);
+        if let Some(batch) = batch {
+            self.handle_pending_history(&batch);
+            let handlers = self.batch_handlers.read().clone();
+            handlers.notify(&batch);
+        }
     }
 
-    /// Gets the configuration being used with this `Client`.
-    fn config(&self) -> &Config {
-        &self.state.config
+    /// Rejoins a channel from the configuration after we were kicked from it, if configured to.
+    fn handle_kicked(&self, chan: &str) -> error::Result<()> {
+        let config = self.config();
+        let config_chan = match config
+            .channels()
+            .iter()
+            .find(|c| c.eq_ignore_ascii_case(chan))
+        {
+            Some(config_chan) if config.auto_rejoin() => config_chan,
+            _ => return Ok(()),
+        };
+
+        let mut attempts = self.rejoin_attempts.lock();
+        let attempts = attempts.entry(chan.to_ascii_lowercase()).or_insert(0);
+        if *attempts >= config.max_rejoin_attempts() {
+            log::info!("not rejoining {} after {} attempts", chan, attempts);
+            return Ok(());
+        }
+        *attempts += 1;
+
+        let key = config.channel_key(config_chan).map(|key| key.to_owned());
+        let join = JOIN(chan.to_owned(), key, None);
+        match config.auto_rejoin_delay() {
+            0 => self.send(join),
+            delay => {
+                let sender = self.sender.clone();
+                self.spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(u64::from(delay))).await;
+                    // If the connection closed in the meantime, there's nothing left to rejoin.
+                    let _ = sender.send(join);
+                });
+                Ok(())
+            }
+        }
     }
 
-    /// Gets a stream of incoming messages from the `Client`'s connection. This is only necessary
-    /// when trying to set up more complex clients, and requires use of the `futures` crate. Most
-    /// You can find some examples of setups using `stream` in the
-    /// [GitHub repository](https://github.com/aatxe/irc/tree/stable/examples).
-    ///
-    /// **Note**: The stream can only be returned once. Subsequent attempts will cause a panic.
-    // FIXME: when impl traits stabilize, we should change this return type.
-    pub fn stream(&mut self) -> error::Result<ClientStream> {
-        let stream = self
-            .incoming
-            .take()
-            .ok_or(error::Error::StreamAlreadyConfigured)?;
-
-        Ok(ClientStream {
-            state: Arc::clone(&self.state),
-            stream,
-            outgoing: self.outgoing.take(),
-        })
+    /// Records invitations to us and notifies the invite handlers of every invitation.
+    fn handle_invite(&self, msg: &Message) {
+        let invite = match Invite::from_message(msg) {
+            Some(invite) => invite,
+            None => return,
+        };
+        if invite.is_for(&self.current_nickname()) {
+            let mut pending = self.pending_invites.write();
+            pending.retain(|other| !other.channel.eq_ignore_ascii_case(&invite.channel));
+            pending.push(invite.clone());
+        }
+        let handlers = self.invite_handlers.read().clone();
+        handlers.notify(&invite);
     }
 
-    /// Gets a list of currently joined channels. This will be `None` if tracking is disabled
-    /// altogether via the `nochanlists` feature.
-    #[cfg(not(feature = "nochanlists"))]
-    pub fn list_channels(&self) -> Option<Vec<String>> {
-        Some(
-            self.state
-                .chanlists
-                .read()
-                .keys()
-                .map(|k| k.to_owned())
-                .collect(),
-        )
+    /// Sends `MONITOR` with the given modifier for the nicknames, spread across as many messages
+    /// as needed to stay within the maximum line length.
+    fn send_monitor<S: AsRef<str>>(&self, modifier: &str, nicks: &[S]) -> error::Result<()> {
+        // Leave room for `MONITOR <modifier> ` and the trailing CRLF.
+        let available = MAX_LINE_LENGTH - "MONITOR  \r\n".len() - modifier.len();
+        let mut targets = String::new();
+        for nick in nicks {
+            let nick = nick.as_ref();
+            if !targets.is_empty() && targets.len() + 1 + nick.len() > available {
+                self.send(Command::MONITOR(
+                    modifier.to_owned(),
+                    Some(std::mem::take(&mut targets)),
+                ))?;
+            }
+            if !targets.is_empty() {
+                targets.push(',');
+            }
+            targets.push_str(nick);
+        }
+        if !targets.is_empty() {
+            self.send(Command::MONITOR(modifier.to_owned(), Some(targets)))?;
+        }
+        Ok(())
     }
 
-    #[cfg(feature = "nochanlists")]
-    pub fn list_channels(&self) -> Option<Vec<String>> {
-        None
+    /// Sends `WATCH` with the given modifier prepended to each nickname, spread across as many
+    /// messages as needed to stay within the maximum line length.
+    fn send_watch<S: AsRef<str>>(&self, modifier: char, nicks: &[S]) -> error::Result<()> {
+        // Leave room for `WATCH` and the trailing CRLF.
+        let available = MAX_LINE_LENGTH - "WATCH\r\n".len();
+        let mut entries = Vec::new();
+        let mut len = 0;
+        for nick in nicks {
+            let entry = format!("{}{}", modifier, nick.as_ref());
+            if !entries.is_empty() && len + 1 + entry.len() > available {
+                self.send(Command::WATCH(std::mem::take(&mut entries)))?;
+                len = 0;
+            }
+            len += 1 + entry.len();
+            entries.push(entry);
+        }
+        if !entries.is_empty() {
+            self.send(Command::WATCH(entries))?;
+        }
+        Ok(())
     }
 
-    /// Gets a list of [`Users`](./data/user/struct.User.html) in the specified channel. If the
-    /// specified channel hasn't been joined or the `nochanlists` feature is enabled, this function
-    /// will return `None`.
-    ///
-    /// For best results, be sure to request `multi-prefix` support from the server. This will allow
-    /// for more accurate tracking of user rank (e.g. oper, half-op, etc.).
-    /// # Requesting multi-prefix support
-    /// ```no_run
-    /// # use irc::client::prelude::*;
-    /// use irc::proto::caps::Capability;
-    ///
-    /// # #[tokio::main]
-    /// # async fn main() -> irc::error::Result<()> {
-    /// # let client = Client::new("config.toml").await?;
-    /// client.send_cap_req(&[Capability::MultiPrefix])?;
-    /// client.identify()?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    #[cfg(not(feature = "nochanlists"))]
-    pub fn list_users(&self, chan: &str) -> Option<Vec<User>> {
-        self.state.chanlists.read().get(&chan.to_owned()).cloned()
+    /// Records whether another user is away, along with their away message.
+    fn set_user_away(&self, nick: &str, message: Option<&str>) {
+        let key = self.fold_case(nick);
+        match message {
+            Some(message) => self.away_users.write().insert(key, message.to_owned()),
+            None => self.away_users.write().remove(&key),
+        };
+        self.handle_away(nick, message);
     }
 
-    #[cfg(feature = "nochanlists")]
-    pub fn list_users(&self, _: &str) -> Option<Vec<User>> {
-        None
+    /// Records the account a user is logged into, or that they are logged out.
+    /// Applies `+b`/`-b` (and `+q`/`-q` where it is a list mode) to the lists we've received.
+    fn handle_list_modes(&self, msg: &Message, chan: &str, modes: &[Mode<ChannelMode>]) {
+        let quiet_list_mode = self
+            .server_info
+            .read()
+            .chanmodes()
+            .split(',')
+            .next()
+            .map_or(false, |list| list.contains('q'));
+        let folded = self.fold_case(chan);
+        for mode in modes {
+            let (adding, mode, mask) = match *mode {
+                Mode::Plus(ref mode, Some(ref mask)) => (true, mode, mask),
+                Mode::Minus(ref mode, Some(ref mask)) => (false, mode, mask),
+                _ => continue,
+            };
+            let lists = match *mode {
+                ChannelMode::Ban => &self.ban_lists,
+                // Servers with quiet lists use `q` for them, which is otherwise the founder mode.
+                ChannelMode::Founder if quiet_list_mode => &self.quiet_lists,
+                _ => continue,
+            };
+            if adding {
+                let set_at = tag_value(msg, "time")
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map_or_else(Utc::now, |time| time.with_timezone(&Utc));
+                let entry = BanEntry {
+                    mask: mask.clone(),
+                    setter: msg.prefix.as_ref().map(|prefix| prefix.to_string()),
+                    set_at: Some(set_at),
+                };
+                lists.write().add(&folded, entry)
+            } else {
+                lists.write().remove(&folded, mask)
+            }
+        }
     }
 
-    /// Gets the current nickname in use. This may be the primary username set in the configuration,
-    /// or it could be any of the alternative nicknames listed as well. As a result, this is the
-    /// preferred way to refer to the client's nickname.
-    pub fn current_nickname(&self) -> &str {
-        self.state.current_nickname()
+    /// Forgets the ban and quiet lists of a channel we've left, and that we knew all its users.
+    fn forget_channel_lists(&self, chan: &str) {
+        let folded = self.fold_case(chan);
+        self.ban_lists.write().remove_channel(&folded);
+        self.quiet_lists.write().remove_channel(&folded);
+        self.names_complete.write().remove(&folded);
+        self.names_index.lock().remove(&folded);
     }
 
-    /// Sends a [`Command`](../proto/command/enum.Command.html) as this `Client`. This is the
-    /// core primitive for sending messages to the server.
-    ///
-    /// # Example
-    /// ```no_run
-    /// # use irc::client::prelude::*;
-    /// # #[tokio::main]
-    /// # async fn main() {
-    /// # let client = Client::new("config.toml").await.unwrap();
-    /// client.send(Command::NICK("example".to_owned())).unwrap();
-    /// client.send(Command::USER("user".to_owned(), "0".to_owned(), "name".to_owned())).unwrap();
-    /// # }
-    /// ```
-    pub fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
-        self.state.send(msg)
+    fn set_user_account(&self, nick: &str, account: Option<&str>) {
+        let key = self.fold_case(nick);
+        match account {
+            Some(account) => self.accounts.write().insert(key, account.to_owned()),
+            None => self.accounts.write().remove(&key),
+        };
+        self.handle_account(nick, account);
     }
 
-    /// Sends a CAP END, NICK and USER to identify.
-    pub fn identify(&self) -> error::Result<()> {
-        // Send a CAP END to signify that we're IRCv3-compliant (and to end negotiations!).
-        self.send(CAP(None, END, None, None))?;
-        if self.config().password() != "" {
-            self.send(PASS(self.config().password().to_owned()))?;
-        }
-        self.send(NICK(self.config().nickname()?.to_owned()))?;
-        self.send(USER(
-            self.config().username().to_owned(),
-            "0".to_owned(),
-            self.config().real_name().to_owned(),
-        ))?;
-        Ok(())
+    fn set_user_realname(&self, nick: &str, realname: &str) {
+        let key = self.fold_case(nick);
+        self.realnames.write().insert(key, realname.to_owned());
     }
 
-    pub_state_base!();
-    pub_sender_base!();
-}
+    fn handle_user_mode(&self, modes: &[Mode<UserMode>]) {
+        for mode in modes {
+            match *mode {
+                Mode::Minus(UserMode::Oper, _) | Mode::Minus(UserMode::LocalOper, _) => {
+                    *self.is_oper.write() = false
+                }
+                Mode::Plus(UserMode::Oper, _) | Mode::Plus(UserMode::LocalOper, _) => {
+                    *self.is_oper.write() = true
+                }
+                Mode::Minus(UserMode::ServerNotices, _) => *self.snomask.write() = None,
+                _ => (),
+            }
+        }
+    }
 
-#[cfg(test)]
-mod test {
-    use std::{collections::HashMap, default::Default, thread, time::Duration};
+    /// Joins the channels from the configuration, along with any others we were in before
+    /// reconnecting. Channels are joined `channels_per_join` at a time, `join_delay` apart, and
+    /// those past the server's `CHANLIMIT` are left out.
+    fn join_channels(&self) -> error::Result<()> {
+        let config = self.config();
+        let mut chans: Vec<(String, Option<String>)> = config
+            .channels()
+            .iter()
+            .map(|chan| (chan.clone(), config.channel_key(chan).map(|k| k.to_owned())))
+            .collect();
+        chans.extend(
+            self.chanlists
+                .read()
+                .keys()
+                .filter(|chan| !config.channels().contains(chan))
+                .map(|chan| (chan.clone(), None)),
+        );
+
+        {
+            let info = self.server_info.read();
+            let mut counts = HashMap::new();
+            chans.retain(|(chan, _)| {
+                let (types, limit) =
+                    match chan.chars().next().and_then(|c| info.chan_limit_group(c)) {
+                        Some(group) => group,
+                        None => return true,
+                    };
+                let count = counts.entry(types).or_insert(0);
+                *count += 1;
+                if *count > limit {
+                    log::warn!(
+                        "not joining {}: the server allows {} {} channels",
+                        chan,
+                        limit,
+                        types
+                    );
+                }
+                *count <= limit
+            });
+        }
+
+        let per_join = config.channels_per_join() as usize;
+        if per_join > 1 {
+            // Keys go to the channels in order, so the channels with keys must come first.
+            chans.sort_by_key(|(_, key)| key.is_none());
+        }
+        let mut joins = group_joins(&chans, per_join).into_iter();
+        let delay = match config.join_delay() {
+            0 => {
+                return joins.try_for_each(|join| match join {
+                    (chans, Some(keys)) => self.send_join_with_keys(chans, keys),
+                    (chans, None) => self.send_join(chans),
+                })
+            }
+            delay => Duration::from_millis(u64::from(delay)),
+        };
+
+        match joins.next() {
+            Some((chans, Some(keys))) => self.send_join_with_keys(chans, keys)?,
+            Some((chans, None)) => self.send_join(chans)?,
+            None => return Ok(()),
+        }
+        let sender = self.sender.clone();
+        self.spawn(async move {
+            for join in joins {
+                tokio::time::sleep(delay).await;
+                let sent = match join {
+                    (chans, Some(keys)) => sender.send_join_with_keys(chans, keys),
+                    (chans, None) => sender.send_join(chans),
+                };
+                // If the connection closed in the meantime, there's nothing left to join.
+                if sent.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Spawns delayed work for the current connection, which is aborted if the connection ends
+    /// first.
+    fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.tasks.lock();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(tokio::spawn(task));
+    }
+
+    /// Identifies with NickServ using the configured line, or `NICKSERV IDENTIFY` by default.
+    fn send_identify(&self) -> error::Result<()> {
+        let password = self.config().nick_password();
+        if password.is_empty() {
+            return Ok(());
+        }
+
+        match self.config().nickserv_identify() {
+            Some(template) => {
+                let line = template
+                    .replace("{nick}", self.config().nickname()?)
+                    .replace("{password}", password);
+                self.send(line.parse::<Message>()?)?
+            }
+            None => self.send(NICKSERV(vec!["IDENTIFY".to_string(), password.to_string()]))?,
+        }
+
+        if self.config().wait_for_identify() {
+            self.identify_pending.store(true, Ordering::SeqCst);
+            let timeout = self.config().identify_timeout();
+            if timeout > 0 {
+                let deadline = tokio::time::sleep(Duration::from_secs(u64::from(timeout)));
+                *self.identify_deadline.lock() = Some(Box::pin(deadline));
+            }
+        }
+        Ok(())
+    }
+
+    /// Joins channels that were put off until our identification was confirmed.
+    fn handle_identified(&self) -> error::Result<()> {
+        self.identify_pending.store(false, Ordering::SeqCst);
+        *self.identify_deadline.lock() = None;
+        if self.joins_deferred.swap(false, Ordering::SeqCst) {
+            self.join_channels()?;
+        }
+        Ok(())
+    }
+
+    /// Stops waiting for our identification to be confirmed once the configured timeout passes.
+    fn poll_identify_timeout(&self, cx: &mut Context<'_>) -> error::Result<()> {
+        let mut deadline = self.identify_deadline.lock();
+        match deadline.as_mut().map(|sleep| sleep.as_mut().poll(cx)) {
+            Some(Poll::Ready(())) => {
+                *deadline = None;
+                drop(deadline);
+                log::warn!("identification was not confirmed in time, joining channels anyway");
+                self.handle_identified()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn send_nick_password(&self) -> error::Result<()> {
+        if self.config().nick_password().is_empty() {
+            Ok(())
+        } else {
+            let mut index = self.alt_nick_index.write();
+            let ghosted = self.config().should_ghost() && *index != 0;
+
+            if ghosted {
+                let seq = match self.config().ghost_sequence() {
+                    Some(seq) => seq,
+                    None => &*self.default_ghost_sequence,
+                };
 
-    use super::Client;
+                for s in seq {
+                    self.send(NICKSERV(vec![
+                        s.to_string(),
+                        self.config().nickname()?.to_string(),
+                        self.config().nick_password().to_string(),
+                    ]))?;
+                }
+                *index = 0;
+                self.send(NICK(self.config().nickname()?.to_owned()))?
+            }
+            drop(index);
+
+            // With a custom identify line, we've already identified when the server welcomed us.
+            if self.config().nickserv_identify().is_some() && !ghosted {
+                return Ok(());
+            }
+            self.send_identify()
+        }
+    }
+
+    fn send_umodes(&self) -> error::Result<()> {
+        if self.config().umodes().is_empty() {
+            Ok(())
+        } else {
+            self.send_user_modes(self.config().umodes())
+        }
+    }
+
+    /// Parses a user mode string such as `+iB` and sends it as a `MODE` for ourselves.
+    fn send_user_modes(&self, modes: &str) -> error::Result<()> {
+        let nickname = self.current_nickname();
+        let pieces: Vec<_> = modes.split(' ').collect();
+        let modes = Mode::as_user_modes(&pieces).map_err(|e| error::Error::InvalidMessage {
+            string: format!("MODE {} {}", nickname, modes),
+            cause: e,
+        })?;
+        self.send_mode(nickname, &modes)
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn is_joined(&self, _: &str) -> bool {
+        false
+    }
+
+    /// Determines whether we are known to be in the channel, i.e. whether it lists us as a user.
+    /// A `NAMES` reply for a channel we're not in lists its users without us.
     #[cfg(not(feature = "nochanlists"))]
-    use crate::client::data::User;
-    use crate::{
-        client::data::Config,
-        error::Error,
-        proto::{
-            command::Command::{Raw, PRIVMSG},
-            ChannelMode, IrcCodec, Mode,
-        },
-    };
-    use anyhow::Result;
-    use futures::prelude::*;
+    fn is_joined(&self, chan: &str) -> bool {
+        let current = self.current_nickname();
+        let info = self.server_info.read();
+        self.chanlists.read().iter().any(|(joined, users)| {
+            info.eq_ignore_case(joined, chan)
+                && users
+                    .iter()
+                    .any(|user| info.eq_ignore_case(user.get_nickname(), &current))
+        })
+    }
 
-    pub fn test_config() -> Config {
-        Config {
-            owners: vec!["test".to_string()],
-            nickname: Some("test".to_string()),
-            alt_nicks: vec!["test2".to_string()],
-            server: Some("irc.test.net".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
-            user_info: Some("Testing.".to_string()),
-            use_mock_connection: true,
-            ..Default::default()
+    #[cfg(feature = "nochanlists")]
+    fn handle_self_join(&self, _: &str) {}
+
+    /// Forgets what we knew of the users in a channel we (re)joined, since the server is about to
+    /// list them all.
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_self_join(&self, chan: &str) {
+        self.chanlists.write().remove(chan);
+        let folded = self.fold_case(chan);
+        self.names_complete.write().remove(&folded);
+        self.names_index.lock().remove(&folded);
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_join(&self, _: &str, _: &str) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_join(&self, src: &str, chan: &str) {
+        if let Some(vec) = self.chanlists.write().get_mut(&chan.to_owned()) {
+            if !src.is_empty() {
+                vec.push(User::new(src))
+            }
+        }
+        self.forget_names_index(chan);
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_part(&self, _: &str, _: &str) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_part(&self, src: &str, chan: &str) {
+        if let Some(vec) = self.chanlists.write().get_mut(&chan.to_owned()) {
+            if !src.is_empty() {
+                if let Some(n) = vec.iter().position(|x| x.get_nickname() == src) {
+                    vec.swap_remove(n);
+                }
+            }
+        }
+        self.forget_names_index(chan);
+    }
+
+    /// Forgets where the users of a channel sit in its user list after they were moved around
+    /// during its `NAMES` burst.
+    #[cfg(not(feature = "nochanlists"))]
+    fn forget_names_index(&self, chan: &str) {
+        let folded = self.fold_case(chan);
+        self.names_index.lock().remove(&folded);
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn forget_unshared_users(&self, _: &[&str], _: Option<&str>) {}
+
+    /// Forgets the away status, account and real name of the given users once we no longer share
+    /// a channel with them, ignoring the channel we just left if there is one.
+    #[cfg(not(feature = "nochanlists"))]
+    fn forget_unshared_users(&self, nicks: &[&str], left: Option<&str>) {
+        let current = self.current_nickname();
+        let info = self.server_info.read();
+        let chanlists = self.chanlists.read();
+        let gone: Vec<_> = nicks
+            .iter()
+            .filter(|nick| !nick.is_empty() && !info.eq_ignore_case(nick, &current))
+            .filter(|nick| {
+                !chanlists
+                    .iter()
+                    .filter(|(chan, _)| Some(&chan[..]) != left)
+                    .any(|(_, users)| {
+                        users
+                            .iter()
+                            .any(|user| info.eq_ignore_case(user.get_nickname(), nick))
+                    })
+            })
+            .map(|nick| info.fold_case(nick))
+            .collect();
+        drop(chanlists);
+        drop(info);
+
+        if gone.is_empty() {
+            return;
+        }
+        let mut away_users = self.away_users.write();
+        let mut accounts = self.accounts.write();
+        let mut realnames = self.realnames.write();
+        for nick in &gone {
+            away_users.remove(nick);
+            accounts.remove(nick);
+            realnames.remove(nick);
+        }
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_quit(&self, _: &str) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_quit(&self, src: &str) {
+        if src.is_empty() {
+            return;
+        }
+
+        for vec in self.chanlists.write().values_mut() {
+            if let Some(p) = vec.iter().position(|x| x.get_nickname() == src) {
+                vec.swap_remove(p);
+            }
+        }
+        self.names_index.lock().clear();
+    }
+
+    /// Applies `f` to the given user's entry in each channel we share with them, comparing
+    /// nicknames under the server's case mapping.
+    #[cfg(not(feature = "nochanlists"))]
+    fn for_each_user<F>(&self, nick: &str, mut f: F)
+    where
+        F: FnMut(&mut User),
+    {
+        let info = self.server_info.read();
+        for users in self.chanlists.write().values_mut() {
+            if let Some(user) = users
+                .iter_mut()
+                .find(|user| info.eq_ignore_case(user.get_nickname(), nick))
+            {
+                f(user);
+            }
+        }
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_nick_change(&self, _: &str, _: &str) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_nick_change(&self, old_nick: &str, new_nick: &str) {
+        if old_nick.is_empty() || new_nick.is_empty() {
+            return;
+        }
+
+        self.for_each_user(old_nick, |user| user.set_nickname(new_nick));
+        // The indexes of unfinished bursts are keyed by the old nickname.
+        self.names_index.lock().clear();
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_mode(&self, _: &str, _: &[Mode<ChannelMode>]) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_mode(&self, chan: &str, modes: &[Mode<ChannelMode>]) {
+        for mode in modes {
+            match *mode {
+                Mode::Plus(_, Some(ref user)) | Mode::Minus(_, Some(ref user)) => {
+                    if let Some(vec) = self.chanlists.write().get_mut(chan) {
+                        if let Some(n) = vec.iter().position(|x| x.get_nickname() == user) {
+                            vec[n].update_access_level(mode)
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_namreply(&self, _: &[String]) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_namreply(&self, args: &[String]) {
+        if args.len() == 4 {
+            let chan = &args[2];
+            // A new burst (e.g. after sending `NAMES` again) replaces the complete list we had.
+            let folded = self.fold_case(chan);
+            if self.names_complete.write().remove(&folded) {
+                self.chanlists.write().insert(chan.clone(), Vec::new());
+            }
+            let mut chanlists = self.chanlists.write();
+            let users = chanlists.entry(chan.clone()).or_default();
+            // Indexed once per burst so that large channels don't scan the list for every name.
+            let mut indexes = self.names_index.lock();
+            let known = indexes.entry(folded).or_insert_with(|| {
+                users
+                    .iter()
+                    .enumerate()
+                    .map(|(i, user)| (user.get_nickname().to_owned(), i))
+                    .collect()
+            });
+            for user in args[3].split(' ').filter(|user| !user.is_empty()) {
+                let user = User::new(user);
+                // Users listed again in an unfinished burst replace their earlier entry.
+                match known.get(user.get_nickname()) {
+                    Some(&i) => users[i] = user,
+                    None => {
+                        known.insert(user.get_nickname().to_owned(), users.len());
+                        users.push(user);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_whoreply(&self, _: &[String]) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_whoreply(&self, args: &[String]) {
+        // <client> <channel> <username> <host> <server> <nick> <flags> :<hopcount> <realname>
+        if let (Some(nick), Some(flags)) = (args.get(5), args.get(6)) {
+            if let Some(mode) = self.server_info.read().bot_mode() {
+                self.handle_bot(nick, flags.contains(mode));
+            }
+        }
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_bot(&self, _: &str, _: bool) {}
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_away(&self, _: &str, _: Option<&str>) {}
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_account(&self, _: &str, _: Option<&str>) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_account(&self, nick: &str, account: Option<&str>) {
+        self.for_each_user(nick, |user| user.set_account(account));
+    }
+
+    #[cfg(feature = "nochanlists")]
+    fn handle_chghost(&self, _: &str, _: &str, _: &str) {}
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_chghost(&self, nick: &str, username: &str, hostname: &str) {
+        self.for_each_user(nick, |user| user.set_host(username, hostname));
+    }
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_away(&self, nick: &str, message: Option<&str>) {
+        self.for_each_user(nick, |user| user.set_away(message));
+    }
+
+    #[cfg(not(feature = "nochanlists"))]
+    fn handle_bot(&self, nick: &str, is_bot: bool) {
+        self.for_each_user(nick, |user| user.set_bot(is_bot));
+    }
+
+    #[cfg(feature = "ctcp")]
+    fn handle_ctcp(&self, resp: &str, tokens: &[&str]) -> error::Result<()> {
+        if tokens.is_empty() {
+            return Ok(());
         }
+        if tokens[0].eq_ignore_ascii_case("FINGER") {
+            self.send_ctcp_internal(
+                resp,
+                &format!(
+                    "FINGER :{} ({})",
+                    expand_placeholders(self.config().real_name()),
+                    self.config().username()
+                ),
+            )
+        } else if tokens[0].eq_ignore_ascii_case("VERSION") {
+            let version = expand_placeholders(self.config().version());
+            self.send_ctcp_internal(resp, &format!("VERSION {}", version))
+        } else if tokens[0].eq_ignore_ascii_case("SOURCE") {
+            self.send_ctcp_internal(resp, &format!("SOURCE {}", self.config().source()))
+        } else if tokens[0].eq_ignore_ascii_case("PING") && tokens.len() > 1 {
+            self.send_ctcp_internal(resp, &format!("PING {}", tokens[1]))
+        } else if tokens[0].eq_ignore_ascii_case("TIME") {
+            self.send_ctcp_internal(resp, &format!("TIME :{}", Local::now().to_rfc2822()))
+        } else if tokens[0].eq_ignore_ascii_case("USERINFO") {
+            let user_info = expand_placeholders(self.config().user_info());
+            self.send_ctcp_internal(resp, &format!("USERINFO :{}", user_info))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "ctcp")]
+    fn send_ctcp_internal(&self, target: &str, msg: &str) -> error::Result<()> {
+        self.send_notice(target, format!("\u{001}{}\u{001}", msg))
+    }
+
+    #[cfg(not(feature = "ctcp"))]
+    fn handle_ctcp(&self, _: &str, _: &[&str]) -> error::Result<()> {
+        Ok(())
+    }
+
+    pub_state_base!();
+}
+
+/// Thread-safe sender that can be used with the client.
+///
+/// Senders are cheap to clone and can be moved into other tasks, so that messages can be sent from
+/// anywhere while a single task owns the stream of incoming messages. Messages sent through any
+/// clone are written by the same connection, in the order they were queued.
+///
+/// # Example
+/// ```no_run
+/// # use irc::client::prelude::*;
+/// # use futures::prelude::*;
+/// # #[tokio::main]
+/// # async fn main() -> irc::error::Result<()> {
+/// let mut client = Client::new("config.toml").await?;
+/// let sender = client.sender();
+/// tokio::spawn(async move {
+///     let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+///     loop {
+///         interval.tick().await;
+///         let _ = sender.send_privmsg("#rust", "Still here!");
+///     }
+/// });
+///
+/// let mut stream = client.stream()?;
+/// while let Some(message) = stream.next().await.transpose()? {
+///     print!("{}", message);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sender {
+    /// Queues batches of messages, each of which is written before the connection is flushed.
+    tx_outgoing: UnboundedSender<Vec<Message>>,
+    /// The total number of messages queued through any clone of this sender.
+    queued: Arc<AtomicUsize>,
+    /// The number of messages that `Outgoing` has flushed to the connection.
+    flushed: watch::Receiver<usize>,
+    /// The number of messages the transport is holding back because of rate limiting.
+    throttled: Arc<AtomicUsize>,
+    /// The most recent messages sent and received by the transport.
+    history: History,
+    /// The traffic counters updated by the transport.
+    stats: Arc<StatCounters>,
+    /// Whether the codec splits long `PRIVMSG` and `NOTICE` messages instead of rejecting them.
+    split_long_messages: bool,
+    /// The configured quit message, sent when quitting without one.
+    quit_message: Arc<str>,
+}
+
+impl Sender {
+    /// Send a single message to the unbounded queue. Fails with `Error::MessageTooLong` if the
+    /// message does not fit on a line and cannot be split (see `Config::split_long_messages`).
+    pub fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
+        let msg = msg.into();
+        IrcCodec::check_line_length(&msg, self.split_long_messages)?;
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        Ok(self.tx_outgoing.send(vec![msg])?)
+    }
+
+    /// Sends several messages to the unbounded queue at once. They are all written before the
+    /// connection is flushed, rather than possibly being flushed one at a time, and nothing sent
+    /// from elsewhere comes between them. Rate limiting still applies to each message.
+    pub fn send_all<I, M>(&self, msgs: I) -> error::Result<()>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        let msgs: Vec<Message> = msgs.into_iter().map(Into::into).collect();
+        if msgs.is_empty() {
+            return Ok(());
+        }
+        for msg in &msgs {
+            IrcCodec::check_line_length(msg, self.split_long_messages)?;
+        }
+        self.queued.fetch_add(msgs.len(), Ordering::SeqCst);
+        Ok(self.tx_outgoing.send(msgs)?)
+    }
+
+    /// Returns a future that resolves once every message queued before this call has been
+    /// written and flushed to the connection.
+    ///
+    /// The outgoing future (either on its own or as part of a `ClientStream`) must be driven for
+    /// this to make progress. If it is dropped first, this resolves to an error.
+    pub fn flush(&self) -> impl Future<Output = error::Result<()>> + 'static {
+        let target = self.queued.load(Ordering::SeqCst);
+        let mut flushed = self.flushed.clone();
+
+        async move {
+            while *flushed.borrow() < target {
+                flushed
+                    .changed()
+                    .await
+                    .map_err(|_| error::Error::AsyncChannelClosed)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Gets the number of outgoing messages currently held back to stay within the configured
+    /// burst rate (see `Config::max_messages_in_burst`).
+    pub fn queue_depth(&self) -> usize {
+        self.throttled.load(Ordering::SeqCst)
+    }
+
+    /// Gets the most recent messages sent and received, oldest first. This keeps up to
+    /// `Config::history_length` messages, and is always empty if that is zero.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.history.entries()
+    }
+
+    /// Gets a snapshot of the messages and bytes sent and received so far, along with the current
+    /// queue depth. Reading the counters is cheap, so this is fine to call on every scrape.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.stats.snapshot(self.queue_depth())
+    }
+
+    fn default_quit_message(&self) -> &str {
+        &self.quit_message
+    }
+
+    pub_state_base!();
+    pub_sender_base!();
+}
+
+/// Future to handle outgoing messages.
+///
+/// Note: this is essentially the same as a version of [SendAll](https://github.com/rust-lang-nursery/futures-rs/blob/master/futures-util/src/sink/send_all.rs) that owns it's sink and stream.
+#[derive(Debug)]
+pub struct Outgoing {
+    sink: SplitSink<Connection, Message>,
+    stream: UnboundedReceiver<Vec<Message>>,
+    /// Messages taken off of `stream` that have yet to be started on the sink.
+    buffered: VecDeque<Message>,
+    /// The number of messages taken off of `stream` so far.
+    received: usize,
+    /// Published with `received` whenever the queue has been drained and flushed.
+    flushed: watch::Sender<usize>,
+    /// User-registered handlers run on each message before it is sent.
+    middleware: Arc<RwLock<Middleware>>,
+    /// Whether the codec splits long `PRIVMSG` and `NOTICE` messages instead of rejecting them.
+    split_long_messages: bool,
+}
+
+impl Outgoing {
+    /// Runs newly received messages through the middleware and buffers them to be sent. Messages
+    /// that a handler made too long to encode are dropped here, rather than failing the sink.
+    fn buffer(&mut self, messages: Vec<Message>) {
+        self.received += messages.len();
+        // Clone the chain so handlers are free to use the client themselves.
+        let middleware = self.middleware.read().clone();
+        let split_long_messages = self.split_long_messages;
+        self.buffered.extend(
+            messages
+                .into_iter()
+                .filter_map(|m| middleware.process(m))
+                .filter(
+                    |m| match IrcCodec::check_line_length(m, split_long_messages) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            log::warn!("Dropping outgoing message after middleware: {}", e);
+                            false
+                        }
+                    },
+                ),
+        );
+    }
+
+    /// Determines whether every message received so far has been written and flushed.
+    fn is_idle(&self) -> bool {
+        self.buffered.is_empty() && *self.flushed.borrow() == self.received
+    }
+
+    /// Sends everything queued so far, e.g. a `QUIT`, and then closes the connection cleanly.
+    /// Dropping this future instead closes the connection without flushing, so messages still
+    /// queued may be lost.
+    pub async fn shutdown(mut self) -> error::Result<()> {
+        while let Ok(messages) = self.stream.try_recv() {
+            self.buffer(messages);
+        }
+        future::poll_fn(|cx| self.start_buffered(cx)).await?;
+        self.sink.close().await?;
+        self.flushed.send_replace(self.received);
+        Ok(())
+    }
+
+    /// Starts sending each of the buffered messages on the sink.
+    fn start_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), error::Error>> {
+        while !self.buffered.is_empty() {
+            ready!(Pin::new(&mut self.sink).poll_ready(cx))?;
+            if let Some(message) = self.buffered.pop_front() {
+                Pin::new(&mut self.sink).start_send(message)?;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl FusedFuture for Outgoing {
+    fn is_terminated(&self) -> bool {
+        // NB: outgoing stream never terminates.
+        // TODO: should it terminate if rx_outgoing is terminated?
+        false
+    }
+}
+
+impl Future for Outgoing {
+    type Output = error::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        ready!(this.start_buffered(cx))?;
+
+        loop {
+            match this.stream.poll_recv(cx) {
+                Poll::Ready(Some(messages)) => {
+                    this.buffer(messages);
+                    ready!(this.start_buffered(cx))?
+                }
+                Poll::Ready(None) => {
+                    ready!(Pin::new(&mut this.sink).poll_flush(cx))?;
+                    this.flushed.send_replace(this.received);
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Pending => {
+                    ready!(Pin::new(&mut this.sink).poll_flush(cx))?;
+                    this.flushed.send_replace(this.received);
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+/// The canonical implementation of a connection to an IRC server.
+///
+/// For a full example usage, see [`irc::client`](./index.html).
+#[derive(Debug)]
+pub struct Client {
+    /// The internal, thread-safe server state.
+    state: Arc<ClientState>,
+    incoming: Option<SplitStream<Connection>>,
+    outgoing: Option<Outgoing>,
+    sender: Sender,
+    #[cfg(test)]
+    /// A view of the logs for a mock connection.
+    view: Option<self::transport::LogView>,
+    #[cfg(test)]
+    /// What a mock connection records.
+    mock_state: Option<Arc<self::mock::MockState>>,
+    #[cfg(test)]
+    /// The handle for injecting lines into a mock connection that is kept open.
+    mock_handle: Option<self::mock::MockHandle>,
+}
+
+impl Client {
+    /// Creates a new `Client` from the configuration at the specified path, connecting
+    /// immediately. This function is short-hand for loading the configuration and then calling
+    /// `Client::from_config` and consequently inherits its behaviors.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// let client = Client::new("config.toml").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new<P: AsRef<Path>>(config: P) -> error::Result<Client> {
+        Client::from_config(Config::load(config)?).await
+    }
+
+    /// Creates a `Future` of an `Client` from the specified configuration and on the event loop
+    /// corresponding to the given handle. This can be used to set up a number of `Clients` on a
+    /// single, shared event loop. It can also be used to take more control over execution and error
+    /// handling. Connection will not occur until the event loop is run.
+    pub async fn from_config(config: Config) -> error::Result<Client> {
+        let (sender, rx_outgoing, tx_flushed) = Client::new_sender(&config);
+        let conn = Connection::new(&config, sender.clone()).await?;
+        Client::with_connection(config, conn, sender, rx_outgoing, tx_flushed)
+    }
+
+    /// Creates a new `Client` over a stream that is already established, e.g. one tunneled over
+    /// SSH or secured with a custom TLS setup. The server's address isn't needed in the
+    /// configuration, and the settings for connecting are ignored. Reconnecting with
+    /// `Client::reconnect_now` still connects as the configuration says.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// let stream = tokio::net::TcpStream::connect("127.0.0.1:6667").await?;
+    /// let config = Config {
+    ///     nickname: Some("test".to_owned()),
+    ///     ..Default::default()
+    /// };
+    /// let client = Client::from_stream(config, stream)?;
+    /// client.identify()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_stream<S>(config: Config, stream: S) -> error::Result<Client>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (sender, rx_outgoing, tx_flushed) = Client::new_sender(&config);
+        let conn = Connection::from_stream(&config, stream, sender.clone())?;
+        Client::with_connection(config, conn, sender, rx_outgoing, tx_flushed)
+    }
+
+    /// Creates the `Sender` for a new client, with the ends of its channels kept by `Outgoing`.
+    fn new_sender(
+        config: &Config,
+    ) -> (
+        Sender,
+        UnboundedReceiver<Vec<Message>>,
+        watch::Sender<usize>,
+    ) {
+        let (tx_outgoing, rx_outgoing) = mpsc::unbounded_channel();
+        let (tx_flushed, rx_flushed) = watch::channel(0);
+        let sender = Sender {
+            tx_outgoing,
+            queued: Arc::new(AtomicUsize::new(0)),
+            flushed: rx_flushed,
+            throttled: Arc::new(AtomicUsize::new(0)),
+            history: History::new(config.history_length() as usize),
+            stats: Arc::new(StatCounters::default()),
+            split_long_messages: config.split_long_messages(),
+            quit_message: expand_placeholders(config.quit_message()).into(),
+        };
+        (sender, rx_outgoing, tx_flushed)
+    }
+
+    /// Creates a new `Client` around the connection, queueing WEBIRC if it is configured.
+    fn with_connection(
+        config: Config,
+        #[cfg_attr(not(test), allow(unused_mut))] mut conn: Connection,
+        sender: Sender,
+        rx_outgoing: UnboundedReceiver<Vec<Message>>,
+        tx_flushed: watch::Sender<usize>,
+    ) -> error::Result<Client> {
+        #[cfg(test)]
+        let view = conn.log_view();
+        #[cfg(test)]
+        let mock_handle = conn.take_mock_handle();
+        #[cfg(test)]
+        let mock_state = conn.mock_state();
+        #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+        let peer_certificate = conn.peer_certificate();
+        let peer_addr = conn.peer_addr();
+        let local_addr = conn.local_addr();
+        let raw_bytes = conn.raw_bytes();
+
+        let (sink, incoming) = conn.split();
+        let split_long_messages = sender.split_long_messages;
+        let outbound_middleware = Arc::new(RwLock::new(Middleware::default()));
+        let state = ClientState::new(sender.clone(), config, Arc::clone(&outbound_middleware));
+        #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+        {
+            *state.peer_certificate.write() = peer_certificate;
+        }
+        *state.peer_addr.write() = peer_addr;
+        *state.local_addr.write() = local_addr;
+        *state.raw_bytes.write() = raw_bytes;
+        state.send_webirc()?;
+
+        Ok(Client {
+            sender,
+            state: Arc::new(state),
+            incoming: Some(incoming),
+            outgoing: Some(Outgoing {
+                sink,
+                stream: rx_outgoing,
+                buffered: VecDeque::new(),
+                received: 0,
+                flushed: tx_flushed,
+                middleware: outbound_middleware,
+                split_long_messages,
+            }),
+            #[cfg(test)]
+            view,
+            #[cfg(test)]
+            mock_state,
+            #[cfg(test)]
+            mock_handle,
+        })
+    }
+
+    /// Gets the log view from the internal transport. Only used for unit testing.
+    #[cfg(test)]
+    fn log_view(&self) -> &self::transport::LogView {
+        self.view
+            .as_ref()
+            .expect("there should be a log during testing")
+    }
+
+    /// Gets the lines sent that did not match the script of the mock connection. Only used for
+    /// unit testing.
+    #[cfg(test)]
+    fn mock_mismatches(&self) -> Vec<self::mock::Mismatch> {
+        self.mock_state().mismatches.lock().clone()
+    }
+
+    /// Gets the number of times data written to the mock connection was flushed. Only used for
+    /// unit testing.
+    #[cfg(test)]
+    fn mock_flushes(&self) -> usize {
+        self.mock_state().flushes.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    fn mock_state(&self) -> &self::mock::MockState {
+        self.mock_state
+            .as_ref()
+            .expect("there should be a mock connection during testing")
+    }
+
+    /// Takes the handle for injecting lines into the mock connection, which must be kept open.
+    /// Only used for unit testing.
+    #[cfg(test)]
+    fn mock_handle(&mut self) -> self::mock::MockHandle {
+        self.mock_handle
+            .take()
+            .expect("the mock connection should be kept open")
+    }
+
+    /// Take the outgoing future in order to drive it yourself.
+    ///
+    /// Must be called before `stream` if you intend to drive this future
+    /// yourself.
+    pub fn outgoing(&mut self) -> Option<Outgoing> {
+        self.outgoing.take()
+    }
+
+    /// Get access to a thread-safe sender that can be used with the client.
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    /// Gets the number of outgoing messages currently held back to stay within the configured
+    /// burst rate (see `Config::max_messages_in_burst`).
+    pub fn queue_depth(&self) -> usize {
+        self.sender.queue_depth()
+    }
+
+    /// Gets a snapshot of the messages and bytes sent and received so far, along with the current
+    /// queue depth (see [`Sender::connection_stats`](struct.Sender.html#method.connection_stats)).
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.sender.connection_stats()
+    }
+
+    /// Gets the most recent messages sent and received, oldest first. This keeps up to
+    /// `Config::history_length` messages, and is always empty if that is zero.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.sender.history()
+    }
+
+    /// Returns a future that resolves once every message sent before this call has been written
+    /// and flushed to the connection. This is useful before operations that depend on earlier
+    /// messages having been delivered, such as a deliberate disconnect.
+    ///
+    /// The outgoing future must be driven for this to complete, either through the `ClientStream`
+    /// or directly after taking it with `outgoing`.
+    pub fn flush(&self) -> impl Future<Output = error::Result<()>> + 'static {
+        self.sender.flush()
+    }
+
+    /// Gets the configuration being used with this `Client`.
+    fn config(&self) -> &Config {
+        &self.state.config
+    }
+
+    /// Gets a stream of incoming messages from the `Client`'s connection. This is only necessary
+    /// when trying to set up more complex clients, and requires use of the `futures` crate. Most
+    /// You can find some examples of setups using `stream` in the
+    /// [GitHub repository](https://github.com/aatxe/irc/tree/stable/examples).
+    ///
+    /// **Note**: The stream can only be returned once. Subsequent attempts will cause a panic.
+    // FIXME: when impl traits stabilize, we should change this return type.
+    pub fn stream(&mut self) -> error::Result<ClientStream> {
+        let stream = self
+            .incoming
+            .take()
+            .ok_or(error::Error::StreamAlreadyConfigured)?;
+
+        Ok(ClientStream {
+            state: Arc::clone(&self.state),
+            stream,
+            outgoing: self.outgoing.take(),
+            reconnecting: None,
+        })
+    }
+
+    /// Registers a middleware handler for incoming messages. Handlers run in the order they were
+    /// added, after the client's own handling (channel tracking, automatic replies, etc.), and
+    /// before messages are yielded by the `ClientStream`. Each handler receives the output of the
+    /// previous one and may return a transformed message, or `None` to drop it entirely.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// // Hide all notices from the stream.
+    /// client.add_middleware(|msg: Message| match msg.command {
+    ///     Command::NOTICE(..) => None,
+    ///     _ => Some(msg),
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_middleware<F>(&self, handler: F)
+    where
+        F: Fn(Message) -> Option<Message> + Send + Sync + 'static,
+    {
+        self.state.middleware.write().push(Arc::new(handler));
+    }
+
+    /// Registers a middleware handler for outgoing messages. Handlers run in the order they were
+    /// added on every message sent through the client or any of its `Sender`s, right before it is
+    /// encoded and written to the connection. Each handler receives the output of the previous one
+    /// and may return a transformed message, or `None` to drop it entirely. A message that a
+    /// handler makes too long to send (see `Config::split_long_messages`) is dropped as well.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// // Never send anything to #secret.
+    /// client.add_outbound_middleware(|msg: Message| match msg.command {
+    ///     Command::PRIVMSG(ref target, _) if target == "#secret" => None,
+    ///     _ => Some(msg),
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_outbound_middleware<F>(&self, handler: F)
+    where
+        F: Fn(Message) -> Option<Message> + Send + Sync + 'static,
+    {
+        self.state
+            .outbound_middleware
+            .write()
+            .push(Arc::new(handler));
+    }
+
+    /// Gets a list of currently joined channels. This will be `None` if tracking is disabled
+    /// altogether via the `nochanlists` feature.
+    #[cfg(not(feature = "nochanlists"))]
+    pub fn list_channels(&self) -> Option<Vec<String>> {
+        Some(
+            self.state
+                .chanlists
+                .read()
+                .keys()
+                .map(|k| k.to_owned())
+                .collect(),
+        )
+    }
+
+    #[cfg(feature = "nochanlists")]
+    pub fn list_channels(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Gets a list of [`Users`](./data/user/struct.User.html) in the specified channel, in the
+    /// order they became known. If the specified channel hasn't been joined or the `nochanlists`
+    /// feature is enabled, this function will return `None`. See also
+    /// [`sorted_users`](#method.sorted_users).
+    ///
+    /// For best results, be sure to request `multi-prefix` support from the server. This will allow
+    /// for more accurate tracking of user rank (e.g. oper, half-op, etc.).
+    /// # Requesting multi-prefix support
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// use irc::proto::caps::Capability;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.send_cap_req(&[Capability::MultiPrefix])?;
+    /// client.identify()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "nochanlists"))]
+    pub fn list_users(&self, chan: &str) -> Option<Vec<User>> {
+        self.state.chanlists.read().get(&chan.to_owned()).cloned()
+    }
+
+    #[cfg(feature = "nochanlists")]
+    pub fn list_users(&self, _: &str) -> Option<Vec<User>> {
+        None
+    }
+
+    /// Gets the current state of a single user in the specified channel, such as their access
+    /// level, away message and account, without copying the whole list of users. Channel names
+    /// and nicknames are matched under the server's case mapping. This is `None` if the user is
+    /// not known to be in the channel, or if the `nochanlists` feature is enabled.
+    #[cfg(not(feature = "nochanlists"))]
+    pub fn find_user(&self, chan: &str, nick: &str) -> Option<User> {
+        let info = self.state.server_info.read();
+        let chanlists = self.state.chanlists.read();
+        let users = match chanlists.get(chan) {
+            Some(users) => users,
+            None => {
+                chanlists
+                    .iter()
+                    .find(|(name, _)| info.eq_ignore_case(name, chan))?
+                    .1
+            }
+        };
+        users
+            .iter()
+            .find(|user| info.eq_ignore_case(user.get_nickname(), nick))
+            .cloned()
+    }
+
+    /// Gets the current state of a single user in the specified channel. This is always `None`,
+    /// since the `nochanlists` feature is enabled.
+    #[cfg(feature = "nochanlists")]
+    pub fn find_user(&self, _: &str, _: &str) -> Option<User> {
+        None
+    }
+
+    /// Gets our highest access level in the specified channel, e.g. `AccessLevel::Oper` if we are
+    /// opped there. This is `None` if we are not known to be in the channel, or if the
+    /// `nochanlists` feature is enabled.
+    pub fn my_access_level(&self, chan: &str) -> Option<AccessLevel> {
+        self.find_user(chan, &self.current_nickname())
+            .map(|user| user.highest_access_level())
+    }
+
+    /// Determines whether we are an operator (or higher, e.g. the owner) in the specified channel,
+    /// and so can kick users or change its modes. This is always `false` if the `nochanlists`
+    /// feature is enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// if client.am_i_operator("#rust") {
+    ///     client.send_kick("#rust", "spammer", "No spamming")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn am_i_operator(&self, chan: &str) -> bool {
+        self.my_access_level(chan)
+            .map_or(false, |level| level >= AccessLevel::Oper)
+    }
+
+    /// Gets the users in the specified channel like [`list_users`](#method.list_users), but sorted
+    /// the way nick lists are usually shown: by access level, highest first as ranked by the
+    /// server's `PREFIX` token, and then by nickname under the server's case mapping.
+    pub fn sorted_users(&self, chan: &str) -> Option<Vec<User>> {
+        let mut users = self.list_users(chan)?;
+        let info = self.state.server_info.read();
+        users.sort_by_cached_key(|user| {
+            (
+                info.access_rank(user.highest_access_level()),
+                info.fold_case(user.get_nickname()),
+            )
+        });
+        Some(users)
+    }
+
+    /// Determines whether the list of users in the specified channel is complete, i.e. the server
+    /// has finished sending it with `RPL_ENDOFNAMES` since we joined. Until then,
+    /// [`list_users`](#method.list_users) only returns the users known so far. This is always
+    /// `false` if the `nochanlists` feature is enabled.
+    #[cfg(not(feature = "nochanlists"))]
+    pub fn is_names_complete(&self, chan: &str) -> bool {
+        let folded = self.state.fold_case(chan);
+        self.state.names_complete.read().contains(&folded)
+    }
+
+    /// Determines whether the list of users in the specified channel is complete. This is always
+    /// `false`, since the `nochanlists` feature is enabled.
+    #[cfg(feature = "nochanlists")]
+    pub fn is_names_complete(&self, _: &str) -> bool {
+        false
+    }
+
+    /// Gets the server's message of the day, with its lines joined by `\n`. This is `None` until
+    /// the server has sent it in full, and if the server has none.
+    pub fn motd(&self) -> Option<String> {
+        self.state.motd.read().clone()
+    }
+
+    /// Gets the certificate the server presented for the current connection in DER format, e.g.
+    /// for logging it or pinning it with `pinned_cert_sha256`. This is `None` if the connection is
+    /// not secured with TLS.
+    #[cfg(any(feature = "tls-native", feature = "tls-rust"))]
+    pub fn peer_certificate(&self) -> Option<Vec<u8>> {
+        self.state.peer_certificate.read().clone()
+    }
+
+    /// Gets the reason given by the server in the last `ERROR` it sent, which it does right before
+    /// closing the connection, e.g. `Closing link: throttled`.
+    pub fn last_error(&self) -> Option<String> {
+        self.state.last_error.read().clone()
+    }
+
+    /// Gets the address of the server for the current connection, e.g. for logging which of its
+    /// addresses was connected to. When connecting through a proxy, this is the address of the
+    /// proxy. This is `None` for a mock connection.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        *self.state.peer_addr.read()
+    }
+
+    /// Gets the local address the current connection was made from. This is `None` for a mock
+    /// connection.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        *self.state.local_addr.read()
+    }
+
+    /// Gets a snapshot of the information advertised by the server so far, such as the tokens
+    /// sent in `RPL_ISUPPORT` replies.
+    pub fn server_info(&self) -> ServerInfo {
+        self.state.server_info.read().clone()
+    }
+
+    /// Gets the likely intended place to respond to the given message: the channel it was sent to,
+    /// or its sender if it was sent privately. Channels are recognized using the server's
+    /// `CHANTYPES` token when it has been advertised.
+    pub fn response_target<'a>(&self, msg: &'a Message) -> Option<&'a str> {
+        msg.response_target_with_chantypes(self.state.server_info.read().chantypes())
+    }
+
+    /// Determines whether the given capability has been acknowledged by the server, and is thus
+    /// enabled for this connection.
+    pub fn is_capability_enabled(&self, capability: &str) -> bool {
+        self.state.enabled_caps.read().contains(capability)
+    }
+
+    /// Sends a message tagged with a unique label using the IRCv3 `labeled-response` extension,
+    /// returning a future that resolves with the server's response to it. The response is
+    /// whatever messages the server labels as such: a single message, every message in a
+    /// `labeled-response` batch, or nothing if the server only acknowledges the command. These
+    /// messages are still yielded by the `ClientStream` as usual.
+    ///
+    /// This fails with `Error::CapabilityNotEnabled` unless the `labeled-response` capability has
+    /// been negotiated. Servers only send batches to clients that enabled the `batch` capability.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// let whois = client.send_labeled(Command::WHOIS(None, "alice".to_owned())).await?;
+    /// for reply in whois {
+    ///     println!("{}", reply);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_labeled<M: Into<Message>>(
+        &self,
+        msg: M,
+    ) -> impl Future<Output = error::Result<Vec<Message>>> + 'static {
+        self.state.send_labeled(msg.into())
+    }
+
+    /// Determines whether the message is one of our own echoed back by the server, which happens
+    /// for every `PRIVMSG`, `NOTICE` and `TAGMSG` we send once the `echo-message` capability is
+    /// enabled. Bots should usually ignore these to avoid replying to themselves.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # use futures::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let mut client = Client::new("config.toml").await?;
+    /// let mut stream = client.stream()?;
+    /// while let Some(message) = stream.next().await.transpose()? {
+    ///     if client.is_echo(&message) {
+    ///         continue;
+    ///     }
+    ///     // ...
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_echo(&self, msg: &Message) -> bool {
+        msg.is_echo(&self.current_nickname())
+    }
+
+    /// Sends a `LIST` for the channels matching all of the given filters, or for every channel if
+    /// there are none. Filters that rely on a search extension the server has not advertised in
+    /// its `ELIST` ISUPPORT token are rejected with `Error::UnsupportedListFilter` rather than
+    /// being sent and silently ignored.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # use irc::client::data::ListFilter;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.send_list(&[ListFilter::Mask("#rust*".to_owned()), ListFilter::MoreUsersThan(10)])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_list(&self, filters: &[ListFilter]) -> error::Result<()> {
+        self.send(self.list_command(filters)?)
+    }
+
+    /// Sends a `LIST` like [`send_list`](#method.send_list), returning a future that resolves with
+    /// the listed channels once the server sends `RPL_LISTEND`. On large networks, consider
+    /// [`list_each`](#method.list_each) to avoid holding every channel in memory at once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// for entry in client.list(&[]).await? {
+    ///     println!("{} ({} users): {}", entry.channel, entry.users, entry.topic);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn list(
+        &self,
+        filters: &[ListFilter],
+    ) -> impl Future<Output = error::Result<Vec<ChannelListEntry>>> + 'static {
+        let listed = self
+            .list_command(filters)
+            .map(|cmd| self.state.list(cmd, ListSink::Collect(Vec::new())));
+        async move { listed?.await }
+    }
+
+    /// Sends a `LIST` like [`send_list`](#method.send_list), passing each listed channel to the
+    /// handler as it arrives. The returned future resolves once the server sends `RPL_LISTEND`.
+    ///
+    /// The handler runs while the client processes the reply, so it should not block.
+    pub fn list_each<F>(
+        &self,
+        filters: &[ListFilter],
+        handler: F,
+    ) -> impl Future<Output = error::Result<()>> + 'static
+    where
+        F: FnMut(ChannelListEntry) + Send + 'static,
+    {
+        let listed = self.list_command(filters).map(|cmd| {
+            self.state
+                .list(cmd, ListSink::Each(Arc::new(Mutex::new(Box::new(handler)))))
+        });
+        async move { listed?.await.map(|_| ()) }
+    }
+
+    /// Builds a `LIST` command for the filters, rejecting those the server does not support.
+    fn list_command(&self, filters: &[ListFilter]) -> error::Result<Command> {
+        let server_info = self.state.server_info.read();
+        if let Some(filter) = filters.iter().find(|filter| {
+            filter
+                .elist_token()
+                .map_or(false, |token| !server_info.supports_elist(token))
+        }) {
+            return Err(error::Error::UnsupportedListFilter {
+                filter: filter.to_string(),
+            });
+        }
+
+        let filters = if filters.is_empty() {
+            None
+        } else {
+            let filters: Vec<_> = filters.iter().map(ListFilter::to_string).collect();
+            Some(filters.join(","))
+        };
+        Ok(Command::LIST(filters, None))
+    }
+
+    /// Starts watching the given nicknames with `MONITOR`. The server replies right away with
+    /// their current status, and again whenever one of them comes online or goes offline; these
+    /// changes are passed to handlers registered with
+    /// [`add_monitor_handler`](#method.add_monitor_handler) and reflected by
+    /// [`monitor_status`](#method.monitor_status). The nicknames are watched again automatically
+    /// after reconnecting.
+    ///
+    /// This fails with `Error::MonitorUnsupported` if the server has not advertised the `MONITOR`
+    /// ISUPPORT token.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.add_monitor_handler(|event| println!("{:?}", event));
+    /// client.monitor(&["alice", "bob"])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn monitor(&self, nicks: &[&str]) -> error::Result<()> {
+        if !self.state.server_info.read().supports("MONITOR") {
+            return Err(error::Error::MonitorUnsupported);
+        }
+        {
+            let keys: Vec<_> = nicks.iter().map(|n| self.state.fold_case(n)).collect();
+            let mut monitored = self.state.monitored.write();
+            for key in keys {
+                monitored.entry(key).or_insert(None);
+            }
+        }
+        self.state.send_monitor("+", nicks)
+    }
+
+    /// Stops watching the given nicknames with `MONITOR`. This fails with
+    /// `Error::MonitorUnsupported` if the server has not advertised the `MONITOR` ISUPPORT token.
+    pub fn unmonitor(&self, nicks: &[&str]) -> error::Result<()> {
+        if !self.state.server_info.read().supports("MONITOR") {
+            return Err(error::Error::MonitorUnsupported);
+        }
+        {
+            let keys: Vec<_> = nicks.iter().map(|n| self.state.fold_case(n)).collect();
+            let mut monitored = self.state.monitored.write();
+            for key in keys {
+                monitored.remove(&key);
+            }
+        }
+        self.state.send_monitor("-", nicks)
+    }
+
+    /// Stops watching every nickname with `MONITOR`. This fails with `Error::MonitorUnsupported`
+    /// if the server has not advertised the `MONITOR` ISUPPORT token.
+    pub fn clear_monitor(&self) -> error::Result<()> {
+        if !self.state.server_info.read().supports("MONITOR") {
+            return Err(error::Error::MonitorUnsupported);
+        }
+        self.state.monitored.write().clear();
+        self.send(Command::MONITOR("C".to_owned(), None))
+    }
+
+    /// Gets the last reported status of a nickname watched with `MONITOR`: `Some(true)` if it is
+    /// online, `Some(false)` if it is offline, and `None` if it is not being watched or the server
+    /// has not reported its status yet.
+    pub fn monitor_status(&self, nick: &str) -> Option<bool> {
+        let key = self.state.fold_case(nick);
+        self.state.monitored.read().get(&key).copied().flatten()
+    }
+
+    /// Starts watching the given nicknames with `WATCH`, for servers that support it rather than
+    /// `MONITOR`. This shares its status tracking with [`monitor`](#method.monitor): the
+    /// nicknames' changes are passed to handlers registered with
+    /// [`add_monitor_handler`](#method.add_monitor_handler) and reflected by
+    /// [`monitor_status`](#method.monitor_status), and they are watched again after reconnecting,
+    /// using `MONITOR` if the new server supports it.
+    ///
+    /// This fails with `Error::WatchUnsupported` if the server has not advertised the `WATCH`
+    /// ISUPPORT token.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.add_monitor_handler(|event| println!("{:?}", event));
+    /// if client.server_info().supports("MONITOR") {
+    ///     client.monitor(&["alice", "bob"])?;
+    /// } else {
+    ///     client.watch(&["alice", "bob"])?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch(&self, nicks: &[&str]) -> error::Result<()> {
+        if !self.state.server_info.read().supports("WATCH") {
+            return Err(error::Error::WatchUnsupported);
+        }
+        {
+            let keys: Vec<_> = nicks.iter().map(|n| self.state.fold_case(n)).collect();
+            let mut monitored = self.state.monitored.write();
+            for key in keys {
+                monitored.entry(key).or_insert(None);
+            }
+        }
+        self.state.send_watch('+', nicks)
+    }
+
+    /// Stops watching the given nicknames with `WATCH`. This fails with `Error::WatchUnsupported`
+    /// if the server has not advertised the `WATCH` ISUPPORT token.
+    pub fn unwatch(&self, nicks: &[&str]) -> error::Result<()> {
+        if !self.state.server_info.read().supports("WATCH") {
+            return Err(error::Error::WatchUnsupported);
+        }
+        {
+            let keys: Vec<_> = nicks.iter().map(|n| self.state.fold_case(n)).collect();
+            let mut monitored = self.state.monitored.write();
+            for key in keys {
+                monitored.remove(&key);
+            }
+        }
+        self.state.send_watch('-', nicks)
+    }
+
+    /// Stops watching every nickname with `WATCH`. This fails with `Error::WatchUnsupported` if
+    /// the server has not advertised the `WATCH` ISUPPORT token.
+    pub fn clear_watch(&self) -> error::Result<()> {
+        if !self.state.server_info.read().supports("WATCH") {
+            return Err(error::Error::WatchUnsupported);
+        }
+        self.state.monitored.write().clear();
+        self.send(Command::WATCH(vec!["C".to_owned()]))
+    }
+
+    /// Registers a handler to be called whenever the server reports that a nickname watched with
+    /// [`monitor`](#method.monitor) has come online or gone offline. Handlers run in the order they
+    /// were added, before the reply is yielded by the `ClientStream`.
+    pub fn add_monitor_handler<F>(&self, handler: F)
+    where
+        F: Fn(&MonitorEvent) + Send + Sync + 'static,
+    {
+        self.state
+            .monitor_handlers
+            .write()
+            .handlers
+            .push(Arc::new(handler));
+    }
+
+    /// Sends a `WHO` for the given mask, returning a future that resolves with the users the server
+    /// lists once it sends `RPL_ENDOFWHO`. If the server advertises WHOX, the query also requests
+    /// each user's account, and its replies are told apart from those of other queries by a
+    /// token. Otherwise, replies are attributed to queries in the order they were sent, so `WHO`s
+    /// sent by other means while this one is pending may be mixed into it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// for user in client.who("#rust").await? {
+    ///     println!("{} is {}", user.nickname, user.realname);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn who(&self, mask: &str) -> impl Future<Output = error::Result<Vec<WhoReply>>> + 'static {
+        self.state.who(mask)
+    }
+
+    /// Joins the channel, using the key if one is given, and waits until the server confirms it.
+    /// The returned future fails with `Error::JoinFailed` if the server refuses, e.g. because the
+    /// channel is full, invite-only, or we are banned from it. Servers that refuse with a standard
+    /// reply such as `FAIL JOIN CHANNEL_IS_FULL` fail it with `Error::RequestFailed` instead, and
+    /// if the server doesn't answer within the configured `join_timeout`, it fails with
+    /// `Error::JoinTimedOut`. If we are known to be in the channel already, nothing is sent and the
+    /// future resolves right away; with the `nochanlists` feature, channels aren't tracked, so the
+    /// `JOIN` is always sent.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.join("#rust", None).await?;
+    /// client.send_privmsg("#rust", "Hello!")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn join(
+        &self,
+        chan: &str,
+        key: Option<&str>,
+    ) -> impl Future<Output = error::Result<()>> + 'static {
+        self.state.join(chan, key)
+    }
+
+    /// Marks ourselves as away with the given message, or as back if there is none. The away
+    /// message is restored automatically after reconnecting.
+    pub fn set_away(&self, message: Option<&str>) -> error::Result<()> {
+        self.send(AWAY(message.map(|m| m.to_owned())))
+    }
+
+    /// Determines whether the given user is known to be away. For ourselves, this reflects the last
+    /// `AWAY` we sent or the server's confirmation of it. For others, this is learned from
+    /// `RPL_AWAY` replies (e.g. to a `PRIVMSG` or `WHOIS`) and, with the `away-notify` capability,
+    /// from `AWAY` messages sent by users in our channels.
+    pub fn is_away(&self, nick: &str) -> bool {
+        self.away_message(nick).is_some()
+    }
+
+    /// Gets the away message of the given user, if they are known to be away. See
+    /// [`is_away`](#method.is_away).
+    pub fn away_message(&self, nick: &str) -> Option<String> {
+        let key = self.state.fold_case(nick);
+        if key == self.state.fold_case(&self.current_nickname()) {
+            return self.state.away_message.read().clone();
+        }
+        self.state.away_users.read().get(&key).cloned()
+    }
+
+    /// Gets the account the given user is logged into, if known. For others, this is learned
+    /// from `JOIN`s with the `extended-join` capability, `ACCOUNT` messages with the
+    /// `account-notify` capability, and replies to [`who`](#method.who) when the server supports
+    /// WHOX. For ourselves, this is learned from `RPL_LOGGEDIN` replies.
+    ///
+    /// Note that this is only kept up to date for users sharing a channel with us.
+    pub fn account_for(&self, nick: &str) -> Option<String> {
+        let key = self.state.fold_case(nick);
+        self.state.accounts.read().get(&key).cloned()
+    }
+
+    /// Changes our real name to the given one using the `setname` capability. The server confirms
+    /// the change by echoing the `SETNAME` back, after which
+    /// [`realname_for`](#method.realname_for) reflects it.
+    ///
+    /// This fails with `Error::CapabilityNotEnabled` unless the `setname` capability has been
+    /// negotiated.
+    pub fn set_realname(&self, realname: &str) -> error::Result<()> {
+        if !self.is_capability_enabled("setname") {
+            return Err(error::Error::CapabilityNotEnabled {
+                capability: "setname".to_owned(),
+            });
+        }
+        self.send(Command::SETNAME(realname.to_owned()))
+    }
+
+    /// Gets the real name of the given user, if known. This is learned from `JOIN`s with the
+    /// `extended-join` capability and from `SETNAME` messages with the `setname` capability.
+    ///
+    /// Note that this is only kept up to date for users sharing a channel with us.
+    pub fn realname_for(&self, nick: &str) -> Option<String> {
+        let key = self.state.fold_case(nick);
+        self.state.realnames.read().get(&key).cloned()
+    }
+
+    /// Determines whether the message is a `PRIVMSG` mentioning our current nickname or one of
+    /// the configured `highlights` as a whole word (see `Message::mentions`). Words are compared
+    /// using the case mapping the server advertised in its `CASEMAPPING` token.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # use futures::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let mut client = Client::new("config.toml").await?;
+    /// let mut stream = client.stream()?;
+    /// while let Some(message) = stream.next().await.transpose()? {
+    ///     if client.mentions_me(&message) {
+    ///         println!("mentioned: {}", message);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mentions_me(&self, msg: &Message) -> bool {
+        let info = self.state.server_info.read();
+        let mentions = |word: &str| msg.mentions_with(word, |text| info.fold_case(text));
+        mentions(&self.current_nickname())
+            || self
+                .config()
+                .highlights()
+                .iter()
+                .any(|keyword| mentions(keyword))
+    }
+
+    /// Ignores `PRIVMSG`s and `NOTICE`s from users matching the given mask, e.g.
+    /// `*!*@spammer.host`, where `*` matches any number of characters and `?` matches exactly
+    /// one. Ignored messages are still handled by the client, but are not yielded by the
+    /// `ClientStream` or passed to middleware. This returns `false` if the mask was already
+    /// ignored.
+    ///
+    /// The masks from the configuration's `ignores` are ignored from the start.
+    pub fn add_ignore(&self, mask: &str) -> bool {
+        self.state.ignores.write().add(mask)
+    }
+
+    /// Stops ignoring users matching the given mask, returning `false` if it was not ignored.
+    pub fn remove_ignore(&self, mask: &str) -> bool {
+        self.state.ignores.write().remove(mask)
+    }
+
+    /// Gets the masks of the users currently ignored.
+    pub fn ignores(&self) -> Vec<String> {
+        self.state.ignores.read().masks().to_vec()
+    }
+
+    /// Gets the ban list of the given channel, if we've received it. The list is requested with
+    /// `MODE <channel> b` (e.g. using [`send_mode`](#method.send_mode) with `Mode::NoPrefix`), and
+    /// is kept up to date with the `+b` and `-b` modes set afterwards until we leave the channel.
+    pub fn ban_list(&self, chan: &str) -> Option<Vec<BanEntry>> {
+        let chan = self.state.fold_case(chan);
+        self.state.ban_lists.read().get(&chan).cloned()
+    }
+
+    /// Gets the quiet list of the given channel, if we've received it. Quiet lists are only
+    /// supported by some servers, which advertise `q` as a list mode in their `CHANMODES` token,
+    /// and are requested with `MODE <channel> q`.
+    pub fn quiet_list(&self, chan: &str) -> Option<Vec<BanEntry>> {
+        let chan = self.state.fold_case(chan);
+        self.state.quiet_lists.read().get(&chan).cloned()
+    }
+
+    /// Requests message history for the given channel or nickname using the IRCv3 `CHATHISTORY`
+    /// extension, returning a future that resolves with at most `limit` messages sorted by their
+    /// `time` tags. To page through history, anchor the next request to the oldest message
+    /// received so far using `MessageReference::from_message`.
+    ///
+    /// This fails with `Error::CapabilityNotEnabled` unless the `draft/chathistory` capability has
+    /// been negotiated. Servers also only send history to clients that enabled the `batch` and
+    /// `server-time` capabilities. If the server refuses the request with a `FAIL CHATHISTORY`,
+    /// e.g. for an invalid target, this fails with `Error::RequestFailed`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// use irc::client::data::{HistoryQuery, MessageReference};
+    ///
+    /// let latest = client.chathistory("#rust", HistoryQuery::Latest(None), 50).await?;
+    /// if let Some(oldest) = latest.first().and_then(MessageReference::from_message) {
+    ///     let older = client.chathistory("#rust", HistoryQuery::Before(oldest), 50).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn chathistory(
+        &self,
+        target: &str,
+        query: HistoryQuery,
+        limit: u32,
+    ) -> impl Future<Output = error::Result<Vec<Message>>> + 'static {
+        self.state.chathistory(target, &query, limit)
+    }
+
+    /// Registers a handler to be called with every batch of messages sent by the server once it
+    /// ends, using the IRCv3 `batch` capability. Batches nested in another batch are included in
+    /// that batch rather than passed to the handler on their own. Handlers run in the order they
+    /// were added, before the message ending the batch is yielded by the `ClientStream`; the
+    /// messages in a batch are still yielded as they arrive. Batches that were already underway
+    /// while no handler was registered and no `CHATHISTORY` request was pending are not reported.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// use irc::proto::BatchSubCommand;
+    ///
+    /// client.add_batch_handler(|batch| {
+    ///     if batch.kind == BatchSubCommand::NETSPLIT {
+    ///         println!("{} users lost in a netsplit", batch.messages.len());
+    ///     }
+    /// });
+    /// client.send_cap_req(&[Capability::Batch])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_batch_handler<F>(&self, handler: F)
+    where
+        F: Fn(&Batch) + Send + Sync + 'static,
+    {
+        self.state
+            .batch_handlers
+            .write()
+            .handlers
+            .push(Arc::new(handler));
+    }
+
+    /// Gets the invitations to us for channels we haven't joined since, oldest first.
+    pub fn pending_invites(&self) -> Vec<Invite> {
+        self.state.pending_invites.read().clone()
+    }
+
+    /// Adds a handler run whenever an invitation is received. Besides invitations to us, this
+    /// includes invitations of other users into channels we're in, which servers only send once
+    /// the `invite-notify` capability is enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// let sender = client.sender();
+    /// let nickname = client.current_nickname().to_owned();
+    /// client.add_invite_handler(move |invite| {
+    ///     if invite.is_for(&nickname) && invite.channel == "#rust" {
+    ///         let _ = sender.send_join(&invite.channel);
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_invite_handler<F>(&self, handler: F)
+    where
+        F: Fn(&Invite) + Send + Sync + 'static,
+    {
+        self.state
+            .invite_handlers
+            .write()
+            .handlers
+            .push(Arc::new(handler));
+    }
+
+    /// Gets the current nickname in use. This may be the primary username set in the configuration,
+    /// any of the alternative nicknames listed as well, or a nickname the server has since assigned
+    /// to us (e.g. due to a collision or services enforcement). As a result, this is the preferred
+    /// way to refer to the client's nickname.
+    pub fn current_nickname(&self) -> String {
+        self.state.current_nickname()
+    }
+
+    /// Sends a [`Command`](../proto/command/enum.Command.html) as this `Client`. This is the
+    /// core primitive for sending messages to the server.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # let client = Client::new("config.toml").await.unwrap();
+    /// client.send(Command::NICK("example".to_owned())).unwrap();
+    /// client.send(Command::USER("user".to_owned(), "0".to_owned(), "name".to_owned())).unwrap();
+    /// # }
+    /// ```
+    pub fn send<M: Into<Message>>(&self, msg: M) -> error::Result<()> {
+        self.state.send(msg)
+    }
+
+    /// Sends several messages as this `Client` at once. They are all written before the connection
+    /// is flushed, and nothing sent from elsewhere comes between them, which saves a write per
+    /// message when sending many commands (e.g. a series of mode changes). Rate limiting still
+    /// applies to each message.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # let client = Client::new("config.toml").await.unwrap();
+    /// let voices = ["alice", "bob", "carol"]
+    ///     .iter()
+    ///     .map(|nick| Command::ChannelMODE("#rust".to_owned(), vec![Mode::Plus(ChannelMode::Voice, Some(nick.to_string()))]));
+    /// client.send_all(voices).unwrap();
+    /// # }
+    /// ```
+    pub fn send_all<I, M>(&self, msgs: I) -> error::Result<()>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        self.state.send_all(msgs)
+    }
+
+    /// Changes several modes on a channel in as few `MODE` commands as possible, respecting the
+    /// number of modes with a parameter the server allows per command in its `MODES` token (or 3
+    /// if it did not advertise one). The commands are sent together, as with `send_all`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// use irc::client::data::ModeChange;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// let ops: Vec<_> = ["alice", "bob", "carol", "dave"]
+    ///     .iter()
+    ///     .map(|nick| ModeChange { adding: true, mode: 'o', param: Some(nick.to_string()) })
+    ///     .collect();
+    /// client.set_modes("#rust", &ops)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_modes(&self, chan: &str, changes: &[ModeChange]) -> error::Result<()> {
+        let limit = self.state.server_info.read().modes_limit();
+        self.send_all(pack_mode_changes(chan, changes, limit))
+    }
+
+    /// Quits the server with the given message, or the configured `quit_message` if `None` is
+    /// given. The returned future resolves once the `QUIT` (and everything sent before it) has
+    /// been flushed to the connection, after which the server will close it. As with `flush`, the
+    /// outgoing future must be driven for it to complete.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.quit(Some("Restarting, be right back!")).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn quit(&self, message: Option<&str>) -> impl Future<Output = error::Result<()>> + 'static {
+        let sent = self.send_quit(message.unwrap_or_default());
+        let flush = self.flush();
+
+        async move {
+            sent?;
+            flush.await
+        }
+    }
+
+    /// Registers an account with the server using the draft
+    /// [account-registration](https://ircv3.net/specs/extensions/account-registration) extension.
+    /// The returned future resolves with the server's `REGISTER` reply (e.g. `SUCCESS` or
+    /// `VERIFICATION_REQUIRED`), or with `Error::RequestFailed` carrying the parsed standard reply
+    /// if the server sends a matching `FAIL REGISTER`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// match client.send_register("bot", None, "hunter2").await {
+    ///     Ok(reply) => println!("registered: {}", reply),
+    ///     Err(irc::error::Error::RequestFailed { reply }) => println!("{}", reply.description),
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_register(
+        &self,
+        account: &str,
+        email: Option<&str>,
+        password: &str,
+    ) -> impl Future<Output = error::Result<Message>> + 'static {
+        let msg = Command::Raw(
+            "REGISTER".to_owned(),
+            vec![
+                account.to_owned(),
+                email.unwrap_or("*").to_owned(),
+                password.to_owned(),
+            ],
+        );
+        self.state.request(msg.into(), vec![account.to_owned()])
+    }
+
+    /// Determines whether or not the server has told us we're an IRC operator, either with
+    /// `RPL_YOUREOPER` or by setting user mode `+o` (or `+O`) on us.
+    pub fn is_oper(&self) -> bool {
+        *self.state.is_oper.read()
+    }
+
+    /// Gets our current server notice mask, if the server has reported one with `RPL_SNOMASK`.
+    pub fn snomask(&self) -> Option<String> {
+        self.state.snomask.read().clone()
+    }
+
+    /// Subscribes to server notices with the specified snomask (e.g. `+cCkn`). This is sent as
+    /// user mode `+s` on ourselves, and generally requires being an IRC operator. Incoming server
+    /// notices can be parsed with [`ServerNotice`](./data/server_notice/struct.ServerNotice.html).
+    pub fn send_snomask<S>(&self, snomask: S) -> error::Result<()>
+    where
+        S: fmt::Display,
+    {
+        self.send_mode(
+            self.current_nickname(),
+            &[Mode::Plus(
+                UserMode::ServerNotices,
+                Some(snomask.to_string()),
+            )],
+        )
+    }
+
+    /// Forces the client to drop its current connection and reconnect immediately. This sends a
+    /// `QUIT` with the configured quit message, and once it has been flushed, the `ClientStream`
+    /// closes the connection, establishes a new one from the same configuration, and registers
+    /// again as with `identify`. Anything tracked about the old connection (channels, nickname,
+    /// oper status, etc.) is forgotten.
+    ///
+    /// The reconnect is carried out by the `ClientStream`, which must be driving the outgoing
+    /// future as well. If the outgoing future was taken with `outgoing`, the stream yields
+    /// `Error::CannotReconnect` instead.
+    pub fn reconnect_now(&self) -> error::Result<()> {
+        self.send_quit("")?;
+        self.state.reconnect_requested.store(true, Ordering::SeqCst);
+        self.state.reconnect_waker.wake();
+        Ok(())
+    }
+
+    /// Sets our own user modes from a mode string such as `+iB`, e.g. to mark ourselves as a bot or
+    /// as invisible. To set modes on every connection, use `Config::umodes` instead.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use irc::client::prelude::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> irc::error::Result<()> {
+    /// # let client = Client::new("config.toml").await?;
+    /// client.set_user_mode("+B")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_user_mode(&self, modes: &str) -> error::Result<()> {
+        self.state.send_user_modes(modes)
+    }
+
+    /// Sends a CAP END, NICK and USER to identify. When the configuration has `WEBIRC` details,
+    /// `WEBIRC` is already queued when the connection is established, ahead of anything else.
+    ///
+    /// When the configuration has `required_capabilities`, a `CAP LS 302` is sent instead of the
+    /// CAP END. The capabilities are requested once the server lists them, and CAP END is only
+    /// sent after they have been acknowledged. If `sasl` is required, CAP END waits until
+    /// authentication succeeds instead: authenticate with [`send_sasl`](#method.send_sasl) once
+    /// the `CAP ACK` arrives, and if it fails, either try again or end negotiation by sending
+    /// CAP END yourself.
+    pub fn identify(&self) -> error::Result<()> {
+        self.state.identify()
+    }
+
+    fn default_quit_message(&self) -> &str {
+        self.sender.default_quit_message()
+    }
+
+    pub_state_base!();
+    pub_sender_base!();
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, default::Default, sync::Arc, thread, time::Duration};
+
+    use parking_lot::Mutex;
+
+    use super::{mock::Mismatch, pack_mode_changes, Client, RELAYED_SOURCE_LENGTH};
+    #[cfg(not(feature = "nochanlists"))]
+    use crate::client::data::User;
+    use crate::{
+        client::data::{
+            Config, Event, HistoryQuery, Invite, LengthPolicy, ListFilter, MessageReference,
+            ModeChange, MonitorEvent,
+        },
+        error::Error,
+        proto::{
+            command::Command::{self, Raw, NICK, NOTICE, PRIVMSG, USER},
+            irc::MAX_LINE_LENGTH,
+            message::Tag,
+            BatchSubCommand, CapSubCommand, Capability, ChannelMode, IrcCodec, Message, Mode,
+            Response, StandardReplyKind,
+        },
+    };
+    use anyhow::Result;
+    use futures::prelude::*;
+
+    pub fn test_config() -> Config {
+        Config {
+            owners: vec!["test".to_string()],
+            nickname: Some("test".to_string()),
+            alt_nicks: vec!["test2".to_string()],
+            server: Some("irc.test.net".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            user_info: Some("Testing.".to_string()),
+            use_mock_connection: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_client_value(client: Client) -> String {
+        // We sleep here because of synchronization issues.
+        // We can't guarantee that everything will have been sent by the time of this call.
+        thread::sleep(Duration::from_millis(100));
+        client
+            .log_view()
+            .sent()
+            .unwrap()
+            .iter()
+            .fold(String::new(), |mut acc, msg| {
+                // NOTE: we have to sanitize here because sanitization happens in IrcCodec after the
+                // messages are converted into strings, but our transport logger catches messages before
+                // they ever reach that point.
+                acc.push_str(&IrcCodec::sanitize(msg.to_string()));
+                acc
+            })
+    }
+
+    #[tokio::test]
+    async fn from_built_config() -> Result<()> {
+        let config = Config::builder()
+            .nickname("test")
+            .server("irc.test.net")
+            .build()?;
+        let mut client = Client::from_config(Config {
+            use_mock_connection: true,
+            ..config
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "CAP END\r\nNICK test\r\nUSER test 0 * test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identify_registration_mode() -> Result<()> {
+        for (mode, user) in [("8", "USER test 8 * test"), ("*", "USER test * * test")] {
+            let mut client = Client::from_config(Config {
+                registration_mode: Some(mode.to_owned()),
+                ..test_config()
+            })
+            .await?;
+            client.identify()?;
+            client.stream()?.collect().await?;
+            assert_eq!(
+                get_client_value(client),
+                format!("CAP END\r\nNICK test\r\n{}\r\n", user)
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stream() -> Result<()> {
+        let exp = "PRIVMSG test :Hi!\r\nPRIVMSG test :This is a test!\r\n\
+                   :test!test@test JOIN #test\r\n";
+
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(exp.to_owned()),
+            ..test_config()
+        })
+        .await?;
+
+        client.stream()?.collect().await?;
+        // assert_eq!(&messages[..], exp);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn middleware() -> Result<()> {
+        let value = ":test!test@test PRIVMSG #test :hello\r\n\
+                     :test!test@test NOTICE #test :spam\r\n\
+                     :test!test@test PRIVMSG #test :world\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.add_middleware(|mut msg: Message| {
+            if let PRIVMSG(_, ref mut text) = msg.command {
+                *text = text.to_uppercase();
+            }
+            Some(msg)
+        });
+        client.add_middleware(|msg: Message| match msg.command {
+            NOTICE(..) => None,
+            _ => Some(msg),
+        });
+        let messages = client.stream()?.collect().await?;
+        assert_eq!(
+            messages.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+            vec![
+                ":test!test@test PRIVMSG #test HELLO\r\n",
+                ":test!test@test PRIVMSG #test WORLD\r\n",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outbound_middleware() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.add_outbound_middleware(|mut msg: Message| {
+            if let PRIVMSG(..) = msg.command {
+                msg.tags
+                    .get_or_insert_with(Vec::new)
+                    .push(Tag("+example/client".to_owned(), Some("irc".to_owned())));
+            }
+            Some(msg)
+        });
+        client.add_outbound_middleware(|msg: Message| match msg.command {
+            PRIVMSG(ref target, _) if target == "#secret" => None,
+            _ => Some(msg),
+        });
+        client.send_privmsg("#test", "Hi there!")?;
+        client.send_privmsg("#secret", "Hi there!")?;
+        client.sender().send_notice("#test", "Hi there!")?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "@+example/client=irc PRIVMSG #test :Hi there!\r\nNOTICE #test :Hi there!\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outbound_middleware_too_long() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.add_outbound_middleware(|msg: Message| match msg.command {
+            Command::TOPIC(target, _) => Some(Command::TOPIC(target, Some("x".repeat(600))).into()),
+            _ => Some(msg),
+        });
+        client.send_topic("#test", "short")?;
+        client.send_privmsg("#test", "Hi there!")?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG #test :Hi there!\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cloned_senders() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let tasks: Vec<_> = ["alice", "bob"]
+            .iter()
+            .map(|nick| {
+                let sender = client.sender();
+                tokio::spawn(async move { sender.send_privmsg(nick, "hi") })
+            })
+            .collect();
+        for task in tasks {
+            task.await??;
+        }
+        client.stream()?.collect().await?;
+        let value = get_client_value(client);
+        assert!(value.contains("PRIVMSG alice hi\r\n"));
+        assert!(value.contains("PRIVMSG bob hi\r\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_message() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_nick_password() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nick_password: Some("password".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NICKSERV IDENTIFY password\r\nJOIN #test\r\n\
+             JOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nickserv_identify_after_welcome() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the network\r\n\
+                     :irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nick_password: Some("password".to_string()),
+            nickserv_identify: Some("PRIVMSG NickServ :IDENTIFY {nick} {password}".to_string()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG NickServ :IDENTIFY test password\r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wait_for_identify() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the network\r\n\
+                     :irc.test.net 376 test :End of /MOTD command.\r\n\
+                     :NickServ!service@services NOTICE test :This nickname is registered.\r\n";
+        let config = Config {
+            nick_password: Some("password".to_string()),
+            nickserv_identify: Some("PRIVMSG NickServ :IDENTIFY {password}".to_string()),
+            wait_for_identify: true,
+            ..test_config()
+        };
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..config.clone()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG NickServ :IDENTIFY password\r\n"
+        );
+
+        let value = format!(
+            "{}:irc.test.net 900 test test!test@host test :You are now logged in as test\r\n",
+            value
+        );
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value),
+            ..config
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG NickServ :IDENTIFY password\r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_identify_timeout() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the network\r\n\
+                     :irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            mock_keep_open: true,
+            nick_password: Some("password".to_string()),
+            nickserv_identify: Some("PRIVMSG NickServ :IDENTIFY {password}".to_string()),
+            wait_for_identify: true,
+            identify_timeout: Some(10),
+            ..test_config()
+        })
+        .await?;
+        let handle = client.mock_handle();
+        let log = client.log_view().clone();
+        let before_timeout = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(9)).await;
+            let sent = log.sent().unwrap().len();
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            handle.disconnect();
+            sent
+        });
+        client.stream()?.collect().await?;
+        // Only the identification had been sent before the timeout.
+        assert_eq!(before_timeout.await.unwrap(), 1);
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG NickServ :IDENTIFY password\r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn motd() -> Result<()> {
+        let value = ":irc.test.net 375 test :- irc.test.net Message of the day - \r\n\
+                     :irc.test.net 372 test :- Welcome to the test network.\r\n\
+                     :irc.test.net 372 test :-   Be nice.\r\n\
+                     :irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        assert_eq!(client.motd(), None);
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.motd().as_deref(),
+            Some("Welcome to the test network.\n  Be nice.")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_motd() -> Result<()> {
+        let value = ":irc.test.net 422 test :MOTD File is missing\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.motd(), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events() -> Result<()> {
+        let value = ":alice!a@host JOIN #test\r\n\
+                     :alice!a@host PRIVMSG #test :Hi!\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let events: Vec<_> = client.stream()?.events().try_collect().await?;
+        assert!(matches!(
+            events[..],
+            [Event::Join { .. }, Event::ChannelMessage { .. }]
+        ));
+        assert_eq!(events[1].message().response_target(), Some("#test"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_error() -> Result<()> {
+        let value = "ERROR :Closing link: throttled\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let mut stream = client.stream()?;
+        let message = stream.next().await.transpose()?;
+        assert_eq!(
+            message.map(|msg| msg.command),
+            Some(Command::ERROR("Closing link: throttled".to_owned()))
+        );
+        match stream.next().await {
+            Some(Err(Error::ServerError { reason })) => {
+                assert_eq!(reason, "Closing link: throttled")
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(
+            client.last_error().as_deref(),
+            Some("Closing link: throttled")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_error_after_quit() -> Result<()> {
+        let value = "ERROR :Closing link: (Quit: bye)\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.send_quit("bye")?;
+        let messages = client.stream()?.collect().await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            client.last_error().as_deref(),
+            Some("Closing link: (Quit: bye)")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_chan_keys() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nickname: Some("test".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            channel_keys: {
+                let mut map = HashMap::new();
+                map.insert("#test2".to_string(), "password".to_string());
+                map
+            },
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #test\r\nJOIN #test2 password\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn handle_end_motd_staggered_joins() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            mock_keep_open: true,
+            channels: (0..10).map(|i| format!("#c{}", i)).collect(),
+            channels_per_join: Some(4),
+            join_delay: Some(1000),
+            ..test_config()
+        })
+        .await?;
+        let handle = client.mock_handle();
+        tokio::spawn(async move {
+            // Only the first two groups have been sent by now.
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            handle.disconnect();
+        });
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #c0,#c1,#c2,#c3\r\nJOIN #c4,#c5,#c6,#c7\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn staggered_joins_end_with_connection() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            mock_keep_open: true,
+            channels: (0..10).map(|i| format!("#c{}", i)).collect(),
+            channels_per_join: Some(4),
+            join_delay: Some(1000),
+            ..test_config()
+        })
+        .await?;
+        let handle = client.mock_handle();
+        let state = Arc::clone(&client.state);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            // As happens when reconnecting, which must not carry on joining for the old connection.
+            state.reset();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            handle.disconnect();
+        });
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #c0,#c1,#c2,#c3\r\nJOIN #c4,#c5,#c6,#c7\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_grouped_joins() -> Result<()> {
+        let value = ":irc.test.net 005 test CHANLIMIT=#:4 :are supported\r\n\
+                     :irc.test.net 376 test :End of /MOTD command\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            channels: (0..6).map(|i| format!("#c{}", i)).collect(),
+            channel_keys: {
+                let mut map = HashMap::new();
+                map.insert("#c2".to_string(), "password".to_string());
+                map
+            },
+            channels_per_join: Some(3),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #c2,#c0,#c1 password\r\nJOIN #c3\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_grouped_chan_keys() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            channels: vec!["#open".to_string(), "#a".to_string(), "#B".to_string()],
+            channel_keys: {
+                let mut map = HashMap::new();
+                map.insert("#a".to_string(), "key1".to_string());
+                map.insert("#b".to_string(), "key2".to_string());
+                map
+            },
+            channels_per_join: Some(5),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #a,#B,#open key1,key2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_ghost() -> Result<()> {
+        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
+                     :irc.test.net 376 test2 :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nickname: Some("test".to_string()),
+            alt_nicks: vec!["test2".to_string()],
+            nick_password: Some("password".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            should_ghost: true,
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NICK test2\r\nNICKSERV GHOST test password\r\n\
+             NICK test\r\nNICKSERV IDENTIFY password\r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_ghost_seq() -> Result<()> {
+        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
+                     :irc.test.net 376 test2 :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nickname: Some("test".to_string()),
+            alt_nicks: vec!["test2".to_string()],
+            nick_password: Some("password".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            should_ghost: true,
+            ghost_sequence: Some(vec!["RECOVER".to_string(), "RELEASE".to_string()]),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NICK test2\r\nNICKSERV RECOVER test password\
+             \r\nNICKSERV RELEASE test password\r\nNICK test\r\nNICKSERV IDENTIFY password\
+             \r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_end_motd_with_umodes() -> Result<()> {
+        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nickname: Some("test".to_string()),
+            umodes: Some("+B".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "MODE test +B\r\nJOIN #test\r\nJOIN #test2\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn umodes_after_registration() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the Test IRC Network test\r\n\
+                     :irc.test.net 376 test :End of /MOTD command.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            umodes: Some("+iB".to_string()),
+            channels: vec![],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "MODE test +i +B\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_user_mode() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.set_user_mode("-i")?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "MODE test -i\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nickname_in_use() -> Result<()> {
+        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "NICK test2\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nickname_in_use_walks_alt_nicks() -> Result<()> {
+        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
+                     :irc.test.net 433 * test2 :Nickname is already in use.\r\n\
+                     :irc.test.net 433 * test3 :Nickname is already in use.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            alt_nicks: vec!["test2".to_string(), "test3".to_string()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.current_nickname(), "test1");
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NICK test2\r\nNICK test3\r\nNICK test1\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nickname_in_use_after_registration() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the Test IRC Network test\r\n\
+                     :irc.test.net 433 test test2 :Nickname is already in use.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.current_nickname(), "test");
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ran_out_of_nicknames() -> Result<()> {
+        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
+                     :irc.test.net 432 * test2 :Erroneous nickname.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let res = client.stream()?.try_collect::<Vec<_>>().await;
+        if let Err(Error::NoUsableNick) = res {
+        } else {
+            panic!("expected error when no valid nicks were specified")
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn current_nickname_from_welcome() -> Result<()> {
+        let value = ":irc.test.net 001 test_ :Welcome to the Test IRC Network test_\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        assert_eq!(client.current_nickname(), "test");
+        client.stream()?.collect().await?;
+        assert_eq!(client.current_nickname(), "test_");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn current_nickname_forced_change() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome to the Test IRC Network test\r\n\
+                     :other!other@test NICK other2\r\n\
+                     :test!test@test NICK Guest1234\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.current_nickname(), "Guest1234");
+        Ok(())
+    }
+
+    fn advertise(client: &Client, tokens: &[&str]) {
+        let mut args = vec!["test".to_owned()];
+        args.extend(tokens.iter().map(|token| token.to_string()));
+        args.push("are supported".to_owned());
+        client.state.server_info.write().handle_isupport(&args);
+    }
+
+    #[tokio::test]
+    async fn length_policy_truncate() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            length_policy: Some(LengthPolicy::Truncate),
+            ..test_config()
+        })
+        .await?;
+        advertise(&client, &["NICKLEN=9", "USERLEN=4"]);
+        client.send(NICK("averylongnickname".to_owned()))?;
+        client.send(USER(
+            "username".to_owned(),
+            "0".to_owned(),
+            "Real".to_owned(),
+        ))?;
+        client.send(NICK("short".to_owned()))?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NICK averylong\r\nUSER user 0 * Real\r\nNICK short\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn length_policy_truncate_tracked() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            length_policy: Some(LengthPolicy::Truncate),
+            mock_script: vec![(
+                "NICK averylong".to_owned(),
+                ":test!test@test NICK averylong\r\n".to_owned(),
+            )],
+            ..test_config()
+        })
+        .await?;
+        advertise(&client, &["NICKLEN=9"]);
+        client.send(NICK("averylongnickname".to_owned()))?;
+        client.stream()?.collect().await?;
+        assert!(client.mock_mismatches().is_empty());
+        assert_eq!(client.current_nickname(), "averylong");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn length_policy_error() -> Result<()> {
+        let client = Client::from_config(Config {
+            length_policy: Some(LengthPolicy::Error),
+            ..test_config()
+        })
+        .await?;
+        client.send(NICK("averylongnickname".to_owned()))?;
+        advertise(&client, &["NICKLEN=9"]);
+        match client.send(NICK("averylongnickname".to_owned())) {
+            Err(Error::NameTooLong { max, .. }) => assert_eq!(max, 9),
+            other => panic!("expected the nickname to be too long, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn length_policy_send() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        advertise(&client, &["NICKLEN=9"]);
+        client.send(NICK("averylongnickname".to_owned()))?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "NICK averylongnickname\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        assert!(client
+            .send(PRIVMSG("#test".to_string(), "Hi there!".to_string()))
+            .is_ok());
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG #test :Hi there!\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flush() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_privmsg("#test", "Hi there!")?;
+        client.send_notice("#test", "Bye for now!")?;
+        let flush = client.flush();
+        tokio::spawn(client.outgoing().unwrap());
+        flush.await?;
+        assert_eq!(client.log_view().sent()?.len(), 2);
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG #test :Hi there!\r\nNOTICE #test :Bye for now!\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_raw_with_tags() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_raw_with_tags(
+            vec![
+                Tag("label".to_owned(), Some("a b;c\\d".to_owned())),
+                Tag("+draft/reply".to_owned(), Some("123".to_owned())),
+                Tag("solo".to_owned(), None),
+            ],
+            Some("relay!relay@relay.test.net"),
+            "PRIVMSG",
+            &["#test"],
+            Some("Hello, world!"),
+        )?;
+        client.send_raw_with_tags(vec![], None, "AWAY", &[], Some(""))?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "@label=a\\sb\\:c\\\\d;+draft/reply=123;solo :relay!relay@relay.test.net \
+             PRIVMSG #test :Hello, world!\r\nAWAY :\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_raw_with_tags_no_injection() -> Result<()> {
+        let client = Client::from_config(test_config()).await?;
+        let tag = |k: &str| vec![Tag(k.to_owned(), None)];
+        assert!(client
+            .send_raw_with_tags(vec![], None, "PRIVMSG", &["#test\r\nQUIT"], Some("hi"))
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], None, "PRIVMSG", &["#test"], Some("hi\nQUIT"))
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], None, "PRIVMSG", &["#a #b"], Some("hi"))
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], None, "PRIVMSG", &[":#test"], None)
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], Some("a b"), "PRIVMSG", &["#test"], None)
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(tag("a b"), None, "PRIVMSG", &["#test"], None)
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], None, "PRIV MSG", &["#test"], None)
+            .is_err());
+        assert!(client
+            .send_raw_with_tags(vec![], None, "", &[], None)
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quit() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let quit = client.quit(Some("bye"));
+        tokio::spawn(client.outgoing().unwrap());
+        quit.await?;
+        assert_eq!(&get_client_value(client)[..], "QUIT bye\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn shutdown() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let stream = client.stream()?;
+        client.send_quit("bye")?;
+        stream.shutdown().await?;
+        assert_eq!(&get_client_value(client)[..], "QUIT bye\r\n");
+
+        let mut client = Client::from_config(test_config()).await?;
+        let outgoing = client.outgoing().unwrap();
+        client.send_quit("bye")?;
+        outgoing.shutdown().await?;
+        assert_eq!(&get_client_value(client)[..], "QUIT bye\r\n");
+
+        // Dropping the outgoing future instead loses whatever is still queued.
+        let mut client = Client::from_config(test_config()).await?;
+        let outgoing = client.outgoing().unwrap();
+        client.send_quit("bye")?;
+        drop(outgoing);
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_quit_default_message() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            quit_message: Some("Restarting.".to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.sender().send_quit("")?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "QUIT Restarting.\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quit_default_message() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            quit_message: Some("Restarting.".to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let quit = client.quit(None);
+        tokio::spawn(client.outgoing().unwrap());
+        quit.await?;
+        assert_eq!(&get_client_value(client)[..], "QUIT Restarting.\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_no_newline_injection() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        assert!(client
+            .send(PRIVMSG(
+                "#test".to_string(),
+                "Hi there!\r\nJOIN #bad".to_string()
+            ))
+            .is_ok());
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG #test :Hi there!\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_raw_is_really_raw() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        assert!(client
+            .send(Raw("PASS".to_owned(), vec!["password".to_owned()]))
+            .is_ok());
+        assert!(client
+            .send(Raw("NICK".to_owned(), vec!["test".to_owned()]))
+            .is_ok());
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PASS password\r\nNICK test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn channel_tracking_names() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.list_channels().unwrap(), vec!["#test".to_owned()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn channel_tracking_names_part() -> Result<()> {
+        use crate::proto::command::Command::PART;
+
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+
+        client.stream()?.collect().await?;
+
+        assert_eq!(client.list_channels(), Some(vec!["#test".to_owned()]));
+        // we ignore the result, as soon as we queue an outgoing message we
+        // update client state, regardless if the queue is available or not.
+        let _ = client.send(PART("#test".to_string(), None));
+        assert_eq!(client.list_channels(), Some(vec![]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("~owner"), User::new("&admin")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_who_bot() -> Result<()> {
+        let value = ":irc.test.net 005 test BOT=B :are supported by this server\r\n\
+                     :irc.test.net 353 test = #test :test robot human\r\n\
+                     :irc.test.net 352 test #test bot host irc.test.net robot H@B :0 Robot\r\n\
+                     :irc.test.net 352 test #test user host irc.test.net human H :0 Human\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let users = client.list_users("#test").unwrap();
+        let is_bot = |nick| users.iter().any(|u| u.get_nickname() == nick && u.is_bot());
+        assert!(is_bot("robot"));
+        assert!(!is_bot("human"));
+        assert!(!is_bot("test"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_bot_tag() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test robot\r\n\
+                     @bot :robot!bot@host PRIVMSG #test :beep\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let users = client.list_users("#test").unwrap();
+        assert!(users
+            .iter()
+            .any(|u| u.get_nickname() == "robot" && u.is_bot()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_away_notify() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test alice bob\r\n\
+                     :alice!a@host AWAY :Out to lunch\r\n\
+                     :bob!b@host AWAY :Gone\r\n\
+                     :bob!b@host AWAY\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let users = client.list_users("#test").unwrap();
+        let alice = users.iter().find(|u| u.get_nickname() == "alice").unwrap();
+        assert_eq!(alice.away_message(), Some("Out to lunch"));
+        assert!(!users
+            .iter()
+            .any(|u| u.get_nickname() == "bob" && u.is_away()));
+        assert!(client.is_away("Alice"));
+        assert!(!client.is_away("bob"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_casemapping() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test Alice[m]\r\n\
+                     :alice{m}!a@host AWAY :Out to lunch\r\n\
+                     :ALICE[M]!a@host CHGHOST ~alice cloaked/alice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let alice = client.find_user("#test", "alice{m}").unwrap();
+        assert_eq!(alice.get_nickname(), "Alice[m]");
+        assert_eq!(alice.away_message(), Some("Out to lunch"));
+        assert_eq!(alice.get_hostname(), Some("cloaked/alice"));
+        assert!(client.is_away("ALICE{M}"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_account_casemapping() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test Alice[m]\r\n\
+                     :alice{m}!a@host ACCOUNT alice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let alice = client.find_user("#test", "Alice[m]").unwrap();
+        assert_eq!(alice.account(), Some("alice"));
+        assert_eq!(client.account_for("ALICE{M}").as_deref(), Some("alice"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_account() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test\r\n\
+                     :alice!a@host JOIN #test alice_acct :Alice\r\n\
+                     :bob!b@host JOIN #test * :Bob\r\n\
+                     :alice!a@host ACCOUNT alice2\r\n\
+                     :bob!b@host ACCOUNT bob\r\n\
+                     :bob!b@host ACCOUNT *\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let users = client.list_users("#test").unwrap();
+        let alice = users.iter().find(|u| u.get_nickname() == "alice").unwrap();
+        assert_eq!(alice.account(), Some("alice2"));
+        let bob = users.iter().find(|u| u.get_nickname() == "bob").unwrap();
+        assert_eq!(bob.account(), None);
+        assert_eq!(client.account_for("Alice").as_deref(), Some("alice2"));
+        assert_eq!(client.account_for("bob"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_forgets_departed_users() -> Result<()> {
+        let value = ":irc.test.net 353 test = #a :test alice bob carol\r\n\
+                     :irc.test.net 353 test = #b :test bob\r\n\
+                     :alice!a@host AWAY :Out to lunch\r\n\
+                     :bob!b@host AWAY :Gone\r\n\
+                     :carol!c@host ACCOUNT carol\r\n\
+                     :alice!a@host PART #a :Bye\r\n\
+                     :op!op@host KICK #a bob :Out\r\n\
+                     :op!op@host KICK #a test :Out\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert!(!client.is_away("alice"));
+        // Bob is still in #b with us.
+        assert!(client.is_away("bob"));
+        assert_eq!(client.account_for("carol"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ban_list() -> Result<()> {
+        let value = ":irc.test.net 367 test #test *!*@host op!op@op 1546612406\r\n\
+                     :irc.test.net 367 test #test *!*@other op!op@op 1546612407\r\n\
+                     :irc.test.net 368 test #test :End of channel ban list\r\n\
+                     @time=2019-01-04T14:33:26.123Z :op!op@op MODE #test +b-b *!*@new *!*@host\r\n\
+                     :op!op@op MODE #other +b *!*@host\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let bans = client.ban_list("#TEST").unwrap();
+        let masks: Vec<_> = bans.iter().map(|ban| &ban.mask[..]).collect();
+        assert_eq!(masks, vec!["*!*@other", "*!*@new"]);
+        assert_eq!(bans[1].setter.as_deref(), Some("op!op@op"));
+        assert_eq!(
+            bans[1].set_at.map(|time| time.timestamp_millis()),
+            Some(1546612406123)
+        );
+        assert_eq!(client.ban_list("#other"), None);
+        assert_eq!(client.quiet_list("#test"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quiet_list() -> Result<()> {
+        let value =
+            ":irc.test.net 005 test CHANMODES=bq,k,l,imnpst PREFIX=(ov)@+ :are supported\r\n\
+                     :irc.test.net 728 test #test q *!*@host op 1546612406\r\n\
+                     :irc.test.net 729 test #test q :End of channel quiet list\r\n\
+                     :op!op@op MODE #test -q *!*@host\r\n\
+                     :irc.test.net 728 test #test2 q *!*@host op 1546612406\r\n\
+                     :irc.test.net 729 test #test2 q :End of channel quiet list\r\n\
+                     :test!test@test PART #test2\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.quiet_list("#test"), Some(vec![]));
+        assert_eq!(client.quiet_list("#test2"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invite() -> Result<()> {
+        let value = ":alice!a@host INVITE test #rust\r\n\
+                     :alice!a@host INVITE test #other\r\n\
+                     :alice!a@host INVITE bob #test\r\n\
+                     :test!test@test JOIN #other\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let invites = Arc::new(Mutex::new(Vec::new()));
+        let handler_invites = invites.clone();
+        client.add_invite_handler(move |invite| handler_invites.lock().push(invite.clone()));
+        client.stream()?.collect().await?;
+
+        let invites = invites.lock();
+        assert_eq!(invites.len(), 3);
+        assert_eq!(invites[2].nickname, "bob");
+        assert_eq!(invites[2].inviter.as_deref(), Some("alice"));
+        assert_eq!(
+            client.pending_invites(),
+            vec![Invite {
+                inviter: Some("alice".to_owned()),
+                nickname: "test".to_owned(),
+                channel: "#rust".to_owned(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn auto_rejoin() -> Result<()> {
+        let value = ":op!op@op KICK #test test :Out\r\n\
+                     :op!op@op KICK #other test :Out\r\n\
+                     :op!op@op KICK #test alice :Out\r\n\
+                     :op!op@op KICK #TEST test :Out\r\n\
+                     :op!op@op KICK #test test :Out\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            auto_rejoin: true,
+            max_rejoin_attempts: Some(2),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #test\r\nJOIN #TEST\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn self_commands_casemapping() -> Result<()> {
+        let value = ":op!op@op KICK #test TEST :Out\r\n\
+                     :TEST!test@test NICK test[away]\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            auto_rejoin: true,
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.current_nickname(), "test[away]");
+        assert_eq!(&get_client_value(client)[..], "JOIN #test\r\n");
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn auto_rejoin_delay_ends_with_connection() -> Result<()> {
+        let value = ":op!op@op KICK #test test :Out\r\n\
+                     :op!op@op KICK #other test :Out\r\n";
+        let config = Config {
+            mock_initial_value: Some(value.to_owned()),
+            mock_keep_open: true,
+            channels: vec!["#test".to_owned(), "#other".to_owned()],
+            auto_rejoin: true,
+            auto_rejoin_delay: Some(5),
+            ..test_config()
+        };
+        let mut client = Client::from_config(config).await?;
+        let handle = client.mock_handle();
+        let state = Arc::clone(&client.state);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            // As happens when reconnecting, after which the old connection's rejoins are moot.
+            state.reset();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            handle.disconnect();
+        });
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn auto_rejoin_injected_kick() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(":test!test@host JOIN #test\r\n".to_owned()),
+            mock_script: vec![(
+                "JOIN #test".to_owned(),
+                ":test!test@host JOIN #test".to_owned(),
+            )],
+            mock_keep_open: true,
+            auto_rejoin: true,
+            ..test_config()
+        })
+        .await?;
+        let mut handle = Some(client.mock_handle());
+        let mut stream = client.stream()?;
+        let mut joins = 0;
+        while let Some(message) = stream.next().await.transpose()? {
+            if let Command::JOIN(..) = message.command {
+                joins += 1;
+                if joins == 1 {
+                    handle
+                        .as_ref()
+                        .unwrap()
+                        .inject(":op!op@op KICK #test test :Out");
+                } else if let Some(handle) = handle.take() {
+                    handle.disconnect();
+                }
+            }
+        }
+        assert_eq!(joins, 2);
+        assert_eq!(client.mock_mismatches(), vec![]);
+        assert_eq!(&get_client_value(client)[..], "JOIN #test\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn auto_rejoin_disabled() -> Result<()> {
+        let value = ":op!op@op KICK #test test :Out\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn account_extended_join() -> Result<()> {
+        let value = ":alice!a@host JOIN #test alice_acct :Alice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.account_for("alice").as_deref(), Some("alice_acct"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_realname() -> Result<()> {
+        let value = ":irc.test.net CAP * ACK :extended-join setname\r\n\
+                     :alice!a@host JOIN #test alice_acct :Alice\r\n\
+                     :alice!a@host SETNAME :Alice Liddell\r\n\
+                     :test!test@test SETNAME :New Real Name\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        client.set_realname("New Real Name")?;
+        stream.collect().await?;
+        assert_eq!(
+            client.realname_for("Alice").as_deref(),
+            Some("Alice Liddell")
+        );
+        assert_eq!(
+            client.realname_for("test").as_deref(),
+            Some("New Real Name")
+        );
+        assert_eq!(&get_client_value(client)[..], "SETNAME :New Real Name\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mentions_me() -> Result<()> {
+        let client = Client::from_config(Config {
+            highlights: vec!["rustbot".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        let privmsg = |text: &str| Message::from(PRIVMSG("#test".to_owned(), text.to_owned()));
+        assert!(client.mentions_me(&privmsg("hey test!")));
+        assert!(client.mentions_me(&privmsg("is RustBot here?")));
+        assert!(!client.mentions_me(&privmsg("testing, testing")));
+        assert!(!client.mentions_me(&privmsg("rustbots everywhere")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mentions_me_casemapping() -> Result<()> {
+        let value = ":irc.test.net 001 [test] :Welcome\r\n\
+                     :irc.test.net 005 [test] CASEMAPPING=ascii :are supported\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let privmsg = |text: &str| Message::from(PRIVMSG("#test".to_owned(), text.to_owned()));
+        assert!(client.mentions_me(&privmsg("hey [TEST]!")));
+        assert!(!client.mentions_me(&privmsg("hey {test}!")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dedup_replayed_messages() -> Result<()> {
+        let value = "@msgid=a :alice!a@host PRIVMSG #test :first\r\n\
+                     @msgid=b :alice!a@host PRIVMSG #test :second\r\n\
+                     @msgid=a :alice!a@host PRIVMSG #test :first\r\n\
+                     @msgid=c :alice!a@host PRIVMSG #test :third\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            dedup_window: Some(16),
+            ..test_config()
+        })
+        .await?;
+        let messages: Vec<_> = client.stream()?.collect().await?;
+        let texts: Vec<_> = messages
+            .iter()
+            .filter_map(|message| match message.command {
+                PRIVMSG(_, ref text) => Some(&text[..]),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["first", "second", "third"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ignores() -> Result<()> {
+        let value = ":spam!bot@spammer.host PRIVMSG #test :Buy now\r\n\
+                     :alice!a@good.host PRIVMSG #test :hi\r\n\
+                     :spam2!x@SPAMMER.host NOTICE test :Buy now\r\n\
+                     :bob!b@good.host PRIVMSG test :hello\r\n\
+                     :spam!bot@spammer.host JOIN #test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ignores: vec!["*!*@spammer.host".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        assert!(client.add_ignore("bob!*@*"));
+        assert!(!client.add_ignore("*!*@spammer.host"));
+        assert_eq!(client.ignores(), vec!["*!*@spammer.host", "bob!*@*"]);
+        let messages: Vec<_> = client.stream()?.collect().await?;
+        let sources: Vec<_> = messages
+            .iter()
+            .map(|message| (message.source_nickname(), &message.command))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                (Some("alice"), &PRIVMSG("#test".to_owned(), "hi".to_owned())),
+                (Some("spam"), &Command::JOIN("#test".to_owned(), None, None)),
+            ]
+        );
+        assert!(client.remove_ignore("BOB!*@*"));
+        assert!(!client.remove_ignore("bob!*@*"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_capability_missing() -> Result<()> {
+        let value = ":irc.test.net CAP * LS * :multi-prefix sasl=PLAIN\r\n\
+                     :irc.test.net CAP * LS :away-notify\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            required_capabilities: vec!["sasl".to_owned(), "tls".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        let result: crate::error::Result<Vec<_>> = client.stream()?.collect().await;
+        match result {
+            Err(Error::RequiredCapabilityUnavailable { capability }) => {
+                assert_eq!(capability, "tls")
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_capability_available() -> Result<()> {
+        let value = ":irc.test.net CAP * LS * :multi-prefix\r\n\
+                     :irc.test.net CAP * LS :sasl=PLAIN,EXTERNAL away-notify\r\n\
+                     :irc.test.net CAP * NAK :away-notify\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            required_capabilities: vec!["sasl".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_capability_refused() -> Result<()> {
+        let value = ":irc.test.net CAP * LS :sasl\r\n\
+                     :irc.test.net CAP * NAK :sasl\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            required_capabilities: vec!["sasl".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        let result: crate::error::Result<Vec<_>> = client.stream()?.collect().await;
+        assert!(matches!(
+            result,
+            Err(Error::RequiredCapabilityUnavailable { capability }) if capability == "sasl"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_capability_negotiated() -> Result<()> {
+        let script = |expected: &str, response: &str| (expected.to_owned(), response.to_owned());
+        let mut client = Client::from_config(Config {
+            mock_script: vec![
+                script("CAP LS 302", ":irc.test.net CAP * LS :multi-prefix sasl"),
+                script("NICK test", ""),
+                script("USER test 0 * test", ""),
+                script(
+                    "CAP REQ multi-prefix",
+                    ":irc.test.net CAP * ACK :multi-prefix",
+                ),
+                script("CAP END", ":irc.test.net 001 test :Welcome"),
+            ],
+            required_capabilities: vec!["multi-prefix".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.mock_mismatches(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_sasl_authenticates_before_cap_end() -> Result<()> {
+        let script = |expected: &str, response: &str| (expected.to_owned(), response.to_owned());
+        let mut client = Client::from_config(Config {
+            mock_script: vec![
+                script("CAP LS 302", ":irc.test.net CAP * LS :sasl=PLAIN"),
+                script("NICK test", ""),
+                script("USER test 0 * test", ""),
+                script("CAP REQ sasl", ":irc.test.net CAP * ACK :sasl"),
+                script("AUTHENTICATE PLAIN", "AUTHENTICATE +"),
+                script(
+                    "AUTHENTICATE dGVzdAB0ZXN0AHBhc3N3b3Jk",
+                    ":irc.test.net 903 test :SASL authentication successful",
+                ),
+                script("CAP END", ":irc.test.net 001 test :Welcome"),
+            ],
+            required_capabilities: vec!["sasl".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        let mut stream = client.stream()?;
+        while let Some(message) = stream.next().await.transpose()? {
+            match message.command {
+                Command::CAP(_, CapSubCommand::ACK, _, _) => client.send_sasl_plain()?,
+                Command::AUTHENTICATE(ref data) if data == "+" => {
+                    client.send_sasl("dGVzdAB0ZXN0AHBhc3N3b3Jk")?
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(client.mock_mismatches(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_capability_without_cap_support() -> Result<()> {
+        let script = |expected: &str, response: &str| (expected.to_owned(), response.to_owned());
+        let mut client = Client::from_config(Config {
+            mock_script: vec![
+                script("CAP LS 302", ""),
+                script("NICK test", ""),
+                script("USER test 0 * test", ":irc.test.net 001 test :Welcome"),
+            ],
+            required_capabilities: vec!["sasl".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        let result: crate::error::Result<Vec<_>> = client.stream()?.collect().await;
+        assert!(matches!(
+            result,
+            Err(Error::RequiredCapabilityUnavailable { capability }) if capability == "sasl"
+        ));
+        assert_eq!(client.mock_mismatches(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_realname_without_capability() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        assert!(matches!(
+            client.set_realname("New Real Name"),
+            Err(Error::CapabilityNotEnabled { capability }) if capability == "setname"
+        ));
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_chghost() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test alice!a@old.host bob!b@bob.host\r\n\
+                     :alice!a@old.host CHGHOST ~alice cloaked/alice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let alice = client.find_user("#test", "alice").unwrap();
+        assert_eq!(alice.get_username(), Some("~alice"));
+        assert_eq!(alice.get_hostname(), Some("cloaked/alice"));
+        let bob = client.find_user("#test", "bob").unwrap();
+        assert_eq!(bob.get_hostname(), Some("bob.host"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_join() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :test2!test@test JOIN #test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![
+                User::new("test"),
+                User::new("~owner"),
+                User::new("&admin"),
+                User::new("test2"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_kick() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :owner!test@test KICK #test test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("&admin"), User::new("~owner"),]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_kick_one_channel() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :irc.test.net 353 test = #test2 :test ~owner\r\n\
+                     :owner!test@test KICK #test test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("&admin"), User::new("~owner")]
+        );
+        assert_eq!(
+            client.list_users("#test2").unwrap(),
+            vec![User::new("test"), User::new("~owner")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_quit() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :irc.test.net 353 test = #test2 :test2 ~owner\r\n\
+                     :owner!test@test QUIT :Leaving\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("&admin")]
+        );
+        assert_eq!(
+            client.list_users("#test2").unwrap(),
+            vec![User::new("test2")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_nick() -> Result<()> {
+        use crate::client::data::AccessLevel;
+
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :irc.test.net 353 test = #test2 :test ~owner\r\n\
+                     :owner!test@test NICK boss\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("~boss"), User::new("&admin")]
+        );
+        assert_eq!(
+            client.list_users("#test2").unwrap(),
+            vec![User::new("test"), User::new("~boss")]
+        );
+        assert_eq!(
+            client.list_users("#test").unwrap()[1].highest_access_level(),
+            AccessLevel::Owner
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn names_complete() -> Result<()> {
+        let value = ":test!test@test JOIN #test\r\n\
+                     :irc.test.net 353 test = #test :test @op\r\n\
+                     :irc.test.net 353 test = #test :voice\r\n\
+                     :irc.test.net 366 test #test :End of /NAMES list.\r\n\
+                     :irc.test.net 353 test = #test :test @op\r\n\
+                     :irc.test.net 366 test #test :End of /NAMES list.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let mut events = client.stream()?.events();
+        let mut completed = Vec::new();
+        while let Some(event) = events.next().await.transpose()? {
+            match event {
+                Event::NamesComplete { channel, .. } => {
+                    assert!(client.is_names_complete(&channel));
+                    completed.push(client.list_users(&channel).unwrap());
+                }
+                Event::Numeric {
+                    response: Response::RPL_NAMREPLY,
+                    ..
+                } => assert!(!client.is_names_complete("#test")),
+                _ => (),
+            }
+        }
+        assert_eq!(
+            completed,
+            vec![
+                vec![User::new("test"), User::new("@op"), User::new("voice")],
+                vec![User::new("test"), User::new("@op")],
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn names_complete_casemapping() -> Result<()> {
+        let value = ":irc.test.net 353 test = #Test[1] :test @op\r\n\
+                     :irc.test.net 366 test #Test[1] :End of /NAMES list.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert!(client.is_names_complete("#test{1}"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn names_repeated() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test @op\r\n\
+                     :irc.test.net 353 test = #test :test +op voice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("+op"), User::new("voice")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn names_burst_interrupted() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test @op\r\n\
+                     :joined!j@j JOIN #test\r\n\
+                     :op!op@op PART #test\r\n\
+                     :irc.test.net 353 test = #test :+voice joined\r\n\
+                     :voice!v@v NICK talker\r\n\
+                     :irc.test.net 353 test = #test :+talker\r\n\
+                     :irc.test.net 366 test #test :End of /NAMES list.\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("joined"), User::new("+talker")]
+        );
+        assert!(client.state.names_index.lock().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn names_after_rejoin() -> Result<()> {
+        let value = ":test!test@test JOIN #test\r\n\
+                     :irc.test.net 353 test = #test :test @op gone\r\n\
+                     :op!op@op KICK #test test :Out\r\n\
+                     :test!test@test JOIN #test\r\n\
+                     :irc.test.net 353 test = #test :test @op\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("@op")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn sorted_users() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :zed +voice test @op Bob ~owner @Alice\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let nicks = |users: Vec<User>| -> Vec<String> {
+            users.iter().map(|u| u.get_nickname().to_owned()).collect()
+        };
+        assert_eq!(
+            nicks(client.sorted_users("#test").unwrap()),
+            vec!["owner", "Alice", "op", "voice", "Bob", "test", "zed"]
+        );
+        assert_eq!(
+            nicks(client.list_users("#test").unwrap()),
+            vec!["zed", "voice", "test", "op", "Bob", "owner", "Alice"]
+        );
+        assert!(client.sorted_users("#other").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn find_user() -> Result<()> {
+        use crate::client::data::AccessLevel;
+
+        let value = ":irc.test.net 353 test = #Test :test ~Owner &admin\r\n\
+                     :admin!a@host AWAY :Lunch\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let owner = client.find_user("#test", "owner").unwrap();
+        assert_eq!(owner.get_nickname(), "Owner");
+        assert_eq!(owner.highest_access_level(), AccessLevel::Owner);
+        assert_eq!(
+            client.find_user("#Test", "admin").unwrap().away_message(),
+            Some("Lunch")
+        );
+        assert!(client.find_user("#test", "nobody").is_none());
+        assert!(client.find_user("#other", "owner").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn am_i_operator() -> Result<()> {
+        use crate::client::data::AccessLevel;
+
+        let value = ":irc.test.net 353 test = #test :+test ~owner\r\n\
+                     :irc.test.net 353 test = #test2 :test ~owner\r\n\
+                     :owner!test@test MODE #test +o test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert!(client.am_i_operator("#test"));
+        assert_eq!(client.my_access_level("#test"), Some(AccessLevel::Oper));
+        assert!(!client.am_i_operator("#test2"));
+        assert_eq!(client.my_access_level("#test2"), Some(AccessLevel::Member));
+        assert!(!client.am_i_operator("#other"));
+        assert_eq!(client.my_access_level("#other"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_part() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
+                     :owner!test@test PART #test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("test"), User::new("&admin")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn user_tracking_names_mode() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :+test ~owner &admin\r\n\
+                     :test!test@test MODE #test +o test\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.list_users("#test").unwrap(),
+            vec![User::new("@test"), User::new("~owner"), User::new("&admin")]
+        );
+        let mut exp = User::new("@test");
+        exp.update_access_level(&Mode::Plus(ChannelMode::Voice, None));
+        assert_eq!(
+            client.list_users("#test").unwrap()[0].highest_access_level(),
+            exp.highest_access_level()
+        );
+        // The following tests if the maintained user contains the same entries as what is expected
+        // but ignores the ordering of these entries.
+        let mut levels = client.list_users("#test").unwrap()[0].access_levels();
+        levels.retain(|l| exp.access_levels().contains(l));
+        assert_eq!(levels.len(), exp.access_levels().len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "nochanlists")]
+    async fn no_user_tracking() -> Result<()> {
+        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert!(client.list_users("#test").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_info_isupport() -> Result<()> {
+        let value =
+            ":irc.test.net 005 test CHANTYPES=# NICKLEN=9 :are supported by this server\r\n\
+                     :irc.test.net 005 test -NICKLEN :are supported by this server\r\n\
+                     :irc.test.net 005 test NICKLEN=30 EXCEPTS :are supported by this server\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let info = client.server_info();
+        assert_eq!(info.isupport("CHANTYPES"), Some("#"));
+        assert_eq!(info.isupport("NICKLEN"), Some("30"));
+        assert!(info.supports("EXCEPTS"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_info_isupport_negation() -> Result<()> {
+        let value = ":irc.test.net 005 test NICKLEN=9 :are supported by this server\r\n\
+                     :irc.test.net 005 test -NICKLEN :are supported by this server\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert!(!client.server_info().supports("NICKLEN"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn response_target() -> Result<()> {
+        let value = ":irc.test.net 005 test CHANTYPES=# :are supported by this server\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+
+        let msg: Message = ":ada!a@b PRIVMSG #test :hi all\r\n".parse()?;
+        assert_eq!(client.response_target(&msg), Some("#test"));
+        let msg: Message = ":ada!a@b PRIVMSG test :hi you\r\n".parse()?;
+        assert_eq!(client.response_target(&msg), Some("ada"));
+        let msg: Message = ":ada!a@b PRIVMSG &test :not a channel here\r\n".parse()?;
+        assert_eq!(client.response_target(&msg), Some("ada"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keep_raw_bytes() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            keep_raw_bytes: true,
+            ..test_config()
+        })
+        .await?;
+        let messages: Vec<_> = client.stream()?.raw().try_collect().await?;
+        assert_eq!(
+            messages[0].message.command,
+            Command::Response(
+                Response::RPL_WELCOME,
+                vec!["test".to_owned(), "Welcome".to_owned()]
+            )
+        );
+        assert_eq!(
+            &messages[0].bytes[..],
+            &b":irc.test.net 001 test :Welcome"[..]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_single_soh() -> Result<()> {
+        let value = ":test!test@test PRIVMSG #test :\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            nickname: Some("test".to_string()),
+            channels: vec!["#test".to_string(), "#test2".to_string()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn finger_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}FINGER\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NOTICE test :\u{001}FINGER :test (test)\u{001}\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn version_response_placeholders() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}VERSION\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            version: Some("bot {version} on {os}".to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            &format!(
+                "NOTICE test :\u{001}VERSION bot {} on {}\u{001}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                std::env::consts::OS,
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn version_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}VERSION\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            &format!(
+                "NOTICE test :\u{001}VERSION {}\u{001}\r\n",
+                crate::VERSION_STR,
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn source_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}SOURCE\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NOTICE test :\u{001}SOURCE https://github.com/aatxe/irc\u{001}\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn ctcp_ping_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}PING test\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NOTICE test :\u{001}PING test\u{001}\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn ctcp_ignored() -> Result<()> {
+        let value = ":spam!bot@spammer.host PRIVMSG test :\u{001}VERSION\u{001}\r\n\
+                     :spam!bot@spammer.host PRIVMSG #test :\u{001}PING 123\u{001}\r\n\
+                     :test!test@test PRIVMSG test :\u{001}PING test\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ignores: vec!["*!*@spammer.host".to_owned()],
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NOTICE test :\u{001}PING test\u{001}\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn time_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}TIME\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        let val = get_client_value(client);
+        assert!(val.starts_with("NOTICE test :\u{001}TIME :"));
+        assert!(val.ends_with("\u{001}\r\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn user_info_response() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test :\u{001}USERINFO\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "NOTICE test :\u{001}USERINFO :Testing.\u{001}\
+             \r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn ctcp_ping_no_timestamp() -> Result<()> {
+        let value = ":test!test@test PRIVMSG test \u{001}PING\u{001}\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identify() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "CAP END\r\nNICK test\r\n\
+             USER test 0 * test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scripted_registration() -> Result<()> {
+        let script = |expected: &str, response: &str| (expected.to_owned(), response.to_owned());
+        let mut client = Client::from_config(Config {
+            mock_script: vec![
+                script("CAP END", ""),
+                script("NICK test", ""),
+                script(
+                    "USER test 0 * test",
+                    ":irc.test.net 433 * test :Nickname is already in use.",
+                ),
+                script(
+                    "NICK test2",
+                    ":irc.test.net 001 test2 :Welcome\r\n\
+                     :irc.test.net 376 test2 :End of /MOTD command.",
+                ),
+                script("JOIN #test", ":test2!test@host JOIN #test"),
+                script("JOIN #test2", ":test2!test@host JOIN #test2"),
+            ],
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.mock_mismatches(), vec![]);
+        assert_eq!(client.current_nickname(), "test2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scripted_sasl() -> Result<()> {
+        let script = |expected: &str, response: &str| (expected.to_owned(), response.to_owned());
+        let mut client = Client::from_config(Config {
+            mock_script: vec![
+                script("CAP REQ sasl", ":irc.test.net CAP * ACK :sasl"),
+                script("AUTHENTICATE PLAIN", "AUTHENTICATE +"),
+                script(
+                    "AUTHENTICATE dGVzdAB0ZXN0AHBhc3N3b3Jk",
+                    ":irc.test.net 903 test :SASL authentication successful",
+                ),
+                script("CAP END", ""),
+            ],
+            ..test_config()
+        })
+        .await?;
+        client.send_cap_req(&[Capability::Sasl])?;
+        let mut stream = client.stream()?;
+        while let Some(message) = stream.next().await.transpose()? {
+            match message.command {
+                Command::CAP(_, CapSubCommand::ACK, _, _) => client.send_sasl_plain()?,
+                Command::AUTHENTICATE(ref data) if data == "+" => {
+                    client.send_sasl("dGVzdAB0ZXN0AHBhc3N3b3Jk")?
+                }
+                Command::Response(Response::RPL_SASLSUCCESS, _) => {
+                    client.send(Command::CAP(None, CapSubCommand::END, None, None))?
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(client.mock_mismatches(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scripted_mismatch() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            mock_script: vec![("PASS password".to_owned(), String::new())],
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            client.mock_mismatches(),
+            vec![Mismatch {
+                expected: "PASS password".to_owned(),
+                sent: "CAP END".to_owned(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn from_stream() -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (local, remote) = tokio::io::duplex(1024);
+        let config = Config {
+            nickname: Some("test".to_owned()),
+            ..Config::default()
+        };
+        let mut client = Client::from_stream(config, local)?;
+        let server = tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(remote);
+            let mut lines = BufReader::new(reader).lines();
+            let mut received = vec![];
+            while let Some(line) = lines.next_line().await? {
+                let is_user = line.starts_with("USER ");
+                received.push(line);
+                if is_user {
+                    writer
+                        .write_all(b":irc.test.net 001 test2 :Welcome to the network\r\n")
+                        .await?;
+                    break;
+                }
+            }
+            anyhow::Ok(received)
+        });
+
+        client.identify()?;
+        let mut stream = client.stream()?;
+        let welcome = stream.next().await.transpose()?.unwrap();
+        assert!(matches!(
+            welcome.command,
+            Command::Response(Response::RPL_WELCOME, _)
+        ));
+        assert_eq!(client.current_nickname(), "test2");
+        assert_eq!(
+            server.await??,
+            ["CAP END", "NICK test", "USER test 0 * test"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identify_with_server_password() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            server_password: Some("server".to_string()),
+            nick_password: Some("account".to_string()),
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "CAP END\r\nPASS server\r\nNICK test\r\nUSER test 0 * test\r\n"
+        );
+
+        // The account password alone is never sent as `PASS`.
+        let mut client = Client::from_config(Config {
+            nick_password: Some("account".to_string()),
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "CAP END\r\nNICK test\r\nUSER test 0 * test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identify_with_webirc() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            server_password: Some("server".to_string()),
+            webirc_password: Some("secret".to_string()),
+            webirc_gateway: Some("gateway".to_string()),
+            webirc_hostname: Some("client.example.com".to_string()),
+            webirc_ip: Some("192.0.2.1".to_string()),
+            ..test_config()
+        })
+        .await?;
+        client.send_cap_req(&[Capability::MultiPrefix])?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "WEBIRC secret gateway client.example.com 192.0.2.1\r\n\
+             CAP REQ multi-prefix\r\nCAP END\r\n\
+             PASS server\r\nNICK test\r\nUSER test 0 * test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identify_with_password() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            nickname: Some("test".to_string()),
+            password: Some("password".to_string()),
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "CAP END\r\nPASS password\r\nNICK test\r\n\
+             USER test 0 * test\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_pong() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_pong("irc.test.net")?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "PONG irc.test.net\r\n");
+        Ok(())
     }
 
-    pub fn get_client_value(client: Client) -> String {
-        // We sleep here because of synchronization issues.
-        // We can't guarantee that everything will have been sent by the time of this call.
-        thread::sleep(Duration::from_millis(100));
-        client
-            .log_view()
-            .sent()
-            .unwrap()
-            .iter()
-            .fold(String::new(), |mut acc, msg| {
-                // NOTE: we have to sanitize here because sanitization happens in IrcCodec after the
-                // messages are converted into strings, but our transport logger catches messages before
-                // they ever reach that point.
-                acc.push_str(&IrcCodec::sanitize(msg.to_string()));
-                acc
-            })
+    #[tokio::test]
+    async fn send_join() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_join("#test,#test2,#test3")?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "JOIN #test,#test2,#test3\r\n"
+        );
+        Ok(())
     }
 
     #[tokio::test]
-    async fn stream() -> Result<()> {
-        let exp = "PRIVMSG test :Hi!\r\nPRIVMSG test :This is a test!\r\n\
-                   :test!test@test JOIN #test\r\n";
+    async fn send_snomask() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_snomask("+cCkn")?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "MODE test +s +cCkn\r\n");
+        Ok(())
+    }
 
+    #[tokio::test]
+    async fn oper_and_snomask_tracking() -> Result<()> {
+        let value = ":irc.test.net 381 test :You are now an IRC operator\r\n\
+                     :irc.test.net 008 test +cCkn :Server notice mask\r\n";
         let mut client = Client::from_config(Config {
-            mock_initial_value: Some(exp.to_owned()),
+            mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-
+        assert!(!client.is_oper());
         client.stream()?.collect().await?;
-        // assert_eq!(&messages[..], exp);
+        assert!(client.is_oper());
+        assert_eq!(client.snomask(), Some("+cCkn".to_owned()));
         Ok(())
     }
 
     #[tokio::test]
-    async fn handle_message() -> Result<()> {
-        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+    async fn deoper_tracking() -> Result<()> {
+        let value = ":irc.test.net 381 test :You are now an IRC operator\r\n\
+                     :irc.test.net 008 test +cCkn :Server notice mask\r\n\
+                     :test MODE test :-os\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
         client.stream()?.collect().await?;
+        assert!(!client.is_oper());
+        assert_eq!(client.snomask(), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconnect_now() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        client.identify()?;
+        let mut stream = client.stream()?;
+        assert!(stream.next().await.transpose()?.is_some());
+        assert_eq!(client.current_nickname(), "test");
+
+        client.reconnect_now()?;
+        // The new connection yields the scripted welcome again before closing.
+        assert_eq!(stream.collect().await?.len(), 1);
+
+        let view = client
+            .state
+            .reconnected_view
+            .read()
+            .clone()
+            .expect("the stream should have reconnected");
+        let reconnected = view.sent()?.iter().fold(String::new(), |mut acc, msg| {
+            acc.push_str(&IrcCodec::sanitize(msg.to_string()));
+            acc
+        });
+        assert_eq!(
+            &reconnected[..],
+            "CAP END\r\nNICK test\r\nUSER test 0 * test\r\n"
+        );
         assert_eq!(
             &get_client_value(client)[..],
-            "JOIN #test\r\nJOIN #test2\r\n"
+            "CAP END\r\nNICK test\r\nUSER test 0 * test\r\nQUIT :Powered by Rust.\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn handle_end_motd_with_nick_password() -> Result<()> {
-        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+    async fn send_register_success() -> Result<()> {
+        let value = ":irc.test.net REGISTER SUCCESS test :Account created\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
-            nick_password: Some("password".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
             ..test_config()
         })
         .await?;
+        let register = client.send_register("test", Some("test@test.net"), "password");
         client.stream()?.collect().await?;
+        let reply = register.await?;
+        assert_eq!(reply.to_string(), value);
         assert_eq!(
             &get_client_value(client)[..],
-            "NICKSERV IDENTIFY password\r\nJOIN #test\r\n\
-             JOIN #test2\r\n"
+            "REGISTER test test@test.net password\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn handle_end_motd_with_chan_keys() -> Result<()> {
-        let value = ":irc.test.net 376 test :End of /MOTD command\r\n";
+    async fn send_register_fail() -> Result<()> {
+        let value = ":irc.test.net FAIL REGISTER ACCOUNT_EXISTS other :Account already exists\r\n\
+                     :irc.test.net FAIL REGISTER ACCOUNT_EXISTS test :Account already exists\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
-            nickname: Some("test".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
-            channel_keys: {
-                let mut map = HashMap::new();
-                map.insert("#test2".to_string(), "password".to_string());
-                map
-            },
             ..test_config()
         })
         .await?;
+        let register = client.send_register("test", None, "password");
         client.stream()?.collect().await?;
+        match register.await {
+            Err(Error::RequestFailed { reply }) => {
+                assert_eq!(reply.code, "ACCOUNT_EXISTS");
+                assert_eq!(reply.context, vec!["test".to_owned()]);
+            }
+            other => panic!("expected the request to fail, got {:?}", other),
+        }
         assert_eq!(
             &get_client_value(client)[..],
-            "JOIN #test\r\nJOIN #test2 password\r\n"
+            "REGISTER test * password\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn handle_end_motd_with_ghost() -> Result<()> {
-        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
-                     :irc.test.net 376 test2 :End of /MOTD command.\r\n";
+    async fn send_privmsg_too_long() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        match client.send(PRIVMSG("#test".to_owned(), "a".repeat(600))) {
+            Err(Error::MessageTooLong { length }) => assert_eq!(length, 616),
+            other => panic!("expected the message to be too long, got {:?}", other),
+        }
+        // The rejected message is never queued, so the connection carries on.
+        client.send_privmsg("#test", "hi there")?;
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "PRIVMSG #test :hi there\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_privmsg_split() -> Result<()> {
+        // "PRIVMSG #test :" and the CRLF take 17 bytes of each line, though the colon is left out
+        // of the sent lines as the text has no spaces.
+        let first = MAX_LINE_LENGTH - 17;
         let mut client = Client::from_config(Config {
-            mock_initial_value: Some(value.to_owned()),
-            nickname: Some("test".to_string()),
-            alt_nicks: vec!["test2".to_string()],
-            nick_password: Some("password".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
-            should_ghost: true,
+            split_long_messages: true,
+            mock_script: vec![
+                (
+                    format!("PRIVMSG #test {}", "a".repeat(first)),
+                    String::new(),
+                ),
+                (
+                    format!("PRIVMSG #test {}", "a".repeat(600 - first)),
+                    String::new(),
+                ),
+            ],
             ..test_config()
         })
         .await?;
+        client.send(PRIVMSG("#test".to_owned(), "a".repeat(600)))?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.mock_mismatches(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_privmsg_long() -> Result<()> {
+        let text = "word ".repeat(200);
+        let text = text.trim_end();
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_privmsg("#test", text)?;
+        client.stream()?.collect().await?;
+
+        let value = get_client_value(client);
+        let lines: Vec<_> = value.split_inclusive("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            assert!(RELAYED_SOURCE_LENGTH + line.len() <= MAX_LINE_LENGTH);
+        }
+        let sent: Vec<_> = lines
+            .iter()
+            .map(|line| line.trim_start_matches("PRIVMSG #test :").trim_end())
+            .collect();
+        assert_eq!(sent.join(" "), text);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_privmsg_long_word() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_privmsg("#test", "é".repeat(500))?;
+        client.stream()?.collect().await?;
+
+        let value = get_client_value(client);
+        let lines: Vec<_> = value.split_inclusive("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines
+            .iter()
+            .all(|line| RELAYED_SOURCE_LENGTH + line.len() <= MAX_LINE_LENGTH));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_privmsg_long_no_empty_lines() -> Result<()> {
+        // The text left for each line once the source, command, target and CRLF are accounted for.
+        let max = MAX_LINE_LENGTH - RELAYED_SOURCE_LENGTH - "PRIVMSG #test :".len() - 2;
+        let mut client = Client::from_config(test_config()).await?;
+        client.send_privmsg("#test", format!("{} ", "a".repeat(max)))?;
+        client.send_privmsg("#test", " ".repeat(max + 1))?;
         client.stream()?.collect().await?;
+
+        let value = get_client_value(client);
+        let lines: Vec<_> = value.split_inclusive("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("PRIVMSG #test {}\r\n", "a".repeat(max)));
+        assert_eq!(lines[1], format!("PRIVMSG #test :{}\r\n", " ".repeat(max)));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_throttled_round_robin() -> Result<()> {
+        let mut client = Client::from_config(Config {
+            burst_window_length: Some(1),
+            max_messages_in_burst: Some(2),
+            ..test_config()
+        })
+        .await?;
+        for i in 1..=4 {
+            client.send_privmsg("#a", i)?;
+        }
+        for i in 1..=2 {
+            client.send_privmsg("#b", i)?;
+        }
+
+        let mut outgoing = client.outgoing().unwrap();
+        assert!(futures::poll!(&mut outgoing).is_pending());
+        assert_eq!(client.queue_depth(), 4);
+
+        let flush = Box::pin(client.flush());
+        future::select(outgoing, flush).await;
+        assert_eq!(client.queue_depth(), 0);
         assert_eq!(
             &get_client_value(client)[..],
-            "NICK test2\r\nNICKSERV GHOST test password\r\n\
-             NICK test\r\nNICKSERV IDENTIFY password\r\nJOIN #test\r\nJOIN #test2\r\n"
+            "PRIVMSG #a 1\r\nPRIVMSG #b 1\r\nPRIVMSG #a 2\r\nPRIVMSG #b 2\r\n\
+             PRIVMSG #a 3\r\nPRIVMSG #a 4\r\n"
         );
         Ok(())
     }
 
-    #[tokio::test]
-    async fn handle_end_motd_with_ghost_seq() -> Result<()> {
-        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
-                     :irc.test.net 376 test2 :End of /MOTD command.\r\n";
+    #[tokio::test(start_paused = true)]
+    async fn send_throttled_exempt() -> Result<()> {
         let mut client = Client::from_config(Config {
-            mock_initial_value: Some(value.to_owned()),
-            nickname: Some("test".to_string()),
-            alt_nicks: vec!["test2".to_string()],
-            nick_password: Some("password".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
-            should_ghost: true,
-            ghost_sequence: Some(vec!["RECOVER".to_string(), "RELEASE".to_string()]),
+            burst_window_length: Some(1),
+            max_messages_in_burst: Some(2),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
+        for i in 1..=3 {
+            client.send_privmsg("#a", i)?;
+        }
+        client.send_pong("irc.test.net")?;
+        client.send_quit("bye")?;
+
+        let mut outgoing = client.outgoing().unwrap();
+        assert!(futures::poll!(&mut outgoing).is_pending());
+        assert_eq!(client.queue_depth(), 1);
+
+        let flush = Box::pin(client.flush());
+        future::select(outgoing, flush).await;
         assert_eq!(
             &get_client_value(client)[..],
-            "NICK test2\r\nNICKSERV RECOVER test password\
-             \r\nNICKSERV RELEASE test password\r\nNICK test\r\nNICKSERV IDENTIFY password\
-             \r\nJOIN #test\r\nJOIN #test2\r\n"
+            "PONG irc.test.net\r\nQUIT bye\r\nPRIVMSG #a 1\r\nPRIVMSG #a 2\r\n\
+             PRIVMSG #a 3\r\n"
         );
         Ok(())
     }
 
-    #[tokio::test]
-    async fn handle_end_motd_with_umodes() -> Result<()> {
-        let value = ":irc.test.net 376 test :End of /MOTD command.\r\n";
+    #[tokio::test(start_paused = true)]
+    async fn send_throttled_bounded() -> Result<()> {
         let mut client = Client::from_config(Config {
-            mock_initial_value: Some(value.to_owned()),
-            nickname: Some("test".to_string()),
-            umodes: Some("+B".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
+            burst_window_length: Some(1),
+            max_messages_in_burst: Some(1),
             ..test_config()
         })
         .await?;
+        for i in 0..2000 {
+            client.send_privmsg("#a", i)?;
+        }
+
+        let mut outgoing = client.outgoing().unwrap();
+        // Each poll only takes so many messages off the channel before yielding.
+        for _ in 0..20 {
+            assert!(futures::poll!(&mut outgoing).is_pending());
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(client.queue_depth(), 1024);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_all() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let voice = |nick: &str| {
+            Command::ChannelMODE(
+                "#test".to_owned(),
+                vec![Mode::Plus(ChannelMode::Voice, Some(nick.to_owned()))],
+            )
+        };
+        client.send_all(vec![voice("alice"), voice("bob"), voice("carol")])?;
         client.stream()?.collect().await?;
+        assert_eq!(client.mock_flushes(), 1);
         assert_eq!(
             &get_client_value(client)[..],
-            "MODE test +B\r\nJOIN #test\r\nJOIN #test2\r\n"
+            "MODE #test +v alice\r\nMODE #test +v bob\r\nMODE #test +v carol\r\n"
         );
         Ok(())
     }
 
+    fn op(nick: &str) -> ModeChange {
+        ModeChange {
+            adding: true,
+            mode: 'o',
+            param: Some(nick.to_owned()),
+        }
+    }
+
     #[tokio::test]
-    async fn nickname_in_use() -> Result<()> {
-        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n";
+    async fn set_modes() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        advertise(&client, &["MODES=4"]);
+        let ops: Vec<_> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|n| op(n))
+            .collect();
+        client.set_modes("#test", &ops)?;
+        client.stream()?.collect().await?;
+        assert_eq!(client.mock_flushes(), 1);
+        assert_eq!(
+            &get_client_value(client)[..],
+            "MODE #test +oooo a b c d\r\nMODE #test +oo e f\r\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_modes_default_limit() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let changes = vec![
+            op("a"),
+            ModeChange {
+                adding: false,
+                mode: 'v',
+                param: Some("b".to_owned()),
+            },
+            ModeChange {
+                adding: true,
+                mode: 'm',
+                param: None,
+            },
+            op("c"),
+            op("d"),
+        ];
+        client.set_modes("#test", &changes)?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "MODE #test +o-v+mo a b c\r\nMODE #test +o d\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pack_mode_changes_line_length() {
+        let nick = "n".repeat(100);
+        let ops: Vec<_> = (0..10).map(|_| op(&nick)).collect();
+        let commands = pack_mode_changes("#test", &ops, None);
+        assert_eq!(commands.len(), 4);
+        for command in commands {
+            let line = Message::from(command).to_string();
+            assert!(RELAYED_SOURCE_LENGTH + line.len() <= MAX_LINE_LENGTH);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_all_throttled() -> Result<()> {
         let mut client = Client::from_config(Config {
-            mock_initial_value: Some(value.to_owned()),
+            burst_window_length: Some(1),
+            max_messages_in_burst: Some(2),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
-        assert_eq!(&get_client_value(client)[..], "NICK test2\r\n");
+        client.send_all((1..=3).map(|i| Command::PRIVMSG("#test".to_owned(), i.to_string())))?;
+
+        let mut outgoing = client.outgoing().unwrap();
+        assert!(futures::poll!(&mut outgoing).is_pending());
+        assert_eq!(client.queue_depth(), 1);
+
+        let flush = Box::pin(client.flush());
+        future::select(outgoing, flush).await;
+        assert_eq!(client.queue_depth(), 0);
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG #test 1\r\nPRIVMSG #test 2\r\nPRIVMSG #test 3\r\n"
+        );
         Ok(())
     }
 
     #[tokio::test]
-    async fn ran_out_of_nicknames() -> Result<()> {
-        let value = ":irc.test.net 433 * test :Nickname is already in use.\r\n\
-                     :irc.test.net 433 * test2 :Nickname is already in use.\r\n";
+    async fn history() -> Result<()> {
+        use crate::client::transport::Direction::{Received, Sent};
+
+        let value = ":irc.test.net 001 test :Welcome\r\n\
+                     :ada!a@b PRIVMSG test :hi\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
+            history_length: Some(10),
             ..test_config()
         })
         .await?;
-        let res = client.stream()?.try_collect::<Vec<_>>().await;
-        if let Err(Error::NoUsableNick) = res {
-        } else {
-            panic!("expected error when no valid nicks were specified")
-        }
+        client.send_privmsg("ada", "hello")?;
+        client.stream()?.collect().await?;
+
+        let history: Vec<_> = client
+            .history()
+            .into_iter()
+            .map(|entry| (entry.direction, entry.message.to_string()))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (Sent, "PRIVMSG ada hello\r\n".to_owned()),
+                (Received, ":irc.test.net 001 test Welcome\r\n".to_owned()),
+                (Received, ":ada!a@b PRIVMSG test hi\r\n".to_owned()),
+            ]
+        );
         Ok(())
     }
 
     #[tokio::test]
-    async fn send() -> Result<()> {
-        let mut client = Client::from_config(test_config()).await?;
-        assert!(client
-            .send(PRIVMSG("#test".to_string(), "Hi there!".to_string()))
-            .is_ok());
+    async fn connection_stats() -> Result<()> {
+        use crate::client::transport::ConnectionStats;
+
+        let value = ":irc.test.net 001 test :Welcome\r\n\
+                     :ada!a@b PRIVMSG test :hi\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        assert_eq!(client.connection_stats(), ConnectionStats::default());
+        client.send_privmsg("ada", "hello")?;
+        client.send_privmsg("ada", "bye")?;
         client.stream()?.collect().await?;
+
         assert_eq!(
-            &get_client_value(client)[..],
-            "PRIVMSG #test :Hi there!\r\n"
+            client.connection_stats(),
+            ConnectionStats {
+                messages_sent: 2,
+                messages_received: 2,
+                bytes_sent: ("PRIVMSG ada hello\r\n".len() + "PRIVMSG ada bye\r\n".len()) as u64,
+                bytes_received: value.len() as u64,
+                queue_depth: 0,
+            }
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn send_no_newline_injection() -> Result<()> {
-        let mut client = Client::from_config(test_config()).await?;
-        assert!(client
-            .send(PRIVMSG(
-                "#test".to_string(),
-                "Hi there!\r\nJOIN #bad".to_string()
-            ))
-            .is_ok());
+    async fn history_bounded() -> Result<()> {
+        let value = ":irc.test.net 001 test :Welcome\r\n\
+                     :ada!a@b PRIVMSG test :one\r\n\
+                     :ada!a@b PRIVMSG test :two\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            history_length: Some(2),
+            ..test_config()
+        })
+        .await?;
         client.stream()?.collect().await?;
+
+        let history: Vec<_> = client.history().into_iter().map(|e| e.message).collect();
         assert_eq!(
-            &get_client_value(client)[..],
-            "PRIVMSG #test :Hi there!\r\n"
+            history,
+            vec![
+                Message::new(Some("ada!a@b"), "PRIVMSG", vec!["test", "one"])?,
+                Message::new(Some("ada!a@b"), "PRIVMSG", vec!["test", "two"])?,
+            ]
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn send_raw_is_really_raw() -> Result<()> {
-        let mut client = Client::from_config(test_config()).await?;
-        assert!(client
-            .send(Raw("PASS".to_owned(), vec!["password".to_owned()]))
-            .is_ok());
-        assert!(client
-            .send(Raw("NICK".to_owned(), vec!["test".to_owned()]))
-            .is_ok());
+    async fn send_list() -> Result<()> {
+        let value = ":irc.test.net 005 test ELIST=MU :are supported by this server\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        client.send_list(&[])?;
+        client.send_list(&[
+            ListFilter::Mask("#test*".to_owned()),
+            ListFilter::MoreUsersThan(10),
+        ])?;
+        stream.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "LIST\r\nLIST #test*,>10\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list() -> Result<()> {
+        let value = ":irc.test.net 321 test Channel :Users  Name\r\n\
+                     :irc.test.net 322 test #rust 42 :Rust talk\r\n\
+                     :irc.test.net 322 test #test 3 :\r\n\
+                     :irc.test.net 322 test #irc 7 :[+nt] All about IRC\r\n\
+                     :irc.test.net 323 test :End of /LIST\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let list = client.list(&[]);
         client.stream()?.collect().await?;
+
+        let entries = list.await?;
         assert_eq!(
-            &get_client_value(client)[..],
-            "PASS password\r\nNICK test\r\n"
+            entries
+                .iter()
+                .map(|e| (&e.channel[..], e.users, &e.topic[..]))
+                .collect::<Vec<_>>(),
+            vec![
+                ("#rust", 42, "Rust talk"),
+                ("#test", 3, ""),
+                ("#irc", 7, "[+nt] All about IRC"),
+            ]
         );
+        assert_eq!(&get_client_value(client)[..], "LIST\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn channel_tracking_names() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+    async fn list_each() -> Result<()> {
+        let value = ":irc.test.net 322 test #rust 42 :Rust talk\r\n\
+                     :irc.test.net 322 test #test 3 :\r\n\
+                     :irc.test.net 323 test :End of /LIST\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        let channels = Arc::new(Mutex::new(Vec::new()));
+        let handler_channels = channels.clone();
+        let list = client.list_each(&[ListFilter::Mask("#rust".to_owned())], move |entry| {
+            handler_channels.lock().push(entry.channel)
+        });
         client.stream()?.collect().await?;
-        assert_eq!(client.list_channels().unwrap(), vec!["#test".to_owned()]);
+
+        list.await?;
+        assert_eq!(*channels.lock(), vec!["#rust", "#test"]);
+        assert_eq!(&get_client_value(client)[..], "LIST #rust\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn channel_tracking_names_part() -> Result<()> {
-        use crate::proto::command::Command::PART;
-
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+    async fn list_each_reentrant() -> Result<()> {
+        let value = ":irc.test.net 322 test #rust 42 :Rust talk\r\n\
+                     :irc.test.net 323 test :End of /LIST\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-
+        let state = Arc::clone(&client.state);
+        let list = client.list_each(&[], move |_| {
+            // Starting another list from the handler must not deadlock.
+            drop(state.list(
+                Command::LIST(None, None),
+                super::ListSink::Collect(Vec::new()),
+            ));
+        });
         client.stream()?.collect().await?;
 
-        assert_eq!(client.list_channels(), Some(vec!["#test".to_owned()]));
-        // we ignore the result, as soon as we queue an outgoing message we
-        // update client state, regardless if the queue is available or not.
-        let _ = client.send(PART("#test".to_string(), None));
-        assert_eq!(client.list_channels(), Some(vec![]));
+        list.await?;
+        assert_eq!(&get_client_value(client)[..], "LIST\r\nLIST\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn user_tracking_names() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+    async fn send_list_unsupported_filter() -> Result<()> {
+        let value = ":irc.test.net 005 test ELIST=MU :are supported by this server\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
-        assert_eq!(
-            client.list_users("#test").unwrap(),
-            vec![User::new("test"), User::new("~owner"), User::new("&admin")]
-        );
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        match client.send_list(&[
+            ListFilter::Mask("#test".to_owned()),
+            ListFilter::CreatedWithin(60),
+        ]) {
+            Err(Error::UnsupportedListFilter { filter }) => assert_eq!(filter, "C<60"),
+            other => panic!("expected the filter to be rejected, got {:?}", other),
+        }
+        stream.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn user_tracking_names_join() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
-                     :test2!test@test JOIN #test\r\n";
+    async fn monitor() -> Result<()> {
+        let value = ":irc.test.net 005 test MONITOR=100 :are supported by this server\r\n\
+                     :irc.test.net 730 test :alice!a@example.com\r\n\
+                     :irc.test.net 731 test :bob\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler_events = events.clone();
+        client.add_monitor_handler(move |event| handler_events.lock().push(event.clone()));
+
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        client.monitor(&["alice", "Bob"])?;
+        stream.collect().await?;
+
         assert_eq!(
-            client.list_users("#test").unwrap(),
+            *events.lock(),
             vec![
-                User::new("test"),
-                User::new("~owner"),
-                User::new("&admin"),
-                User::new("test2"),
+                MonitorEvent::Online("alice".to_owned()),
+                MonitorEvent::Offline("bob".to_owned()),
             ]
         );
+        assert_eq!(client.monitor_status("Alice"), Some(true));
+        assert_eq!(client.monitor_status("bob"), Some(false));
+        assert_eq!(client.monitor_status("carol"), None);
+        assert_eq!(&get_client_value(client)[..], "MONITOR + alice,Bob\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn user_tracking_names_kick() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
-                     :owner!test@test KICK #test test\r\n";
+    async fn monitor_unsupported() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        assert!(matches!(
+            client.monitor(&["alice"]),
+            Err(Error::MonitorUnsupported)
+        ));
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watch() -> Result<()> {
+        let value = ":irc.test.net 005 test WATCH=128 :are supported by this server\r\n\
+                     :irc.test.net 600 test alice a example.com 1546612406 :logged online\r\n\
+                     :irc.test.net 601 test bob b example.com 1546612406 :logged offline\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler_events = events.clone();
+        client.add_monitor_handler(move |event| handler_events.lock().push(event.clone()));
+        assert!(matches!(
+            client.monitor(&["alice"]),
+            Err(Error::MonitorUnsupported)
+        ));
+
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        client.watch(&["alice", "Bob"])?;
+        stream.collect().await?;
+
         assert_eq!(
-            client.list_users("#test").unwrap(),
-            vec![User::new("&admin"), User::new("~owner"),]
+            *events.lock(),
+            vec![
+                MonitorEvent::Online("alice".to_owned()),
+                MonitorEvent::Offline("bob".to_owned()),
+            ]
         );
+        assert_eq!(client.monitor_status("Alice"), Some(true));
+        assert_eq!(client.monitor_status("bob"), Some(false));
+        assert_eq!(&get_client_value(client)[..], "WATCH +alice +Bob\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn user_tracking_names_part() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n\
-                     :owner!test@test PART #test\r\n";
+    async fn who() -> Result<()> {
+        let value =
+            ":irc.test.net 352 test #a ~alice example.com irc.test.net alice H@ :0 Alice\r\n\
+                     :irc.test.net 352 test #a ~bob example.com irc.test.net bob G :2 Bob\r\n\
+                     :irc.test.net 315 test #a :End of WHO list\r\n\
+                     :irc.test.net 315 test #b :End of WHO list\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        let who_a = client.who("#a");
+        let who_b = client.who("#b");
         client.stream()?.collect().await?;
-        assert_eq!(
-            client.list_users("#test").unwrap(),
-            vec![User::new("test"), User::new("&admin")]
-        );
+
+        let replies = who_a.await?;
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].nickname, "alice");
+        assert_eq!(replies[0].flags, "H@");
+        assert_eq!(replies[1].nickname, "bob");
+        assert_eq!(replies[1].hopcount, Some(2));
+        assert_eq!(replies[1].realname, "Bob");
+        assert!(who_b.await?.is_empty());
+        assert_eq!(&get_client_value(client)[..], "WHO #a\r\nWHO #b\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(not(feature = "nochanlists"))]
-    async fn user_tracking_names_mode() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :+test ~owner &admin\r\n\
-                     :test!test@test MODE #test +o test\r\n";
+    async fn join() -> Result<()> {
+        let value = ":test!test@test JOIN #rust\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        let joined = client.join("#Rust", Some("key"));
         client.stream()?.collect().await?;
-        assert_eq!(
-            client.list_users("#test").unwrap(),
-            vec![User::new("@test"), User::new("~owner"), User::new("&admin")]
-        );
-        let mut exp = User::new("@test");
-        exp.update_access_level(&Mode::Plus(ChannelMode::Voice, None));
-        assert_eq!(
-            client.list_users("#test").unwrap()[0].highest_access_level(),
-            exp.highest_access_level()
-        );
-        // The following tests if the maintained user contains the same entries as what is expected
-        // but ignores the ordering of these entries.
-        let mut levels = client.list_users("#test").unwrap()[0].access_levels();
-        levels.retain(|l| exp.access_levels().contains(l));
-        assert_eq!(levels.len(), exp.access_levels().len());
+        joined.await?;
+        assert_eq!(&get_client_value(client)[..], "JOIN #Rust key\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "nochanlists")]
-    async fn no_user_tracking() -> Result<()> {
-        let value = ":irc.test.net 353 test = #test :test ~owner &admin\r\n";
+    async fn join_banned() -> Result<()> {
+        let value = ":irc.test.net 474 test #rust :Cannot join channel (+b)\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        let joined = client.join("#rust", None);
         client.stream()?.collect().await?;
-        assert!(client.list_users("#test").is_none());
+        match joined.await {
+            Err(Error::JoinFailed { channel, response }) => {
+                assert_eq!(channel, "#rust");
+                assert_eq!(response, Response::ERR_BANNEDFROMCHAN);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
         Ok(())
     }
 
     #[tokio::test]
-    async fn handle_single_soh() -> Result<()> {
-        let value = ":test!test@test PRIVMSG #test :\u{001}\r\n";
+    async fn join_standard_reply() -> Result<()> {
+        let value = ":irc.test.net FAIL JOIN CHANNEL_IS_FULL #x :Channel is full\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
-            nickname: Some("test".to_string()),
-            channels: vec!["#test".to_string(), "#test2".to_string()],
             ..test_config()
         })
         .await?;
+        let joined = client.join("#X", None);
         client.stream()?.collect().await?;
+        match joined.await {
+            Err(Error::RequestFailed { reply }) => {
+                assert_eq!(reply.kind, StandardReplyKind::Fail);
+                assert_eq!(reply.code, "CHANNEL_IS_FULL");
+                assert_eq!(reply.context, vec!["#x".to_owned()]);
+                assert_eq!(reply.description, "Channel is full");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn finger_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}FINGER\u{001}\r\n";
+    async fn dropped_requests_pruned() -> Result<()> {
+        let client = Client::from_config(test_config()).await?;
+        drop(client.join("#a", None));
+        drop(client.who("#a"));
+        let _joined = client.join("#b", None);
+        let _who = client.who("#b");
+        assert_eq!(client.state.pending_joins.lock().len(), 1);
+        assert_eq!(client.state.pending_whos.lock().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    #[cfg(not(feature = "nochanlists"))]
+    async fn join_already_joined() -> Result<()> {
+        let value = ":irc.test.net 353 test = #rust :test alice\r\n\
+                     :irc.test.net 353 test = #other :alice bob\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
-        assert_eq!(
-            &get_client_value(client)[..],
-            "NOTICE test :\u{001}FINGER :test (test)\u{001}\r\n"
-        );
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        stream.next().await.transpose()?;
+        client.join("#RUST", None).await?;
+        // Listing a channel's users doesn't mean we're in it.
+        let joined = client.join("#other", None).await;
+        assert!(matches!(joined, Err(Error::JoinTimedOut { channel }) if channel == "#other"));
+        stream.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "JOIN #other\r\n");
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn version_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}VERSION\u{001}\r\n";
+    async fn join_forwarded() -> Result<()> {
+        let value = ":irc.test.net 470 test #rust ##rust :Forwarding to another channel\r\n\
+                     :irc.test.net 477 test #registered :Cannot join channel (+r)\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        let forwarded = client.join("#rust", None);
+        let registered = client.join("#registered", None);
         client.stream()?.collect().await?;
-        assert_eq!(
-            &get_client_value(client)[..],
-            &format!(
-                "NOTICE test :\u{001}VERSION {}\u{001}\r\n",
-                crate::VERSION_STR,
-            )
-        );
+        assert!(matches!(
+            forwarded.await,
+            Err(Error::JoinFailed {
+                response: Response::ERR_LINKCHANNEL,
+                ..
+            })
+        ));
+        assert!(matches!(
+            registered.await,
+            Err(Error::JoinFailed {
+                response: Response::ERR_NOCHANMODES,
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn join_timeout() -> Result<()> {
+        let client = Client::from_config(Config {
+            join_timeout: Some(5),
+            ..test_config()
+        })
+        .await?;
+        let started = tokio::time::Instant::now();
+        let joined = client.join("#rust", None).await;
+        assert!(matches!(joined, Err(Error::JoinTimedOut { channel }) if channel == "#rust"));
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn source_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}SOURCE\u{001}\r\n";
+    async fn who_whox() -> Result<()> {
+        let value = ":irc.test.net 005 test WHOX :are supported by this server\r\n\
+                     :irc.test.net 354 test 2 #b ~bob example.com irc.test.net bob H 0 0 :Bob\r\n\
+                     :irc.test.net 354 test 1 #a ~alice example.com irc.test.net alice H 0 alice :Alice\r\n\
+                     :irc.test.net 315 test #a :End of WHO list\r\n\
+                     :irc.test.net 315 test #b :End of WHO list\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        let who_a = client.who("#a");
+        let who_b = client.who("#b");
+        stream.collect().await?;
+
+        let replies = who_a.await?;
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].nickname, "alice");
+        assert_eq!(replies[0].account.as_deref(), Some("alice"));
+        assert_eq!(client.account_for("alice").as_deref(), Some("alice"));
+        let replies = who_b.await?;
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].nickname, "bob");
+        assert_eq!(replies[0].account, None);
         assert_eq!(
             &get_client_value(client)[..],
-            "NOTICE test :\u{001}SOURCE https://github.com/aatxe/irc\u{001}\r\n"
+            "WHO #a %tcuhsnfdar,1\r\nWHO #b %tcuhsnfdar,2\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn ctcp_ping_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}PING test\u{001}\r\n";
+    async fn set_away() -> Result<()> {
+        let value = ":irc.test.net 301 test alice :Out to lunch\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
+        client.set_away(Some("Gone fishing"))?;
+        assert_eq!(client.away_message("test").as_deref(), Some("Gone fishing"));
+        client.set_away(None)?;
+        assert!(!client.is_away("test"));
         client.stream()?.collect().await?;
+        assert_eq!(
+            client.away_message("alice").as_deref(),
+            Some("Out to lunch")
+        );
         assert_eq!(
             &get_client_value(client)[..],
-            "NOTICE test :\u{001}PING test\u{001}\r\n"
+            "AWAY :Gone fishing\r\nAWAY\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn time_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}TIME\u{001}\r\n";
+    async fn echo_message() -> Result<()> {
+        let value = ":test!test@host PRIVMSG #test :Hello, world!\r\n\
+                     :Test!test@host NOTICE #test :Hi\r\n\
+                     :test2!test@host PRIVMSG #test :Hello, test!\r\n\
+                     :test!test@host JOIN #test\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
-        let val = get_client_value(client);
-        assert!(val.starts_with("NOTICE test :\u{001}TIME :"));
-        assert!(val.ends_with("\u{001}\r\n"));
+        let messages = client.stream()?.collect().await?;
+        assert_eq!(
+            messages
+                .iter()
+                .map(|msg| client.is_echo(msg))
+                .collect::<Vec<_>>(),
+            vec![true, true, false, false]
+        );
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn user_info_response() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test :\u{001}USERINFO\u{001}\r\n";
+    async fn send_labeled() -> Result<()> {
+        let value = ":irc.test.net CAP * ACK :batch labeled-response\r\n\
+                     @label=1 :irc.test.net BATCH +w1 labeled-response\r\n\
+                     @batch=w1 :irc.test.net 311 test alice a host * :Alice\r\n\
+                     :irc.test.net NOTICE test :Unrelated\r\n\
+                     @batch=w1 :irc.test.net 318 test alice :End of /WHOIS list.\r\n\
+                     :irc.test.net BATCH -w1\r\n\
+                     @label=3 :irc.test.net ACK\r\n\
+                     @label=2 :irc.test.net 303 test :bob\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        assert!(client.is_capability_enabled("labeled-response"));
+        let whois = client.send_labeled(Command::WHOIS(None, "alice".to_owned()));
+        let ison = client.send_labeled(Command::ISON(vec!["bob".to_owned()]));
+        let away = client.send_labeled(Command::AWAY(None));
+        stream.collect().await?;
+
+        let whois = whois.await?;
+        assert_eq!(whois.len(), 2);
+        assert!(matches!(
+            whois[0].command,
+            Command::Response(Response::RPL_WHOISUSER, _)
+        ));
+        assert!(matches!(
+            whois[1].command,
+            Command::Response(Response::RPL_ENDOFWHOIS, _)
+        ));
+        let ison = ison.await?;
+        assert_eq!(ison.len(), 1);
+        assert!(away.await?.is_empty());
         assert_eq!(
             &get_client_value(client)[..],
-            "NOTICE test :\u{001}USERINFO :Testing.\u{001}\
-             \r\n"
+            "@label=1 WHOIS alice\r\n@label=2 ISON bob\r\n@label=3 AWAY\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    #[cfg(feature = "ctcp")]
-    async fn ctcp_ping_no_timestamp() -> Result<()> {
-        let value = ":test!test@test PRIVMSG test \u{001}PING\u{001}\r\n";
+    async fn send_labeled_without_capability() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        let whois = client.send_labeled(Command::WHOIS(None, "alice".to_owned()));
+        assert!(matches!(
+            whois.await,
+            Err(Error::CapabilityNotEnabled { capability }) if capability == "labeled-response"
+        ));
+        client.stream()?.collect().await?;
+        assert_eq!(&get_client_value(client)[..], "");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_handler() -> Result<()> {
+        let value = ":irc.test.net BATCH +netjoin1 netjoin irc.hub other.host\r\n\
+                     @batch=netjoin1 :aji!a@a JOIN #test\r\n\
+                     @batch=netjoin1 :nenolod!a@a JOIN #test\r\n\
+                     :irc.test.net BATCH -netjoin1\r\n\
+                     :irc.test.net BATCH +history chathistory #test\r\n\
+                     @batch=history :irc.test.net BATCH +nested example.com/nested\r\n\
+                     @batch=nested :nick!user@host PRIVMSG #test :Nested\r\n\
+                     :irc.test.net BATCH -nested\r\n\
+                     @batch=history :nick!user@host PRIVMSG #test :Hi\r\n\
+                     :irc.test.net BATCH -history\r\n";
         let mut client = Client::from_config(Config {
             mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.stream()?.collect().await?;
-        assert_eq!(&get_client_value(client)[..], "");
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let handler_batches = batches.clone();
+        client.add_batch_handler(move |batch| handler_batches.lock().push(batch.clone()));
+        let messages = client.stream()?.collect().await?;
+        assert_eq!(messages.len(), 10);
+
+        let batches = batches.lock();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].kind, BatchSubCommand::NETJOIN);
+        assert_eq!(batches[0].messages.len(), 2);
+        assert_eq!(batches[1].reference, "history");
+        assert_eq!(batches[1].messages.len(), 1);
+        assert_eq!(batches[1].batches.len(), 1);
+        assert_eq!(batches[1].batches[0].reference, "nested");
+        assert_eq!(batches[1].batches[0].messages.len(), 1);
         Ok(())
     }
 
     #[tokio::test]
-    async fn identify() -> Result<()> {
-        let mut client = Client::from_config(test_config()).await?;
-        client.identify()?;
-        client.stream()?.collect().await?;
+    async fn chathistory_latest() -> Result<()> {
+        let value = ":irc.test.net CAP * ACK :batch server-time draft/chathistory\r\n\
+                     :irc.test.net BATCH +h1 chathistory #other\r\n\
+                     :irc.test.net BATCH -h1\r\n\
+                     :irc.test.net BATCH +h2 chathistory #test\r\n\
+                     @batch=h2;msgid=b;time=2019-01-04T14:33:27.000Z :alice!a@host PRIVMSG #test :second\r\n\
+                     @batch=h2;msgid=a;time=2019-01-04T14:33:26.000Z :bob!b@host PRIVMSG #test :first\r\n\
+                     @batch=h2;msgid=c;time=2019-01-04T14:33:28.000Z :alice!a@host PRIVMSG #test :third\r\n\
+                     :irc.test.net BATCH -h2\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            ..test_config()
+        })
+        .await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        let history = client.chathistory("#test", HistoryQuery::Latest(None), 3);
+        stream.collect().await?;
+
+        let history = history.await?;
+        let texts: Vec<_> = history
+            .iter()
+            .map(|msg| match msg.command {
+                PRIVMSG(_, ref text) => &text[..],
+                _ => "",
+            })
+            .collect();
+        assert_eq!(texts, vec!["first", "second", "third"]);
+        assert_eq!(
+            MessageReference::from_message(&history[0]),
+            Some(MessageReference::MsgId("a".to_owned()))
+        );
         assert_eq!(
             &get_client_value(client)[..],
-            "CAP END\r\nNICK test\r\n\
-             USER test 0 * test\r\n"
+            "CHATHISTORY LATEST #test * 3\r\n"
         );
         Ok(())
     }
 
     #[tokio::test]
-    async fn identify_with_password() -> Result<()> {
+    async fn chathistory_fail() -> Result<()> {
+        let value = ":irc.test.net CAP * ACK :batch server-time draft/chathistory\r\n\
+                     :irc.test.net FAIL CHATHISTORY INVALID_TARGET LATEST #b :No such channel\r\n\
+                     :irc.test.net BATCH +h1 chathistory #a\r\n\
+                     :irc.test.net BATCH -h1\r\n";
         let mut client = Client::from_config(Config {
-            nickname: Some("test".to_string()),
-            password: Some("password".to_string()),
+            mock_initial_value: Some(value.to_owned()),
             ..test_config()
         })
         .await?;
-        client.identify()?;
-        client.stream()?.collect().await?;
-        assert_eq!(
-            &get_client_value(client)[..],
-            "CAP END\r\nPASS password\r\nNICK test\r\n\
-             USER test 0 * test\r\n"
-        );
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        let history_a = client.chathistory("#a", HistoryQuery::Latest(None), 3);
+        let history_b = client.chathistory("#b", HistoryQuery::Latest(None), 3);
+        stream.collect().await?;
+
+        assert!(history_a.await?.is_empty());
+        match history_b.await {
+            Err(Error::RequestFailed { reply }) => assert_eq!(reply.code, "INVALID_TARGET"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(client.state.pending_histories.lock().is_empty());
         Ok(())
     }
 
     #[tokio::test]
-    async fn send_pong() -> Result<()> {
-        let mut client = Client::from_config(test_config()).await?;
-        client.send_pong("irc.test.net")?;
-        client.stream()?.collect().await?;
-        assert_eq!(&get_client_value(client)[..], "PONG irc.test.net\r\n");
+    async fn chathistory_with_dedup() -> Result<()> {
+        let value = ":irc.test.net CAP * ACK :batch server-time draft/chathistory\r\n\
+                     @msgid=a;time=2019-01-04T14:33:26.000Z :bob!b@host PRIVMSG #test :first\r\n\
+                     :irc.test.net BATCH +h1 chathistory #test\r\n\
+                     @batch=h1;msgid=a;time=2019-01-04T14:33:26.000Z :bob!b@host PRIVMSG #test :first\r\n\
+                     @batch=h1;msgid=b;time=2019-01-04T14:33:27.000Z :alice!a@host PRIVMSG #test :second\r\n\
+                     :irc.test.net BATCH -h1\r\n\
+                     @msgid=a;time=2019-01-04T14:33:26.000Z :bob!b@host PRIVMSG #test :first\r\n";
+        let mut client = Client::from_config(Config {
+            mock_initial_value: Some(value.to_owned()),
+            dedup_window: Some(16),
+            ..test_config()
+        })
+        .await?;
+        let mut stream = client.stream()?;
+        stream.next().await.transpose()?;
+        let history = client.chathistory("#test", HistoryQuery::Latest(None), 2);
+        let messages: Vec<_> = stream.collect().await?;
+
+        assert_eq!(history.await?.len(), 2);
+        // The live message replayed at the end is the only one suppressed.
+        let privmsgs = messages
+            .iter()
+            .filter(|msg| matches!(msg.command, PRIVMSG(..)))
+            .count();
+        assert_eq!(privmsgs, 3);
         Ok(())
     }
 
     #[tokio::test]
-    async fn send_join() -> Result<()> {
+    async fn chathistory_without_capability() -> Result<()> {
         let mut client = Client::from_config(test_config()).await?;
-        client.send_join("#test,#test2,#test3")?;
+        let history = client.chathistory("#test", HistoryQuery::Latest(None), 3);
+        assert!(matches!(
+            history.await,
+            Err(Error::CapabilityNotEnabled { .. })
+        ));
         client.stream()?.collect().await?;
-        assert_eq!(
-            &get_client_value(client)[..],
-            "JOIN #test,#test2,#test3\r\n"
-        );
+        assert_eq!(&get_client_value(client)[..], "");
         Ok(())
     }
 
@@ -1758,6 +8400,38 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn send_topic_too_long() -> Result<()> {
+        let mut client = Client::from_config(test_config()).await?;
+        // "TOPIC #test " and the CRLF take 14 bytes, as a topic without spaces needs no colon.
+        let fits = MAX_LINE_LENGTH - RELAYED_SOURCE_LENGTH - 14;
+        match client.send_topic("#test", "a".repeat(fits + 10)) {
+            Err(Error::MessageTooLong { length }) => assert_eq!(length, MAX_LINE_LENGTH + 10),
+            other => panic!("expected the topic to be too long, got {:?}", other),
+        }
+        client.send_topic("#test", "a".repeat(fits))?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            get_client_value(client),
+            format!("TOPIC #test {}\r\n", "a".repeat(fits))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_join_and_kick_too_long() -> Result<()> {
+        let client = Client::from_config(test_config()).await?;
+        assert!(matches!(
+            client.send_join_with_keys("#test", "k".repeat(500)),
+            Err(Error::MessageTooLong { .. })
+        ));
+        assert!(matches!(
+            client.send_kick("#test", "test", "r".repeat(400)),
+            Err(Error::MessageTooLong { .. })
+        ));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn send_kill() -> Result<()> {
         let mut client = Client::from_config(test_config()).await?;
@@ -1861,6 +8535,26 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    #[cfg(feature = "ctcp")]
+    async fn send_dcc() -> Result<()> {
+        use crate::client::dcc::{DccMessage, DccResume};
+
+        let mut client = Client::from_config(test_config()).await?;
+        let accept = DccMessage::Accept(DccResume {
+            filename: "my file.txt".to_owned(),
+            port: 5000,
+            position: 512,
+        });
+        client.send_dcc("test", &accept)?;
+        client.stream()?.collect().await?;
+        assert_eq!(
+            &get_client_value(client)[..],
+            "PRIVMSG test :\u{001}DCC ACCEPT \"my file.txt\" 5000 512\u{001}\r\n"
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg(feature = "ctcp")]
     async fn send_action() -> Result<()> {
@@ -2,24 +2,28 @@
 //! automatic PING replies, automatic sending of PINGs, and message rate-limiting. This can be used
 //! as the basis for implementing a more full IRC client.
 use std::{
+    collections::{HashMap, VecDeque},
     pin::Pin,
-    sync::{Arc, RwLock, RwLockReadGuard},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard,
+    },
     task::{Context, Poll},
     time::Duration,
 };
 
 use chrono::prelude::*;
 use futures_util::{future::Future, ready, sink::Sink, stream::Stream};
+use parking_lot::Mutex;
 use pin_project::pin_project;
-use tokio::sync::mpsc::UnboundedSender;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    time::{self, Interval, Sleep},
+    time::{self, Instant, Interval, Sleep},
 };
 use tokio_util::codec::Framed;
 
 use crate::{
-    client::data::Config,
+    client::{data::Config, Sender},
     error,
     proto::{Command, IrcCodec, Message, Response},
 };
@@ -27,7 +31,7 @@ use crate::{
 /// Pinger-based futures helper.
 #[pin_project]
 struct Pinger {
-    tx: UnboundedSender<Message>,
+    tx: Sender,
     // Whether this pinger pings.
     enabled: bool,
     /// The amount of time to wait before timing out from no ping response.
@@ -42,7 +46,7 @@ struct Pinger {
 
 impl Pinger {
     /// Construct a new pinger helper.
-    pub fn new(tx: UnboundedSender<Message>, config: &Config) -> Pinger {
+    pub fn new(tx: Sender, config: &Config) -> Pinger {
         let ping_time = Duration::from_secs(u64::from(config.ping_time()));
         let ping_timeout = Duration::from_secs(u64::from(config.ping_timeout()));
 
@@ -81,10 +85,7 @@ impl Pinger {
 
     /// Send a pong.
     fn send_pong(self: Pin<&mut Self>, data: &str) -> error::Result<()> {
-        self.project()
-            .tx
-            .send(Command::PONG(data.to_owned(), None).into())?;
-        Ok(())
+        self.project().tx.send(Command::PONG(data.to_owned(), None))
     }
 
     /// Sends a ping via the transport.
@@ -96,7 +97,7 @@ impl Pinger {
 
         let mut this = self.project();
 
-        this.tx.send(Command::PING(data, None).into())?;
+        this.tx.send(Command::PING(data, None))?;
 
         if this.ping_deadline.is_none() {
             let ping_deadline = time::sleep(*this.ping_timeout);
@@ -133,6 +134,236 @@ impl Future for Pinger {
     }
 }
 
+/// The most messages a `Throttle` holds back before the transport stops accepting more.
+const MAX_THROTTLED_MESSAGES: usize = 1024;
+
+/// Holds back outgoing messages so that no more than `max_messages_in_burst` are sent within any
+/// `burst_window_length`, releasing them round-robin between the targets they are addressed to so
+/// that a busy target can't starve the others. `PONG` and `QUIT` are never held back, since a late
+/// `PONG` can get us disconnected for timing out.
+struct Throttle {
+    /// The length of the rolling window.
+    window: Duration,
+    /// The maximum number of messages sent within the window, or zero for no limit.
+    max_in_window: usize,
+    /// The instants at which the messages still in the window were sent.
+    sent: VecDeque<Instant>,
+    /// Targets with queued messages, in the order they will next be served.
+    targets: VecDeque<String>,
+    /// Queued messages by target. Messages without a target share the empty target.
+    queues: HashMap<String, VecDeque<Message>>,
+    /// Queued messages that are sent ahead of the others without waiting for the rate limit.
+    exempt: VecDeque<Message>,
+    /// A timer for when the oldest message in the window expires.
+    delay: Option<Pin<Box<Sleep>>>,
+    /// The number of queued messages, shared with the `Sender`.
+    depth: Arc<AtomicUsize>,
+}
+
+impl Throttle {
+    fn new(config: &Config, depth: Arc<AtomicUsize>) -> Throttle {
+        Throttle {
+            window: Duration::from_secs(u64::from(config.burst_window_length())),
+            max_in_window: config.max_messages_in_burst() as usize,
+            sent: VecDeque::new(),
+            targets: VecDeque::new(),
+            queues: HashMap::new(),
+            exempt: VecDeque::new(),
+            delay: None,
+            depth,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.exempt.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() >= MAX_THROTTLED_MESSAGES
+    }
+
+    fn len(&self) -> usize {
+        self.exempt.len() + self.queues.values().map(VecDeque::len).sum::<usize>()
+    }
+
+    fn len_for(&self, target: &str) -> usize {
+        self.queues.get(target).map_or(0, VecDeque::len)
+    }
+
+    /// Queues a message behind any others for the same target.
+    fn push(&mut self, message: Message) {
+        self.depth.fetch_add(1, Ordering::SeqCst);
+        if let Command::PONG(..) | Command::QUIT(..) = message.command {
+            self.exempt.push_back(message);
+            return;
+        }
+
+        let target = match message.command {
+            Command::PRIVMSG(ref target, _) | Command::NOTICE(ref target, _) => target.clone(),
+            _ => String::new(),
+        };
+        let queue = self.queues.entry(target.clone()).or_default();
+        if queue.is_empty() {
+            self.targets.push_back(target);
+        }
+        queue.push_back(message);
+    }
+
+    /// Resolves once another message can be sent without exceeding the rate limit.
+    fn poll_permit(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.max_in_window == 0 || !self.exempt.is_empty() {
+            return Poll::Ready(());
+        }
+
+        loop {
+            let now = Instant::now();
+            while self
+                .sent
+                .front()
+                .map_or(false, |sent| *sent + self.window <= now)
+            {
+                self.sent.pop_front();
+            }
+
+            let oldest = match self.sent.front() {
+                Some(oldest) if self.sent.len() >= self.max_in_window => *oldest,
+                _ => return Poll::Ready(()),
+            };
+
+            let deadline = oldest + self.window;
+            let delay = self
+                .delay
+                .get_or_insert_with(|| Box::pin(time::sleep_until(deadline)));
+            if delay.deadline() != deadline {
+                delay.as_mut().reset(deadline);
+            }
+            ready!(delay.as_mut().poll(cx));
+        }
+    }
+
+    /// Takes the next message to send, moving on to the next target afterwards. Exempt messages
+    /// come first, and don't count against the rate limit.
+    fn pop(&mut self) -> Option<Message> {
+        if let Some(message) = self.exempt.pop_front() {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+            return Some(message);
+        }
+
+        let target = self.targets.pop_front()?;
+        let queue = self.queues.get_mut(&target)?;
+        let message = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&target);
+        } else {
+            self.targets.push_back(target);
+        }
+
+        if self.max_in_window > 0 {
+            self.sent.push_back(Instant::now());
+        }
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+        Some(message)
+    }
+}
+
+/// The direction in which a message recorded in a [`HistoryEntry`](struct.HistoryEntry.html) went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The message was sent to the server.
+    Sent,
+    /// The message was received from the server.
+    Received,
+}
+
+/// A message recorded in a transport's history.
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    /// Whether the message was sent or received.
+    pub direction: Direction,
+    /// When the message was written to or read from the connection.
+    pub time: DateTime<Local>,
+    /// The message itself.
+    pub message: Message,
+}
+
+/// A bounded record of the most recent messages sent and received, shared between the transport
+/// and the `Sender`s that can inspect it.
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    capacity: usize,
+    entries: Arc<Mutex<VecDeque<HistoryEntry>>>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    fn record(&self, direction: Direction, message: &Message) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(HistoryEntry {
+            direction,
+            time: Local::now(),
+            message: message.clone(),
+        });
+    }
+
+    /// Gets a copy of the recorded messages, oldest first.
+    pub(crate) fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.lock().iter().cloned().collect()
+    }
+}
+
+/// A snapshot of the traffic over a client's connections, e.g. for exporting as metrics. The
+/// counters add up the traffic of every connection the client made, so they keep increasing
+/// across reconnections.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The number of messages written to the connection.
+    pub messages_sent: u64,
+    /// The number of messages read from the connection.
+    pub messages_received: u64,
+    /// The number of bytes written to the connection, including line endings.
+    pub bytes_sent: u64,
+    /// The number of bytes read from the connection, including line endings.
+    pub bytes_received: u64,
+    /// The number of outgoing messages currently held back by rate limiting.
+    pub queue_depth: usize,
+}
+
+/// The traffic counters behind `ConnectionStats`, shared between the transport and the `Sender`s
+/// that can inspect them.
+#[derive(Debug, Default)]
+pub(crate) struct StatCounters {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl StatCounters {
+    /// Takes a snapshot of the counters, along with the given queue depth.
+    pub(crate) fn snapshot(&self, queue_depth: usize) -> ConnectionStats {
+        ConnectionStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            queue_depth,
+        }
+    }
+}
+
 /// An IRC transport that handles core functionality for the IRC protocol. This is used in the
 /// implementation of `Connection` and ultimately `IrcServer`, and plays an important role in
 /// handling connection timeouts, message throttling, and ping response.
@@ -144,6 +375,19 @@ pub struct Transport<T> {
     /// Helper for handle pinging.
     #[pin]
     pinger: Option<Pinger>,
+    /// Queued messages waiting on the rate limit.
+    throttle: Throttle,
+    /// Where to record messages as they are written, if the transport is being logged.
+    sent_log: Option<Arc<RwLock<Vec<Message>>>>,
+    /// The most recent messages sent and received.
+    history: History,
+    /// The traffic counters, shared with the `Sender`.
+    stats: Arc<StatCounters>,
+    /// The number of bytes the codec had decoded when the counters were last updated.
+    bytes_decoded: u64,
+    /// Where to put the bytes of each received line if the codec keeps them, shared with the
+    /// client this transport belongs to.
+    raw_bytes: Arc<Mutex<Option<Vec<u8>>>>,
 }
 
 impl<T> Transport<T>
@@ -151,14 +395,56 @@ where
     T: Unpin + AsyncRead + AsyncWrite,
 {
     /// Creates a new `Transport` from the given IRC stream.
-    pub fn new(
-        config: &Config,
-        inner: Framed<T, IrcCodec>,
-        tx: UnboundedSender<Message>,
-    ) -> Transport<T> {
+    pub fn new(config: &Config, inner: Framed<T, IrcCodec>, tx: Sender) -> Transport<T> {
+        let throttle = Throttle::new(config, tx.throttled.clone());
+        let history = tx.history.clone();
+        let stats = tx.stats.clone();
         let pinger = Some(Pinger::new(tx, config));
+        let bytes_decoded = inner.codec().bytes_decoded();
+
+        Transport {
+            inner,
+            pinger,
+            throttle,
+            sent_log: None,
+            history,
+            stats,
+            bytes_decoded,
+            raw_bytes: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Gets the number of messages held back by rate limiting.
+    pub fn queue_depth(&self) -> usize {
+        self.throttle.len()
+    }
+
+    /// Gets the number of messages to the given target held back by rate limiting. Messages that
+    /// are not addressed to a channel or user are counted under the empty target.
+    pub fn queue_depth_for(&self, target: &str) -> usize {
+        self.throttle.len_for(target)
+    }
+
+    /// Gets a snapshot of the traffic counters, which are shared with the client this transport
+    /// belongs to.
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.snapshot(self.queue_depth())
+    }
+
+    /// Gets the slot the bytes of the most recently received line are put in, if the codec keeps
+    /// them.
+    pub(crate) fn raw_bytes(&self) -> Arc<Mutex<Option<Vec<u8>>>> {
+        self.raw_bytes.clone()
+    }
+
+    /// Gets a reference to the stream underlying the `Transport`.
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
+    }
 
-        Transport { inner, pinger }
+    /// Gets a mutable reference to the stream underlying the `Transport`.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
     }
 
     /// Gets the inner stream underlying the `Transport`.
@@ -182,12 +468,25 @@ where
         }
 
         let result = ready!(self.as_mut().project().inner.poll_next(cx));
+        let this = self.as_mut().project();
+        if let Some(bytes) = this.inner.get_mut().codec_mut().take_raw_bytes() {
+            *this.raw_bytes.lock() = Some(bytes);
+        }
 
         let message = match result {
             None => return Poll::Ready(None),
             Some(message) => message?,
         };
 
+        self.history.record(Direction::Received, &message);
+        let this = self.as_mut().project();
+        let bytes_decoded = this.inner.codec().bytes_decoded();
+        this.stats
+            .bytes_received
+            .fetch_add(bytes_decoded - *this.bytes_decoded, Ordering::Relaxed);
+        this.stats.messages_received.fetch_add(1, Ordering::Relaxed);
+        *this.bytes_decoded = bytes_decoded;
+
         if let Some(pinger) = self.as_mut().project().pinger.as_pin_mut() {
             pinger.handle_message(&message)?;
         }
@@ -202,23 +501,63 @@ where
 {
     type Error = error::Error;
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.project().inner.poll_ready(cx))?;
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Messages are queued until they can be sent, but only so many of them.
+        if self.throttle.is_full() {
+            if let Poll::Ready(result) = self.as_mut().poll_flush(cx) {
+                result?;
+            }
+            if self.throttle.is_full() {
+                return Poll::Pending;
+            }
+        }
         Poll::Ready(Ok(()))
     }
 
     fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
-        log::trace!("[SEND] {}", item);
-        self.project().inner.start_send(item)?;
+        self.project().throttle.push(item);
         Ok(())
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.project().inner.poll_flush(cx))?;
+        let mut this = self.project();
+
+        while !this.throttle.is_empty() {
+            if this.throttle.poll_permit(cx).is_pending() {
+                // Make sure everything released so far goes out while we wait.
+                ready!(this.inner.as_mut().poll_flush(cx))?;
+                return Poll::Pending;
+            }
+
+            ready!(this.inner.as_mut().poll_ready(cx))?;
+            if let Some(message) = this.throttle.pop() {
+                log::trace!("[SEND] {}", message);
+                this.history.record(Direction::Sent, &message);
+                let logged = this.sent_log.as_ref().map(|_| message.clone());
+                let buffered = this.inner.write_buffer().len();
+                this.inner.as_mut().start_send(message)?;
+                let written = this.inner.write_buffer().len() - buffered;
+                this.stats
+                    .bytes_sent
+                    .fetch_add(written as u64, Ordering::Relaxed);
+                this.stats.messages_sent.fetch_add(1, Ordering::Relaxed);
+                if let (Some(sent_log), Some(message)) = (this.sent_log.as_ref(), logged) {
+                    sent_log
+                        .write()
+                        .map_err(|_| error::Error::PoisonedLog)?
+                        .push(message);
+                }
+            }
+        }
+
+        // The framed writer keeps whatever the stream didn't accept in its buffer, and writes the
+        // rest on later flushes, so a message is never cut short by a partial write.
+        ready!(this.inner.poll_flush(cx))?;
         Poll::Ready(Ok(()))
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
         ready!(self.project().inner.poll_close(cx))?;
         Poll::Ready(Ok(()))
     }
@@ -233,12 +572,12 @@ pub struct LogView {
 
 impl LogView {
     /// Gets a read guard for all the messages sent on the transport.
-    pub fn sent(&self) -> error::Result<RwLockReadGuard<Vec<Message>>> {
+    pub fn sent(&self) -> error::Result<RwLockReadGuard<'_, Vec<Message>>> {
         self.sent.read().map_err(|_| error::Error::PoisonedLog)
     }
 
     /// Gets a read guard for all the messages received on the transport.
-    pub fn received(&self) -> error::Result<RwLockReadGuard<Vec<Message>>> {
+    pub fn received(&self) -> error::Result<RwLockReadGuard<'_, Vec<Message>>> {
         self.received.read().map_err(|_| error::Error::PoisonedLog)
     }
 }
@@ -257,20 +596,45 @@ where
     T: AsyncRead + AsyncWrite,
 {
     /// Wraps the given `Transport` in logging.
-    pub fn wrap(inner: Transport<T>) -> Logged<T> {
-        Logged {
-            inner,
-            view: LogView {
-                sent: Arc::new(RwLock::new(vec![])),
-                received: Arc::new(RwLock::new(vec![])),
-            },
-        }
+    pub fn wrap(mut inner: Transport<T>) -> Logged<T> {
+        let view = LogView {
+            sent: Arc::new(RwLock::new(vec![])),
+            received: Arc::new(RwLock::new(vec![])),
+        };
+        // Sent messages are recorded by the transport once they are released by the rate limit.
+        inner.sent_log = Some(view.sent.clone());
+
+        Logged { inner, view }
     }
 
     /// Gets a view of the logging for this transport.
     pub fn view(&self) -> LogView {
         self.view.clone()
     }
+
+    /// Gets the slot the logged `Transport` puts the bytes of received lines in.
+    pub(crate) fn raw_bytes(&self) -> Arc<Mutex<Option<Vec<u8>>>>
+    where
+        T: Unpin,
+    {
+        self.inner.raw_bytes()
+    }
+
+    /// Gets a reference to the stream underlying the logged `Transport`.
+    pub fn get_ref(&self) -> &T
+    where
+        T: Unpin,
+    {
+        self.inner.get_ref()
+    }
+
+    /// Gets a mutable reference to the stream underlying the logged `Transport`.
+    pub fn get_mut(&mut self) -> &mut T
+    where
+        T: Unpin,
+    {
+        self.inner.get_mut()
+    }
 }
 
 impl<T> Stream for Logged<T>
@@ -314,20 +678,143 @@ where
     }
 
     fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
-        let this = self.project();
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures::{future, SinkExt};
+    use tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        time,
+    };
+    use tokio_util::codec::Framed;
+
+    use super::{Pinger, Transport};
+    use crate::{
+        client::{
+            data::Config,
+            mock::MockStream,
+            test::{get_client_value, test_config},
+            Client,
+        },
+        error::Error,
+        proto::{IrcCodec, Message},
+    };
+
+    /// A stream that accepts at most one byte per write, and is only ready for every other write.
+    struct Trickle {
+        inner: MockStream,
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = buf.len().min(1);
+            Pin::new(&mut self.inner).poll_write(cx, &buf[..len])
+        }
 
-        this.inner.start_send(item.clone())?;
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
 
-        this.view
-            .sent
-            .write()
-            .map_err(|_| error::Error::PoisonedLog)?
-            .push(item);
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
 
+    #[tokio::test]
+    async fn partial_writes() -> anyhow::Result<()> {
+        let client = Client::from_config(test_config()).await?;
+        let stream = Trickle {
+            inner: MockStream::empty(),
+            ready: false,
+        };
+        let framed = Framed::new(stream, IrcCodec::new("UTF-8")?);
+        let mut transport = Box::pin(Transport::new(&test_config(), framed, client.sender()));
+
+        transport.send("PRIVMSG #test :first line".parse()?).await?;
+        transport
+            .send("PRIVMSG #test :second line".parse()?)
+            .await?;
+        let written = String::from_utf8(transport.get_ref().inner.written().to_vec())?;
+        assert_eq!(
+            written,
+            "PRIVMSG #test :first line\r\nPRIVMSG #test :second line\r\n"
+        );
+        assert_eq!(transport.stats().bytes_sent, written.len() as u64);
         Ok(())
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().inner.poll_flush(cx)
+    #[tokio::test(start_paused = true)]
+    async fn self_ping() -> anyhow::Result<()> {
+        let config = Config {
+            ping_time: Some(60),
+            ping_timeout: Some(90),
+            ..test_config()
+        };
+        let mut client = Client::from_config(config.clone()).await?;
+        let mut pinger = Box::pin(Pinger::new(client.sender(), &config));
+
+        // Nothing is sent before registration is complete.
+        assert!(futures::poll!(pinger.as_mut()).is_pending());
+        let end_of_motd: Message = ":irc.test.net 376 test :End of /MOTD command.".parse()?;
+        pinger.as_mut().handle_message(&end_of_motd)?;
+
+        time::advance(Duration::from_secs(60)).await;
+        assert!(futures::poll!(pinger.as_mut()).is_pending());
+        time::advance(Duration::from_secs(60)).await;
+        assert!(futures::poll!(pinger.as_mut()).is_pending());
+
+        // A reply clears the deadline of the first unanswered ping.
+        pinger
+            .as_mut()
+            .handle_message(&"PONG :irc.test.net".parse()?)?;
+        time::advance(Duration::from_secs(60)).await;
+        assert!(futures::poll!(pinger.as_mut()).is_pending());
+        time::advance(Duration::from_secs(90)).await;
+        assert!(matches!(
+            futures::poll!(pinger.as_mut()),
+            std::task::Poll::Ready(Err(Error::PingTimeout))
+        ));
+        drop(pinger);
+
+        let outgoing = client.outgoing().unwrap();
+        let flush = Box::pin(client.flush());
+        future::select(outgoing, flush).await;
+        let sent = get_client_value(client);
+        assert_eq!(sent.lines().filter(|l| l.starts_with("PING ")).count(), 3);
+        Ok(())
     }
 }
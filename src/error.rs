@@ -8,7 +8,11 @@ use tokio::sync::mpsc::error::{SendError, TrySendError};
 #[cfg(feature = "tls-rust")]
 use tokio_rustls::rustls::client::InvalidDnsNameError;
 
-use crate::proto::error::{MessageParseError, ProtocolError};
+use crate::client::data::AddressFamily;
+use crate::proto::{
+    error::{MessageParseError, ProtocolError},
+    Response, StandardReply,
+};
 
 /// A specialized `Result` type for the `irc` crate.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -120,9 +124,145 @@ pub enum Error {
     #[error("none of the specified nicknames were usable")]
     NoUsableNick,
 
+    /// A request was rejected by the server with a `FAIL` standard reply.
+    #[error("request failed: {}", reply)]
+    RequestFailed {
+        /// The standard reply sent by the server.
+        reply: StandardReply,
+    },
+
+    /// The server sent an `ERROR` other than in answer to our `QUIT`, which it does right before
+    /// closing the connection.
+    #[error("server error: {}", reason)]
+    ServerError {
+        /// The reason given by the server, e.g. `Closing link: throttled`.
+        reason: String,
+    },
+
+    /// None of the server's addresses belong to the IP version we are restricted to, either by
+    /// `address_family` or by the bind address.
+    #[error("the server has no {} address", family)]
+    NoAddressInFamily {
+        /// The IP version we are restricted to.
+        family: AddressFamily,
+    },
+
+    /// The server's TLS certificate does not have the pinned fingerprint.
+    #[error("the server's certificate does not match the pinned fingerprint")]
+    PinnedCertMismatch,
+
+    /// The server refused to let us join a channel, e.g. because we are banned from it.
+    #[error("cannot join {}: {:?}", channel, response)]
+    JoinFailed {
+        /// The channel we tried to join.
+        channel: String,
+        /// The error reply sent by the server, e.g. `ERR_BANNEDFROMCHAN`.
+        response: Response,
+    },
+
+    /// The server did not confirm that we joined a channel within the configured `join_timeout`.
+    #[error("timed out joining {}", channel)]
+    JoinTimedOut {
+        /// The channel we tried to join.
+        channel: String,
+    },
+
+    /// A received line exceeded the maximum line length and was discarded.
+    #[error("line exceeded the maximum length of {} bytes", max)]
+    LineTooLong {
+        /// The maximum line length in bytes.
+        max: usize,
+    },
+
+    /// A message exceeded the maximum line length and could not be sent.
+    #[error("message is too long ({} bytes)", length)]
+    MessageTooLong {
+        /// The length of the message in bytes, excluding tags. For messages the server relays to
+        /// others, this includes room for the source it prepends. Anything past 512 bytes is the
+        /// amount the message has to be shortened by.
+        length: usize,
+    },
+
+    /// A received line was not valid in the encoding used to decode it.
+    #[error("failed to decode line as {}", encoding)]
+    DecodeFailed {
+        /// The name of the encoding.
+        encoding: &'static str,
+        /// The raw bytes of the line, without the line ending.
+        line: Vec<u8>,
+    },
+
+    /// A line could not be represented in the encoding used to send it.
+    #[error("failed to encode line as {}", encoding)]
+    EncodeFailed {
+        /// The name of the encoding.
+        encoding: &'static str,
+        /// The line that failed to encode.
+        line: String,
+    },
+
+    /// A nickname or username was longer than the server allows, and `length_policy` is set to
+    /// `LengthPolicy::Error`.
+    #[error("{} {:?} is longer than {} bytes", kind, name, max)]
+    NameTooLong {
+        /// The kind of name, i.e. `nickname` or `username`.
+        kind: &'static str,
+        /// The name that was too long.
+        name: String,
+        /// The maximum length advertised by the server.
+        max: usize,
+    },
+
+    /// A `LIST` filter relies on a search extension the server does not advertise in `ELIST`.
+    #[error("the server does not support the LIST filter {}", filter)]
+    UnsupportedListFilter {
+        /// The unsupported filter.
+        filter: String,
+    },
+
+    /// A feature was used that requires a capability which has not been negotiated.
+    #[error("the {} capability is not enabled", capability)]
+    CapabilityNotEnabled {
+        /// The name of the capability.
+        capability: String,
+    },
+
+    /// A capability from `Config::required_capabilities` was missing from the server's `CAP LS`
+    /// reply or was refused with `CAP NAK`.
+    #[error("the required {} capability is unavailable", capability)]
+    RequiredCapabilityUnavailable {
+        /// The name of the capability.
+        capability: String,
+    },
+
+    /// `MONITOR` was used, but the server does not advertise support for it.
+    #[error("the server does not support MONITOR")]
+    MonitorUnsupported,
+
+    /// `WATCH` was used, but the server does not advertise support for it.
+    #[error("the server does not support WATCH")]
+    WatchUnsupported,
+
+    /// A DCC transfer did not carry the number of bytes that was advertised.
+    #[error(
+        "DCC transfer size mismatch: expected {} bytes, got {}",
+        expected,
+        received
+    )]
+    DccSizeMismatch {
+        /// The advertised size of the file in bytes.
+        expected: u64,
+        /// The number of bytes transferred.
+        received: u64,
+    },
+
     /// Stream has already been configured.
     #[error("stream has already been configured")]
     StreamAlreadyConfigured,
+
+    /// A reconnect was requested, but the outgoing future is not driven by the stream.
+    #[error("cannot reconnect while the outgoing future is driven separately from the stream")]
+    CannotReconnect,
 }
 
 /// Errors that occur with configurations.
@@ -169,6 +309,52 @@ pub enum ConfigError {
     #[error("server not specified")]
     ServerNotSpecified,
 
+    /// The nickname is empty or contains a space.
+    #[error("invalid nickname: {:?}", nickname)]
+    InvalidNickname {
+        /// The invalid nickname.
+        nickname: String,
+    },
+
+    /// The port is outside the usable range.
+    #[error("invalid port: {}", port)]
+    InvalidPort {
+        /// The invalid port.
+        port: u16,
+    },
+
+    /// The bind address is not a valid IP address.
+    #[error("invalid bind address: {:?}", address)]
+    InvalidBindAddress {
+        /// The invalid address.
+        address: String,
+    },
+
+    /// The pinned certificate fingerprint is not a SHA-256 hash in hex.
+    #[error("invalid certificate fingerprint: {:?}", fingerprint)]
+    InvalidFingerprint {
+        /// The invalid fingerprint.
+        fingerprint: String,
+    },
+
+    /// An option was set that cannot take effect given another setting.
+    #[error("{} cannot be used with {}", option, conflict)]
+    ConflictingOptions {
+        /// The option that was set.
+        option: &'static str,
+        /// The setting it conflicts with.
+        conflict: &'static str,
+    },
+
+    /// An environment variable could not be parsed as the type of its field.
+    #[error("invalid value for environment variable {}: {:?}", name, value)]
+    InvalidEnvVar {
+        /// The name of the environment variable.
+        name: String,
+        /// The value that could not be parsed.
+        value: String,
+    },
+
     /// The specified file could not be read.
     #[error("could not read file {}", file)]
     FileMissing {
@@ -196,6 +382,14 @@ impl From<ProtocolError> for Error {
             ProtocolError::InvalidMessage { string, cause } => {
                 Error::InvalidMessage { string, cause }
             }
+            ProtocolError::LineTooLong { max } => Error::LineTooLong { max },
+            ProtocolError::MessageTooLong { length } => Error::MessageTooLong { length },
+            ProtocolError::DecodeFailed { encoding, line } => {
+                Error::DecodeFailed { encoding, line }
+            }
+            ProtocolError::EncodeFailed { encoding, line } => {
+                Error::EncodeFailed { encoding, line }
+            }
         }
     }
 }